@@ -209,7 +209,7 @@ class UsbDeviceHandlerRust {
 
             // Send RESET opcode (8) with NORESP flag (64)
             // Space is FILE (0) according to C# reference
-            await this.core.sendCommand(8, 0, 64, null);
+            await this.core.sendCommand({ opcode: 8, space: 0, flags: 64 });
             
             // Also call core.reset() to set DTR = false and wait 500ms (matching C# Reset() method)
             await this.core.reset();
@@ -233,7 +233,7 @@ class UsbDeviceHandlerRust {
             }
 
             // INFO opcode (11), Space SNES (1), Flags 0
-            const response = await this.core.sendCommand(11, 1, 0, null);
+            const response = await this.core.sendCommand({ opcode: 11, space: 1 });
             
             if (!parseInfoResponse) {
                 throw new Error('parseInfoResponse function not available');
@@ -271,7 +271,7 @@ class UsbDeviceHandlerRust {
             // Args: address (hex string), size (hex string)
             const addressHex = address.toString(16).padStart(8, '0');
             const sizeHex = size.toString(16).padStart(8, '0');
-            const response = await this.core.sendCommand(0, 1, 0, [addressHex, sizeHex]);
+            const response = await this.core.sendCommand({ opcode: 0, space: 1, args: [addressHex, sizeHex] });
             
             // Parse response - GET returns data starting at byte 0
             // The actual data is in the response packet
@@ -301,7 +301,7 @@ class UsbDeviceHandlerRust {
             const sizeHex = data.length.toString(16).padStart(8, '0');
             
             // PUT requires data in the packet - send command first
-            const response = await this.core.sendCommand(1, 1, 0, [addressHex, sizeHex]);
+            const response = await this.core.sendCommand({ opcode: 1, space: 1, args: [addressHex, sizeHex] });
             
             // After PUT command, data must be written separately
             // This matches the JavaScript handler behavior
@@ -332,7 +332,7 @@ class UsbDeviceHandlerRust {
             const sizeHex = cmdData.length.toString(16).padStart(8, '0');
             
             // Send PUT command to CMD space
-            const response = await this.core.sendCommand(1, 3, 0, [addressHex, sizeHex]);
+            const response = await this.core.sendCommand({ opcode: 1, space: 3, args: [addressHex, sizeHex] });
             
             // After PUT command to CMD space, data must be written separately
             // This matches the JavaScript handler behavior (usbDeviceHandler.js line 1706-1720)
@@ -358,7 +358,7 @@ class UsbDeviceHandlerRust {
 
             // LS opcode (4), Space FILE (0), Flags 0
             // Args: path (string)
-            const response = await this.core.sendCommand(4, 0, 0, [dirPath || '/']);
+            const response = await this.core.sendCommand({ opcode: 4, space: 0, args: [dirPath || '/'] });
             
             // Parse LS response (type byte, filename null-terminated pairs)
             // This matches the JavaScript handler's _parseDirectoryListing method
@@ -405,7 +405,7 @@ class UsbDeviceHandlerRust {
 
             // MKDIR opcode (5), Space FILE (0), Flags NORESP (64)
             // MKDIR uses NORESP flag (fire-and-forget) - no response expected
-            await this.core.sendCommand(5, 0, 64, [dirPath]);
+            await this.core.sendCommand({ opcode: 5, space: 0, flags: 64, args: [dirPath] });
             
             return true;
         } catch (error) {
@@ -425,7 +425,7 @@ class UsbDeviceHandlerRust {
             }
 
             // RM opcode (6), Space FILE (0), Flags 0
-            const response = await this.core.sendCommand(6, 0, 0, [path]);
+            const response = await this.core.sendCommand({ opcode: 6, space: 0, args: [path] });
             
             return response && response[4] === 15; // RESPONSE opcode = 15
         } catch (error) {
@@ -446,7 +446,7 @@ class UsbDeviceHandlerRust {
 
             // MV opcode (7), Space FILE (0), Flags 0
             // Args: sourcePath (string), destPath (string)
-            const response = await this.core.sendCommand(7, 0, 0, [sourcePath, destPath]);
+            const response = await this.core.sendCommand({ opcode: 7, space: 0, args: [sourcePath, destPath] });
             
             return response && response[4] === 15; // RESPONSE opcode = 15
         } catch (error) {
@@ -467,7 +467,7 @@ class UsbDeviceHandlerRust {
 
             // BOOT opcode (9), Space FILE (0), Flags 0
             // Args: filePath (string)
-            const response = await this.core.sendCommand(9, 0, 0, [filePath]);
+            const response = await this.core.sendCommand({ opcode: 9, space: 0, args: [filePath] });
             
             return response && response[4] === 15; // RESPONSE opcode = 15
         } catch (error) {
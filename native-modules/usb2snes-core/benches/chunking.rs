@@ -0,0 +1,45 @@
+// Host data phases with adaptive and pinned write chunk sizes against the simulated device
+// Each PUT sends 32 blocks. The adaptive connection starts at one block per flush and
+// grows by a block per chunk, so it passes 4KB within the first call and settles on
+// the largest size (8KB) in the second. The simulated port charges nothing per
+// flush, so the times show what the chunking itself costs, not what it saves on a
+// real link. Run with `cargo bench --bench chunking`.
+
+mod common;
+
+use napi::bindgen_prelude::Buffer;
+use usb2snes_core::ConnectOptions;
+
+/// Simulated round-trip latency per RESPONSE
+const LATENCY_MS: u32 = 1;
+
+/// Bytes per PUT: 32 blocks of 512
+const SIZE: usize = 32 * 512;
+
+/// WRAM, writable and large enough for SIZE
+const ADDRESS: u32 = 0xF50000;
+
+const ITERATIONS: u32 = 20;
+
+fn main() {
+    println!("put_memory, {} bytes per call, {}ms latency per RESPONSE", SIZE, LATENCY_MS);
+    for pinned in [None, Some(1), Some(4), Some(16)] {
+        let options = ConnectOptions { write_chunk_blocks: pinned, ..Default::default() };
+        let core = common::simulated_core_with(LATENCY_MS, options);
+        let data: Vec<u8> = (0..SIZE).map(|i| i as u8).collect();
+
+        // First call alone, to show where the adaptive size gets to from one block
+        core.put_memory(ADDRESS, Buffer::from(data.clone()), None, None).expect("put_memory");
+        let after_first = core.stats().write_chunk_bytes;
+        let mean = common::time_per_run(ITERATIONS, || {
+            core.put_memory(ADDRESS, Buffer::from(data.clone()), None, None).expect("put_memory");
+        });
+
+        let label = pinned.map_or("adaptive".to_string(), |blocks| format!("pinned, {} blocks", blocks));
+        println!(
+            "  {:<18} {:>8.2?} per call, chunk {} bytes after the first call, {} at the end",
+            label, mean, after_first, core.stats().write_chunk_bytes
+        );
+        core.disconnect(None).expect("disconnect");
+    }
+}
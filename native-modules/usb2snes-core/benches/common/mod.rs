@@ -0,0 +1,62 @@
+// Shared by the benches: a simulated device to measure against
+// Bench binaries run without Node, so nothing provides the napi calls the core's
+// bindings link against. None of them is reached (no JS values are involved), so each
+// is stubbed to panic if it ever is.
+
+// Each bench uses only some of the helpers
+#![allow(dead_code)]
+
+use std::time::{Duration, Instant};
+use usb2snes_core::simulator::SimulatorProfile;
+use usb2snes_core::{ConnectOptions, Usb2SnesCore};
+
+macro_rules! napi_stubs {
+    ($($name:ident),* $(,)?) => {$(
+        #[no_mangle]
+        extern "C" fn $name() -> i32 {
+            unreachable!(concat!(stringify!($name), " called in a bench binary"))
+        }
+    )*};
+}
+
+napi_stubs!(
+    napi_call_function, napi_call_threadsafe_function, napi_coerce_to_string,
+    napi_create_array_with_length, napi_create_async_work, napi_create_buffer,
+    napi_create_buffer_copy, napi_create_double, napi_create_error, napi_create_external,
+    napi_create_external_buffer, napi_create_function, napi_create_int32, napi_create_object,
+    napi_create_promise, napi_create_reference, napi_create_string_utf8,
+    napi_create_threadsafe_function, napi_create_uint32, napi_delete_async_work,
+    napi_delete_reference, napi_fatal_error, napi_fatal_exception,
+    napi_get_and_clear_last_exception, napi_get_array_length, napi_get_boolean,
+    napi_get_buffer_info, napi_get_cb_info, napi_get_element, napi_get_global,
+    napi_get_named_property, napi_get_null, napi_get_reference_value, napi_get_undefined,
+    napi_get_value_bigint_words, napi_get_value_bool, napi_get_value_double, napi_get_value_int32,
+    napi_get_value_string_utf8, napi_get_value_uint32, napi_is_array, napi_is_buffer, napi_is_error,
+    napi_is_exception_pending, napi_new_instance, napi_queue_async_work, napi_reference_unref,
+    napi_reject_deferred, napi_release_threadsafe_function, napi_resolve_deferred, napi_set_element,
+    napi_set_named_property, napi_throw, napi_typeof, napi_unref_threadsafe_function, napi_unwrap,
+    napi_wrap,
+);
+
+/// A core connected to a simulated device whose RESPONSEs arrive `latency_ms` late
+pub fn simulated_core(latency_ms: u32) -> Usb2SnesCore {
+    simulated_core_with(latency_ms, ConnectOptions::default())
+}
+
+/// simulated_core() connected with `options`
+pub fn simulated_core_with(latency_ms: u32, options: ConnectOptions) -> Usb2SnesCore {
+    let core = Usb2SnesCore::new();
+    let profile = SimulatorProfile { latency_ms: Some(latency_ms), ..Default::default() };
+    core.connect_simulated(Some(profile), Some(options)).expect("connect_simulated");
+    core
+}
+
+/// Mean time of `iterations` runs of `f`, after one warm-up run
+pub fn time_per_run(iterations: u32, mut f: impl FnMut()) -> Duration {
+    f();
+    let started = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    started.elapsed() / iterations
+}
@@ -0,0 +1,39 @@
+// read_multiple() with and without pipelining against the simulated device
+// Every RESPONSE is held back by a fixed latency, like a USB round trip, so writing
+// VGETs ahead should cut the time per call roughly by the pipeline depth.
+// Run with `cargo bench --bench pipeline`.
+
+mod common;
+
+use usb2snes_core::pipeline::{ReadMultipleOptions, ReadRequest};
+use usb2snes_core::regions::MemoryRegion;
+
+/// Simulated round-trip latency per RESPONSE
+const LATENCY_MS: u32 = 4;
+
+/// Reads per call: 32 pairs make 4 VGET packets
+const READS: u32 = 32;
+
+const ITERATIONS: u32 = 20;
+
+fn main() {
+    let core = common::simulated_core(LATENCY_MS);
+    let reads = || (0..READS)
+        .map(|i| ReadRequest { region: MemoryRegion::Wram, offset: i * 0x100, size: 64 })
+        .collect::<Vec<_>>();
+
+    println!("read_multiple, {} reads of 64 bytes, {}ms latency per RESPONSE", READS, LATENCY_MS);
+    for depth in [None, Some(2), Some(4)] {
+        let options = || ReadMultipleOptions {
+            pipeline: Some(depth.is_some()),
+            pipeline_depth: depth,
+            ..Default::default()
+        };
+        let mean = common::time_per_run(ITERATIONS, || {
+            core.read_multiple(reads(), Some(options())).expect("read_multiple");
+        });
+        let label = depth.map_or("sequential".to_string(), |depth| format!("pipelined, depth {}", depth));
+        println!("  {:<20} {:>8.2?} per call", label, mean);
+    }
+    core.disconnect(None).expect("disconnect");
+}
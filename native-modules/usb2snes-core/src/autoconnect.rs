@@ -0,0 +1,108 @@
+// Find the FxPak without knowing its port name
+// auto_connect() opens each candidate serial port in turn, sends INFO with a short
+// timeout and keeps the first port that answers with a valid USBA RESPONSE. Ports
+// with the FxPak's USB IDs are tried first, then other USB ports (bridges for the
+// original SD2SNES), then everything else (Bluetooth, onboard UARTs). A port that
+// fails to open or answer is closed again before the next one is tried.
+
+use napi_derive::napi;
+use serialport::SerialPortType;
+
+use crate::device_manager::{FXPAK_PID, FXPAK_VID};
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::{ConnectOptions, Usb2SnesCore};
+
+/// INFO timeout per candidate port unless given
+const DEFAULT_PROBE_TIMEOUT_MS: u32 = 500;
+
+/// Options for auto_connect()
+#[napi(object)]
+#[derive(Default)]
+pub struct AutoConnectOptions {
+    /// How long each port gets to answer INFO (default 500ms)
+    pub probe_timeout_ms: Option<u32>,
+    /// Only try ports with the FxPak's USB VID/PID (default false)
+    pub known_ids_only: Option<bool>,
+    /// Options for the connection that is kept; `verify` is always on
+    pub connect: Option<ConnectOptions>,
+}
+
+/// A candidate port that was tried and rejected
+#[napi(object)]
+pub struct ProbeAttempt {
+    pub port_name: String,
+    pub error: String,
+}
+
+/// Result of auto_connect()
+#[napi(object)]
+pub struct AutoConnectResult {
+    pub port_name: String,
+    /// Ports tried before it, in order
+    pub rejected: Vec<ProbeAttempt>,
+}
+
+/// Serial ports to probe, most likely first
+/// On macOS the tty.* twin of a cu.* port is dropped: it's the same device, and opening
+/// it waits for carrier detect.
+fn candidate_ports(known_ids_only: bool) -> Result<Vec<String>> {
+    let ports = serialport::available_ports()
+        .map_err(|e| CoreError::new(ErrorCode::IoError, format!("Failed to enumerate serial ports: {}", e)))?;
+    let names: Vec<String> = ports.iter().map(|port| port.port_name.clone()).collect();
+
+    let mut candidates: Vec<(u8, String)> = ports.into_iter()
+        .filter(|port| match port.port_name.strip_prefix("/dev/tty.") {
+            Some(rest) => !names.contains(&format!("/dev/cu.{}", rest)),
+            None => true,
+        })
+        .filter_map(|port| {
+            let rank = match port.port_type {
+                SerialPortType::UsbPort(usb) if usb.vid == FXPAK_VID && usb.pid == FXPAK_PID => 0,
+                _ if known_ids_only => return None,
+                SerialPortType::UsbPort(_) => 1,
+                _ => 2,
+            };
+            Some((rank, port.port_name))
+        })
+        .collect();
+    // Stable, so the OS order is kept within a rank
+    candidates.sort_by_key(|&(rank, _)| rank);
+    Ok(candidates.into_iter().map(|(_, name)| name).collect())
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Connect to the first serial port that answers INFO like an FxPak/sd2snes
+    /// Each candidate is opened with connect_with_options() and `verify` on, using
+    /// `probe_timeout_ms` (default 500ms) as the INFO timeout. Returns the port kept
+    /// and the ports rejected before it; if none answers, fails with "NoDeviceFound: ..."
+    /// listing each port's error.
+    #[napi]
+    pub fn auto_connect(&self, options: Option<AutoConnectOptions>) -> Result<AutoConnectResult> {
+        let options = options.unwrap_or_default();
+        let candidates = candidate_ports(options.known_ids_only.unwrap_or(false))?;
+        let connect = ConnectOptions {
+            verify: Some(true),
+            verify_timeout_ms: Some(options.probe_timeout_ms.unwrap_or(DEFAULT_PROBE_TIMEOUT_MS)),
+            ..options.connect.unwrap_or_default()
+        };
+
+        let mut rejected = Vec::new();
+        for port_name in candidates {
+            match self.connect_with_options(port_name.clone(), Some(connect.clone())) {
+                Ok(()) => return Ok(AutoConnectResult { port_name, rejected }),
+                Err(e) => rejected.push(ProbeAttempt { port_name, error: e.reason }),
+            }
+        }
+
+        let reason = if rejected.is_empty() {
+            "NoDeviceFound: no candidate serial ports".to_string()
+        } else {
+            let tried: Vec<String> = rejected.iter()
+                .map(|attempt| format!("{}: {}", attempt.port_name, attempt.error))
+                .collect();
+            format!("NoDeviceFound: no port answered INFO ({})", tried.join("; "))
+        };
+        Err(CoreError::new(ErrorCode::NotConnected, reason))
+    }
+}
@@ -0,0 +1,154 @@
+// Opt-in, short-TTL cache for repeated identical reads
+// Several UI components read the same ROM header and INFO within milliseconds of
+// each other; with the cache on, those reads are answered once per TTL.
+// Any command that changes device state invalidates everything.
+
+use napi_derive::napi;
+use crate::errors::Result;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::regions::MemoryRegion;
+use crate::{Usb2SnesCore, SPACE_SNES};
+
+/// Default cache lifetime of an entry
+const DEFAULT_TTL_MS: u32 = 250;
+
+/// Entries kept before the cache is flushed (reads are small and TTLs short)
+const MAX_ENTRIES: usize = 64;
+
+/// Options for configure_read_cache(); omitted fields keep their current value
+#[napi(object)]
+pub struct ReadCacheOptions {
+    /// Turn the cache on or off (off by default)
+    pub enabled: Option<bool>,
+    /// Entry lifetime in milliseconds (default 250)
+    pub ttl_ms: Option<u32>,
+    /// Regions whose GETs may be cached (default only Rom; WRAM/SRAM change constantly)
+    pub regions: Option<Vec<MemoryRegion>>,
+    /// Cache INFO responses too (default true)
+    pub cache_info: Option<bool>,
+}
+
+pub(crate) struct ReadCache {
+    enabled: bool,
+    ttl: Duration,
+    regions: Vec<MemoryRegion>,
+    cache_info: bool,
+    entries: HashMap<(u8, u32, u32), (Instant, Vec<u8>)>,
+    info: Option<(Instant, Vec<u8>)>,
+    pub(crate) hits: u32,
+    pub(crate) misses: u32,
+}
+
+impl Default for ReadCache {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl: Duration::from_millis(DEFAULT_TTL_MS as u64),
+            regions: vec![MemoryRegion::Rom],
+            cache_info: true,
+            entries: HashMap::new(),
+            info: None,
+            hits: 0,
+            misses: 0,
+        }
+    }
+}
+
+impl ReadCache {
+    /// Whether a GET of this range may be cached
+    fn cacheable(&self, space: u8, address: u32, size: u32) -> bool {
+        self.enabled && space == SPACE_SNES && self.regions.iter().any(|region| {
+            let (_, base, region_size) = region.layout();
+            address >= base && (address as u64) + (size as u64) <= (base as u64) + (region_size as u64)
+        })
+    }
+
+    /// Cached GET data, if fresh; counts a hit or miss for cacheable ranges
+    pub(crate) fn get(&mut self, space: u8, address: u32, size: u32) -> Option<Vec<u8>> {
+        if !self.cacheable(space, address, size) {
+            return None;
+        }
+        let fresh = self.entries.get(&(space, address, size))
+            .filter(|(at, _)| at.elapsed() < self.ttl)
+            .map(|(_, data)| data.clone());
+        self.count(fresh.is_some());
+        fresh
+    }
+
+    pub(crate) fn put(&mut self, space: u8, address: u32, size: u32, data: &[u8]) {
+        if !self.cacheable(space, address, size) {
+            return;
+        }
+        if self.entries.len() >= MAX_ENTRIES {
+            self.entries.retain(|_, (at, _)| at.elapsed() < self.ttl);
+            if self.entries.len() >= MAX_ENTRIES {
+                self.entries.clear();
+            }
+        }
+        self.entries.insert((space, address, size), (Instant::now(), data.to_vec()));
+    }
+
+    /// Cached INFO response, if fresh
+    pub(crate) fn get_info(&mut self) -> Option<Vec<u8>> {
+        if !(self.enabled && self.cache_info) {
+            return None;
+        }
+        let fresh = self.info.as_ref()
+            .filter(|(at, _)| at.elapsed() < self.ttl)
+            .map(|(_, response)| response.clone());
+        self.count(fresh.is_some());
+        fresh
+    }
+
+    pub(crate) fn put_info(&mut self, response: &[u8]) {
+        if self.enabled && self.cache_info {
+            self.info = Some((Instant::now(), response.to_vec()));
+        }
+    }
+
+    /// Drop every entry (after any write, boot, reset, ...)
+    pub(crate) fn invalidate(&mut self) {
+        self.entries.clear();
+        self.info = None;
+    }
+
+    fn count(&mut self, hit: bool) {
+        if hit {
+            self.hits = self.hits.saturating_add(1);
+        } else {
+            self.misses = self.misses.saturating_add(1);
+        }
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Configure the read cache (off by default)
+    /// Changing the configuration always flushes the cache
+    #[napi]
+    pub fn configure_read_cache(&self, options: ReadCacheOptions) -> Result<()> {
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(enabled) = options.enabled {
+            cache.enabled = enabled;
+        }
+        if let Some(ttl_ms) = options.ttl_ms {
+            cache.ttl = Duration::from_millis(ttl_ms as u64);
+        }
+        if let Some(regions) = options.regions {
+            cache.regions = regions;
+        }
+        if let Some(cache_info) = options.cache_info {
+            cache.cache_info = cache_info;
+        }
+        cache.invalidate();
+        Ok(())
+    }
+
+    /// Drop all cached reads
+    #[napi]
+    pub fn clear_read_cache(&self) {
+        self.cache.lock().unwrap().invalidate();
+    }
+}
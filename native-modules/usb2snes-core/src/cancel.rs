@@ -0,0 +1,209 @@
+// Cancellation of in-flight transfers
+// A CancelToken passed to the *_async transfer methods stops the transfer when
+// cancel() is called: the job rejects with ErrorCode::Cancelled ("Cancelled: ...")
+// instead of running to completion. The firmware has no way to abort a data phase
+// once the RESPONSE is out, so the core stops doing the transfer's work at the next
+// 512-byte block and finishes the phase as cheaply as it can to keep the protocol in
+// sync: the rest of a GET is read and thrown away, the rest of a PUT is sent as zeros
+// without touching the host file, and the half-written file is removed from the SD
+// card. A token cancelled before its job gets the port never sends anything.
+
+use napi_derive::napi;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::{
+    build_packet, check_device_error, data_block_len, check_put_size, exchange, parse_get_response, path_command_locked, read_data_with,
+    write_data_with, Connection, Usb2SnesCore, SPACE_FILE,
+};
+
+/// Stops the transfer it was passed to (AbortSignal-style); one token may serve several
+#[napi]
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+#[napi]
+impl CancelToken {
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop every transfer using this token; it stays cancelled
+    #[napi]
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    #[napi(getter)]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl CancelToken {
+    /// Fail with "Cancelled: ..." once cancel() was called
+    pub(crate) fn check(&self, what: &str) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(CoreError::new(ErrorCode::Cancelled, format!("Cancelled: {} was cancelled", what)));
+        }
+        Ok(())
+    }
+}
+
+/// Uncached GET of memory that stops keeping data once `cancel` fires
+/// The rest of the data phase is still read, so the device is left ready.
+pub(crate) fn get_cancellable_locked(
+    conn: &mut Connection,
+    space: u8,
+    flags: u8,
+    address: u32,
+    size: u32,
+    cancel: &CancelToken,
+) -> Result<Vec<u8>> {
+    let what = format!("GET of {:X} bytes at {:X}", size, address);
+    cancel.check(&what)?;
+    let flags = conn.block_flags | flags;
+    let packet = build_packet(0, space, flags, Some(vec![format!("{:X}", address), format!("{:X}", size)]))?;
+    let response = exchange(conn, &packet)?;
+    let data_size = parse_get_response(response)?;
+
+    let mut data = Vec::with_capacity(data_size as usize);
+    let mut cut = false;
+    read_data_with(conn, data_size as usize, data_block_len(flags), |block| {
+        cut = cut || cancel.is_cancelled();
+        if !cut {
+            data.extend_from_slice(block);
+        }
+    })?;
+    if cut {
+        cancel.check(&what)?;
+    }
+    data.truncate(size as usize);
+    Ok(data)
+}
+
+/// PUT a file from memory, switching to a zeroed data phase once `cancel` fires
+/// `on_block` gets the bytes sent so far after each block; its first error is returned
+/// once the data phase is over. A cancelled upload's partial file is removed.
+pub(crate) fn put_file_cancellable_locked(
+    conn: &mut Connection,
+    path: &str,
+    data: &[u8],
+    cancel: &CancelToken,
+    mut on_block: impl FnMut(u32) -> Result<()>,
+) -> Result<()> {
+    let what = format!("PUT of {}", path);
+    cancel.check(&what)?;
+    let packet = build_packet(1, SPACE_FILE, 0, Some(vec![path.to_string(), format!("{:X}", data.len())]))?;
+    let response = exchange(conn, &packet)?;
+    check_device_error(&response, "PUT", path)?;
+    check_put_size(conn, &response, data.len() as u32, SPACE_FILE, path)?;
+
+    let mut chunks = data.chunks(512);
+    let mut cut = false;
+    let mut sent = 0u32;
+    let mut block_error = None;
+    write_data_with(conn, data.len(), |block| {
+        let chunk = chunks.next().unwrap_or_default();
+        cut = cut || cancel.is_cancelled();
+        if !cut {
+            block[..chunk.len()].copy_from_slice(chunk);
+            sent += chunk.len() as u32;
+            if block_error.is_none() {
+                block_error = on_block(sent).err();
+            }
+        }
+    })?;
+    if cut {
+        return discard_if_cancelled(conn, path, cancel.check(&what));
+    }
+    match block_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Remove the device file of an upload that `result` says was cancelled
+pub(crate) fn discard_if_cancelled<T>(conn: &mut Connection, path: &str, result: Result<T>) -> Result<T> {
+    if let Err(e) = &result {
+        if e.code == ErrorCode::Cancelled {
+            // Best effort: the cancellation is what gets reported
+            let _ = path_command_locked(conn, 6, "RM", vec![path.to_string()]);
+        }
+    }
+    result
+}
+
+impl Usb2SnesCore {
+    /// upload_host_file() that stops at the next block once `cancel` fires
+    /// The partial device file is removed; `device_path` must already be normalized.
+    pub(crate) fn upload_from_cancellable(
+        &self,
+        host_path: &str,
+        device_path: &str,
+        cancel: &CancelToken,
+        mut on_block: impl FnMut(u32, u32) -> Result<()>,
+    ) -> Result<u32> {
+        let what = format!("upload of {}", host_path);
+        let result = self.upload_host_file(host_path, device_path, false, |sent, total| {
+            cancel.check(&what)?;
+            on_block(sent, total)
+        });
+        match result {
+            Err(e) if e.code == ErrorCode::Cancelled => {
+                self.with_connection(|conn| discard_if_cancelled(conn, device_path, Err(e)))
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_file_locked, info_locked, lookup_entry_locked};
+
+    fn simulated_core() -> Usb2SnesCore {
+        let core = Usb2SnesCore::new();
+        core.connect_simulated(None, None).unwrap();
+        core
+    }
+
+    #[test]
+    fn cancellable_put_reports_each_block() {
+        let core = simulated_core();
+        let data: Vec<u8> = (0..1200).map(|i| i as u8).collect();
+        let mut sent = Vec::new();
+        core.with_connection(|conn| {
+            put_file_cancellable_locked(conn, "/save.srm", &data, &CancelToken::new(), |done| {
+                sent.push(done);
+                Ok(())
+            })
+        }).unwrap();
+        assert_eq!(sent, [512, 1024, 1200]);
+        assert_eq!(core.with_connection(|conn| get_file_locked(conn, "/save.srm")).unwrap(), data);
+    }
+
+    #[test]
+    fn cancelled_put_removes_the_partial_file() {
+        let core = simulated_core();
+        let cancel = CancelToken::new();
+        let error = core.with_connection(|conn| {
+            put_file_cancellable_locked(conn, "/save.srm", &[7; 2048], &cancel, |_| {
+                cancel.cancel();
+                Ok(())
+            })
+        }).unwrap_err();
+        assert_eq!(error.code, ErrorCode::Cancelled, "{}", error.reason);
+
+        // The rest of the data phase went out zeroed, so the device answers the next command
+        core.with_connection(|conn| {
+            assert_eq!(lookup_entry_locked(conn, "/save.srm")?, None);
+            info_locked(conn)
+        }).unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+// Adaptive chunking of host data phases (PUT, FILE PUT, upload_from)
+// Data is always sent as 512-byte blocks, but how many blocks go out per write+flush
+// decides throughput: USB2 FxPaks take large bursts, older boards and virtual ports
+// overrun on them. Each connection starts at one block per flush, adds a block after
+// every chunk whose per-block time stays near the best seen, and halves on a write
+// error or latency spike. The learned size lasts for the connection.
+
+use std::time::Duration;
+
+/// Most blocks written per flush (8KB)
+pub(crate) const MAX_CHUNK_BLOCKS: u32 = 16;
+
+/// A chunk slower per block than this multiple of the best seen counts as a spike...
+const SPIKE_FACTOR: f64 = 2.0;
+
+/// ...unless it is within this many ms of the best (jitter on very fast links)
+const SPIKE_MIN_MS: f64 = 1.0;
+
+/// Data-phase chunking of one connection (see stats().write_chunk_bytes)
+pub(crate) struct ChunkTuner {
+    blocks: u32,
+    /// Set by ConnectOptions.write_chunk_blocks: never adapt
+    pinned: bool,
+    /// Fastest per-block time seen so far, in ms
+    best_ms_per_block: Option<f64>,
+}
+
+impl Default for ChunkTuner {
+    fn default() -> Self {
+        ChunkTuner { blocks: 1, pinned: false, best_ms_per_block: None }
+    }
+}
+
+impl ChunkTuner {
+    /// Start over for a new connection, optionally pinned to `blocks` per flush
+    pub(crate) fn reset(&mut self, pinned_blocks: Option<u32>) {
+        *self = ChunkTuner::default();
+        if let Some(blocks) = pinned_blocks {
+            self.blocks = blocks.clamp(1, MAX_CHUNK_BLOCKS);
+            self.pinned = true;
+        }
+    }
+
+    /// Blocks to write before the next flush
+    pub(crate) fn blocks(&self) -> u32 {
+        self.blocks
+    }
+
+    pub(crate) fn pinned(&self) -> bool {
+        self.pinned
+    }
+
+    /// A chunk of `blocks` blocks was written and flushed in `elapsed`
+    pub(crate) fn record_success(&mut self, blocks: u32, elapsed: Duration) {
+        if self.pinned {
+            return;
+        }
+        let per_block = elapsed.as_secs_f64() * 1000.0 / blocks as f64;
+        match self.best_ms_per_block {
+            Some(best) if per_block > best * SPIKE_FACTOR && per_block - best > SPIKE_MIN_MS => self.back_off(),
+            best => {
+                self.best_ms_per_block = Some(best.map_or(per_block, |best| best.min(per_block)));
+                // Only a chunk that used the full size says the size is fine
+                if blocks == self.blocks {
+                    self.blocks = (self.blocks + 1).min(MAX_CHUNK_BLOCKS);
+                }
+            }
+        }
+    }
+
+    /// A chunk failed to write
+    pub(crate) fn record_failure(&mut self) {
+        if !self.pinned {
+            self.back_off();
+        }
+    }
+
+    fn back_off(&mut self) {
+        self.blocks = (self.blocks / 2).max(1);
+    }
+}
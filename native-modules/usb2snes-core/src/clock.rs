@@ -0,0 +1,171 @@
+// TIME (opcode 14): set the cart's real-time clock
+// set_clock() sends TIME with a calendar date and time, which the firmware writes to
+// the FxPak's RTC; S-RTC games (Daikaijuu Monogatari II) and the menu read it from
+// there. The fields go in the argument area as the firmware's struct tm: second,
+// minute, hour, day, month (1..12) at bytes 252..256, the full year big-endian at
+// 257..258 and the weekday (0 = Sunday) at 259. The RTC has no time zone: the fields
+// are written as given, so pass local time for the clock a game shows. There is no
+// opcode to read the clock back.
+
+use napi_derive::napi;
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::{build_packet, check_device_error, exchange, Connection, Usb2SnesCore, SPACE_FILE};
+
+/// First byte of the TIME fields in the packet
+pub(crate) const TIME_FIELDS_OFFSET: usize = 252;
+
+/// Calendar date and time for set_clock()
+#[napi(object)]
+#[derive(Clone)]
+pub struct ClockTime {
+    /// Full year, e.g. 2026
+    pub year: u32,
+    /// 1..12 (unlike Date.getMonth())
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    /// 0 = Sunday .. 6 (default: computed from the date)
+    pub weekday: Option<u32>,
+}
+
+fn is_leap_year(year: u32) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Day of the week of a Gregorian date, 0 = Sunday (Sakamoto's method)
+fn weekday_of(year: u32, month: u32, day: u32) -> u32 {
+    const OFFSETS: [u32; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+    let year = if month < 3 { year - 1 } else { year };
+    (year + year / 4 - year / 100 + year / 400 + OFFSETS[month as usize - 1] + day) % 7
+}
+
+impl ClockTime {
+    /// Check the fields and return them in TIME argument order (second, minute, hour,
+    /// day, month, year, weekday)
+    fn to_fields(&self) -> Result<[u32; 7]> {
+        let invalid = |what: String| Err(CoreError::new(ErrorCode::ArgValidation, format!("Invalid clock time: {}", what)));
+        if !(1..=9999).contains(&self.year) {
+            return invalid(format!("year {} is outside 1..9999", self.year));
+        }
+        if !(1..=12).contains(&self.month) {
+            return invalid(format!("month {} is outside 1..12", self.month));
+        }
+        let days = days_in_month(self.year, self.month);
+        if !(1..=days).contains(&self.day) {
+            return invalid(format!("day {} is outside 1..{} for {}-{:02}", self.day, days, self.year, self.month));
+        }
+        if self.hour > 23 || self.minute > 59 || self.second > 59 {
+            return invalid(format!("{:02}:{:02}:{:02} is not a time of day", self.hour, self.minute, self.second));
+        }
+        let weekday = match self.weekday {
+            Some(weekday) if weekday > 6 => return invalid(format!("weekday {} is outside 0..6", weekday)),
+            Some(weekday) => weekday,
+            None => weekday_of(self.year, self.month, self.day),
+        };
+        Ok([self.second, self.minute, self.hour, self.day, self.month, self.year, weekday])
+    }
+
+    fn describe(&self) -> String {
+        format!("{}-{:02}-{:02} {:02}:{:02}:{:02}", self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+/// set_clock() on an already-locked port
+pub(crate) fn set_clock_locked(conn: &mut Connection, time: &ClockTime) -> Result<()> {
+    let args = time.to_fields()?.iter().map(|field| format!("{:X}", field)).collect();
+    let packet = build_packet(14, SPACE_FILE, 0, Some(args))?;
+    let response = exchange(conn, &packet)?;
+    check_device_error(&response, "TIME", &time.describe())
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Set the cart's real-time clock (TIME opcode 14), e.g. for S-RTC games
+    /// The weekday is computed from the date unless given.
+    #[napi]
+    pub fn set_clock(&self, time: ClockTime) -> Result<()> {
+        self.with_connection(|conn| set_clock_locked(conn, &time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(year: u32, month: u32, day: u32) -> ClockTime {
+        ClockTime { year, month, day, hour: 13, minute: 45, second: 30, weekday: None }
+    }
+
+    #[test]
+    fn date_validation() {
+        let cases = [
+            (2024, 2, 29, true),
+            (2023, 2, 29, false),
+            (2000, 2, 29, true),
+            (1900, 2, 29, false),
+            (2026, 2, 28, true),
+            (2026, 4, 30, true),
+            (2026, 4, 31, false),
+            (2026, 12, 31, true),
+            (2026, 1, 0, false),
+            (2026, 13, 1, false),
+            (2026, 0, 1, false),
+            (0, 1, 1, false),
+            (9999, 12, 31, true),
+            (10000, 1, 1, false),
+        ];
+        for (year, month, day, valid) in cases {
+            let name = format!("{}-{:02}-{:02}", year, month, day);
+            assert_eq!(time(year, month, day).to_fields().is_ok(), valid, "{}", name);
+        }
+
+        let mut bad_time = time(2026, 1, 1);
+        bad_time.hour = 24;
+        assert!(bad_time.to_fields().is_err(), "hour 24");
+        bad_time.hour = 0;
+        bad_time.weekday = Some(7);
+        assert!(bad_time.to_fields().is_err(), "weekday 7");
+    }
+
+    #[test]
+    fn sakamoto_weekday() {
+        let cases = [
+            (1970, 1, 1, 4),
+            (2000, 1, 1, 6),
+            (2000, 2, 29, 2),
+            (2000, 3, 1, 3),
+            (1900, 3, 1, 4),
+            (2024, 2, 29, 4),
+            (2026, 10, 15, 4),
+            (2026, 12, 31, 4),
+            (1, 1, 1, 1),
+        ];
+        for (year, month, day, weekday) in cases {
+            assert_eq!(weekday_of(year, month, day), weekday, "{}-{:02}-{:02}", year, month, day);
+        }
+    }
+
+    #[test]
+    fn time_field_layout() {
+        let mut given = time(2026, 10, 15);
+        let args = given.to_fields().unwrap().iter().map(|field| format!("{:X}", field)).collect();
+        let packet = build_packet(14, SPACE_FILE, 0, Some(args)).unwrap();
+        // second, minute, hour, day, month, year (big-endian), weekday
+        assert_eq!(packet[TIME_FIELDS_OFFSET..TIME_FIELDS_OFFSET + 8], [30, 45, 13, 15, 10, 0x07, 0xEA, 4]);
+
+        given.weekday = Some(0);
+        assert_eq!(given.to_fields().unwrap()[6], 0, "explicit weekday");
+    }
+}
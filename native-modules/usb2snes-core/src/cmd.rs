@@ -0,0 +1,118 @@
+// CMD space (3): the FxPak's command area on the SNES bus
+// GET/PUT in CMD space reach the buffer the firmware shares with the SNES at
+// $2A00-$2FFF: the menu/firmware command bytes and, from $2C00, the NMI hook's
+// execution area. When the byte at $2C00 is non-zero the hook jumps there on the next
+// NMI, so a routine is uploaded with that byte cleared and armed by writing its first
+// byte last; by the usb2snes convention the routine clears $2C00 itself and ends with
+// JMP ($FFEA) into the game's NMI handler. A half-written routine can therefore never
+// run, and execute_cmd() refuses to overwrite one that hasn't run yet. CMD reads are
+// never cached: the firmware changes the area behind the host's back.
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+use std::time::{Duration, Instant};
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::{
+    get_with_flags_locked, protocol, put_flagged_locked, validate_address_range, validation, Usb2SnesCore, CLRX_FLAG,
+    DATA64B_FLAG, FRAME_MS, SETX_FLAG, SPACE_CMD,
+};
+
+/// Addresses CMD space GET/PUT may use
+pub(crate) const CMD_SPACE_START: u32 = 0x2A00;
+pub(crate) const CMD_SPACE_END: u32 = 0x3000;
+
+/// Trigger byte and start of the routine the NMI hook runs
+pub(crate) const CMD_EXEC_ADDRESS: u32 = 0x2C00;
+
+/// Options for execute_cmd()
+#[napi(object)]
+#[derive(Default)]
+pub struct CmdExecOptions {
+    /// Wait up to this long for the routine to clear its trigger byte (default 0: return
+    /// once it is armed)
+    pub wait_ms: Option<u32>,
+}
+
+impl Usb2SnesCore {
+    /// Whether the routine at $2C00 has been run (its trigger byte is clear)
+    fn cmd_routine_done(&self) -> Result<bool> {
+        self.with_connection(|conn| get_with_flags_locked(conn, SPACE_CMD, 0, CMD_EXEC_ADDRESS, 1))
+            .map(|data| data[0] == 0)
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Read `size` bytes of CMD space at `address` ($2A00-$2FFF), bypassing the read cache
+    #[napi]
+    pub fn get_cmd_memory(&self, address: u32, size: u32) -> Result<Buffer> {
+        validate_address_range(SPACE_CMD, address, size)?;
+        if size == 0 {
+            return Ok(Vec::new().into());
+        }
+        self.with_connection(|conn| get_with_flags_locked(conn, SPACE_CMD, 0, address, size)).map(Buffer::from)
+    }
+
+    /// Write `data` to CMD space at `address` ($2A00-$2FFF)
+    /// `flags` takes Flags.ClrX/Flags.SetX (clear/set the execute bit with the write)
+    /// and Flags.Data64B. Writing at $2C00 directly can arm a half-written routine; use
+    /// execute_cmd() for code.
+    #[napi]
+    pub fn put_cmd_memory(&self, address: u32, data: Buffer, flags: Option<u32>) -> Result<()> {
+        let flags = protocol::method_flags("put_cmd_memory", flags, DATA64B_FLAG | CLRX_FLAG | SETX_FLAG)?;
+        validation::validate_method_flags("put_cmd_memory", 1, SPACE_CMD, flags)?;
+        validate_address_range(SPACE_CMD, address, data.len() as u32)?;
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.with_connection(|conn| put_flagged_locked(conn, SPACE_CMD, flags, address, &data))
+    }
+
+    /// Run a 65816 routine through the NMI hook at $2C00 (see cmd.rs)
+    /// `code` is written from $2C00; its first byte, usually PHP (0x08), doubles as the
+    /// trigger and must be non-zero. Fails with DeviceBusy while an earlier routine
+    /// hasn't run, e.g. when the game has NMI off. Returns true once the routine was
+    /// seen to run, false if it didn't within wait_ms (or wait_ms is 0).
+    #[napi]
+    pub fn execute_cmd(&self, code: Buffer, options: Option<CmdExecOptions>) -> Result<bool> {
+        let max_len = (CMD_SPACE_END - CMD_EXEC_ADDRESS) as usize;
+        match code.first() {
+            None | Some(0) => {
+                return Err(CoreError::new(ErrorCode::ArgValidation,
+                    "execute_cmd: code must start with a non-zero byte, which arms the NMI hook"
+                ));
+            }
+            Some(_) if code.len() > max_len => {
+                return Err(CoreError::new(ErrorCode::ArgValidation, format!(
+                    "execute_cmd: {} bytes of code don't fit the {} bytes at $2C00", code.len(), max_len
+                )));
+            }
+            Some(_) => {}
+        }
+
+        self.with_connection(|conn| {
+            let trigger = get_with_flags_locked(conn, SPACE_CMD, 0, CMD_EXEC_ADDRESS, 1)?;
+            if trigger[0] != 0 {
+                return Err(CoreError::new(ErrorCode::DeviceBusy,
+                    "execute_cmd: the routine at $2C00 hasn't run yet (is NMI enabled?)"
+                ));
+            }
+            // Body first with the trigger still clear, then the first byte arms it
+            let mut body = code.to_vec();
+            body[0] = 0;
+            put_flagged_locked(conn, SPACE_CMD, 0, CMD_EXEC_ADDRESS, &body)?;
+            put_flagged_locked(conn, SPACE_CMD, 0, CMD_EXEC_ADDRESS, &code[..1])
+        })?;
+
+        let wait = Duration::from_millis(options.and_then(|o| o.wait_ms).unwrap_or(0) as u64);
+        let deadline = Instant::now() + wait;
+        while Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(FRAME_MS));
+            if self.cmd_routine_done()? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
@@ -0,0 +1,329 @@
+// CONFIG space access (firmware menu settings)
+// GET/PUT in CONFIG space address the firmware's settings block directly, so a bad
+// value can leave the menu unbootable: writes are read back and always warned about.
+// Only the registers listed in ConfigSetting can be addressed, and registers that
+// depend on hardware are refused unless INFO reports the matching feature flag.
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+use napi::{JsFunction, JsUnknown};
+use crate::errors::{CoreError, ErrorCode, Result};
+
+use crate::{get_locked, info_locked, put_locked, Connection, Usb2SnesCore};
+
+/// CONFIG space byte
+const SPACE_CONFIG: u8 = 4;
+
+/// INFO feature flag (byte 6 of the RESPONSE) that gates CONFIG writes
+const FEAT_CMD_UNLOCK: &str = "FEAT_CMD_UNLOCK";
+
+/// Well-known settings with their offsets in CONFIG space
+/// Offsets follow the firmware's packed settings block (cfg_t in cfg.h)
+#[napi]
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigSetting {
+    /// Menu video mode (0 = 60Hz, 1 = 50Hz, 2 = auto)
+    VidmodeMenu,
+    /// In-game video mode (0 = 60Hz, 1 = 50Hz, 2 = auto)
+    VidmodeGame,
+    /// Allow pairing mode (0/1)
+    PairModeAllowed,
+    /// $213F region override, i.e. the PAL/NTSC bit games see (0 = off, 1 = on); FxPak Pro only
+    R213fOverride,
+    /// MSU-1 audio volume boost (0 = none .. 4 = max); MSU-1 capable firmware only
+    MsuVolumeBoost,
+}
+
+/// Video region the firmware forces on games (the VidmodeGame register)
+#[napi]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Region {
+    /// 60Hz
+    Ntsc,
+    /// 50Hz
+    Pal,
+    /// Follow the ROM header
+    Auto,
+}
+
+impl Region {
+    fn from_vidmode(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Region::Ntsc),
+            1 => Ok(Region::Pal),
+            2 => Ok(Region::Auto),
+            other => Err(CoreError::new(ErrorCode::InvalidResponse, format!(
+                "VidmodeGame holds {}, which is not a known video mode (0 = 60Hz, 1 = 50Hz, 2 = auto)", other
+            ))),
+        }
+    }
+
+    fn vidmode(self) -> u8 {
+        match self {
+            Region::Ntsc => 0,
+            Region::Pal => 1,
+            Region::Auto => 2,
+        }
+    }
+}
+
+/// Every register that read_config()/write_config() may address, in offset order
+const KNOWN_SETTINGS: [ConfigSetting; 5] = [
+    ConfigSetting::VidmodeMenu,
+    ConfigSetting::VidmodeGame,
+    ConfigSetting::PairModeAllowed,
+    ConfigSetting::R213fOverride,
+    ConfigSetting::MsuVolumeBoost,
+];
+
+impl ConfigSetting {
+    /// (offset, length) of the setting in CONFIG space
+    fn location(self) -> (u32, u32) {
+        match self {
+            ConfigSetting::VidmodeMenu => (0x00, 1),
+            ConfigSetting::VidmodeGame => (0x01, 1),
+            ConfigSetting::PairModeAllowed => (0x02, 1),
+            ConfigSetting::R213fOverride => (0x10, 1),
+            ConfigSetting::MsuVolumeBoost => (0x9B, 1),
+        }
+    }
+
+    /// INFO feature flag the running firmware must report for this register to exist
+    fn required_feature(self) -> Option<&'static str> {
+        match self {
+            ConfigSetting::R213fOverride => Some("FEAT_213F"),
+            ConfigSetting::MsuVolumeBoost => Some("FEAT_MSU1"),
+            _ => None,
+        }
+    }
+}
+
+/// The known registers covered by `len` bytes at `key_offset`
+/// Every byte of the range must belong to a known register.
+fn settings_in_range(key_offset: u32, len: u32) -> Result<Vec<ConfigSetting>> {
+    let end = key_offset as u64 + len as u64;
+    let mut settings = Vec::new();
+    let mut covered = key_offset as u64;
+    for setting in KNOWN_SETTINGS {
+        let (offset, size) = setting.location();
+        let (start, stop) = (offset as u64, offset as u64 + size as u64);
+        if stop <= covered || start >= end {
+            continue;
+        }
+        if start > covered {
+            break;
+        }
+        settings.push(setting);
+        covered = stop;
+    }
+    if len == 0 || covered < end {
+        return Err(CoreError::new(ErrorCode::ArgValidation, format!(
+            "AddressOutOfRange: CONFIG 0x{:X} + {} byte(s) is not covered by known registers (unknown at 0x{:X})",
+            key_offset, len, covered
+        )));
+    }
+    Ok(settings)
+}
+
+/// Refuse registers the running firmware variant doesn't have (detected via INFO)
+/// Returns the INFO fields when INFO had to be fetched
+fn check_supported_locked(conn: &mut Connection, settings: &[ConfigSetting]) -> Result<Option<Vec<String>>> {
+    if settings.iter().all(|setting| setting.required_feature().is_none()) {
+        return Ok(None);
+    }
+    let info = info_locked(conn)?;
+    for setting in settings {
+        if let Some(feature) = setting.required_feature() {
+            if !has_feature(&info, feature) {
+                return Err(CoreError::new(ErrorCode::Unsupported, format!(
+                    "Unsupported: CONFIG register {:?} needs {}, which firmware {} does not report",
+                    setting, feature, info.first().map(String::as_str).unwrap_or("(unknown)")
+                )));
+            }
+        }
+    }
+    Ok(Some(info))
+}
+
+/// Whether INFO's feature flag string lists `feature`
+fn has_feature(info: &[String], feature: &str) -> bool {
+    info.get(3).is_some_and(|flags| flags.split('|').any(|f| f == feature))
+}
+
+/// Passed to write_config()'s `on_warning` before every CONFIG write
+#[napi(object)]
+pub struct ConfigWriteWarning {
+    pub key_offset: u32,
+    pub len: u32,
+    pub message: String,
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Read `len` bytes of CONFIG space at `key_offset`
+    /// The range must consist of known registers (see ConfigSetting)
+    #[napi]
+    pub fn read_config(&self, key_offset: u32, len: u32) -> Result<Buffer> {
+        let settings = settings_in_range(key_offset, len)?;
+        self.with_connection(|conn| {
+            check_supported_locked(conn, &settings)?;
+            get_locked(conn, SPACE_CONFIG, key_offset, len)
+        }).map(Buffer::from)
+    }
+
+    /// Write `data` to CONFIG space at `key_offset`, then read it back to confirm
+    /// The range must consist of known registers supported by the running firmware,
+    /// and the firmware must report FEAT_CMD_UNLOCK. `on_warning` is always called
+    /// (with a ConfigWriteWarning) before the write, since a bad value can make the
+    /// menu unbootable; the warning is also kept in diagnostic_snapshot().
+    #[napi]
+    pub fn write_config(&self, key_offset: u32, data: Buffer, on_warning: Option<JsFunction>) -> Result<()> {
+        if data.is_empty() {
+            return Err(CoreError::new(ErrorCode::ArgValidation, "write_config: data is empty"));
+        }
+        let settings = settings_in_range(key_offset, data.len() as u32)?;
+
+        let message = format!(
+            "Writing {} byte(s) to CONFIG space at 0x{:X}; a bad value can make the menu unbootable",
+            data.len(), key_offset
+        );
+        if let Some(callback) = on_warning.as_ref() {
+            callback.call1::<ConfigWriteWarning, JsUnknown>(ConfigWriteWarning {
+                key_offset,
+                len: data.len() as u32,
+                message: message.clone(),
+            })?;
+        }
+        self.diagnostics.lock().unwrap().record_warning(&message);
+
+        self.with_connection(|conn| {
+            let info = match check_supported_locked(conn, &settings)? {
+                Some(info) => info,
+                None => info_locked(conn)?,
+            };
+            if !has_feature(&info, FEAT_CMD_UNLOCK) {
+                return Err(CoreError::new(ErrorCode::Unsupported,
+                    "Unsupported: firmware does not report FEAT_CMD_UNLOCK, CONFIG writes are disabled"
+                ));
+            }
+
+            put_locked(conn, SPACE_CONFIG, key_offset, &data)?;
+            let readback = get_locked(conn, SPACE_CONFIG, key_offset, data.len() as u32)?;
+            if readback[..] != data[..] {
+                return Err(CoreError::new(ErrorCode::DeviceError, format!(
+                    "CONFIG write at 0x{:X} did not read back (wrote {:02X?}, read {:02X?})",
+                    key_offset, &data[..], readback
+                )));
+            }
+            Ok(())
+        })
+    }
+
+    /// Read a well-known setting
+    #[napi]
+    pub fn read_config_setting(&self, setting: ConfigSetting) -> Result<Buffer> {
+        let (offset, len) = setting.location();
+        self.read_config(offset, len)
+    }
+
+    /// Read the video region forced on games (VidmodeGame)
+    #[napi]
+    pub fn get_region(&self) -> Result<Region> {
+        let value = self.read_config_setting(ConfigSetting::VidmodeGame)?;
+        Region::from_vidmode(value[0])
+    }
+
+    /// Force games to 60Hz or 50Hz, or let the ROM header decide (see write_config())
+    /// Fails with "Unsupported: ..." on firmware without FEAT_CMD_UNLOCK. Takes effect
+    /// on the next game boot.
+    #[napi]
+    pub fn set_region(&self, region: Region, on_warning: Option<JsFunction>) -> Result<()> {
+        self.write_config_setting(ConfigSetting::VidmodeGame, vec![region.vidmode()].into(), on_warning)
+    }
+
+    /// Write a well-known setting (see write_config())
+    #[napi]
+    pub fn write_config_setting(
+        &self,
+        setting: ConfigSetting,
+        data: Buffer,
+        on_warning: Option<JsFunction>,
+    ) -> Result<()> {
+        let (offset, len) = setting.location();
+        if data.len() != len as usize {
+            return Err(CoreError::new(ErrorCode::ArgValidation, format!(
+                "{:?} is {} byte(s), got {}", setting, len, data.len()
+            )));
+        }
+        self.write_config(offset, data, on_warning)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_setting_offsets() {
+        let cases = [
+            (ConfigSetting::VidmodeMenu, (0x00, 1), None),
+            (ConfigSetting::VidmodeGame, (0x01, 1), None),
+            (ConfigSetting::PairModeAllowed, (0x02, 1), None),
+            (ConfigSetting::R213fOverride, (0x10, 1), Some("FEAT_213F")),
+            (ConfigSetting::MsuVolumeBoost, (0x9B, 1), Some("FEAT_MSU1")),
+        ];
+        assert_eq!(cases.len(), KNOWN_SETTINGS.len());
+        for ((setting, location, feature), known) in cases.into_iter().zip(KNOWN_SETTINGS) {
+            assert_eq!(setting, known, "KNOWN_SETTINGS is out of offset order");
+            assert_eq!(setting.location(), location, "{:?}", setting);
+            assert_eq!(setting.required_feature(), feature, "{:?}", setting);
+        }
+    }
+
+    #[test]
+    fn ranges_of_known_registers() {
+        let cases = [
+            (0x00, 1, vec![ConfigSetting::VidmodeMenu]),
+            (0x01, 1, vec![ConfigSetting::VidmodeGame]),
+            (0x00, 3, vec![ConfigSetting::VidmodeMenu, ConfigSetting::VidmodeGame, ConfigSetting::PairModeAllowed]),
+            (0x01, 2, vec![ConfigSetting::VidmodeGame, ConfigSetting::PairModeAllowed]),
+            (0x10, 1, vec![ConfigSetting::R213fOverride]),
+            (0x9B, 1, vec![ConfigSetting::MsuVolumeBoost]),
+        ];
+        for (offset, len, expected) in cases {
+            let settings = settings_in_range(offset, len).unwrap_or_else(|e| panic!("0x{:X}+{}: {}", offset, len, e.reason));
+            assert_eq!(settings, expected, "0x{:X}+{}", offset, len);
+        }
+    }
+
+    #[test]
+    fn ranges_with_unknown_bytes_are_rejected() {
+        // Empty, gaps between registers, past the last one, and offsets that overflow
+        let cases = [(0x00, 0), (0x03, 1), (0x00, 4), (0x02, 0x0F), (0x10, 2), (0x9A, 2), (0x9C, 1), (u32::MAX, 2)];
+        for (offset, len) in cases {
+            let error = settings_in_range(offset, len).expect_err(&format!("0x{:X}+{}", offset, len));
+            assert_eq!(error.code, ErrorCode::ArgValidation, "0x{:X}+{}", offset, len);
+            assert!(error.reason.starts_with("AddressOutOfRange"), "{}", error.reason);
+        }
+    }
+
+    #[test]
+    fn feature_flags_from_info() {
+        let info = |flags: &str| vec!["1.11.0".to_string(), "0x1100".to_string(), "/sd2snes/menu.bin".to_string(), flags.to_string()];
+        assert!(has_feature(&info("FEAT_MSU1|FEAT_CMD_UNLOCK"), FEAT_CMD_UNLOCK));
+        assert!(has_feature(&info("FEAT_213F"), "FEAT_213F"));
+        assert!(!has_feature(&info("FEAT_MSU1"), FEAT_CMD_UNLOCK));
+        assert!(!has_feature(&info(""), "FEAT_MSU1"));
+        assert!(!has_feature(&info("FEAT_MSU1X"), "FEAT_MSU1"));
+        assert!(!has_feature(&info("")[..3], "FEAT_MSU1"));
+    }
+
+    #[test]
+    fn region_vidmode_values() {
+        for (region, value) in [(Region::Ntsc, 0), (Region::Pal, 1), (Region::Auto, 2)] {
+            assert_eq!(region.vidmode(), value);
+            assert_eq!(Region::from_vidmode(value).ok(), Some(region));
+        }
+        assert_eq!(Region::from_vidmode(3).unwrap_err().code, ErrorCode::InvalidResponse);
+    }
+}
@@ -0,0 +1,134 @@
+// Copier headers
+// Dumps made with old copiers (.smc/.swc/.fig) start with a 512-byte header the
+// cartridge never had; the file is then 512 bytes over a multiple of 1KB. Left in, it
+// shifts every ROM offset by 512, so the console runs garbage and patches land in the
+// wrong place. The ROM launch paths (launch_rom(), flash_and_boot(), patch_and_boot())
+// strip it before uploading; plain file uploads are left byte-exact.
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::mapping::{rom_file_header, MemoryMapping};
+
+pub(crate) const COPIER_HEADER_LEN: usize = 512;
+
+/// Offset of the Super Wild Card signature (AA BB 04) in a copier header
+const SWC_SIGNATURE_OFFSET: usize = 8;
+const SWC_SIGNATURE: [u8; 3] = [0xAA, 0xBB, 0x04];
+
+/// Length of the copier header on a ROM file of `size` bytes (0 or 512)
+pub(crate) fn copier_header_len(size: u64) -> usize {
+    if size % 1024 == COPIER_HEADER_LEN as u64 { COPIER_HEADER_LEN } else { 0 }
+}
+
+/// A ROM file without its copier header, if it has one
+pub(crate) fn without_copier_header(rom: &[u8]) -> &[u8] {
+    &rom[copier_header_len(rom.len() as u64)..]
+}
+
+/// Whether a ROM file starts with a 512-byte copier header (judged by its size)
+#[napi]
+pub fn has_copier_header(rom: Buffer) -> bool {
+    copier_header_len(rom.len() as u64) != 0
+}
+
+/// A copy of a ROM file without its copier header; unheadered ROMs come back unchanged
+#[napi]
+pub fn strip_copier_header(rom: Buffer) -> Buffer {
+    without_copier_header(&rom).to_vec().into()
+}
+
+/// A copy of a ROM file with a Super Wild Card copier header in front, for tools
+/// that still expect one. The header records the size in 8KB units, HiROM and the
+/// SRAM size from the internal header. Fails if the ROM already has a copier header.
+#[napi]
+pub fn add_copier_header(rom: Buffer) -> Result<Buffer> {
+    Ok(with_copier_header(&rom)?.into())
+}
+
+pub(crate) fn with_copier_header(rom: &[u8]) -> Result<Vec<u8>> {
+    if copier_header_len(rom.len() as u64) != 0 {
+        return Err(CoreError::new(ErrorCode::ArgValidation,
+            "ROM already has a copier header (size is 512 bytes over a multiple of 1KB)"
+        ));
+    }
+    let mut header = vec![0u8; COPIER_HEADER_LEN];
+    header[..2].copy_from_slice(&((rom.len().div_ceil(8192)) as u16).to_le_bytes());
+    if let Some((info, _)) = rom_file_header(rom) {
+        // Bits 4-5: HiROM DRAM/SRAM mapping; bits 2-3: SRAM 256Kbit/64Kbit/16Kbit/none
+        if info.mapping != MemoryMapping::LoRom {
+            header[2] |= 0x30;
+        }
+        header[2] |= match info.sram_size_kb {
+            0 => 0x0C,
+            1..=2 => 0x08,
+            3..=8 => 0x04,
+            _ => 0x00,
+        };
+    }
+    header[SWC_SIGNATURE_OFFSET..SWC_SIGNATURE_OFFSET + 3].copy_from_slice(&SWC_SIGNATURE);
+    header.extend_from_slice(rom);
+    Ok(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 64KB ROM with a valid internal header for `mapping` and `sram` as the SRAM size byte
+    fn rom(mapping: MemoryMapping, sram: u8) -> Vec<u8> {
+        let (offset, map_mode) = match mapping {
+            MemoryMapping::LoRom => (0x7FC0, 0x20),
+            _ => (0xFFC0, 0x21),
+        };
+        let mut rom = vec![0u8; 0x10000];
+        let header = &mut rom[offset..offset + 32];
+        header[..21].copy_from_slice(b"COPIER TEST          ");
+        header[0x15] = map_mode;
+        header[0x18] = sram;
+        header[0x1C..0x20].copy_from_slice(&[0xFF, 0xFF, 0x00, 0x00]);
+        rom
+    }
+
+    #[test]
+    fn header_detection() {
+        let cases = [(0, 0), (512, 512), (1024, 0), (0x10000, 0), (0x10200, 512), (0x10100, 0), (0x10201, 0)];
+        for (size, expected) in cases {
+            assert_eq!(copier_header_len(size), expected, "{} bytes", size);
+            let file = vec![0u8; size as usize];
+            assert_eq!(without_copier_header(&file).len(), size as usize - expected, "{} bytes stripped", size);
+        }
+    }
+
+    #[test]
+    fn add_then_strip_round_trip() {
+        let original = rom(MemoryMapping::LoRom, 3);
+        let headered = with_copier_header(&original).unwrap();
+        assert_eq!(headered.len(), original.len() + COPIER_HEADER_LEN);
+        assert_eq!(copier_header_len(headered.len() as u64), COPIER_HEADER_LEN);
+        assert_eq!(headered[..2], [8, 0], "size in 8KB units");
+        assert_eq!(headered[SWC_SIGNATURE_OFFSET..SWC_SIGNATURE_OFFSET + 3], SWC_SIGNATURE);
+        assert_eq!(without_copier_header(&headered), original.as_slice());
+
+        let error = with_copier_header(&headered).unwrap_err();
+        assert_eq!(error.code, ErrorCode::ArgValidation, "already headered");
+    }
+
+    #[test]
+    fn swc_mapping_and_sram_flags() {
+        let cases = [
+            ("LoROM, no SRAM", MemoryMapping::LoRom, 0, 0x0C),
+            ("LoROM, 2KB SRAM", MemoryMapping::LoRom, 1, 0x08),
+            ("LoROM, 8KB SRAM", MemoryMapping::LoRom, 3, 0x04),
+            ("LoROM, 32KB SRAM", MemoryMapping::LoRom, 5, 0x00),
+            ("HiROM, no SRAM", MemoryMapping::HiRom, 0, 0x3C),
+            ("HiROM, 8KB SRAM", MemoryMapping::HiRom, 3, 0x34),
+        ];
+        for (name, mapping, sram, flags) in cases {
+            assert_eq!(with_copier_header(&rom(mapping, sram)).unwrap()[2], flags, "{}", name);
+        }
+        // No recognizable internal header: no flags
+        assert_eq!(with_copier_header(&[0xFF; 0x10000]).unwrap()[2], 0, "no internal header");
+    }
+}
@@ -0,0 +1,234 @@
+// Multi-device orchestration - one Usb2SnesCore per attached FxPak/sd2snes
+
+use napi_derive::napi;
+use napi::threadsafe_function::ThreadSafeCallContext;
+use napi::JsFunction;
+use crate::errors::{CoreError, ErrorCode, Result};
+use serialport::SerialPortType;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::hotplug::{HotplugEngine, HotplugEvent, HotplugKind};
+use crate::recording::unix_millis;
+use crate::Usb2SnesCore;
+
+/// Status of one device known to a DeviceManager
+#[napi(object)]
+pub struct DeviceStatus {
+    /// Key used with get()/connect(): USB serial number if available, else port name
+    pub key: String,
+    pub port_name: String,
+    pub serial_number: Option<String>,
+    pub connected: bool,
+}
+
+/// USB IDs of the sd2snes / FxPak Pro (pid.codes "SD2SNES")
+pub(crate) const FXPAK_VID: u16 = 0x1209;
+pub(crate) const FXPAK_PID: u16 = 0x5A22;
+
+/// A serial port that belongs to an sd2snes / FxPak Pro (see list_devices())
+#[napi(object)]
+pub struct FxPakPort {
+    pub port_name: String,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+}
+
+/// List serial ports whose USB VID/PID is the sd2snes / FxPak Pro's
+/// On macOS each device shows up twice (cu.* and tty.*); both are listed.
+#[napi]
+pub fn list_devices() -> Result<Vec<FxPakPort>> {
+    let ports = serialport::available_ports()
+        .map_err(|e| CoreError::new(ErrorCode::IoError, format!("Failed to enumerate serial ports: {}", e)))?;
+    Ok(ports.into_iter()
+        .filter_map(|port| match port.port_type {
+            SerialPortType::UsbPort(usb) if usb.vid == FXPAK_VID && usb.pid == FXPAK_PID => Some(FxPakPort {
+                port_name: port.port_name,
+                serial_number: usb.serial_number,
+                manufacturer: usb.manufacturer,
+                product: usb.product,
+            }),
+            _ => None,
+        })
+        .collect())
+}
+
+pub(crate) struct ManagedDevice {
+    port_name: String,
+    serial_number: Option<String>,
+    /// USB IDs are the sd2snes / FxPak Pro's
+    fx_pak: bool,
+    core: Usb2SnesCore,
+}
+
+/// Known devices by key
+pub(crate) type DeviceMap = BTreeMap<String, ManagedDevice>;
+
+impl ManagedDevice {
+    fn hotplug_event(&self, kind: HotplugKind, key: &str) -> HotplugEvent {
+        HotplugEvent {
+            kind,
+            key: key.to_string(),
+            port_name: self.port_name.clone(),
+            serial_number: self.serial_number.clone(),
+            fx_pak: self.fx_pak,
+            unix_ms: unix_millis() as f64,
+        }
+    }
+}
+
+/// Body of refresh(); returns the devices that arrived and the ones that were removed
+pub(crate) fn refresh_devices(devices: &Mutex<DeviceMap>) -> Result<Vec<HotplugEvent>> {
+    let ports = serialport::available_ports()
+        .map_err(|e| CoreError::new(ErrorCode::IoError, format!("Failed to enumerate serial ports: {}", e)))?;
+
+    let mut devices = devices.lock().unwrap();
+    let mut previous = std::mem::take(&mut *devices);
+    let mut changes = Vec::new();
+
+    for port in ports {
+        let SerialPortType::UsbPort(usb) = port.port_type else {
+            continue;
+        };
+
+        let key = usb.serial_number.clone().unwrap_or_else(|| port.port_name.clone());
+        let device = match previous.remove(&key) {
+            Some(mut existing) => {
+                existing.port_name = port.port_name;
+                existing
+            }
+            None => {
+                let device = ManagedDevice {
+                    port_name: port.port_name,
+                    serial_number: usb.serial_number,
+                    fx_pak: usb.vid == FXPAK_VID && usb.pid == FXPAK_PID,
+                    core: Usb2SnesCore::new(),
+                };
+                changes.push(device.hotplug_event(HotplugKind::Arrived, &key));
+                device
+            }
+        };
+        devices.insert(key, device);
+    }
+
+    for (key, gone) in previous {
+        changes.push(gone.hotplug_event(HotplugKind::Removed, &key));
+        gone.core.disconnect_force()?;
+    }
+
+    Ok(changes)
+}
+
+/// Every known device as an arrival, for the first event of start_hotplug()
+pub(crate) fn present_devices(devices: &Mutex<DeviceMap>) -> Vec<HotplugEvent> {
+    devices.lock().unwrap()
+        .iter()
+        .map(|(key, device)| device.hotplug_event(HotplugKind::Arrived, key))
+        .collect()
+}
+
+/// Owns one Usb2SnesCore per USB serial port so several devices can be
+/// driven from one process without the caller juggling raw instances
+#[napi]
+#[derive(Default)]
+pub struct DeviceManager {
+    devices: Arc<Mutex<DeviceMap>>,
+    /// Hot-plug thread (see start_hotplug())
+    hotplug: Mutex<Option<HotplugEngine>>,
+}
+
+#[napi]
+impl DeviceManager {
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rescan USB serial ports
+    /// Cores for ports that are still present are kept (including open connections);
+    /// cores for ports that disappeared are disconnected and dropped
+    /// Returns the number of devices known after the scan
+    #[napi]
+    pub fn refresh(&self) -> Result<u32> {
+        refresh_devices(&self.devices)?;
+        Ok(self.devices.lock().unwrap().len() as u32)
+    }
+
+    /// List all known devices and whether each is connected
+    #[napi]
+    pub fn devices(&self) -> Vec<DeviceStatus> {
+        self.devices.lock().unwrap()
+            .iter()
+            .map(|(key, device)| DeviceStatus {
+                key: key.clone(),
+                port_name: device.port_name.clone(),
+                serial_number: device.serial_number.clone(),
+                connected: device.core.is_connected(),
+            })
+            .collect()
+    }
+
+    /// Get the core for a device (shares the connection with the manager)
+    #[napi]
+    pub fn get(&self, key: String) -> Result<Usb2SnesCore> {
+        self.devices.lock().unwrap()
+            .get(&key)
+            .map(|device| device.core.clone())
+            .ok_or_else(|| CoreError::new(ErrorCode::ArgValidation, format!("Unknown device: {}", key)))
+    }
+
+    /// Connect a device's core to its port and return it
+    #[napi]
+    pub fn connect(&self, key: String) -> Result<Usb2SnesCore> {
+        let (core, port_name) = {
+            let devices = self.devices.lock().unwrap();
+            let device = devices.get(&key)
+                .ok_or_else(|| CoreError::new(ErrorCode::ArgValidation, format!("Unknown device: {}", key)))?;
+            (device.core.clone(), device.port_name.clone())
+        };
+
+        if !core.is_connected() {
+            core.connect(port_name)?;
+        }
+        Ok(core)
+    }
+
+    /// Disconnect every managed device
+    #[napi]
+    pub fn disconnect_all(&self) -> Result<()> {
+        for device in self.devices.lock().unwrap().values() {
+            device.core.disconnect(None)?;
+        }
+        Ok(())
+    }
+
+    /// Watch for USB serial devices being plugged in and removed
+    /// `on_change(err, event)` first gets an Arrived event for every device present,
+    /// then one per device that arrives or is removed, after the device list has been
+    /// updated - so connect(event.key) works from the callback. A failing port scan is
+    /// reported once until one succeeds again. The thread keeps Node's event loop alive
+    /// until stop_hotplug().
+    #[napi(ts_args_type = "onChange: (err: Error | null, event: HotplugEvent) => void")]
+    pub fn start_hotplug(&self, on_change: JsFunction) -> Result<()> {
+        let mut hotplug = self.hotplug.lock().unwrap();
+        if hotplug.is_some() {
+            return Err(CoreError::new(ErrorCode::DeviceBusy, "start_hotplug: hot-plug detection is already running"));
+        }
+        let callback = on_change
+            .create_threadsafe_function(0, |ctx: ThreadSafeCallContext<HotplugEvent>| Ok(vec![ctx.value]))
+            .map_err(CoreError::from)?;
+        *hotplug = Some(HotplugEngine::start(self.devices.clone(), callback));
+        Ok(())
+    }
+
+    /// Stop hot-plug detection; returns false if it wasn't running
+    #[napi]
+    pub fn stop_hotplug(&self) -> Result<bool> {
+        let Some(engine) = self.hotplug.lock().unwrap().take() else {
+            return Ok(false);
+        };
+        engine.stop()?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,268 @@
+// Diagnostics for bug reports
+// Keeps a small ring buffer of command/response headers (payloads elided) plus the
+// last INFO and last error, cheap enough to stay on permanently.
+
+use napi_derive::napi;
+use crate::errors::{CoreError, ErrorCode, Result};
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::selftest::SelfTestReport;
+use crate::{build_packet, data_block_len, exchange_uncached, Usb2SnesCore, SPACE_FILE};
+
+/// Number of command exchanges kept for diagnostic_snapshot()
+const HISTORY_LEN: usize = 16;
+
+/// Header bytes kept from each response (magic, opcode, error byte, flags, reserved)
+const RESPONSE_HEADER_LEN: usize = 8;
+
+/// One command exchange as recorded in the ring buffer
+struct ExchangeRecord {
+    at: SystemTime,
+    opcode: u8,
+    space: u8,
+    flags: u8,
+    latency: Duration,
+    response_header: Option<[u8; RESPONSE_HEADER_LEN]>,
+    error: Option<String>,
+}
+
+/// Shared between a core and its current connection so history survives disconnects
+#[derive(Default)]
+pub(crate) struct DiagnosticsLog {
+    history: VecDeque<ExchangeRecord>,
+    /// Parsed fields of the last successful INFO (see parse_info_response())
+    last_info: Option<Vec<String>>,
+    last_error: Option<(SystemTime, String)>,
+    /// Risky operations worth flagging in a report (e.g. CONFIG writes)
+    warnings: VecDeque<(SystemTime, String)>,
+    commands_sent: u32,
+    command_errors: u32,
+    /// Summary and per-check lines of the last self_test()
+    last_self_test: Option<(SystemTime, String, Vec<String>)>,
+}
+
+/// Counters for this core since it was created (see stats())
+#[napi(object)]
+pub struct CoreStats {
+    /// Command packets exchanged with the device (not counting cache hits)
+    pub commands_sent: u32,
+    /// Exchanges that failed (I/O, framing or bad RESPONSE)
+    pub command_errors: u32,
+    pub cache_hits: u32,
+    pub cache_misses: u32,
+    /// Bytes written per flush in host data phases (PUT, FILE PUT), adapted per connection
+    pub write_chunk_bytes: u32,
+    /// Whether write_chunk_bytes was pinned with ConnectOptions.write_chunk_blocks
+    pub write_chunk_pinned: bool,
+    /// Calls waiting for the port right now (see queue.rs)
+    pub queued_calls: u32,
+}
+
+/// INFO round-trip times from measure_latency(), in milliseconds
+#[napi(object)]
+pub struct LatencyStats {
+    pub samples: u32,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    pub mean_ms: f64,
+    pub median_ms: f64,
+}
+
+impl DiagnosticsLog {
+    /// Record one command exchange; `response` is None for NORESP commands and write failures
+    pub(crate) fn record_exchange(
+        &mut self,
+        packet: &[u8],
+        latency: Duration,
+        response: Option<&[u8]>,
+        error: Option<&CoreError>,
+    ) {
+        self.commands_sent = self.commands_sent.saturating_add(1);
+        if error.is_some() {
+            self.command_errors = self.command_errors.saturating_add(1);
+        }
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(ExchangeRecord {
+            at: SystemTime::now(),
+            opcode: packet[4],
+            space: packet[5],
+            flags: packet[6],
+            latency,
+            response_header: response.map(|r| {
+                let mut header = [0u8; RESPONSE_HEADER_LEN];
+                header.copy_from_slice(&r[..RESPONSE_HEADER_LEN]);
+                header
+            }),
+            error: error.map(|e| e.reason.clone()),
+        });
+    }
+
+    pub(crate) fn record_info(&mut self, info: Vec<String>) {
+        self.last_info = Some(info);
+    }
+
+    pub(crate) fn record_warning(&mut self, message: &str) {
+        if self.warnings.len() == HISTORY_LEN {
+            self.warnings.pop_front();
+        }
+        self.warnings.push_back((SystemTime::now(), message.to_string()));
+    }
+
+    pub(crate) fn record_self_test(&mut self, report: &SelfTestReport) {
+        let checks = report.checks.iter().map(|check| check.describe()).collect();
+        self.last_self_test = Some((SystemTime::now(), report.summary.clone(), checks));
+    }
+
+    pub(crate) fn record_error(&mut self, error: &CoreError) {
+        self.last_error = Some((SystemTime::now(), error.reason.clone()));
+    }
+}
+
+/// Error code of a reason string: the "Code:" prefix used by typed errors
+/// (e.g. "AddressOutOfRange: ..."), or GenericFailure for untyped messages
+fn error_code(reason: &str) -> &str {
+    match reason.split_once(':') {
+        Some((code, _)) if !code.is_empty() && code.chars().all(|c| c.is_ascii_alphanumeric()) => code,
+        _ => "GenericFailure",
+    }
+}
+
+fn unix_millis(at: SystemTime) -> u128 {
+    at.duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0)
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Command and cache counters
+    #[napi]
+    pub fn stats(&self) -> CoreStats {
+        let (cache_hits, cache_misses) = {
+            let cache = self.cache.lock().unwrap();
+            (cache.hits, cache.misses)
+        };
+        let (write_chunk_bytes, write_chunk_pinned) = {
+            let chunking = self.chunking.lock().unwrap();
+            (chunking.blocks() * 512, chunking.pinned())
+        };
+        let log = self.diagnostics.lock().unwrap();
+        CoreStats {
+            commands_sent: log.commands_sent,
+            command_errors: log.command_errors,
+            cache_hits,
+            cache_misses,
+            write_chunk_bytes,
+            write_chunk_pinned,
+            queued_calls: self.queue.depth(),
+        }
+    }
+
+    /// Time `samples` INFO round trips (bypassing the read cache)
+    /// Only the exchange itself is timed; the port is released between samples so
+    /// other work isn't starved while measuring.
+    #[napi]
+    pub fn measure_latency(&self, samples: u32) -> Result<LatencyStats> {
+        if samples == 0 {
+            return Err(CoreError::new(ErrorCode::ArgValidation, "measure_latency: samples must be at least 1"));
+        }
+        let packet = build_packet(11, SPACE_FILE, 0, None)?;
+
+        let mut times = Vec::with_capacity(samples as usize);
+        for _ in 0..samples {
+            let elapsed = self.with_connection(|conn| {
+                let started = Instant::now();
+                exchange_uncached(conn, &packet)?;
+                Ok(started.elapsed())
+            })?;
+            times.push(elapsed.as_secs_f64() * 1000.0);
+        }
+
+        times.sort_by(f64::total_cmp);
+        let mid = times.len() / 2;
+        Ok(LatencyStats {
+            samples,
+            min_ms: times[0],
+            max_ms: times[times.len() - 1],
+            mean_ms: times.iter().sum::<f64>() / times.len() as f64,
+            median_ms: if times.len() % 2 == 0 { (times[mid - 1] + times[mid]) / 2.0 } else { times[mid] },
+        })
+    }
+
+    /// Plain-text snapshot of connection state for "Report a problem"
+    /// Contains the port and its settings, the last INFO, the last 16 command
+    /// headers with their response headers and latency, and the most recent error.
+    /// Payloads (file contents, paths, memory data) are never included.
+    #[napi]
+    pub fn diagnostic_snapshot(&self) -> String {
+        let mut out = String::new();
+
+        let port_name = self.port_name();
+        let _ = writeln!(out, "usb2snes-core {}", env!("CARGO_PKG_VERSION"));
+        let _ = writeln!(out, "port: {}", port_name.as_deref().unwrap_or("(not connected)"));
+        let _ = writeln!(out, "settings: 9600 8N1, no flow control");
+        {
+            let port_guard = self.port.lock().unwrap();
+            if let Some(conn) = port_guard.as_ref() {
+                let _ = writeln!(out, "timeouts: {}", conn.timeouts.describe());
+                let _ = writeln!(out, "dtr: {:?} rts: {:?}", conn.dtr, conn.rts);
+                let _ = writeln!(out, "reset strategy: {}", match conn.reset_strategy {
+                    Some(strategy) => format!("{:?}", strategy),
+                    None => "DtrPulse (default)".to_string(),
+                });
+                let _ = writeln!(out, "memory block size: {}", data_block_len(conn.block_flags));
+            }
+        }
+
+        let stats = self.stats();
+        let _ = writeln!(out, "stats: {} commands, {} errors, cache {} hits / {} misses",
+            stats.commands_sent, stats.command_errors, stats.cache_hits, stats.cache_misses);
+
+        let log = self.diagnostics.lock().unwrap();
+        match &log.last_info {
+            Some(info) => {
+                let _ = writeln!(out, "firmware: {}", info.join(" | "));
+            }
+            None => {
+                let _ = writeln!(out, "firmware: (no INFO yet)");
+            }
+        }
+
+        let _ = writeln!(out, "last error: {}", match &log.last_error {
+            Some((at, reason)) => format!("[{}] {} @{}", error_code(reason), reason, unix_millis(*at)),
+            None => "(none)".to_string(),
+        });
+
+        match &log.last_self_test {
+            Some((at, summary, checks)) => {
+                let _ = writeln!(out, "self test: {} @{}", summary, unix_millis(*at));
+                for check in checks {
+                    let _ = writeln!(out, "  {}", check);
+                }
+            }
+            None => {
+                let _ = writeln!(out, "self test: (not run)");
+            }
+        }
+
+        for (at, message) in &log.warnings {
+            let _ = writeln!(out, "warning: {} @{}", message, unix_millis(*at));
+        }
+
+        let _ = writeln!(out, "history (oldest first): time_ms opcode space flags latency_us response");
+        for record in &log.history {
+            let response = match (&record.response_header, &record.error) {
+                (_, Some(error)) => format!("error: {}", error),
+                (Some(header), None) => header.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" "),
+                (None, None) => "(no response expected)".to_string(),
+            };
+            let _ = writeln!(out, "  {} {:02X} {:02X} {:02X} {} {}",
+                unix_millis(record.at), record.opcode, record.space, record.flags,
+                record.latency.as_micros(), response);
+        }
+
+        out
+    }
+}
@@ -0,0 +1,139 @@
+// Multi-file downloads for save backups
+// download_many() walks a list of SD card files one GET at a time, releasing the port
+// between files so polling isn't starved during a long backup. A file that fails is
+// reported in its result and the batch moves on. A disconnect or a cancelled
+// CancelToken stops the batch at the next file boundary; a data phase in progress
+// always runs to the end.
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+use napi::{JsFunction, JsUnknown};
+use crate::cancel::CancelToken;
+use crate::errors::{CoreError, Result};
+use crate::queue::Lane;
+
+use crate::{download_file_locked, normalize_path, Usb2SnesCore};
+
+/// Result of one file of download_many()
+#[napi(object)]
+pub struct DownloadManyResult {
+    pub path: String,
+    /// File contents; unset on failure and when streaming to `on_data`
+    pub data: Option<Buffer>,
+    /// File size; unset on failure
+    pub size: Option<u32>,
+    pub error: Option<String>,
+}
+
+/// Progress event passed to download_many()'s `on_progress` after each file
+#[napi(object)]
+pub struct DownloadManyProgress {
+    /// Index of the file just finished
+    pub index: u32,
+    pub path: String,
+    pub files_done: u32,
+    pub files_total: u32,
+    /// Bytes of the files downloaded so far; sizes of the remaining files aren't known
+    /// until their GET is answered (LS carries no sizes), so there is no byte total
+    pub bytes_done: f64,
+}
+
+/// Data block passed to download_many()'s `on_data` as it arrives
+#[napi(object)]
+pub struct DownloadChunk {
+    pub path: String,
+    pub offset: u32,
+    pub data: Buffer,
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Download several files, continuing past individual failures
+    /// All paths are validated and normalized before the first GET. `on_progress`
+    /// receives a DownloadManyProgress after each file. With `on_data` every block is
+    /// handed over as a DownloadChunk instead of being collected, so memory stays flat
+    /// for large batches; `on_data` runs while the port is held and must not call back
+    /// into this core. `cancel` is checked before each file. Files not reached because
+    /// of a disconnect or `cancel` are reported with the "Cancelled: ..." error.
+    #[napi]
+    pub fn download_many(
+        &self,
+        paths: Vec<String>,
+        on_progress: Option<JsFunction>,
+        on_data: Option<JsFunction>,
+        cancel: Option<&CancelToken>,
+    ) -> Result<Vec<DownloadManyResult>> {
+        let normalized = paths.iter()
+            .map(|path| normalize_path(path))
+            .collect::<Result<Vec<String>>>()?;
+
+        let files_total = normalized.len() as u32;
+        let mut results = Vec::with_capacity(normalized.len());
+        let mut bytes_done = 0u64;
+        let mut cancelled: Option<String> = None;
+        // Blocks are collected here and copied out per file, so the buffer only grows
+        // for the largest file instead of from empty for every one
+        let mut data = Vec::new();
+
+        for (index, path) in normalized.into_iter().enumerate() {
+            if let (None, Some(Err(e))) = (&cancelled, cancel.map(|cancel| cancel.check("download_many()"))) {
+                cancelled = Some(e.reason);
+            }
+            if let Some(reason) = &cancelled {
+                results.push(DownloadManyResult { path, data: None, size: None, error: Some(reason.clone()) });
+                continue;
+            }
+
+            data.clear();
+            let outcome = self.with_connection_in(Lane::Bulk, |conn| {
+                let mut offset = 0u32;
+                download_file_locked(conn, &path, |block| {
+                    let start = offset;
+                    offset += block.len() as u32;
+                    match on_data.as_ref() {
+                        Some(callback) => callback.call1::<DownloadChunk, JsUnknown>(DownloadChunk {
+                            path: path.clone(),
+                            offset: start,
+                            data: block.to_vec().into(),
+                        }).map(|_| ()).map_err(CoreError::from),
+                        None => {
+                            data.extend_from_slice(block);
+                            Ok(())
+                        }
+                    }
+                })
+            });
+
+            let result = match outcome {
+                Ok(size) => {
+                    bytes_done += size as u64;
+                    DownloadManyResult {
+                        path: path.clone(),
+                        data: on_data.is_none().then(|| data.as_slice().into()),
+                        size: Some(size),
+                        error: None,
+                    }
+                }
+                Err(e) => {
+                    if e.reason.starts_with("Cancelled") {
+                        cancelled = Some(e.reason.clone());
+                    }
+                    DownloadManyResult { path: path.clone(), data: None, size: None, error: Some(e.reason) }
+                }
+            };
+            results.push(result);
+
+            if let Some(callback) = on_progress.as_ref() {
+                callback.call1::<DownloadManyProgress, JsUnknown>(DownloadManyProgress {
+                    index: index as u32,
+                    path,
+                    files_done: index as u32 + 1,
+                    files_total,
+                    bytes_done: bytes_done as f64,
+                })?;
+            }
+        }
+
+        Ok(results)
+    }
+}
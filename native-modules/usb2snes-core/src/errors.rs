@@ -0,0 +1,102 @@
+// Error codes for programmatic handling on the JS side
+// Every failure is a CoreError: an ErrorCode plus the human-readable reason. When a
+// method throws, the JS Error's `code` property is the ErrorCode name and its message
+// is the reason, unchanged, so existing checks on message prefixes ("Cancelled: ...",
+// "AddressOutOfRange: ...") keep working. Errors raised inside napi calls are mapped
+// by their napi status; an exception thrown by a JS callback comes back as a new Error
+// with the callback's message. Arguments napi can't convert at all are rejected before
+// the method runs, with napi's own codes (NumberExpected, InvalidArg, ...).
+
+use napi_derive::napi;
+use napi::bindgen_prelude::JsError;
+use napi::{Error as NapiError, Status};
+
+/// Category of a failure, exposed as the thrown Error's `code`
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// No connection, or it was closed or lost while the call waited
+    NotConnected,
+    /// The device didn't answer (or stopped answering) within the read deadline
+    Timeout,
+    /// The device answered with something that isn't a valid reply
+    InvalidResponse,
+    /// The target is in use, e.g. a reserved memory region
+    DeviceBusy,
+    /// An argument was rejected before anything was sent
+    ArgValidation,
+    /// Serial port or host filesystem I/O failed
+    IoError,
+    /// The device reported an error or didn't do what was asked
+    DeviceError,
+    /// The firmware or connection doesn't support the operation
+    Unsupported,
+    /// The call was stopped with its CancelToken, or by a disconnect() in progress
+    Cancelled,
+}
+
+impl AsRef<str> for ErrorCode {
+    fn as_ref(&self) -> &str {
+        match self {
+            ErrorCode::NotConnected => "NotConnected",
+            ErrorCode::Timeout => "Timeout",
+            ErrorCode::InvalidResponse => "InvalidResponse",
+            ErrorCode::DeviceBusy => "DeviceBusy",
+            ErrorCode::ArgValidation => "ArgValidation",
+            ErrorCode::IoError => "IoError",
+            ErrorCode::DeviceError => "DeviceError",
+            ErrorCode::Unsupported => "Unsupported",
+            ErrorCode::Cancelled => "Cancelled",
+        }
+    }
+}
+
+/// A failure with its ErrorCode
+#[derive(Debug)]
+pub struct CoreError {
+    pub code: ErrorCode,
+    pub reason: String,
+}
+
+pub type Result<T> = std::result::Result<T, CoreError>;
+
+impl CoreError {
+    pub fn new(code: ErrorCode, reason: impl Into<String>) -> Self {
+        CoreError { code, reason: reason.into() }
+    }
+}
+
+impl From<NapiError> for CoreError {
+    fn from(error: NapiError) -> Self {
+        let code = match error.status {
+            Status::InvalidArg
+            | Status::ObjectExpected
+            | Status::StringExpected
+            | Status::NameExpected
+            | Status::FunctionExpected
+            | Status::NumberExpected
+            | Status::BooleanExpected
+            | Status::ArrayExpected
+            | Status::BigintExpected
+            | Status::DateExpected
+            | Status::ArrayBufferExpected => ErrorCode::ArgValidation,
+            Status::Cancelled | Status::Closing => ErrorCode::NotConnected,
+            _ => ErrorCode::DeviceError,
+        };
+        CoreError { code, reason: error.reason }
+    }
+}
+
+/// For napi traits (Task, callbacks) that need a plain napi::Error; the code is dropped
+impl From<CoreError> for NapiError {
+    fn from(error: CoreError) -> Self {
+        NapiError::from_reason(error.reason)
+    }
+}
+
+/// Used by the generated bindings when a method throws
+impl From<CoreError> for JsError<ErrorCode> {
+    fn from(error: CoreError) -> Self {
+        JsError::from(NapiError::new(error.code, error.reason))
+    }
+}
@@ -0,0 +1,232 @@
+// Connection lifecycle events: let JS react to the connection coming and going
+// Listeners registered with on() are told when a connection is made ("connected"),
+// closed or lost ("disconnected"), when a command fails in a way that points at the
+// link or the device ("error"), and when the device itself went away ("deviceRemoved",
+// raised together with "disconnected"). Failed I/O is one way to notice a removal; the
+// other is a monitor thread, running while any listener is registered, that checks
+// every second whether the serial port still exists, so unplugging the console is
+// reported even when no command is running. With auto-reconnect (see reconnect.rs)
+// there are also "reconnecting", "reconnected" and "reconnectFailed". Listeners don't
+// keep Node's event loop alive.
+
+use napi_derive::napi;
+use napi::threadsafe_function::{ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::{Env, JsFunction};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::recording::unix_millis;
+use crate::simulator::SIMULATOR_PORT_NAME;
+use crate::{Connection, DisconnectKind, Usb2SnesCore, DEVICE_DISCONNECTED};
+
+/// Time between two checks of the monitor thread
+const LIVENESS_POLL_MS: u64 = 1000;
+
+/// What happened to the connection
+#[napi(string_enum = "camelCase")]
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// A connection was made
+    Connected,
+    /// The connection was closed or lost
+    Disconnected,
+    /// A command failed with Timeout, InvalidResponse, IoError or DeviceError
+    Error,
+    /// The device went away (port removed, emulator gone); "disconnected" is raised too
+    DeviceRemoved,
+    /// Auto-reconnect is about to wait `delay_ms` and make attempt `attempt`
+    Reconnecting,
+    /// Auto-reconnect connected again
+    Reconnected,
+    /// Auto-reconnect gave up after max_attempts
+    ReconnectFailed,
+}
+
+/// Passed to on()'s callback
+#[napi(object)]
+pub struct ConnectionEventInfo {
+    pub event: ConnectionEvent,
+    /// Port or URI of the connection the event is about
+    pub port: Option<String>,
+    /// Set for "error", and for "deviceRemoved"/"disconnected" caused by a failure
+    pub code: Option<ErrorCode>,
+    pub message: Option<String>,
+    /// Reconnect events: number of the attempt, from 1
+    pub attempt: Option<u32>,
+    /// Reconnect events: wait before the attempt
+    pub delay_ms: Option<u32>,
+    /// "disconnected": Graceful after disconnect() drained in-flight work, else Forced
+    pub kind: Option<DisconnectKind>,
+    pub unix_ms: f64,
+}
+
+struct Listener {
+    id: u32,
+    event: ConnectionEvent,
+    callback: ThreadsafeFunction<ConnectionEventInfo, ErrorStrategy::Fatal>,
+}
+
+/// Registered listeners of one core and the stop flag of its monitor thread
+#[derive(Default)]
+pub(crate) struct Lifecycle {
+    next_id: u32,
+    listeners: Vec<Listener>,
+    monitor: Option<Arc<AtomicBool>>,
+}
+
+/// Whether a serial port still exists; None for connections that aren't serial ports
+fn serial_port_present(port_name: &str) -> Option<bool> {
+    if port_name.contains("://") || port_name == SIMULATOR_PORT_NAME {
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        Some(std::path::Path::new(port_name).exists())
+    }
+    #[cfg(not(unix))]
+    {
+        // Enumeration failing says nothing about the port
+        let ports = serialport::available_ports().ok()?;
+        Some(ports.iter().any(|port| port.port_name.eq_ignore_ascii_case(port_name)))
+    }
+}
+
+/// Errors that point at the link or the device rather than at the caller
+fn is_link_error(error: &CoreError) -> bool {
+    matches!(error.code, ErrorCode::Timeout | ErrorCode::InvalidResponse | ErrorCode::IoError | ErrorCode::DeviceError)
+}
+
+impl Usb2SnesCore {
+    /// Call the listeners for `event`
+    pub(crate) fn emit_event(&self, event: ConnectionEvent, port: Option<String>, error: Option<&CoreError>) {
+        self.emit_info(event, port, error, None, None);
+    }
+
+    /// Call the "disconnected" listeners, telling them how the connection ended
+    pub(crate) fn emit_disconnected(&self, port: Option<String>, error: Option<&CoreError>, kind: DisconnectKind) {
+        self.emit_info(ConnectionEvent::Disconnected, port, error, None, Some(kind));
+    }
+
+    /// Call the listeners for a reconnect `event`; `attempt` is (number, delay_ms)
+    pub(crate) fn emit_attempt_event(
+        &self,
+        event: ConnectionEvent,
+        port: Option<String>,
+        error: Option<&CoreError>,
+        attempt: Option<(u32, u32)>,
+    ) {
+        self.emit_info(event, port, error, attempt, None);
+    }
+
+    fn emit_info(
+        &self,
+        event: ConnectionEvent,
+        port: Option<String>,
+        error: Option<&CoreError>,
+        attempt: Option<(u32, u32)>,
+        kind: Option<DisconnectKind>,
+    ) {
+        let lifecycle = self.lifecycle.lock().unwrap();
+        let unix_ms = unix_millis() as f64;
+        for listener in lifecycle.listeners.iter().filter(|listener| listener.event == event) {
+            let info = ConnectionEventInfo {
+                event,
+                port: port.clone(),
+                code: error.map(|e| e.code),
+                message: error.map(|e| e.reason.clone()),
+                attempt: attempt.map(|(number, _)| number),
+                delay_ms: attempt.map(|(_, delay_ms)| delay_ms),
+                kind,
+                unix_ms,
+            };
+            listener.callback.call(info, ThreadsafeFunctionCallMode::NonBlocking);
+        }
+    }
+
+    /// Report a failed command: "error" for link failures
+    pub(crate) fn emit_command_error(&self, error: &CoreError) {
+        if is_link_error(error) {
+            self.emit_event(ConnectionEvent::Error, self.port_name.lock().unwrap().clone(), Some(error));
+        }
+    }
+
+    /// Drop a connection whose device went away, so is_connected() tells the truth
+    /// `error` is the failure that showed it, if any
+    pub(crate) fn lose_connection(&self, port_guard: &mut Option<Connection>, error: Option<&CoreError>) {
+        if port_guard.take().is_none() {
+            return;
+        }
+        let port = self.port_name.lock().unwrap().take();
+        self.reservations.lock().unwrap().clear();
+        self.emit_event(ConnectionEvent::DeviceRemoved, port.clone(), error);
+        self.emit_disconnected(port, error, DisconnectKind::Forced);
+        self.begin_reconnect();
+    }
+
+    /// Body of the monitor thread; returns when `stop` is set
+    fn monitor_loop(&self, stop: &AtomicBool) {
+        while !stop.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(LIVENESS_POLL_MS));
+            let Some(port_name) = self.port_name.lock().unwrap().clone() else {
+                continue;
+            };
+            if serial_port_present(&port_name) != Some(false) {
+                continue;
+            }
+            // A running command holds the port; it fails on its own and reports the removal
+            if let Ok(mut port_guard) = self.port.try_lock() {
+                if self.port_name.lock().unwrap().as_deref() == Some(port_name.as_str()) {
+                    let error = CoreError::new(ErrorCode::NotConnected,
+                        format!("{}: {} no longer exists", DEVICE_DISCONNECTED, port_name)
+                    );
+                    self.diagnostics.lock().unwrap().record_error(&error);
+                    self.lose_connection(&mut port_guard, Some(&error));
+                }
+            }
+        }
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Call `callback(info)` whenever `event` happens; returns the listener's id
+    /// Events are "connected", "disconnected", "error" and "deviceRemoved". Listeners
+    /// don't keep Node's event loop alive; remove them with off().
+    #[napi(ts_args_type = "event: ConnectionEvent, callback: (info: ConnectionEventInfo) => void")]
+    pub fn on(&self, env: Env, event: ConnectionEvent, callback: JsFunction) -> Result<u32> {
+        let mut callback: ThreadsafeFunction<ConnectionEventInfo, ErrorStrategy::Fatal> = callback
+            .create_threadsafe_function(0, |ctx: ThreadSafeCallContext<ConnectionEventInfo>| Ok(vec![ctx.value]))
+            .map_err(CoreError::from)?;
+        callback.unref(&env).map_err(CoreError::from)?;
+
+        let mut lifecycle = self.lifecycle.lock().unwrap();
+        lifecycle.next_id += 1;
+        let id = lifecycle.next_id;
+        lifecycle.listeners.push(Listener { id, event, callback });
+        if lifecycle.monitor.is_none() {
+            let stop = Arc::new(AtomicBool::new(false));
+            let core = self.clone();
+            let thread_stop = stop.clone();
+            std::thread::spawn(move || core.monitor_loop(&thread_stop));
+            lifecycle.monitor = Some(stop);
+        }
+        Ok(id)
+    }
+
+    /// Remove a listener added with on(); returns false if there was none with that id
+    /// The monitor thread stops with the last listener.
+    #[napi]
+    pub fn off(&self, id: u32) -> bool {
+        let mut lifecycle = self.lifecycle.lock().unwrap();
+        let before = lifecycle.listeners.len();
+        lifecycle.listeners.retain(|listener| listener.id != id);
+        if lifecycle.listeners.is_empty() {
+            if let Some(stop) = lifecycle.monitor.take() {
+                stop.store(true, Ordering::SeqCst);
+            }
+        }
+        lifecycle.listeners.len() != before
+    }
+}
@@ -0,0 +1,155 @@
+// Hot-plug detection of USB serial devices (see DeviceManager::start_hotplug())
+// A thread waits until the set of serial ports may have changed, rescans them into the
+// manager's device list and reports each device that arrived or was removed. On Linux
+// it is woken by a udev monitor on the tty subsystem, so a plugged-in FxPak is reported
+// as soon as its port node exists. Without udev (other systems, or containers where
+// the monitor can't be opened) the ports are rescanned every 500ms; on Windows and
+// macOS that scan is the registry/IOKit enumeration serialport does, which is cheap.
+
+use napi_derive::napi;
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::device_manager::{present_devices, refresh_devices, DeviceMap};
+use crate::errors::{CoreError, ErrorCode, Result};
+
+/// Time between two port scans when no OS notifications are available
+const HOTPLUG_POLL_MS: u64 = 500;
+
+/// Longest wait for a udev event, so stop_hotplug() is noticed
+#[cfg(target_os = "linux")]
+const UDEV_WAIT_MS: i32 = 200;
+
+/// Whether a device was plugged in or removed
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum HotplugKind {
+    Arrived,
+    Removed,
+}
+
+/// Passed to start_hotplug()'s callback for each device that arrived or was removed
+#[napi(object)]
+pub struct HotplugEvent {
+    pub kind: HotplugKind,
+    /// Key for DeviceManager get()/connect(): USB serial number if available, else port name
+    pub key: String,
+    pub port_name: String,
+    pub serial_number: Option<String>,
+    /// USB IDs are the sd2snes / FxPak Pro's
+    pub fx_pak: bool,
+    pub unix_ms: f64,
+}
+
+/// What wakes the hot-plug thread
+enum PortChanges {
+    #[cfg(target_os = "linux")]
+    Udev(libudev::MonitorSocket),
+    Polling,
+}
+
+impl PortChanges {
+    /// A udev monitor where possible, else polling
+    fn open() -> Self {
+        #[cfg(target_os = "linux")]
+        if let Ok(socket) = udev_tty_monitor() {
+            return PortChanges::Udev(socket);
+        }
+        PortChanges::Polling
+    }
+
+    /// Wait a short while; true if the serial ports may have changed
+    fn wait(&mut self) -> bool {
+        match self {
+            #[cfg(target_os = "linux")]
+            PortChanges::Udev(socket) => {
+                use std::os::unix::io::AsRawFd;
+                let mut fd = libc::pollfd { fd: socket.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+                // SAFETY: one valid pollfd for the duration of the call
+                if unsafe { libc::poll(&mut fd, 1, UDEV_WAIT_MS) } <= 0 {
+                    return false;
+                }
+                let mut changed = false;
+                while let Some(event) = socket.receive_event() {
+                    changed |= matches!(event.event_type(), libudev::EventType::Add | libudev::EventType::Remove);
+                }
+                changed
+            }
+            PortChanges::Polling => {
+                std::thread::sleep(Duration::from_millis(HOTPLUG_POLL_MS));
+                true
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn udev_tty_monitor() -> std::result::Result<libudev::MonitorSocket, libudev::Error> {
+    let context = libudev::Context::new()?;
+    let mut monitor = libudev::Monitor::new(&context)?;
+    monitor.match_subsystem("tty")?;
+    monitor.listen()
+}
+
+/// The hot-plug thread of a DeviceManager
+pub(crate) struct HotplugEngine {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl HotplugEngine {
+    pub(crate) fn start(
+        devices: Arc<Mutex<DeviceMap>>,
+        callback: ThreadsafeFunction<HotplugEvent, ErrorStrategy::CalleeHandled>,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || hotplug_loop(&devices, callback, &thread_stop));
+        HotplugEngine { stop, thread }
+    }
+
+    pub(crate) fn stop(self) -> Result<()> {
+        self.stop.store(true, Ordering::SeqCst);
+        self.thread.join()
+            .map_err(|_| CoreError::new(ErrorCode::DeviceError, "stop_hotplug: hot-plug thread panicked"))
+    }
+}
+
+/// Body of the hot-plug thread; returns when `stop` is set
+fn hotplug_loop(
+    devices: &Mutex<DeviceMap>,
+    callback: ThreadsafeFunction<HotplugEvent, ErrorStrategy::CalleeHandled>,
+    stop: &AtomicBool,
+) {
+    // Open the monitor before the first scan so nothing plugged in between is missed
+    let mut changes = PortChanges::open();
+    let mut first = true;
+    let mut failing = false;
+    while !stop.load(Ordering::SeqCst) {
+        if !first && !changes.wait() {
+            continue;
+        }
+        match refresh_devices(devices) {
+            Ok(found) => {
+                failing = false;
+                let events = if first { present_devices(devices) } else { found };
+                first = false;
+                for event in events {
+                    callback.call(Ok(event), ThreadsafeFunctionCallMode::NonBlocking);
+                }
+            }
+            Err(e) => {
+                if !failing {
+                    failing = true;
+                    callback.call(Err(e.into()), ThreadsafeFunctionCallMode::NonBlocking);
+                }
+                if first {
+                    std::thread::sleep(Duration::from_millis(HOTPLUG_POLL_MS));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+// Session journal of destructive SD card operations
+// Every RM, MV and PUT that may replace a file is recorded with its paths and size so
+// the UI can show what a sync actually did. Atomic overwrites (flash_and_boot, the
+// rename copy fallback) can keep the replaced file as a timestamped .bak next to it;
+// the journal entry names the backup so the UI can offer a restore. The journal lives
+// in memory for the core's lifetime; persisting it is the app's job.
+
+use napi_derive::napi;
+use crate::errors::Result;
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{list_dir_locked, path_command_locked, split_path, Connection, Usb2SnesCore, LS_TYPE_DIR, MAX_MV_DEST_PATH_LEN};
+
+/// Entries kept; the oldest are dropped beyond this
+const MAX_JOURNAL_ENTRIES: usize = 1024;
+
+/// Kind of a journaled operation
+#[napi(string_enum)]
+pub enum JournalOp {
+    /// RM of `path`
+    Remove,
+    /// MV (or copy + RM) of `path` to `to`
+    Move,
+    /// Plain PUT of `path`; there is no existence check, so it may have replaced a file
+    Put,
+    /// Atomic PUT that replaced the existing `path`
+    Overwrite,
+}
+
+/// One destructive operation (see journal())
+#[napi(object)]
+#[derive(Clone)]
+pub struct JournalEntry {
+    pub op: JournalOp,
+    pub at_ms: f64,
+    pub path: String,
+    /// Move destination
+    pub to: Option<String>,
+    /// Bytes written (Put/Overwrite, copy-fallback Move); unset when unknown
+    pub size: Option<u32>,
+    /// Where an Overwrite kept the replaced file, if backups are enabled
+    pub backup: Option<String>,
+}
+
+/// Options for configure_journal()
+#[napi(object)]
+pub struct JournalOptions {
+    /// Replaced files kept as .bak per path on atomic overwrites (default 0 = none)
+    pub backup_count: u32,
+}
+
+#[derive(Default)]
+pub(crate) struct Journal {
+    entries: VecDeque<JournalEntry>,
+    backup_count: u32,
+}
+
+impl Journal {
+    pub(crate) fn record(&mut self, op: JournalOp, path: &str, to: Option<&str>, size: Option<u32>, backup: Option<String>) {
+        if self.entries.len() == MAX_JOURNAL_ENTRIES {
+            self.entries.pop_front();
+        }
+        let at_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as f64).unwrap_or(0.0);
+        self.entries.push_back(JournalEntry {
+            op,
+            at_ms,
+            path: path.to_string(),
+            to: to.map(String::from),
+            size,
+            backup,
+        });
+    }
+}
+
+/// Backup name pattern: "<name>.<unix_ms>.bak" in the same directory
+fn backup_stamp<'a>(entry: &'a str, name: &str) -> Option<&'a str> {
+    let stamp = entry.strip_prefix(name)?.strip_prefix('.')?.strip_suffix(".bak")?;
+    (!stamp.is_empty() && stamp.bytes().all(|b| b.is_ascii_digit())).then_some(stamp)
+}
+
+/// Move the existing file at `path` aside as a .bak, pruning older backups of it down to
+/// the configured count; returns the backup path, or None if backups are off or the
+/// name would be too long (the caller then removes the file instead)
+pub(crate) fn back_up_locked(conn: &mut Connection, path: &str) -> Result<Option<String>> {
+    let keep = conn.journal.lock().unwrap().backup_count as usize;
+    let (dir, name) = split_path(path);
+    let join = |entry: &str| if dir == "/" { format!("/{}", entry) } else { format!("{}/{}", dir, entry) };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    // Longest name this can produce; checked before touching the card
+    if keep == 0 || join(&format!("{}.{}.bak", name, u128::MAX)).len() > MAX_MV_DEST_PATH_LEN {
+        return Ok(None);
+    }
+
+    // Oldest first; make room for the one about to be created
+    let mut existing: Vec<(u128, String)> = list_dir_locked(conn, dir)?.unwrap_or_default().into_iter()
+        .filter(|(file_type, _)| *file_type != LS_TYPE_DIR)
+        .filter_map(|(_, entry)| Some((backup_stamp(&entry, name)?.parse().ok()?, entry)))
+        .collect();
+    existing.sort();
+    // Two overwrites within the same millisecond must not collide
+    let stamp = existing.last().map_or(now, |(newest, _)| now.max(newest + 1));
+    let excess = (existing.len() + 1).saturating_sub(keep);
+    for (_, old) in existing.into_iter().take(excess) {
+        path_command_locked(conn, 6, "RM", vec![join(&old)])?;
+    }
+
+    let backup = join(&format!("{}.{}.bak", name, stamp));
+    path_command_locked(conn, 7, "MV", vec![path.to_string(), backup.clone()])?;
+    Ok(Some(backup))
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Destructive operations of this session, oldest first (at most 1024)
+    #[napi]
+    pub fn journal(&self) -> Vec<JournalEntry> {
+        self.journal.lock().unwrap().entries.iter().cloned().collect()
+    }
+
+    /// Forget all journal entries (backups on the SD card are left alone)
+    #[napi]
+    pub fn clear_journal(&self) {
+        self.journal.lock().unwrap().entries.clear();
+    }
+
+    /// Set how many replaced files atomic overwrites keep as backups per path
+    #[napi]
+    pub fn configure_journal(&self, options: JournalOptions) {
+        self.journal.lock().unwrap().backup_count = options.backup_count;
+    }
+}
@@ -0,0 +1,166 @@
+// Minimal JSON for the usb2snes WebSocket protocol
+// Requests and replies are small objects of strings and string arrays, so this reads
+// and writes just that instead of pulling in a JSON library.
+
+/// A parsed JSON value; numbers and booleans aren't needed by the protocol, only recognized
+pub(crate) enum Json {
+    Null,
+    Bool,
+    Number,
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+/// Parse a complete JSON document
+pub(crate) fn parse_json(text: &str) -> Option<Json> {
+    JsonParser::parse(text)
+}
+
+impl JsonParser<'_> {
+    fn parse(text: &str) -> Option<Json> {
+        let mut parser = JsonParser { bytes: text.as_bytes(), pos: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        (parser.pos == parser.bytes.len()).then_some(value)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.bytes.get(self.pos).is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let matched = self.bytes.get(self.pos) == Some(&byte);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Option<Json> {
+        self.bytes[self.pos..].starts_with(word.as_bytes()).then(|| {
+            self.pos += word.len();
+            value
+        })
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.skip_whitespace();
+        match *self.bytes.get(self.pos)? {
+            b'{' => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                if self.eat(b'}') {
+                    return Some(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    if !self.eat(b':') {
+                        return None;
+                    }
+                    fields.push((key, self.value()?));
+                    if self.eat(b'}') {
+                        return Some(Json::Object(fields));
+                    }
+                    if !self.eat(b',') {
+                        return None;
+                    }
+                }
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.eat(b']') {
+                    return Some(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    if self.eat(b']') {
+                        return Some(Json::Array(items));
+                    }
+                    if !self.eat(b',') {
+                        return None;
+                    }
+                }
+            }
+            b'"' => self.string().map(Json::Str),
+            b't' => self.literal("true", Json::Bool),
+            b'f' => self.literal("false", Json::Bool),
+            b'n' => self.literal("null", Json::Null),
+            b'-' | b'0'..=b'9' => {
+                let start = self.pos;
+                while self.bytes.get(self.pos).is_some_and(|b| b"+-.eE0123456789".contains(b)) {
+                    self.pos += 1;
+                }
+                std::str::from_utf8(&self.bytes[start..self.pos]).ok()?.parse::<f64>().ok().map(|_| Json::Number)
+            }
+            _ => None,
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if self.bytes.get(self.pos) != Some(&b'"') {
+            return None;
+        }
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let byte = *self.bytes.get(self.pos)?;
+            self.pos += 1;
+            match byte {
+                b'"' => return String::from_utf8(out).ok(),
+                b'\\' => {
+                    let escaped = *self.bytes.get(self.pos)?;
+                    self.pos += 1;
+                    let ch = match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let hex = std::str::from_utf8(self.bytes.get(self.pos..self.pos + 4)?).ok()?;
+                            self.pos += 4;
+                            // Surrogate pairs aren't needed for paths; they decode as U+FFFD
+                            char::from_u32(u32::from_str_radix(hex, 16).ok()?).unwrap_or('\u{FFFD}')
+                        }
+                        _ => return None,
+                    };
+                    out.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                _ => out.push(byte),
+            }
+        }
+    }
+}
+
+/// Quote `text` as a JSON string
+pub(crate) fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,574 @@
+// ROM launch flow: upload, verify, boot and confirm in one call
+// Each phase is timed and reported separately so a failure can be attributed
+// ("upload ok, boot unconfirmed") instead of surfacing as one opaque error.
+// patch_and_boot() puts patching and the header checksum fix in front of the same
+// phases, for the usual rhplay flow of a base ROM plus an IPS/BPS hack.
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+use napi::{JsFunction, JsUnknown};
+use crate::errors::{CoreError, ErrorCode, Result};
+use std::time::{Duration, Instant};
+
+use crate::copier::without_copier_header;
+use crate::mapping::{fix_rom_checksum, rom_file_header};
+use crate::patching::apply_patch;
+use crate::queue::Lane;
+use crate::{
+    download_file_locked, info_locked, normalize_path, path_command_locked, put_file_atomic_locked,
+    Connection, Usb2SnesCore, MAX_PATH_LEN,
+};
+
+/// Default time to wait for INFO to report the booted ROM
+const DEFAULT_CONFIRM_TIMEOUT_MS: u32 = 5000;
+
+/// Delay between INFO polls while waiting for the ROM to start
+const CONFIRM_POLL_MS: u64 = 250;
+
+/// Settle time between the end of an upload and BOOT, so the file is closed on the SD card
+const BOOT_SETTLE_MS: u64 = 100;
+
+/// Time the firmware needs after BOOT to leave the menu before INFO reflects the new ROM
+const POST_BOOT_SETTLE_MS: u64 = 250;
+
+/// File name patch_and_boot() uploads as when neither `file_name` nor the header title gives one
+const DEFAULT_PATCHED_NAME: &str = "patched";
+
+/// Characters FAT doesn't allow in a file name
+const FAT_RESERVED_CHARS: &[char] = &['"', '*', '/', ':', '<', '>', '?', '\\', '|'];
+
+/// DOS device names FAT won't create as files, with or without an extension
+const DOS_DEVICE_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// flash_and_boot() reports progress every this many bytes (and at the end)
+const FLASH_PROGRESS_INTERVAL: u32 = 64 * 1024;
+
+/// Options for launch_rom()
+#[napi(object)]
+pub struct LaunchOptions {
+    /// Read the uploaded file back and compare CRC32s before booting (default false)
+    pub verify_crc: Option<bool>,
+    /// How long to poll INFO for the ROM to be running (default 5000ms)
+    pub confirm_timeout_ms: Option<u32>,
+    /// Upload a ROM with a copier header without it (default true)
+    pub strip_copier_header: Option<bool>,
+    /// Give the ROM the save stored under this key before booting (see activate_game_save())
+    pub save_key: Option<String>,
+}
+
+/// Options for boot_rom()
+#[napi(object)]
+pub struct BootRomOptions {
+    /// Poll INFO until the ROM is running and throw if it never is (default true)
+    pub confirm: Option<bool>,
+    /// How long to poll INFO for the ROM to be running (default 5000ms)
+    pub confirm_timeout_ms: Option<u32>,
+}
+
+/// Result of boot_rom()
+#[napi(object)]
+pub struct BootRomResult {
+    pub path: String,
+    /// INFO reported the ROM as running; false when confirmation was skipped
+    pub confirmed: bool,
+    /// romRunning from the last INFO poll; unset when confirmation was skipped
+    pub rom_running: Option<String>,
+    /// BOOT through confirmation
+    pub elapsed_ms: u32,
+}
+
+/// Phases of a launch, in order (Patch and Checksum only in patch_and_boot(), Save
+/// only with a `save_key`)
+#[napi(string_enum)]
+pub enum LaunchPhase {
+    Patch,
+    Checksum,
+    Upload,
+    Verify,
+    /// activate_game_save() for the launch's `save_key`
+    Save,
+    Boot,
+    Confirm,
+}
+
+/// Outcome of one launch phase
+#[napi(object)]
+pub struct LaunchPhaseReport {
+    pub phase: LaunchPhase,
+    pub ok: bool,
+    pub elapsed_ms: u32,
+    pub error: Option<String>,
+}
+
+/// Result of launch_rom()
+/// `phases` lists every phase that ran; the launch stops at the first failed phase
+#[napi(object)]
+pub struct LaunchReport {
+    pub path: String,
+    pub phases: Vec<LaunchPhaseReport>,
+    /// INFO reported the ROM as running before the deadline
+    pub boot_confirmed: bool,
+    /// romRunning from the last INFO poll, if any
+    pub rom_running: Option<String>,
+    /// Human-readable outcome, e.g. "upload ok, boot confirmed"
+    pub summary: String,
+}
+
+/// Progress event passed to launch_rom()'s `on_progress` when a phase starts
+#[napi(object)]
+pub struct LaunchProgress {
+    pub phase: LaunchPhase,
+    /// Destination path; for patch_and_boot() without a `file_name`, the directory
+    /// until the patched ROM's header has named the file
+    pub path: String,
+}
+
+/// Options for patch_and_boot()
+#[napi(object)]
+pub struct PatchAndBootOptions {
+    /// Unpatched ROM the patch was made for
+    pub base_rom: Buffer,
+    /// IPS or BPS patch, recognized by its header
+    pub patch: Buffer,
+    /// SD card directory to upload into; created if missing
+    pub remote_dir: String,
+    /// Name to upload as, made FAT-safe with ".sfc" added unless it ends in .sfc/.smc
+    /// (default: the patched ROM's header title)
+    pub file_name: Option<String>,
+    /// Recompute the header checksum after patching (default true)
+    pub fix_checksum: Option<bool>,
+    /// Read the uploaded file back and compare CRC32s before booting (default false)
+    pub verify_crc: Option<bool>,
+    /// BOOT the ROM and confirm it is running once uploaded (default true)
+    pub boot_after_upload: Option<bool>,
+    /// Give the ROM the save stored under this key before booting (see activate_game_save())
+    pub save_key: Option<String>,
+    /// How long to poll INFO for the ROM to be running (default 5000ms)
+    pub confirm_timeout_ms: Option<u32>,
+}
+
+/// Options for flash_and_boot()
+#[napi(object)]
+pub struct FlashOptions {
+    /// How long to poll INFO for the ROM to be running (default 5000ms)
+    pub confirm_timeout_ms: Option<u32>,
+}
+
+/// Upload progress passed to flash_and_boot()'s callback
+#[napi(object)]
+pub struct FlashProgress {
+    pub bytes_sent: u32,
+    pub total: u32,
+}
+
+/// Result of flash_and_boot()
+#[napi(object)]
+pub struct FlashResult {
+    pub bytes_transferred: u32,
+    /// Upload, boot and confirmation, end to end
+    pub elapsed_ms: u32,
+    /// INFO reported the ROM as running before the deadline
+    pub boot_confirmed: bool,
+    /// romRunning from the last INFO poll
+    pub rom_running: Option<String>,
+}
+
+/// Whether INFO's romRunning refers to `path` (firmware may drop the leading '/')
+pub(crate) fn rom_running_matches(rom_running: &str, path: &str) -> bool {
+    rom_running.trim_start_matches('/').eq_ignore_ascii_case(path.trim_start_matches('/'))
+}
+
+/// Make `name` a valid FAT file name of at most `limit` bytes with a ROM extension
+/// Reserved and control characters become '_', leading/trailing dots and spaces go,
+/// DOS device names get a '_' prefix and an empty result becomes "patched".
+pub(crate) fn fat_safe_file_name(name: &str, limit: usize) -> String {
+    let cleaned: String = name.chars()
+        .map(|c| if c.is_control() || FAT_RESERVED_CHARS.contains(&c) { '_' } else { c })
+        .collect();
+    let cleaned = cleaned.trim_matches(|c| c == '.' || c == ' ');
+    let (stem, extension) = match cleaned.rsplit_once('.') {
+        Some((stem, ext)) if ext.eq_ignore_ascii_case("sfc") || ext.eq_ignore_ascii_case("smc") => {
+            (stem.trim_end_matches([' ', '.']), format!(".{}", ext))
+        }
+        _ => (cleaned, ".sfc".to_string()),
+    };
+    let mut stem = if stem.is_empty() { DEFAULT_PATCHED_NAME.to_string() } else { stem.to_string() };
+    if DOS_DEVICE_NAMES.iter().any(|device| device.eq_ignore_ascii_case(&stem)) {
+        stem.insert(0, '_');
+    }
+
+    let mut stem_limit = limit.saturating_sub(extension.len());
+    while !stem.is_char_boundary(stem_limit.min(stem.len())) {
+        stem_limit -= 1;
+    }
+    stem.truncate(stem_limit);
+    let stem = stem.trim_end_matches([' ', '.']);
+    format!("{}{}", stem, extension)
+}
+
+/// Record one phase of a launch: report its start to `on_progress`, run it and add
+/// its outcome to `report`. Returns whether it succeeded; only a throwing callback
+/// is an error.
+fn run_phase(
+    report: &mut LaunchReport,
+    phase: LaunchPhase,
+    on_progress: Option<&JsFunction>,
+    run: impl FnOnce(&mut LaunchReport) -> Result<()>,
+) -> Result<bool> {
+    if let Some(callback) = on_progress {
+        callback.call1::<LaunchProgress, JsUnknown>(LaunchProgress { phase, path: report.path.clone() })?;
+    }
+    let started = Instant::now();
+    let outcome = run(report);
+    let ok = outcome.is_ok();
+    report.phases.push(LaunchPhaseReport {
+        phase,
+        ok,
+        elapsed_ms: started.elapsed().as_millis() as u32,
+        error: outcome.err().map(|e| e.reason),
+    });
+    Ok(ok)
+}
+
+/// CRC32 of a file on the SD card, computed as its blocks arrive
+fn file_crc32_locked(conn: &mut Connection, path: &str) -> Result<u32> {
+    let mut hasher = crc32fast::Hasher::new();
+    download_file_locked(conn, path, |block| {
+        hasher.update(block);
+        Ok(())
+    })?;
+    Ok(hasher.finalize())
+}
+
+impl Usb2SnesCore {
+    /// Run one of the phases talking to the device (Upload through Confirm)
+    fn run_device_phase(
+        &self,
+        phase: LaunchPhase,
+        path: &str,
+        data: &[u8],
+        save_key: Option<&str>,
+        confirm_timeout: u32,
+        report: &mut LaunchReport,
+    ) -> Result<()> {
+        match phase {
+            LaunchPhase::Upload => self.with_connection_in(Lane::Bulk, |conn| put_file_atomic_locked(conn, path, data)),
+            LaunchPhase::Verify => self.with_connection_in(Lane::Bulk, |conn| file_crc32_locked(conn, path))
+                .and_then(|crc| {
+                    let expected = crc32fast::hash(data);
+                    if crc == expected {
+                        Ok(())
+                    } else {
+                        Err(CoreError::new(ErrorCode::DeviceError, format!(
+                            "CRC mismatch: uploaded {:08X}, read back {:08X}", expected, crc
+                        )))
+                    }
+                }),
+            LaunchPhase::Save => match save_key {
+                Some(key) => self.activate_game_save(path.to_string(), key.to_string(), None).map(|_| ()),
+                None => Ok(()),
+            },
+            LaunchPhase::Boot => {
+                std::thread::sleep(Duration::from_millis(BOOT_SETTLE_MS));
+                self.send_boot(path)
+            }
+            LaunchPhase::Confirm => self.confirm_boot(path, confirm_timeout)
+                .map(|(confirmed, rom_running)| {
+                    report.boot_confirmed = confirmed;
+                    report.rom_running = rom_running;
+                }),
+            LaunchPhase::Patch | LaunchPhase::Checksum => Ok(()),
+        }
+    }
+
+    /// Send BOOT for `path`
+    fn send_boot(&self, path: &str) -> Result<()> {
+        self.with_connection(|conn| path_command_locked(conn, 9, "BOOT", vec![path.to_string()]).map(|_| ()))
+    }
+
+    /// Wait for a ROM just sent BOOT to be running: POST_BOOT_SETTLE_MS so INFO no
+    /// longer reports the previous ROM (which may be the same path), then poll INFO
+    /// for up to `confirm_timeout` ms. Returns (confirmed, last romRunning).
+    fn confirm_boot(&self, path: &str, confirm_timeout: u32) -> Result<(bool, Option<String>)> {
+        std::thread::sleep(Duration::from_millis(POST_BOOT_SETTLE_MS));
+        self.wait_for_rom_running(path, Duration::from_millis(confirm_timeout as u64))
+    }
+
+    /// Poll INFO until romRunning matches `path` or `timeout` passes
+    /// The port is released between polls. Returns (confirmed, last romRunning).
+    pub(crate) fn wait_for_rom_running(&self, path: &str, timeout: Duration) -> Result<(bool, Option<String>)> {
+        let deadline = Instant::now() + timeout;
+        let mut rom_running = None;
+        loop {
+            let info = self.with_connection(info_locked)?;
+            let running = info.get(2).cloned().unwrap_or_default();
+            let confirmed = rom_running_matches(&running, path);
+            rom_running = Some(running).or(rom_running);
+            if confirmed {
+                return Ok((true, rom_running));
+            }
+            if Instant::now() >= deadline {
+                return Ok((false, rom_running));
+            }
+            std::thread::sleep(Duration::from_millis(CONFIRM_POLL_MS));
+        }
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Upload a ROM atomically, optionally verify it, BOOT it and confirm it is running
+    /// A copier header is stripped first unless `strip_copier_header` is false.
+    /// Phase failures don't throw: they are recorded in the report and the launch stops
+    /// there. Only an invalid path is thrown up front. `on_progress` receives a
+    /// LaunchProgress as each phase starts.
+    #[napi]
+    pub fn launch_rom(
+        &self,
+        remote_path: String,
+        data: Buffer,
+        options: Option<LaunchOptions>,
+        on_progress: Option<JsFunction>,
+    ) -> Result<LaunchReport> {
+        let path = normalize_path(&remote_path)?;
+        let verify_crc = options.as_ref().and_then(|o| o.verify_crc).unwrap_or(false);
+        let save_key = options.as_ref().and_then(|o| o.save_key.clone());
+        let confirm_timeout = options.as_ref()
+            .and_then(|o| o.confirm_timeout_ms)
+            .unwrap_or(DEFAULT_CONFIRM_TIMEOUT_MS);
+        let data: &[u8] = match options.as_ref().and_then(|o| o.strip_copier_header).unwrap_or(true) {
+            true => without_copier_header(&data),
+            false => &data,
+        };
+
+        let mut report = LaunchReport {
+            path: path.clone(),
+            phases: Vec::new(),
+            boot_confirmed: false,
+            rom_running: None,
+            summary: String::new(),
+        };
+
+        let mut phases = vec![LaunchPhase::Upload];
+        if verify_crc {
+            phases.push(LaunchPhase::Verify);
+        }
+        if save_key.is_some() {
+            phases.push(LaunchPhase::Save);
+        }
+        phases.extend([LaunchPhase::Boot, LaunchPhase::Confirm]);
+
+        for phase in phases {
+            let ok = run_phase(&mut report, phase, on_progress.as_ref(), |report| {
+                self.run_device_phase(phase, &path, data, save_key.as_deref(), confirm_timeout, report)
+            })?;
+            if !ok {
+                break;
+            }
+        }
+
+        report.summary = summarize(&report);
+        Ok(report)
+    }
+
+    /// Patch a base ROM in memory, fix its header checksum, upload it and boot it
+    /// The uploaded ROM never has a copier header, whether or not the base ROM had.
+    /// Runs the Patch and Checksum phases, then launch_rom()'s phases for
+    /// `remote_dir`/<file name>; like launch_rom(), a failed phase (e.g. a patch made
+    /// for another base ROM) is recorded in the report and ends the run rather than
+    /// throwing. Only an invalid `remote_dir` is thrown up front. `on_progress` receives
+    /// a LaunchProgress as each phase starts. With `boot_after_upload` false the run
+    /// ends after the upload (and verification).
+    #[napi]
+    pub fn patch_and_boot(&self, options: PatchAndBootOptions, on_progress: Option<JsFunction>) -> Result<LaunchReport> {
+        let dir = match options.remote_dir.trim_matches(['/', '\\']) {
+            "" => String::new(),
+            dir => normalize_path(dir)?,
+        };
+        let name_limit = MAX_PATH_LEN.saturating_sub(dir.len() + 1);
+        let named_path = |name: &str| format!("{}/{}", dir, fat_safe_file_name(name, name_limit));
+        let fix_checksum = options.fix_checksum.unwrap_or(true);
+        let verify_crc = options.verify_crc.unwrap_or(false);
+        let boot = options.boot_after_upload.unwrap_or(true);
+        let confirm_timeout = options.confirm_timeout_ms.unwrap_or(DEFAULT_CONFIRM_TIMEOUT_MS);
+
+        let mut report = LaunchReport {
+            path: match options.file_name.as_deref() {
+                Some(name) => named_path(name),
+                None if dir.is_empty() => "/".to_string(),
+                None => dir.clone(),
+            },
+            phases: Vec::new(),
+            boot_confirmed: false,
+            rom_running: None,
+            summary: String::new(),
+        };
+        let on_progress = on_progress.as_ref();
+
+        let mut rom = Vec::new();
+        let mut ok = run_phase(&mut report, LaunchPhase::Patch, on_progress, |_| {
+            rom = apply_patch(&options.base_rom, &options.patch)?;
+            // A headered base ROM (IPS) leaves the header in the result
+            rom.drain(..rom.len() - without_copier_header(&rom).len());
+            Ok(())
+        })?;
+        if ok && fix_checksum {
+            ok = run_phase(&mut report, LaunchPhase::Checksum, on_progress, |_| {
+                fix_rom_checksum(&mut rom).map(|_| ())
+            })?;
+        }
+
+        if ok {
+            if options.file_name.is_none() {
+                let title = rom_file_header(&rom).map(|(header, _)| header.title).unwrap_or_default();
+                report.path = named_path(&title);
+            }
+            let mut phases = vec![LaunchPhase::Upload];
+            if verify_crc {
+                phases.push(LaunchPhase::Verify);
+            }
+            if boot {
+                if options.save_key.is_some() {
+                    phases.push(LaunchPhase::Save);
+                }
+                phases.extend([LaunchPhase::Boot, LaunchPhase::Confirm]);
+            }
+
+            let path = report.path.clone();
+            for phase in phases {
+                let ok = run_phase(&mut report, phase, on_progress, |report| {
+                    if matches!(phase, LaunchPhase::Upload) && !dir.is_empty() {
+                        self.mkdir_p(dir.clone())?;
+                    }
+                    self.run_device_phase(phase, &path, &rom, options.save_key.as_deref(), confirm_timeout, report)
+                })?;
+                if !ok {
+                    break;
+                }
+            }
+        }
+
+        report.summary = summarize(&report);
+        Ok(report)
+    }
+
+    /// BOOT a ROM already on the SD card and wait for it to be running
+    /// After BOOT the firmware takes a moment to leave the menu, so INFO is only polled
+    /// after a short settle delay. With confirmation (the default) a ROM that isn't
+    /// reported by INFO before the deadline throws "BootFailed: ...", naming what is
+    /// running instead.
+    #[napi]
+    pub fn boot_rom(&self, path: String, options: Option<BootRomOptions>) -> Result<BootRomResult> {
+        let path = normalize_path(&path)?;
+        let confirm = options.as_ref().and_then(|o| o.confirm).unwrap_or(true);
+        let confirm_timeout = options.as_ref()
+            .and_then(|o| o.confirm_timeout_ms)
+            .unwrap_or(DEFAULT_CONFIRM_TIMEOUT_MS);
+        let started = Instant::now();
+
+        self.send_boot(&path)?;
+        if !confirm {
+            return Ok(BootRomResult {
+                path,
+                confirmed: false,
+                rom_running: None,
+                elapsed_ms: started.elapsed().as_millis() as u32,
+            });
+        }
+
+        let (confirmed, rom_running) = self.confirm_boot(&path, confirm_timeout)?;
+        if !confirmed {
+            return Err(CoreError::new(ErrorCode::DeviceError, format!(
+                "BootFailed: {} was not running after {}ms (device reports {})",
+                path,
+                started.elapsed().as_millis(),
+                match rom_running.as_deref() {
+                    Some(running) if !running.is_empty() => format!("'{}' running", running),
+                    _ => "no ROM running".to_string(),
+                }
+            )));
+        }
+
+        Ok(BootRomResult {
+            path,
+            confirmed,
+            rom_running,
+            elapsed_ms: started.elapsed().as_millis() as u32,
+        })
+    }
+
+    /// Flash a ROM from the host and run it: upload_from(), BOOT, then confirm via INFO
+    /// A copier header in the host file is skipped (see copier.rs).
+    /// `progress_callback` receives a FlashProgress every 64KB of upload and at the end.
+    /// It runs while the port is held, so it must not call back into this core.
+    /// An unconfirmed boot is reported in the result rather than thrown.
+    #[napi]
+    pub fn flash_and_boot(
+        &self,
+        host_path: String,
+        device_path: String,
+        progress_callback: Option<JsFunction>,
+        options: Option<FlashOptions>,
+    ) -> Result<FlashResult> {
+        let device_path = normalize_path(&device_path)?;
+        let confirm_timeout = options.as_ref()
+            .and_then(|o| o.confirm_timeout_ms)
+            .unwrap_or(DEFAULT_CONFIRM_TIMEOUT_MS);
+        let started = Instant::now();
+
+        let mut reported = 0u32;
+        let bytes_transferred = self.upload_host_file(&host_path, &device_path, true, |sent, total| {
+            if sent - reported < FLASH_PROGRESS_INTERVAL && sent < total {
+                return Ok(());
+            }
+            reported = sent;
+            match progress_callback.as_ref() {
+                Some(callback) => callback
+                    .call1::<FlashProgress, JsUnknown>(FlashProgress { bytes_sent: sent, total })
+                    .map(|_| ())
+                    .map_err(CoreError::from),
+                None => Ok(()),
+            }
+        })?;
+
+        std::thread::sleep(Duration::from_millis(BOOT_SETTLE_MS));
+        self.send_boot(&device_path)?;
+        let (boot_confirmed, rom_running) = self.confirm_boot(&device_path, confirm_timeout)?;
+
+        Ok(FlashResult {
+            bytes_transferred,
+            elapsed_ms: started.elapsed().as_millis() as u32,
+            boot_confirmed,
+            rom_running,
+        })
+    }
+}
+
+fn summarize(report: &LaunchReport) -> String {
+    let mut parts: Vec<String> = report.phases.iter()
+        .filter(|p| !matches!(p.phase, LaunchPhase::Confirm))
+        .map(|p| format!("{} {}", phase_name(p.phase), if p.ok { "ok" } else { "failed" }))
+        .collect();
+    match report.phases.iter().find(|p| matches!(p.phase, LaunchPhase::Confirm)) {
+        Some(p) if !p.ok => parts.push("boot confirmation failed".to_string()),
+        Some(_) if report.boot_confirmed => parts.push("boot confirmed".to_string()),
+        Some(_) => parts.push("boot unconfirmed".to_string()),
+        None => {}
+    }
+    parts.join(", ")
+}
+
+fn phase_name(phase: LaunchPhase) -> &'static str {
+    match phase {
+        LaunchPhase::Patch => "patch",
+        LaunchPhase::Checksum => "checksum",
+        LaunchPhase::Upload => "upload",
+        LaunchPhase::Verify => "verify",
+        LaunchPhase::Save => "save",
+        LaunchPhase::Boot => "boot",
+        LaunchPhase::Confirm => "confirm",
+    }
+}
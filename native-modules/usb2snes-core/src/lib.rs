@@ -2,16 +2,370 @@
 // Ported from usb2snes/Core
 
 use napi_derive::napi;
-use napi::{Error as NapiError, Result as NapiResult};
-use serialport::SerialPort;
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use std::io::{Read, Write};
+use napi::bindgen_prelude::{Buffer, Either};
+use napi::{JsFunction, JsUnknown};
+use errors::{CoreError, ErrorCode, Result};
+use events::{ConnectionEvent, Lifecycle};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
+use cache::ReadCache;
+use cancel::{get_cancellable_locked, put_file_cancellable_locked, CancelToken};
+use chunking::ChunkTuner;
+use diagnostics::DiagnosticsLog;
+use journal::{Journal, JournalOp};
+use reconnect::Supervisor;
+use protocol::CommandRequest;
+use queue::{CommandQueue, Lane};
+use recording::Recordings;
+use reservations::Reservations;
+use resync::{is_garbled_response, realign_response_locked, recover_framing_locked, resync_locked, ResyncOptions};
+use server::WsServer;
+use session::GameSession;
+use simulator::SimState;
+use stream::{stream_busy_error, StreamEngine};
+use transport::{SerialTransport, Transport};
+use timeouts::{OpcodeClass, ReadDeadlines, RetryOptions, TimeoutOptions, TimeoutSource, TimeoutTable};
+use watches::Watches;
+
+pub mod autoconnect;
+pub mod cache;
+pub mod cancel;
+pub mod chunking;
+pub mod clock;
+pub mod cmd;
+pub mod config;
+pub mod copier;
+pub mod device_manager;
+pub mod diagnostics;
+pub mod downloads;
+pub mod errors;
+pub mod events;
+pub mod hotplug;
+pub mod journal;
+pub mod json;
+pub mod launch;
+pub mod listing;
+pub mod macros;
+pub mod mapping;
+pub mod nwa;
+pub mod patching;
+pub mod pipeline;
+pub mod protocol;
+pub mod queue;
+pub mod reconnect;
+pub mod recording;
+pub mod regions;
+pub mod reservations;
+pub mod resync;
+pub mod retroarch;
+pub mod saves;
+pub mod selftest;
+pub mod server;
+pub mod session;
+pub mod simulator;
+pub mod sni;
+pub mod snapshot;
+pub mod stream;
+pub mod tasks;
+pub mod timeouts;
+pub mod torn;
+pub mod transfers;
+pub mod transport;
+pub mod validation;
+pub mod vectors;
+pub mod watches;
+pub mod wsclient;
+
+/// State is shared behind Arcs so a clone is a handle to the same connection
 #[napi]
+#[derive(Clone)]
 pub struct Usb2SnesCore {
-    port: Arc<Mutex<Option<Box<dyn SerialPort>>>>,
-    port_name: Mutex<Option<String>>,
+    port: Arc<Mutex<Option<Connection>>>,
+    port_name: Arc<Mutex<Option<String>>>,
+    /// Set while disconnect() drains in-flight work; new commands are rejected
+    closing: Arc<AtomicBool>,
+    /// Set when disconnect()'s grace period ran out with a command still holding the
+    /// port; that command stops at the next block and closes the port on its way out
+    abandoned: Arc<AtomicBool>,
+    diagnostics: Arc<Mutex<DiagnosticsLog>>,
+    cache: Arc<Mutex<ReadCache>>,
+    /// Named scratch-region claims (see reserve_region())
+    reservations: Arc<Mutex<Reservations>>,
+    /// romRunning transitions seen in INFO (see game_events())
+    session: Arc<Mutex<GameSession>>,
+    /// Background memory recordings (see record_memory())
+    recordings: Arc<Mutex<Recordings>>,
+    /// Memory watches and their polling thread (see start_watches())
+    watches: Arc<Mutex<Watches>>,
+    /// usb2snes WebSocket server (see start_server())
+    server: Arc<Mutex<Option<WsServer>>>,
+    /// Kill switch for pipelined reads (see set_pipelining_allowed())
+    pipelining_allowed: Arc<AtomicBool>,
+    /// send_command() opcode/space/flags checks (see set_command_validation())
+    command_validation: Arc<AtomicBool>,
+    /// Data-phase write size learned on the current connection
+    chunking: Arc<Mutex<ChunkTuner>>,
+    /// Destructive operations of this session (see journal())
+    journal: Arc<Mutex<Journal>>,
+    /// Connection event listeners and their monitor thread (see on())
+    lifecycle: Arc<Mutex<Lifecycle>>,
+    /// Auto-reconnect settings and the reconnect in progress (see enable_auto_reconnect())
+    supervisor: Arc<Mutex<Supervisor>>,
+    /// Order in which waiting callers get the port (see with_connection_in())
+    queue: Arc<CommandQueue>,
+    /// The STREAM in progress, which owns the port (see start_stream())
+    stream: Arc<Mutex<Option<StreamEngine>>>,
+}
+
+/// An open transport plus the protocol state that lives exactly as long as it does
+struct Connection {
+    transport: Box<dyn Transport>,
+    /// Last full RESPONSE packet received (see last_response())
+    last_response: Option<Vec<u8>>,
+    /// Last DTR/RTS levels written (None = never set on this connection)
+    dtr: Option<bool>,
+    rts: Option<bool>,
+    /// The owning core's diagnostics log (exchanges are recorded here)
+    diagnostics: Arc<Mutex<DiagnosticsLog>>,
+    /// The owning core's read cache (see configure_read_cache())
+    cache: Arc<Mutex<ReadCache>>,
+    /// The owning core's scratch-region claims, dropped on BOOT/RESET
+    reservations: Arc<Mutex<Reservations>>,
+    /// The owning core's game session, fed by every INFO
+    session: Arc<Mutex<GameSession>>,
+    /// The owning core's data-phase chunking (see write_data_with())
+    chunking: Arc<Mutex<ChunkTuner>>,
+    /// The owning core's journal, for overwrites done below the public API
+    journal: Arc<Mutex<Journal>>,
+    /// Per-opcode-class timeouts chosen at connect time
+    timeouts: TimeoutTable,
+    /// Per-call override (see with_timeout_locked()), beats the table
+    timeout_override: Option<Duration>,
+    /// Deadlines of the command in progress, and where their values came from
+    read_deadlines: ReadDeadlines,
+    /// Timeout currently set on the transport itself
+    port_timeout: Duration,
+    /// When the last command packet went out, for the inter-command delay
+    last_command_at: Option<Instant>,
+    /// Strategy chosen at connect time (None = DtrPulse, best-effort)
+    reset_strategy: Option<ResetStrategy>,
+    /// Recover from garbled RESPONSEs on its own (see resync.rs)
+    auto_resync: bool,
+    /// Flags of memory GETs/PUTs: DATA64B_FLAG for ConnectOptions.block_size 64
+    block_flags: u8,
+    /// The simulated device behind `transport` (see connect_simulated())
+    simulator: Option<Arc<Mutex<SimState>>>,
+    /// The owning core's abandoned flag: exchanges and data phases stop at the next
+    /// packet or block once disconnect() gave up waiting
+    abandoned: Arc<AtomicBool>,
+    /// Listing ls_paged() serves later pages from: (path, entries) as read for page 0
+    /// Dropped by any command that may change the device, like the read cache.
+    ls_pages: Option<(String, Vec<(u8, String)>)>,
+}
+
+impl Connection {
+    fn new(
+        transport: Box<dyn Transport>,
+        diagnostics: Arc<Mutex<DiagnosticsLog>>,
+        cache: Arc<Mutex<ReadCache>>,
+        reservations: Arc<Mutex<Reservations>>,
+        session: Arc<Mutex<GameSession>>,
+        chunking: Arc<Mutex<ChunkTuner>>,
+        journal: Arc<Mutex<Journal>>,
+    ) -> Self {
+        let timeouts = TimeoutTable::new(TimeoutOptions::default(), RetryOptions::default());
+        Self {
+            transport,
+            last_response: None,
+            dtr: None,
+            rts: None,
+            diagnostics,
+            cache,
+            reservations,
+            session,
+            chunking,
+            journal,
+            timeout_override: None,
+            read_deadlines: ReadDeadlines {
+                first_byte: (Duration::from_millis(READ_TIMEOUT_MS), TimeoutSource::PortDefault),
+                progress: (Duration::from_millis(READ_TIMEOUT_MS), TimeoutSource::PortDefault),
+                poll: timeouts.poll(),
+            },
+            port_timeout: Duration::from_millis(READ_TIMEOUT_MS),
+            timeouts,
+            last_command_at: None,
+            reset_strategy: None,
+            auto_resync: true,
+            block_flags: 0,
+            simulator: None,
+            abandoned: Arc::new(AtomicBool::new(false)),
+            ls_pages: None,
+        }
+    }
+}
+
+/// Options for connect_with_options()
+#[napi(object)]
+#[derive(Clone, Default)]
+pub struct ConnectOptions {
+    /// DTR level applied right after opening (default true, matching connect())
+    pub initial_dtr: Option<bool>,
+    /// RTS level applied right after opening (default: left as the driver opened it)
+    pub initial_rts: Option<bool>,
+    /// Send INFO and require a valid RESPONSE before reporting success (default false)
+    pub verify: Option<bool>,
+    /// How long `verify` waits for the RESPONSE (default 1000ms)
+    pub verify_timeout_ms: Option<u32>,
+    /// How reset() resets the device (default DtrPulse)
+    pub reset_strategy: Option<ResetStrategy>,
+    /// Per-opcode-class read timeouts (see TimeoutOptions for the defaults)
+    pub timeouts: Option<TimeoutOptions>,
+    /// Retries of timed-out reads and the inter-command delay (default: none)
+    pub retry: Option<RetryOptions>,
+    /// Pin host data phases to this many 512-byte blocks per flush (1-16) instead of
+    /// adapting to the link (see stats().write_chunk_bytes)
+    pub write_chunk_blocks: Option<u32>,
+    /// Get back in step after a garbled RESPONSE without resync() (default true)
+    pub auto_resync: Option<bool>,
+    /// Data phase block size of memory GETs/PUTs, 64 or 512 (default 512). 64 sets the
+    /// DATA64B flag, so a small poll moves a 64-byte block instead of 512; meant for
+    /// polling, as large reads are faster in 512-byte blocks. Files always use 512.
+    pub block_size: Option<u32>,
+}
+
+/// What get_memory() reads; `address` and `size` are required
+#[napi(object)]
+pub struct MemoryRead {
+    pub address: u32,
+    pub size: u32,
+    /// Address space (default Space.Snes); Space.File is addressed by path, see get_file()
+    pub space: Option<u8>,
+    /// Flags values or'd together; only Flags.Data64B applies (default none)
+    pub flags: Option<u32>,
+}
+
+/// Options for get_file() / download_to()
+#[napi(object)]
+pub struct TransferOptions {
+    /// Also return the CRC32 (IEEE) of the received data
+    pub compute_crc32: Option<bool>,
+}
+
+/// get_file() result when a CRC32 was requested
+#[napi(object)]
+pub struct FileDownload {
+    pub data: Buffer,
+    pub crc32: u32,
+}
+
+/// download_to() result when a CRC32 was requested
+#[napi(object)]
+pub struct FileTransfer {
+    pub size: u32,
+    pub crc32: u32,
+}
+
+/// Options for remove_many()
+#[napi(object)]
+pub struct RemoveManyOptions {
+    /// Treat paths that don't exist as successfully removed
+    pub ignore_not_found: Option<bool>,
+}
+
+/// Per-path outcome of remove_many()
+#[napi(object)]
+pub struct RemoveResult {
+    pub path: String,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+/// Options for rename()
+#[napi(object)]
+pub struct RenameOptions {
+    /// Fall back to download + atomic upload + delete if the firmware rejects a cross-directory MV
+    pub allow_copy_fallback: Option<bool>,
+}
+
+/// How rename() moved the file
+#[napi(string_enum)]
+pub enum RenameStrategy {
+    Move,
+    CopyDelete,
+}
+
+/// How a disconnect ended, so the UI can tell a clean close from a cut-off one
+#[napi(string_enum)]
+pub enum DisconnectKind {
+    /// In-flight work finished and DTR was deasserted before closing
+    Graceful,
+    /// The port was closed without waiting (or the grace period ran out)
+    Forced,
+}
+
+/// How reset() resets the device
+/// - FxPak Pro (USB handled by the cart firmware): Opcode is what resets the SNES;
+///   line pulses only matter if a bridge sits in between
+/// - original SD2SNES behind a USB-serial bridge: DtrPulse (or Combined if unsure)
+/// - either, when the OS drops and re-enumerates the port on reset: Full
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum ResetStrategy {
+    /// Drop DTR for 500ms, then raise it again (C# Reset() behavior)
+    DtrPulse,
+    /// Drop RTS for 500ms, for boards that reset on RTS
+    RtsPulse,
+    /// Send the RESET opcode with NORESP; no line changes
+    Opcode,
+    /// Drop DTR and RTS together for 500ms, then send the RESET opcode
+    Combined,
+    /// Combined, then close and reopen the port (restoring DTR/RTS levels), for
+    /// systems where the old handle is dead after the reset
+    Full,
+}
+
+/// Result of rename()
+#[napi(object)]
+pub struct RenameResult {
+    pub strategy: RenameStrategy,
+}
+
+/// Modem control line snapshot for diagnostics
+/// Input lines are None when the platform/driver can't report them;
+/// dtr/rts are the levels we last set (None if never set on this connection)
+#[napi(object)]
+pub struct ModemStatus {
+    pub cts: Option<bool>,
+    pub dsr: Option<bool>,
+    pub cd: Option<bool>,
+    pub ri: Option<bool>,
+    pub dtr: Option<bool>,
+    pub rts: Option<bool>,
+}
+
+/// What the connected transport can do (see capabilities())
+#[napi(object)]
+pub struct Capabilities {
+    /// set_dtr() and the DTR pulse of reset()
+    pub dtr_control: bool,
+    /// set_rts()
+    pub rts_control: bool,
+    /// modem_status() input lines (CTS/DSR/CD/RI)
+    pub signal_read: bool,
+    /// Sending a break condition (no API for it yet, so always false)
+    pub break_support: bool,
+}
+
+/// Progress event for batch operations, fired once per item
+#[napi(object)]
+pub struct ItemProgress {
+    pub index: u32,
+    pub total: u32,
+    pub path: String,
 }
 
 #[napi]
@@ -20,14 +374,32 @@ impl Usb2SnesCore {
     pub fn new() -> Self {
         Self {
             port: Arc::new(Mutex::new(None)),
-            port_name: Mutex::new(None),
+            port_name: Arc::new(Mutex::new(None)),
+            closing: Arc::new(AtomicBool::new(false)),
+            abandoned: Arc::new(AtomicBool::new(false)),
+            diagnostics: Arc::new(Mutex::new(DiagnosticsLog::default())),
+            cache: Arc::new(Mutex::new(ReadCache::default())),
+            reservations: Arc::new(Mutex::new(Reservations::default())),
+            session: Arc::new(Mutex::new(GameSession::default())),
+            recordings: Arc::new(Mutex::new(Recordings::default())),
+            watches: Arc::new(Mutex::new(Watches::default())),
+            server: Arc::new(Mutex::new(None)),
+            pipelining_allowed: Arc::new(AtomicBool::new(true)),
+            command_validation: Arc::new(AtomicBool::new(true)),
+            chunking: Arc::new(Mutex::new(ChunkTuner::default())),
+            journal: Arc::new(Mutex::new(Journal::default())),
+            lifecycle: Arc::new(Mutex::new(Lifecycle::default())),
+            supervisor: Arc::new(Mutex::new(Supervisor::default())),
+            queue: Arc::new(CommandQueue::default()),
+            stream: Arc::new(Mutex::new(None)),
         }
     }
 
     /// Check if connected
     #[napi]
     pub fn is_connected(&self) -> bool {
-        let port = self.port.lock().unwrap();
+        let mut port = self.port.lock().unwrap();
+        self.close_if_abandoned(&mut port);
         port.is_some()
     }
 
@@ -38,378 +410,2031 @@ impl Usb2SnesCore {
     /// - DataBits = 8
     /// - StopBits = One
     /// - Handshake = None (no flow control!)
-    /// - ReadTimeout = 5000ms
+    /// - ReadTimeout = 5000ms (until the first command; then per opcode class, see TimeoutOptions)
     /// - WriteTimeout = 5000ms
     /// - DTR = true
     #[napi]
-    pub fn connect(&self, port_name: String) -> NapiResult<()> {
+    pub fn connect(&self, port_name: String) -> Result<()> {
+        self.connect_with_options(port_name, None)
+    }
+
+    /// Connect with explicit control line levels applied right after the port opens
+    /// (before any command is sent). Some serial bridges reset the FxPak into its
+    /// bootloader unless DTR/RTS are at a particular level. `initial_dtr` defaults
+    /// to true (as connect() does); RTS is left at the driver default unless set.
+    /// With `verify` a single INFO must get a valid RESPONSE within 1s (or
+    /// `verify_timeout_ms`), otherwise the port is closed again and
+    /// "NotAnFxPakDevice: ..." is returned.
+    /// A "mock://" port name connects to the simulated device (see connect_simulated())
+    #[napi]
+    pub fn connect_with_options(&self, port_name: String, options: Option<ConnectOptions>) -> Result<()> {
+        if simulator::is_mock_uri(&port_name) {
+            return self.connect_simulated(None, options);
+        }
+        let options = options.unwrap_or_default();
+        let mut port_guard = self.lock_for_connect()?;
+
+        let transport = open_serial_port(&port_name)?;
+        self.attach_port(&mut port_guard, transport, port_name.clone(), options.clone(), |_| {})?;
+        self.set_reconnect_target(&port_name, options);
+        Ok(())
+    }
+
+    /// Lock the connection slot for a new connection, disconnecting first if connected
+    /// A reconnect in progress is stopped and its target forgotten
+    pub(crate) fn lock_for_connect(&self) -> Result<MutexGuard<'_, Option<Connection>>> {
+        self.cancel_reconnect();
+        self.supervisor.lock().unwrap().forget_target();
         let mut port_guard = self.port.lock().unwrap();
-        
-        // Disconnect first if connected
         if port_guard.is_some() {
             drop(port_guard);
-            self.disconnect()?;
+            self.disconnect_force()?;
             port_guard = self.port.lock().unwrap();
         }
+        Ok(port_guard)
+    }
+
+    /// Finish connecting over an open transport: control lines, timeouts, optional verify
+    /// `setup` runs on the new connection before anything is sent
+    pub(crate) fn attach_port(
+        &self,
+        port_guard: &mut Option<Connection>,
+        transport: Box<dyn Transport>,
+        port_name: String,
+        options: ConnectOptions,
+        setup: impl FnOnce(&mut Connection),
+    ) -> Result<()> {
+        let mut conn = Connection::new(
+            transport,
+            self.diagnostics.clone(),
+            self.cache.clone(),
+            self.reservations.clone(),
+            self.session.clone(),
+            self.chunking.clone(),
+            self.journal.clone(),
+        );
+        conn.block_flags = block_size_flags(options.block_size)?;
+        conn.abandoned = self.abandoned.clone();
+        conn.cache.lock().unwrap().invalidate();
+        conn.chunking.lock().unwrap().reset(options.write_chunk_blocks);
+        setup(&mut conn);
+
+        // Set DTR = true (matching C# DtrEnable = true) unless overridden
+        // The default is best-effort: virtual ports (ptys, some bridges) can't set it
+        match options.initial_dtr {
+            Some(dtr) => {
+                conn.transport.set_dtr(dtr)
+                    .map_err(|e| CoreError::new(ErrorCode::IoError,
+                        format!("Failed to set DTR on {}: {}", port_name, e)
+                    ))?;
+                conn.dtr = Some(dtr);
+            }
+            None => match conn.transport.set_dtr(true) {
+                Ok(()) => conn.dtr = Some(true),
+                Err(e) => conn.diagnostics.lock().unwrap().record_warning(&format!(
+                    "Could not raise DTR on {} ({}); firmware that waits for DTR may not answer", port_name, e
+                )),
+            },
+        }
+
+        if let Some(rts) = options.initial_rts {
+            conn.transport.set_rts(rts)
+                .map_err(|e| CoreError::new(ErrorCode::IoError,
+                    format!("Failed to set RTS on {}: {}", port_name, e)
+                ))?;
+            conn.rts = Some(rts);
+        }
+
+        conn.reset_strategy = options.reset_strategy;
+        conn.auto_resync = options.auto_resync.unwrap_or(true);
+        conn.timeouts = TimeoutTable::new(options.timeouts.unwrap_or_default(), options.retry.unwrap_or_default());
+
+        if options.verify.unwrap_or(false) {
+            let timeout = options.verify_timeout_ms.map_or(VERIFY_TIMEOUT_MS, u64::from);
+            verify_fxpak_locked(&mut conn, Duration::from_millis(timeout)).map_err(|e| CoreError::new(ErrorCode::InvalidResponse,
+                format!("NotAnFxPakDevice: {} did not answer INFO ({})", port_name, e.reason)
+            ))?;
+        }
+
+        *port_guard = Some(conn);
+        *self.port_name.lock().unwrap() = Some(port_name.clone());
+        self.emit_event(ConnectionEvent::Connected, Some(port_name), None);
+
+        Ok(())
+    }
+
+    /// Disconnect from serial port, letting in-flight work finish first
+    /// New commands are rejected with "Cancelled: ..." immediately; the command
+    /// currently running (including its data phase) gets up to `grace_ms`
+    /// (default 2000ms) to complete. DTR is then deasserted and the port closed.
+    /// Returns Forced if the grace period ran out and DTR was left alone: the running
+    /// command then stops at its next packet or data block, fails with "Cancelled: ...",
+    /// and closes the port itself, without disconnect() waiting for it.
+    #[napi]
+    pub fn disconnect(&self, grace_ms: Option<u32>) -> Result<DisconnectKind> {
+        self.cancel_reconnect();
+        self.closing.store(true, Ordering::SeqCst);
+
+        let deadline = std::time::Instant::now()
+            + Duration::from_millis(grace_ms.unwrap_or(DEFAULT_DISCONNECT_GRACE_MS) as u64);
+        let kind = loop {
+            if let Ok(mut port_guard) = self.port.try_lock() {
+                if let Some(mut conn) = port_guard.take() {
+                    // Set DTR = false before closing (matching C# Disconnect())
+                    if let Err(e) = conn.transport.set_dtr(false) {
+                        conn.diagnostics.lock().unwrap().record_warning(&format!("Could not drop DTR on disconnect ({})", e));
+                    }
+                }
+                break DisconnectKind::Graceful;
+            }
+            if std::time::Instant::now() >= deadline {
+                // The running command owns the port until its current read/write returns,
+                // so leave closing it to that command (see close_if_abandoned())
+                self.abandoned.store(true, Ordering::SeqCst);
+                if let Ok(mut port_guard) = self.port.try_lock() {
+                    self.close_if_abandoned(&mut port_guard);
+                }
+                break DisconnectKind::Forced;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        };
+
+        let port = self.port_name.lock().unwrap().take();
+        self.reservations.lock().unwrap().clear();
+        self.closing.store(false, Ordering::SeqCst);
+        if port.is_some() {
+            self.emit_disconnected(port, None, kind);
+        }
+        Ok(kind)
+    }
+
+    /// Disconnect immediately without waiting or touching DTR (the old behavior)
+    /// Commands waiting for the port are cancelled
+    #[napi]
+    pub fn disconnect_force(&self) -> Result<DisconnectKind> {
+        self.cancel_reconnect();
+        self.closing.store(true, Ordering::SeqCst);
+        self.port.lock().unwrap().take();
+        self.abandoned.store(false, Ordering::SeqCst);
+        let port = self.port_name.lock().unwrap().take();
+        self.reservations.lock().unwrap().clear();
+        self.closing.store(false, Ordering::SeqCst);
+        if port.is_some() {
+            self.emit_disconnected(port, None, DisconnectKind::Forced);
+        }
+        Ok(DisconnectKind::Forced)
+    }
+
+    /// Reset device (matching C# Reset() method)
+    /// Uses the reset_strategy from connect_with_options(); the default DtrPulse sets
+    /// DTR = false, waits 500ms and raises it again. The default is best-effort on
+    /// ports that can't drive DTR (it then only waits), an explicit strategy is not.
+    /// Full also reopens the port; if that fails the core is left disconnected.
+    #[napi]
+    pub fn reset(&self) -> Result<()> {
+        let port_name = self.port_name.lock().unwrap().clone().unwrap_or_default();
+        let mut port_guard = self.port.lock().unwrap();
+        let conn = port_guard.as_mut()
+            .ok_or_else(|| CoreError::new(ErrorCode::NotConnected, "Not connected - cannot reset"))?;
+        conn.last_response = None;
+        conn.cache.lock().unwrap().invalidate();
+        conn.reservations.lock().unwrap().clear();
+
+        match conn.reset_strategy {
+            None => {
+                if let Err(e) = pulse_lines_locked(conn, true, false) {
+                    conn.diagnostics.lock().unwrap().record_warning(&format!(
+                        "Reset could not pulse DTR ({}); only waited", e.reason
+                    ));
+                }
+            }
+            Some(strategy) => reset_locked(conn, strategy).map_err(|e| CoreError::new(e.code,
+                format!("Reset ({:?}) failed: {}", strategy, e.reason)
+            ))?,
+        }
+        // The simulated device has no port to reopen
+        if conn.reset_strategy == Some(ResetStrategy::Full) && conn.simulator.is_none() {
+            let conn = port_guard.take().unwrap();
+            match reopen_connection(conn, &port_name) {
+                Ok(conn) => *port_guard = Some(conn),
+                Err(e) => {
+                    // The old handle is gone, so this is a disconnect
+                    *self.port_name.lock().unwrap() = None;
+                    self.reservations.lock().unwrap().clear();
+                    let error = CoreError::new(e.code, format!("Reset (Full) failed to reopen {}: {}", port_name, e.reason));
+                    self.emit_disconnected(Some(port_name), Some(&error), DisconnectKind::Forced);
+                    return Err(error);
+                }
+            }
+        }
+        drop(port_guard);
+
+        // Wait 500ms (matching C# Thread.Sleep(500))
+        std::thread::sleep(Duration::from_millis(RESET_WAIT_MS));
+        Ok(())
+    }
+
+    /// Set the DTR line level
+    #[napi]
+    pub fn set_dtr(&self, level: bool) -> Result<()> {
+        self.with_connection(|conn| set_dtr_locked(conn, level))
+    }
+
+    /// Set the RTS line level
+    #[napi]
+    pub fn set_rts(&self, level: bool) -> Result<()> {
+        self.with_connection(|conn| set_rts_locked(conn, level))
+    }
+
+    /// Recover from a framing error (e.g. "Invalid response magic header") without reconnecting
+    /// Discards whatever the device is still sending (for up to 2s, stopping once the
+    /// line has been quiet for 100ms), optionally resets the device and drains again,
+    /// then requires a clean INFO RESPONSE (see resync.rs).
+    /// Returns the number of stray bytes discarded.
+    #[napi]
+    pub fn resync(&self, options: Option<ResyncOptions>) -> Result<u32> {
+        self.with_connection(|conn| resync_locked(conn, &options.unwrap_or_default()))
+    }
+
+    /// Send command packet (matching C# SendCommand method)
+    /// Packet format: 512 bytes
+    /// - Bytes 0-3: "USBA" magic header (0x55, 0x53, 0x42, 0x41)
+    /// - Byte 4: opcode
+    /// - Byte 5: space
+    /// - Byte 6: flags
+    /// - Bytes 7-511: arguments/padding (format depends on opcode)
+    /// `command.timeout_ms` overrides the read timeout for this call only
+    /// Combinations that can't work fail with "InvalidCommand: ..." unless
+    /// set_command_validation(false) was called (see validation.rs)
+    /// See CommandRequest for the fields; a missing `opcode` or `space` is rejected
+    /// before the packet is built
+    #[napi]
+    pub fn send_command(&self, command: CommandRequest) -> Result<Vec<u8>> {
+        let CommandRequest { opcode, space, flags, args, timeout_ms } = command;
+        let (opcode, space, flags) = (opcode as u8, space as u8, protocol::check_flags(flags.unwrap_or(0))?);
+        if self.command_validation.load(Ordering::SeqCst) {
+            validation::validate_command(opcode, space, flags)?;
+        }
+        let args = args.map(|args| protocol::encode_args(opcode, space, args)).transpose()?;
+        let packet = build_packet(opcode, space, flags, args)?;
+        self.with_connection(|conn| match timeout_ms {
+            Some(ms) => with_timeout_locked(conn, Duration::from_millis(ms as u64), |conn| exchange(conn, &packet)),
+            None => exchange(conn, &packet),
+        })
+    }
+
+    /// Send a command whose argument layout send_command() can't express
+    /// Builds the USBA header and opcode/space/flags, then copies `extra` into the
+    /// packet at `extra_offset` (7..512, so the header can't be overwritten). Without
+    /// `expect_response` nothing is read and a zeroed packet is returned, as for NORESP;
+    /// only use that for commands the device doesn't answer, or the reply is left on the line.
+    /// The read cache is always invalidated, since the command's effect is unknown.
+    #[napi]
+    pub fn send_command_with_buffer(
+        &self,
+        opcode: u8,
+        space: u8,
+        flags: u8,
+        extra: Vec<u8>,
+        extra_offset: u32,
+        expect_response: bool,
+    ) -> Result<Vec<u8>> {
+        let offset = extra_offset as usize;
+        if offset < CUSTOM_ARGS_OFFSET || offset + extra.len() > PACKET_SIZE {
+            return Err(CoreError::new(ErrorCode::ArgValidation, format!(
+                "AddressOutOfRange: {} byte(s) at offset {} don't fit packet bytes {}..{}",
+                extra.len(), extra_offset, CUSTOM_ARGS_OFFSET, PACKET_SIZE
+            )));
+        }
+        let mut packet = vec![0u8; PACKET_SIZE];
+        packet[..4].copy_from_slice(b"USBA");
+        packet[4] = opcode;
+        packet[5] = space;
+        packet[6] = flags;
+        packet[offset..offset + extra.len()].copy_from_slice(&extra);
+
+        self.with_connection(|conn| {
+            conn.cache.lock().unwrap().invalidate();
+            if matches!(opcode, 8 | 9 | 10 | 12) {
+                conn.reservations.lock().unwrap().clear();
+            }
+            if expect_response {
+                return exchange_uncached(conn, &packet);
+            }
+
+            let started = Instant::now();
+            let result = send_packet_locked(conn, &packet);
+            conn.diagnostics.lock().unwrap().record_exchange(&packet, started.elapsed(), None, result.as_ref().err());
+            result.map(|_| vec![0u8; PACKET_SIZE])
+        })
+    }
+
+    /// Get port name
+    #[napi]
+    pub fn port_name(&self) -> Option<String> {
+        self.port_name.lock().unwrap().clone()
+    }
+
+    /// Raw bytes of the last 512-byte RESPONSE received (None if none since connect/reset)
+    /// Lets tools post-mortem a parsing failure without re-issuing the command
+    #[napi]
+    pub fn last_response(&self) -> Option<Vec<u8>> {
+        self.port.lock().unwrap()
+            .as_ref()
+            .and_then(|conn| conn.last_response.clone())
+    }
+
+    /// Read the modem control lines (CTS/DSR/CD/RI) plus our last DTR/RTS levels
+    /// Useful for "connects but nothing works" reports and for confirming DTR toggles
+    #[napi]
+    pub fn modem_status(&self) -> Result<ModemStatus> {
+        self.with_connection(|conn| {
+            let lines = conn.transport.modem_lines();
+            Ok(ModemStatus {
+                cts: lines.cts,
+                dsr: lines.dsr,
+                cd: lines.cd,
+                ri: lines.ri,
+                dtr: conn.dtr,
+                rts: conn.rts,
+            })
+        })
+    }
+
+    /// Serial line controls the connected transport supports
+    /// Only a real serial port on Unix or Windows drives DTR/RTS and reads the modem
+    /// lines; the simulator and the WebSocket, RetroArch, SNI and NWA backends don't.
+    /// A real port can still refuse them: pseudo-terminals and some virtual COM
+    /// drivers fail set_dtr() even where this reports support.
+    #[napi]
+    pub fn capabilities(&self) -> Result<Capabilities> {
+        self.with_connection(|conn| {
+            let lines = conn.transport.drives_modem_lines();
+            Ok(Capabilities {
+                dtr_control: lines,
+                rts_control: lines,
+                signal_read: lines,
+                break_support: false,
+            })
+        })
+    }
+
+    /// Send INFO and return its fields by name (answered from the read cache if enabled)
+    #[napi]
+    pub fn device_info(&self) -> Result<DeviceInfo> {
+        self.with_connection_replayable(Lane::Interactive, info_locked).map(DeviceInfo::from_fields)
+    }
+
+    /// Send INFO and return firmware_version, version_string, rom_running and the feature
+    /// flags as a DeviceInfo (same as device_info())
+    #[napi]
+    pub fn info(&self) -> Result<DeviceInfo> {
+        self.device_info()
+    }
+
+    /// Check whether a file or directory exists on the SD card
+    /// Lists the parent directory and looks for the final path component
+    /// A missing parent directory is reported as "does not exist", not as an error
+    #[napi]
+    pub fn file_exists(&self, path: String) -> Result<bool> {
+        Ok(self.lookup_entry(&path)?.is_some())
+    }
+
+    /// Remove a file or empty directory (RM opcode 6, FILE space)
+    #[napi]
+    pub fn remove(&self, path: String) -> Result<()> {
+        self.with_connection(|conn| path_command_locked(conn, 6, "RM", vec![path.clone()]))?;
+        self.journal.lock().unwrap().record(JournalOp::Remove, &path, None, None, None);
+        Ok(())
+    }
+
+    /// Create a single directory (MKDIR opcode 5, FILE space)
+    /// The parent directory must already exist - see mkdir_p()
+    #[napi]
+    pub fn mkdir(&self, path: String) -> Result<()> {
+        self.with_connection(|conn| path_command_locked(conn, 5, "MKDIR", vec![path]).map(|_| ()))
+    }
+
+    /// Boot a ROM from the SD card (BOOT opcode 9)
+    /// `timeout_ms` overrides the read timeout for this call only. `flags` takes
+    /// Flags.SkipReset (load without resetting the SNES) or Flags.OnlyReset (reset the
+    /// SNES, `path` isn't loaded).
+    #[napi]
+    pub fn boot(&self, path: String, timeout_ms: Option<u32>, flags: Option<u32>) -> Result<()> {
+        let path = normalize_path(&path)?;
+        let flags = protocol::method_flags("boot", flags, SKIPRESET_FLAG | ONLYRESET_FLAG)?;
+        validation::validate_method_flags("boot", 9, SPACE_FILE, flags)?;
+        self.with_connection(|conn| {
+            let boot = |conn: &mut Connection| {
+                let packet = build_packet(9, SPACE_FILE, flags, Some(vec![path.clone()]))?;
+                let response = exchange(conn, &packet)?;
+                check_device_error(&response, "BOOT", &path)
+            };
+            match timeout_ms {
+                Some(ms) => with_timeout_locked(conn, Duration::from_millis(ms as u64), boot),
+                None => boot(conn),
+            }
+        })
+    }
+
+    /// Return to the menu (MENU_RESET opcode 12, sent with NORESP)
+    /// With Flags.OnlyReset in `flags` the running game is reset instead, and with
+    /// Flags.SkipReset the menu is loaded without resetting the SNES. Returns once the
+    /// command is sent; the device doesn't answer it.
+    #[napi]
+    pub fn menu_reset(&self, flags: Option<u32>) -> Result<()> {
+        let flags = protocol::method_flags("menu_reset", flags, SKIPRESET_FLAG | ONLYRESET_FLAG)?;
+        validation::validate_method_flags("menu_reset", 12, SPACE_SNES, flags | NORESP_FLAG)?;
+        self.with_connection(|conn| menu_reset_locked(conn, flags))
+    }
+
+    /// Download a whole file from the SD card (GET, FILE space)
+    /// With `compute_crc32` the CRC32 of the received bytes is returned alongside
+    /// them ({ data, crc32 }), computed block by block as the data arrives
+    #[napi(ts_return_type = "Buffer | FileDownload")]
+    pub fn get_file(&self, path: String, options: Option<TransferOptions>) -> Result<Either<Buffer, FileDownload>> {
+        let path = normalize_path(&path)?;
+        let compute_crc32 = options.and_then(|o| o.compute_crc32).unwrap_or(false);
+        if !compute_crc32 {
+            return self.with_connection_replayable(Lane::Bulk, |conn| get_file_locked(conn, &path))
+                .map(|data| Either::A(data.into()));
+        }
+
+        let mut data = Vec::new();
+        let mut hasher = crc32fast::Hasher::new();
+        self.with_connection_replayable(Lane::Bulk, |conn| {
+            data.clear();
+            hasher = crc32fast::Hasher::new();
+            download_file_locked(conn, &path, |block| {
+                hasher.update(block);
+                data.extend_from_slice(block);
+                Ok(())
+            })
+        })?;
+        Ok(Either::B(FileDownload { data: data.into(), crc32: hasher.finalize() }))
+    }
+
+    /// Upload a whole file to the SD card (PUT, FILE space)
+    #[napi]
+    pub fn put_file(&self, path: String, data: Buffer) -> Result<()> {
+        self.put_file_with(path, &data, None)
+    }
+
+    /// put_file(), stopped by `cancel` if given (see cancel.rs)
+    pub(crate) fn put_file_with(&self, path: String, data: &[u8], cancel: Option<&CancelToken>) -> Result<()> {
+        let path = normalize_path(&path)?;
+        self.with_connection_in(Lane::Bulk, |conn| match cancel {
+            Some(cancel) => put_file_cancellable_locked(conn, &path, data, cancel, |_| Ok(())),
+            None => put_file_locked(conn, &path, data),
+        })?;
+        self.journal.lock().unwrap().record(JournalOp::Put, &path, None, Some(data.len() as u32), None);
+        Ok(())
+    }
+
+    /// Download a file from the SD card straight to a host path
+    /// The data phase is streamed to disk block by block; returns the byte count.
+    /// The file only replaces an existing one at `host_path` once the download is complete.
+    /// Host filesystem failures are reported as "HostIoError: ..." so callers can
+    /// tell them apart from device/protocol errors.
+    /// With `compute_crc32` the result is { size, crc32 } instead of the byte count.
+    #[napi(ts_return_type = "number | FileTransfer")]
+    pub fn download_to(
+        &self,
+        device_path: String,
+        host_path: String,
+        options: Option<TransferOptions>,
+    ) -> Result<Either<u32, FileTransfer>> {
+        let device_path = normalize_path(&device_path)?;
+        let compute_crc32 = options.and_then(|o| o.compute_crc32).unwrap_or(false);
+        let mut hasher = crc32fast::Hasher::new();
+        let size = self.download_host_file(&device_path, &host_path, |block, _| {
+            if compute_crc32 {
+                hasher.update(block);
+            }
+            Ok(())
+        })?;
+        if compute_crc32 {
+            Ok(Either::B(FileTransfer { size, crc32: hasher.finalize() }))
+        } else {
+            Ok(Either::A(size))
+        }
+    }
+
+    /// download_to() with `on_block(block, total)` called after each block is written
+    /// `device_path` must already be normalized
+    pub(crate) fn download_host_file(
+        &self,
+        device_path: &str,
+        host_path: &str,
+        mut on_block: impl FnMut(&[u8], u32) -> Result<()>,
+    ) -> Result<u32> {
+        // Download into a temporary file next to the target, so a failed transfer never
+        // touches an existing file there (e.g. the previous good backup). Creating it
+        // first also means a bad host path never starts a transfer.
+        let temp_path = host_temp_path(host_path);
+        let temp = temp_path.to_string_lossy();
+        let file = File::create(&temp_path).map_err(|e| host_io_error("create", &temp, e))?;
+        let mut writer = BufWriter::new(file);
+
+        let result = self.with_connection_in(Lane::Bulk, |conn| {
+            download_file_sized_locked(conn, device_path, |block, total| {
+                writer.write_all(block).map_err(|e| host_io_error("write", &temp, e))?;
+                on_block(block, total)
+            })
+        }).and_then(|size| {
+            writer.flush().map_err(|e| host_io_error("write", &temp, e))?;
+            drop(writer);
+            std::fs::rename(&temp_path, host_path).map_err(|e| host_io_error("replace", host_path, e))?;
+            Ok(size)
+        });
+
+        // Only the temporary file goes on failure; the target is left as it was
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    /// Upload a host file to the SD card, streaming it from disk block by block
+    /// Returns the byte count; host filesystem failures are "HostIoError: ..."
+    #[napi]
+    pub fn upload_from(&self, host_path: String, device_path: String) -> Result<u32> {
+        let device_path = normalize_path(&device_path)?;
+        self.upload_host_file(&host_path, &device_path, false, |_, _| Ok(()))
+    }
+
+    /// upload_from() with `on_block(sent, total)` called after each 512-byte block
+    /// `device_path` must already be normalized. With `strip_copier_header` a ROM's
+    /// copier header (see copier.rs) is skipped and not counted in the total.
+    pub(crate) fn upload_host_file(
+        &self,
+        host_path: &str,
+        device_path: &str,
+        strip_copier_header: bool,
+        mut on_block: impl FnMut(u32, u32) -> Result<()>,
+    ) -> Result<u32> {
+        let mut file = File::open(host_path).map_err(|e| host_io_error("open", host_path, e))?;
+        let mut len = file.metadata().map_err(|e| host_io_error("stat", host_path, e))?.len();
+        if strip_copier_header {
+            let skip = copier::copier_header_len(len) as u64;
+            file.seek(SeekFrom::Start(skip)).map_err(|e| host_io_error("seek", host_path, e))?;
+            len -= skip;
+        }
+        let size = u32::try_from(len).map_err(|_| CoreError::new(ErrorCode::IoError,
+            format!("HostIoError: {} is {} bytes, larger than the 4GB transfer limit", host_path, len)
+        ))?;
+        let mut reader = BufReader::new(file);
+        let mut sent = 0u32;
+
+        self.with_connection_in(Lane::Bulk, |conn| {
+            upload_file_locked(conn, device_path, size, |block| {
+                reader.read_exact(block).map_err(|e| host_io_error("read", host_path, e))?;
+                sent += block.len() as u32;
+                on_block(sent, size)
+            })
+        })?;
+        self.journal.lock().unwrap().record(JournalOp::Put, device_path, None, Some(size), None);
+        Ok(size)
+    }
+
+    /// Rename/move a file (MV opcode 7)
+    /// Both paths are validated against the packet limits (247 bytes for the source,
+    /// 255 for the destination) instead of being silently truncated.
+    /// Some firmware rejects moves between directories; with `allow_copy_fallback`
+    /// the file is then downloaded, uploaded atomically to the destination and only
+    /// then removed from the source. The result reports which strategy was used.
+    #[napi]
+    pub fn rename(&self, from: String, to: String, options: Option<RenameOptions>) -> Result<RenameResult> {
+        let from = normalize_path(&from)?;
+        let to = normalize_path_with_limit(&to, MAX_MV_DEST_PATH_LEN)?;
+        let allow_copy_fallback = options.and_then(|o| o.allow_copy_fallback).unwrap_or(false);
+
+        self.with_connection(|conn| {
+            let packet = build_packet(7, SPACE_FILE, 0, Some(vec![from.clone(), to.clone()]))?;
+            let response = exchange(conn, &packet)?;
+            if response[5] == 0 {
+                conn.journal.lock().unwrap().record(JournalOp::Move, &from, Some(&to), None, None);
+                return Ok(RenameResult { strategy: RenameStrategy::Move });
+            }
+
+            let cross_directory = split_path(&from).0 != split_path(&to).0;
+            if !(allow_copy_fallback && cross_directory) {
+                return check_device_error(&response, "MV", &from)
+                    .map(|_| RenameResult { strategy: RenameStrategy::Move });
+            }
+
+            // Source is only removed once the destination is complete
+            let data = get_file_locked(conn, &from)?;
+            put_file_atomic_locked(conn, &to, &data)?;
+            path_command_locked(conn, 6, "RM", vec![from.clone()])?;
+            conn.journal.lock().unwrap().record(JournalOp::Move, &from, Some(&to), Some(data.len() as u32), None);
+            Ok(RenameResult { strategy: RenameStrategy::CopyDelete })
+        })
+    }
+
+    /// Remove a file only if it is present
+    /// Returns true if the path existed and was removed, false if it was not found
+    /// Failures of the RM itself are still returned as errors
+    #[napi]
+    pub fn remove_if_exists(&self, path: String) -> Result<bool> {
+        if !self.file_exists(path.clone())? {
+            return Ok(false);
+        }
+
+        self.remove(path)?;
+        Ok(true)
+    }
+
+    /// Create a directory and any missing parents (like `mkdir -p`)
+    /// Components that already exist as directories are skipped
+    #[napi]
+    pub fn mkdir_p(&self, path: String) -> Result<()> {
+        let mut current = String::new();
+
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            current.push('/');
+            current.push_str(component);
+
+            match self.lookup_entry(&current)? {
+                Some(LS_TYPE_DIR) => continue,
+                Some(_) => {
+                    return Err(CoreError::new(ErrorCode::DeviceError,
+                        format!("MKDIR failed for {}: a file with that name already exists", current)
+                    ));
+                }
+                None => {
+                    if let Err(e) = self.mkdir(current.clone()) {
+                        // Tolerate "already exists" (e.g. created between LS and MKDIR)
+                        if self.lookup_entry(&current)? != Some(LS_TYPE_DIR) {
+                            return Err(e);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove several paths in one call
+    /// All paths are validated and normalized before the first RM is sent; RMs are then
+    /// issued sequentially and the batch keeps going after individual failures
+    /// `on_progress` is called with an ItemProgress after each item
+    #[napi]
+    pub fn remove_many(
+        &self,
+        paths: Vec<String>,
+        options: Option<RemoveManyOptions>,
+        on_progress: Option<JsFunction>,
+    ) -> Result<Vec<RemoveResult>> {
+        let ignore_not_found = options.and_then(|o| o.ignore_not_found).unwrap_or(false);
+        let normalized = paths.iter()
+            .map(|path| normalize_path(path))
+            .collect::<Result<Vec<String>>>()?;
+
+        let total = normalized.len() as u32;
+        let mut results = Vec::with_capacity(normalized.len());
+
+        for (index, path) in normalized.into_iter().enumerate() {
+            let outcome = match self.remove(path.clone()) {
+                Ok(()) => Ok(()),
+                Err(e) => match self.file_exists(path.clone()) {
+                    Ok(false) if ignore_not_found => Ok(()),
+                    Ok(false) => Err(format!("Not found: {}", path)),
+                    _ => Err(e.reason),
+                },
+            };
+
+            if let Some(callback) = on_progress.as_ref() {
+                callback.call1::<ItemProgress, JsUnknown>(ItemProgress {
+                    index: index as u32,
+                    total,
+                    path: path.clone(),
+                })?;
+            }
+
+            results.push(RemoveResult {
+                path,
+                ok: outcome.is_ok(),
+                error: outcome.err(),
+            });
+        }
+
+        Ok(results)
+    }
+
+    /// Conditionally write memory (compare-and-swap style)
+    /// Reads `expected.len()` bytes at `address` and only PUTs `new_data` if they match
+    /// The GET and PUT run back-to-back while holding the port, so no other command
+    /// can be interleaved between the read and the write.
+    /// NOTE: this is best-effort, NOT atomic with respect to the running game - the
+    /// SNES can still change the value between the two commands.
+    /// `retries` repeats the read-compare step (about one frame apart) on a mismatch
+    /// Returns true if the write happened
+    #[napi]
+    pub fn compare_and_write(
+        &self,
+        space: u8,
+        address: u32,
+        expected: Buffer,
+        new_data: Buffer,
+        retries: Option<u32>,
+    ) -> Result<bool> {
+        if expected.is_empty() || expected.len() != new_data.len() {
+            return Err(CoreError::new(ErrorCode::ArgValidation,
+                format!("compare_and_write: expected ({} bytes) and new data ({} bytes) must be the same non-zero length",
+                    expected.len(), new_data.len())
+            ));
+        }
+
+        for attempt in 0..=retries.unwrap_or(0) {
+            if attempt > 0 {
+                std::thread::sleep(Duration::from_millis(FRAME_MS));
+            }
+
+            let written = self.with_connection(|conn| {
+                let current = get_locked(conn, space, address, expected.len() as u32)?;
+                if current[..] != expected[..] {
+                    return Ok(false);
+                }
+                put_locked(conn, space, address, &new_data)?;
+                Ok(true)
+            })?;
+            if written {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Read `read.size` bytes of memory at `read.address` (GET, SNES space unless
+    /// `read.space` is given)
+    /// The data phase following the RESPONSE is read in full, its length taken from the
+    /// RESPONSE size field. Answered from the read cache when it's enabled.
+    /// Flags.Data64B in `read.flags` uses 64-byte blocks for this call whatever the
+    /// ConnectOptions.block_size. `cancel` stops the read at the next block (see cancel.rs).
+    #[napi]
+    pub fn get_memory(&self, read: MemoryRead, cancel: Option<&CancelToken>) -> Result<Buffer> {
+        let flags = protocol::method_flags("get_memory", read.flags, DATA64B_FLAG)?;
+        self.get_memory_with(read.address, read.size, read.space, flags, cancel).map(Buffer::from)
+    }
+
+    /// get_memory() with checked `flags`, stopped by `cancel` if given (see cancel.rs)
+    pub(crate) fn get_memory_with(
+        &self,
+        address: u32,
+        size: u32,
+        space: Option<u8>,
+        flags: u8,
+        cancel: Option<&CancelToken>,
+    ) -> Result<Vec<u8>> {
+        let space = space.unwrap_or(SPACE_SNES);
+        if space == SPACE_FILE {
+            return Err(CoreError::new(ErrorCode::ArgValidation, "get_memory: FILE space is addressed by path, use get_file()"));
+        }
+        validate_address_range(space, address, size)?;
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        self.with_connection_replayable(Lane::Interactive, |conn| match cancel {
+            Some(cancel) => get_cancellable_locked(conn, space, flags, address, size, cancel),
+            None => get_flagged_locked(conn, space, flags, address, size),
+        })
+    }
+
+    /// Write `data` to memory at `address` (PUT, SNES space unless `space` is given)
+    /// The data phase is sent in 512-byte blocks (64 with ConnectOptions.block_size),
+    /// the last one zero-padded. Fails if the
+    /// RESPONSE reports an error or acknowledges a different size ("ShortWrite: ...").
+    /// `flags` takes Flags.Data64B (as for get_memory()), and in the CMD space (3)
+    /// Flags.ClrX or Flags.SetX to clear or set the execute bit with the write.
+    #[napi]
+    pub fn put_memory(&self, address: u32, data: Buffer, space: Option<u8>, flags: Option<u32>) -> Result<()> {
+        let space = space.unwrap_or(SPACE_SNES);
+        if space == SPACE_FILE {
+            return Err(CoreError::new(ErrorCode::ArgValidation, "put_memory: FILE space is addressed by path, use put_file()"));
+        }
+        let flags = protocol::method_flags("put_memory", flags, DATA64B_FLAG | CLRX_FLAG | SETX_FLAG)?;
+        validation::validate_method_flags("put_memory", 1, space, flags)?;
+        validate_address_range(space, address, data.len() as u32)?;
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.with_connection_in(Lane::Interactive, |conn| put_flagged_locked(conn, space, flags, address, &data))
+    }
+
+    /// Look up the LS type byte of a path (None if the path or its parent doesn't exist)
+    fn lookup_entry(&self, path: &str) -> Result<Option<u8>> {
+        self.with_connection_replayable(Lane::Normal, |conn| lookup_entry_locked(conn, path))
+    }
+
+    /// Run `f` with the connection locked, so nothing can interleave with its commands
+    /// Fails with "Cancelled: ..." while a disconnect is in progress, including for
+    /// callers that were already waiting for the lock when it started
+    pub(crate) fn with_connection<T>(&self, f: impl FnOnce(&mut Connection) -> Result<T>) -> Result<T> {
+        self.with_connection_in(Lane::Normal, f)
+    }
+
+    /// with_connection(), queued in `lane` behind other waiting callers (see queue.rs)
+    /// Fails with DeviceBusy while a stream owns the port.
+    pub(crate) fn with_connection_in<T>(&self, lane: Lane, f: impl FnOnce(&mut Connection) -> Result<T>) -> Result<T> {
+        if self.is_streaming() {
+            return Err(stream_busy_error());
+        }
+        self.with_port_in(lane, f)
+    }
+
+    /// with_connection_in() without the stream check, for the stream itself
+    pub(crate) fn with_port_in<T>(&self, lane: Lane, f: impl FnOnce(&mut Connection) -> Result<T>) -> Result<T> {
+        if self.closing.load(Ordering::SeqCst) {
+            return Err(cancelled_error());
+        }
+        let _turn = self.queue.wait_turn(lane);
+        let mut port_guard = self.port.lock().unwrap();
+        self.close_if_abandoned(&mut port_guard);
+        if self.closing.load(Ordering::SeqCst) {
+            return Err(cancelled_error());
+        }
+        let conn = port_guard.as_mut()
+            .ok_or_else(|| CoreError::new(ErrorCode::NotConnected, "Not connected"))?;
+        let result = f(conn);
+        if let Err(e) = &result {
+            self.diagnostics.lock().unwrap().record_error(e);
+            if e.reason.starts_with(DEVICE_DISCONNECTED) {
+                self.lose_connection(&mut port_guard, Some(e));
+            } else {
+                self.emit_command_error(e);
+                self.supervise_failure(&mut port_guard, e);
+            }
+        }
+        self.close_if_abandoned(&mut port_guard);
+        result
+    }
+
+    /// Close the port a timed-out disconnect() left to whoever holds it next
+    fn close_if_abandoned(&self, port_guard: &mut Option<Connection>) {
+        if self.abandoned.swap(false, Ordering::SeqCst) {
+            port_guard.take();
+        }
+    }
+
+}
+
+impl Default for Usb2SnesCore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// LS entry type for a directory (0 = file, 1 = dir)
+pub(crate) const LS_TYPE_DIR: u8 = 1;
+
+/// FILE/SNES space bytes and the end of the SNES space 24-bit address window
+pub(crate) const SPACE_FILE: u8 = 0;
+pub(crate) const SPACE_SNES: u8 = 1;
+
+/// CMD space byte (see cmd.rs)
+pub(crate) const SPACE_CMD: u8 = 3;
+const SNES_SPACE_END: u64 = 0x100_0000;
+
+/// One NTSC frame, used as the delay between polling retries
+pub(crate) const FRAME_MS: u64 = 16;
+
+/// VGET/VPUT (size, address) pairs: 5 bytes each starting at byte 32, at most 8
+pub(crate) const VGET_PAIRS_OFFSET: usize = 32;
+pub(crate) const VGET_PAIR_LEN: usize = 5;
+pub(crate) const VGET_MAX_PAIRS: usize = 8;
+/// End of the pair region; the path/size fields start at 252
+const VGET_PAIRS_END: usize = 252;
+const _: () = assert!(VGET_PAIRS_OFFSET + VGET_MAX_PAIRS * VGET_PAIR_LEN <= VGET_PAIRS_END);
+
+/// Serial read timeout (matching C# ReadTimeout = 5000ms)
+pub(crate) const READ_TIMEOUT_MS: u64 = 5000;
+
+/// RESPONSE timeout for connect-time verification
+const VERIFY_TIMEOUT_MS: u64 = 1000;
+
+/// DTR/RTS pulse length and post-reset wait (matching C# Thread.Sleep(500))
+const RESET_WAIT_MS: u64 = 500;
+
+/// SKIPRESET command flag: BOOT/MENU_RESET load without resetting the SNES
+pub(crate) const SKIPRESET_FLAG: u8 = 0x01;
+
+/// ONLYRESET command flag: BOOT/MENU_RESET reset the SNES without loading anything
+pub(crate) const ONLYRESET_FLAG: u8 = 0x02;
+
+/// CLRX/SETX command flags: a CMD-space PUT clears/sets the execute bit
+pub(crate) const CLRX_FLAG: u8 = 0x04;
+pub(crate) const SETX_FLAG: u8 = 0x08;
+
+/// STREAM_BURST command flag
+pub(crate) const STREAM_BURST_FLAG: u8 = 0x10;
+
+/// NORESP command flag: the device sends no RESPONSE packet
+pub(crate) const NORESP_FLAG: u8 = 0x40;
+
+/// DATA64B command flag: the data phase uses 64-byte blocks instead of 512 (required by VGET)
+pub(crate) const DATA64B_FLAG: u8 = 0x80;
+
+/// resync(): give up draining after this long, or once the line is quiet this long
+const RESYNC_DRAIN_MS: u64 = 2000;
+const RESYNC_QUIET_MS: u64 = 100;
+
+/// How long disconnect() waits for the running command by default
+const DEFAULT_DISCONNECT_GRACE_MS: u32 = 2000;
+
+/// Size of every command and RESPONSE packet
+pub(crate) const PACKET_SIZE: usize = 512;
+
+/// Error code of writes that failed because the device went away (see transport.rs)
+pub(crate) const DEVICE_DISCONNECTED: &str = "DeviceDisconnected";
+
+/// First packet byte send_command_with_buffer() may write (bytes 0-6 are the header)
+const CUSTOM_ARGS_OFFSET: usize = 7;
+
+/// Maximum encoded length of a path argument (bytes 8-255 of the packet)
+pub(crate) const MAX_PATH_LEN: usize = 247;
+
+/// Maximum encoded length of the MV destination path (bytes 256-511 of the packet)
+pub(crate) const MAX_MV_DEST_PATH_LEN: usize = 255;
+
+/// Split a normalized path into (parent directory, final component)
+pub(crate) fn split_path(path: &str) -> (&str, &str) {
+    let trimmed = path.trim_end_matches('/');
+    match trimmed.rfind('/') {
+        Some(0) => ("/", &trimmed[1..]),
+        Some(i) => (&trimmed[..i], &trimmed[i + 1..]),
+        None => ("/", trimmed),
+    }
+}
+
+/// Send a FILE-space path command (LS/MKDIR/RM/MV/BOOT) and check the device error byte
+pub(crate) fn path_command_locked(conn: &mut Connection, opcode: u8, name: &str, args: Vec<String>) -> Result<Vec<u8>> {
+    let path = args[0].clone();
+    let packet = build_packet(opcode, SPACE_FILE, 0, Some(args))?;
+    let response = exchange(conn, &packet)?;
+    check_device_error(&response, name, &path)?;
+    Ok(response)
+}
+
+/// MENU_RESET with NORESP and `flags` (SKIPRESET/ONLYRESET) on an already-locked port
+pub(crate) fn menu_reset_locked(conn: &mut Connection, flags: u8) -> Result<()> {
+    let packet = build_packet(12, SPACE_SNES, NORESP_FLAG | flags, None)?;
+    exchange(conn, &packet).map(|_| ())
+}
+
+/// List a directory (LS opcode 4, FILE space)
+/// Returns None if the device reports an error (directory not found)
+pub(crate) fn list_dir_locked(conn: &mut Connection, path: &str) -> Result<Option<Vec<(u8, String)>>> {
+    let packet = build_packet(4, SPACE_FILE, 0, Some(vec![path.to_string()]))?;
+    let response = exchange(conn, &packet)?;
+    if response[5] != 0 {
+        return Ok(None);
+    }
+
+    // Listing follows the RESPONSE in 512-byte data blocks. A block that is used to
+    // the end without a terminator continues in the next one, possibly mid-entry,
+    // so the unparsed tail is carried over and parsed together with the next block.
+    let mut entries = Vec::new();
+    let mut pending = Vec::with_capacity(1024);
+    loop {
+        if conn.abandoned.load(Ordering::SeqCst) {
+            return Err(cancelled_error());
+        }
+        let mut block = [0u8; 512];
+        conn.transport.read_data(&mut block, conn.read_deadlines)?;
+        pending.extend_from_slice(&block);
+
+        let (mut parsed, cursor, terminated) = parse_ls_response_internal(&pending);
+        entries.append(&mut parsed);
+        if terminated || is_ls_padding(&pending, cursor) {
+            return Ok(Some(entries));
+        }
+        pending.drain(..cursor);
+    }
+}
+
+/// Look up the LS type byte of a path by listing its parent directory
+/// Returns None if the path (or its parent) does not exist
+pub(crate) fn lookup_entry_locked(conn: &mut Connection, path: &str) -> Result<Option<u8>> {
+    let (parent, name) = split_path(path);
+
+    // The root directory always exists
+    if name.is_empty() {
+        return Ok(Some(LS_TYPE_DIR));
+    }
+
+    let entries = match list_dir_locked(conn, parent)? {
+        Some(entries) => entries,
+        None => return Ok(None),
+    };
+
+    Ok(entries.into_iter()
+        .find(|(_, filename)| filename == name)
+        .map(|(file_type, _)| file_type))
+}
+
+/// GET a whole file from the SD card (FILE space), including the data phase
+pub(crate) fn get_file_locked(conn: &mut Connection, path: &str) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    download_file_locked(conn, path, |block| {
+        data.extend_from_slice(block);
+        Ok(())
+    })?;
+    Ok(data)
+}
+
+/// GET a file from the SD card, handing each data block to `sink` as it arrives
+/// A sink failure doesn't abort the data phase: the remaining blocks are still
+/// drained so the device is left ready for the next command. Returns the file size.
+pub(crate) fn download_file_locked(
+    conn: &mut Connection,
+    path: &str,
+    mut sink: impl FnMut(&[u8]) -> Result<()>,
+) -> Result<u32> {
+    download_file_sized_locked(conn, path, |block, _| sink(block))
+}
+
+/// download_file_locked() that also hands `sink` the file size with each block
+pub(crate) fn download_file_sized_locked(
+    conn: &mut Connection,
+    path: &str,
+    mut sink: impl FnMut(&[u8], u32) -> Result<()>,
+) -> Result<u32> {
+    let packet = build_packet(0, SPACE_FILE, 0, Some(vec![path.to_string()]))?;
+    let response = exchange(conn, &packet)?;
+    check_device_error(&response, "GET", path)?;
+
+    let size = parse_get_response(response)?;
+    let mut sink_error = None;
+    read_data_with(conn, size as usize, 512, |block| {
+        if sink_error.is_none() {
+            sink_error = sink(block, size).err();
+        }
+    })?;
+
+    match sink_error {
+        Some(e) => Err(e),
+        None => Ok(size),
+    }
+}
+
+/// PUT a file of `size` bytes to the SD card, filling each data block from `source`
+/// If `source` fails mid-transfer the remaining blocks are sent zeroed so the
+/// device isn't left waiting for data, then the source error is returned
+pub(crate) fn upload_file_locked(
+    conn: &mut Connection,
+    path: &str,
+    size: u32,
+    mut source: impl FnMut(&mut [u8]) -> Result<()>,
+) -> Result<()> {
+    let packet = build_packet(1, SPACE_FILE, 0, Some(vec![path.to_string(), format!("{:X}", size)]))?;
+    let response = exchange(conn, &packet)?;
+    check_device_error(&response, "PUT", path)?;
+    check_put_size(conn, &response, size, SPACE_FILE, path)?;
+
+    let mut source_error = None;
+    write_data_with(conn, size as usize, |block| {
+        if source_error.is_none() {
+            source_error = source(block).err();
+        }
+    })?;
+
+    match source_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// PUT a whole file to the SD card (FILE space), including the data phase
+pub(crate) fn put_file_locked(conn: &mut Connection, path: &str, data: &[u8]) -> Result<()> {
+    let packet = build_packet(1, SPACE_FILE, 0, Some(vec![path.to_string(), format!("{:X}", data.len())]))?;
+    let response = exchange(conn, &packet)?;
+    check_device_error(&response, "PUT", path)?;
+    check_put_size(conn, &response, data.len() as u32, SPACE_FILE, path)?;
+
+    write_data_locked(conn, data)
+}
+
+/// Upload to a temporary file next to `path`, then MV it into place
+/// A failed transfer only ever leaves the temporary file behind (and removes it),
+/// so an existing destination is not replaced until the new data is complete
+pub(crate) fn put_file_atomic_locked(conn: &mut Connection, path: &str, data: &[u8]) -> Result<()> {
+    let (dir, name) = split_path(path);
+    let temp = if dir == "/" {
+        format!("/.{}.part", name)
+    } else {
+        format!("{}/.{}.part", dir, name)
+    };
+
+    if let Err(e) = put_file_locked(conn, &temp, data) {
+        let _ = path_command_locked(conn, 6, "RM", vec![temp]);
+        return Err(e);
+    }
+
+    // MV won't replace an existing file on FAT, so clear the destination first
+    // (moving it aside as a backup if the journal keeps them)
+    let replaced = lookup_entry_locked(conn, path)?.is_some();
+    let backup = if replaced {
+        let backup = journal::back_up_locked(conn, path)?;
+        if backup.is_none() {
+            path_command_locked(conn, 6, "RM", vec![path.to_string()])?;
+        }
+        backup
+    } else {
+        None
+    };
+    path_command_locked(conn, 7, "MV", vec![temp, path.to_string()])?;
+    if replaced {
+        conn.journal.lock().unwrap().record(JournalOp::Overwrite, path, None, Some(data.len() as u32), backup);
+    }
+    Ok(())
+}
+
+fn set_dtr_locked(conn: &mut Connection, level: bool) -> Result<()> {
+    conn.transport.set_dtr(level)
+        .map_err(|e| CoreError::new(ErrorCode::IoError, format!("Failed to set DTR: {}", e)))?;
+    conn.dtr = Some(level);
+    Ok(())
+}
+
+fn set_rts_locked(conn: &mut Connection, level: bool) -> Result<()> {
+    conn.transport.set_rts(level)
+        .map_err(|e| CoreError::new(ErrorCode::IoError, format!("Failed to set RTS: {}", e)))?;
+    conn.rts = Some(level);
+    Ok(())
+}
+
+/// Drop the selected lines together for RESET_WAIT_MS, then raise them again
+fn pulse_lines_locked(conn: &mut Connection, dtr: bool, rts: bool) -> Result<()> {
+    if dtr {
+        set_dtr_locked(conn, false)?;
+    }
+    if rts {
+        set_rts_locked(conn, false)?;
+    }
+    std::thread::sleep(Duration::from_millis(RESET_WAIT_MS));
+    if dtr {
+        set_dtr_locked(conn, true)?;
+    }
+    if rts {
+        set_rts_locked(conn, true)?;
+    }
+    Ok(())
+}
+
+/// Open a serial port with exact C# settings
+/// A "ws://" port name opens the usb2snes WebSocket client backend instead (see
+/// wsclient.rs), a "retroarch://" one the RetroArch backend (see retroarch.rs), an
+/// "sni://" one the SNI backend (see sni.rs) and an "nwa://" one the NWA backend (see nwa.rs)
+fn open_serial_port(port_name: &str) -> Result<Box<dyn Transport>> {
+    if wsclient::is_ws_uri(port_name) {
+        return Ok(Box::new(SerialTransport::emulated(wsclient::open_ws_port(port_name)?)));
+    }
+    if retroarch::is_retroarch_uri(port_name) {
+        return retroarch::open_retroarch(port_name);
+    }
+    if sni::is_sni_uri(port_name) {
+        return sni::open_sni(port_name);
+    }
+    if nwa::is_nwa_uri(port_name) {
+        return nwa::open_nwa(port_name);
+    }
+    // DTR is driven once the port is open (see attach_port())
+    let builder = serialport::new(port_name, 9600)
+        .data_bits(serialport::DataBits::Eight)
+        .stop_bits(serialport::StopBits::One)
+        .parity(serialport::Parity::None)
+        .flow_control(serialport::FlowControl::None); // Handshake.None = no flow control!
+
+    // Set timeouts (matching C# ReadTimeout/WriteTimeout = 5000ms)
+    // serialport 4.x uses timeout() for both read and write
+    let builder = builder.timeout(Duration::from_millis(READ_TIMEOUT_MS));
+
+    let port = builder.open()
+        .map_err(|e| CoreError::new(ErrorCode::IoError,
+            format!("Failed to open serial port {}: {}", port_name, e)
+        ))?;
+    Ok(Box::new(SerialTransport::new(port)))
+}
+
+/// Perform one reset strategy (the post-reset settle wait is left to the caller)
+fn reset_locked(conn: &mut Connection, strategy: ResetStrategy) -> Result<()> {
+    let send_reset_opcode = |conn: &mut Connection| {
+        let packet = build_packet(8, SPACE_SNES, NORESP_FLAG, None)?;
+        exchange(conn, &packet).map(|_| ())
+    };
+    match strategy {
+        ResetStrategy::DtrPulse => pulse_lines_locked(conn, true, false),
+        ResetStrategy::RtsPulse => pulse_lines_locked(conn, false, true),
+        ResetStrategy::Opcode => send_reset_opcode(conn),
+        // The reopen half is done by reset(), which owns the connection slot
+        ResetStrategy::Combined | ResetStrategy::Full => {
+            pulse_lines_locked(conn, true, true)?;
+            send_reset_opcode(conn)
+        }
+    }
+}
+
+/// Close a connection's port and open it again, keeping the connection's settings and
+/// restoring its DTR/RTS levels (the old handle must go first: ports open exclusively)
+fn reopen_connection(conn: Connection, port_name: &str) -> Result<Connection> {
+    let Connection {
+        transport, dtr, rts, diagnostics, cache, reservations, session, chunking, journal, timeouts, reset_strategy,
+        auto_resync, block_flags, abandoned, ..
+    } = conn;
+    drop(transport);
+    // Let the OS release (and possibly re-enumerate) the device before reopening
+    std::thread::sleep(Duration::from_millis(RESET_WAIT_MS));
+
+    let transport = open_serial_port(port_name)?;
+    let mut conn = Connection::new(transport, diagnostics, cache, reservations, session, chunking, journal);
+    conn.timeouts = timeouts;
+    conn.reset_strategy = reset_strategy;
+    conn.auto_resync = auto_resync;
+    conn.block_flags = block_flags;
+    conn.abandoned = abandoned;
+    if let Some(dtr) = dtr {
+        set_dtr_locked(&mut conn, dtr)?;
+    }
+    if let Some(rts) = rts {
+        set_rts_locked(&mut conn, rts)?;
+    }
+    Ok(conn)
+}
+
+/// Read and discard pending input until the line is quiet or RESYNC_DRAIN_MS passes
+pub(crate) fn drain_input_locked(conn: &mut Connection) -> Result<u32> {
+    let result = conn.transport.drain_input(
+        Duration::from_millis(RESYNC_QUIET_MS),
+        Duration::from_millis(RESYNC_DRAIN_MS),
+    );
+    let _ = conn.transport.set_timeout(conn.port_timeout);
+    result
+}
+
+/// Send INFO and parse the reply (see parse_info_response() for the field order)
+pub(crate) fn info_locked(conn: &mut Connection) -> Result<Vec<String>> {
+    let packet = build_packet(11, SPACE_FILE, 0, None)?;
+    let response = exchange(conn, &packet)?;
+    parse_info_response(response)
+}
+
+/// Check that the port speaks USB2SNES: one INFO with a short RESPONSE timeout
+fn verify_fxpak_locked(conn: &mut Connection, timeout: Duration) -> Result<()> {
+    // A cached INFO would prove nothing about the line right now
+    conn.cache.lock().unwrap().invalidate();
+    let packet = build_packet(11, SPACE_FILE, 0, None)?;
+    with_timeout_locked(conn, timeout, |conn| {
+        exchange(conn, &packet).map(|_| ())
+    })
+}
+
+/// Run `f` with every command using `timeout` instead of the timeout table
+/// (port timeout and read-loop deadline), restoring the previous setting
+/// afterwards even if `f` fails
+fn with_timeout_locked<T>(
+    conn: &mut Connection,
+    timeout: Duration,
+    f: impl FnOnce(&mut Connection) -> Result<T>,
+) -> Result<T> {
+    let previous = conn.timeout_override.replace(timeout);
+    let result = f(conn);
+    conn.timeout_override = previous;
+    result
+}
+
+/// Pick the first-byte deadline for `packet` (override > connect options > table
+/// default) and the progress deadline, and apply the shorter one to the port so a
+/// blocking read never outlasts either
+pub(crate) fn apply_timeout_locked(conn: &mut Connection, packet: &[u8]) -> Result<()> {
+    let first_byte = match conn.timeout_override {
+        Some(timeout) => (timeout, TimeoutSource::PerCall),
+        None => conn.timeouts.lookup(OpcodeClass::of_packet(packet)),
+    };
+    let progress = conn.timeouts.progress();
+    let timeout = first_byte.0.min(progress.0);
+    if conn.port_timeout != timeout {
+        conn.transport.set_timeout(timeout)
+            .map_err(|e| CoreError::new(ErrorCode::IoError, format!("Failed to set timeout: {}", e)))?;
+        conn.port_timeout = timeout;
+    }
+    conn.read_deadlines = ReadDeadlines { first_byte, progress, poll: conn.timeouts.poll() };
+    Ok(())
+}
+
+/// Normalize an SD card path: forward slashes, single leading '/', no trailing '/'
+/// Rejects empty, relative-escaping ("..") and over-long paths
+pub(crate) fn normalize_path(path: &str) -> Result<String> {
+    normalize_path_with_limit(path, MAX_PATH_LEN)
+}
+
+/// normalize_path() with an explicit byte limit (e.g. 255 for the MV destination)
+fn normalize_path_with_limit(path: &str, limit: usize) -> Result<String> {
+    let mut normalized = String::new();
+    for component in path.split(['/', '\\']).filter(|c| !c.is_empty() && *c != ".") {
+        if component == ".." {
+            return Err(CoreError::new(ErrorCode::ArgValidation, format!("Invalid path {}: '..' is not supported", path)));
+        }
+        normalized.push('/');
+        normalized.push_str(component);
+    }
+
+    if normalized.is_empty() {
+        return Err(CoreError::new(ErrorCode::ArgValidation, format!("Invalid path '{}': empty path", path)));
+    }
+    if normalized.contains('\0') {
+        return Err(CoreError::new(ErrorCode::ArgValidation, format!("Invalid path {}: contains NUL", path)));
+    }
+    if normalized.len() > limit {
+        return Err(CoreError::new(ErrorCode::ArgValidation,
+            format!("Invalid path {}: {} bytes exceeds the {}-byte limit", path, normalized.len(), limit)
+        ));
+    }
+
+    Ok(normalized)
+}
+
+/// Maximum encoded length in bytes of a path argument (LS, MKDIR, RM, BOOT, GET/PUT, MV source)
+#[napi]
+pub fn max_path_length() -> u32 {
+    MAX_PATH_LEN as u32
+}
+
+/// Maximum encoded length in bytes of MV's destination path
+#[napi]
+pub fn max_mv_second_path_length() -> u32 {
+    MAX_MV_DEST_PATH_LEN as u32
+}
+
+/// Size in bytes of a command/RESPONSE packet
+#[napi]
+pub fn packet_size() -> u32 {
+    PACKET_SIZE as u32
+}
+
+/// Check a path the way every path command will, without sending anything
+/// Fails for empty paths, '..', NUL, and paths whose normalized UTF-8 encoding
+/// exceeds max_path_length()
+#[napi]
+pub fn validate_path(path: String) -> Result<()> {
+    normalize_path(&path).map(|_| ())
+}
+
+/// Validate that an address range fits the space it targets
+/// SNES space is a 24-bit window (ROM, SRAM, WRAM, VRAM, ...), so anything past
+/// 0xFFFFFF is a typo'd address; FILE and other spaces allow large offsets
+pub(crate) fn validate_address_range(space: u8, address: u32, size: u32) -> Result<()> {
+    if space == SPACE_SNES && (address as u64) + (size as u64) > SNES_SPACE_END {
+        return Err(CoreError::new(ErrorCode::ArgValidation,
+            format!("AddressOutOfRange: 0x{:X} + 0x{:X} bytes exceeds the SNES space window 0x000000-0x{:06X}",
+                address, size, SNES_SPACE_END - 1)
+        ));
+    }
+    if space == SPACE_CMD && (address < cmd::CMD_SPACE_START || (address as u64) + (size as u64) > cmd::CMD_SPACE_END as u64) {
+        return Err(CoreError::new(ErrorCode::ArgValidation,
+            format!("AddressOutOfRange: 0x{:X} + 0x{:X} bytes is outside the CMD space window 0x{:04X}-0x{:04X}",
+                address, size, cmd::CMD_SPACE_START, cmd::CMD_SPACE_END - 1)
+        ));
+    }
+    Ok(())
+}
+
+/// Check the error byte of a RESPONSE packet (byte 5, non-zero on failure)
+fn check_device_error(response: &[u8], command: &str, path: &str) -> Result<()> {
+    if response[5] != 0 {
+        return Err(CoreError::new(ErrorCode::DeviceError,
+            format!("{} failed for {}: device reported error {}", command, path, response[5])
+        ));
+    }
+    Ok(())
+}
+
+/// Build a 512-byte command packet (matching C# SendCommand packet encoding)
+pub(crate) fn build_packet(opcode: u8, space: u8, flags: u8, args: Option<Vec<String>>) -> Result<Vec<u8>> {
+    // Build 512-byte packet (matching C# byte[] numArray = new byte[512])
+    let mut packet = vec![0u8; PACKET_SIZE];
+
+    // Magic header "USBA" (matching C# lines 553, 482, 557, 853)
+    packet[0] = 0x55; // 'U'
+    packet[1] = 0x53; // 'S'
+    packet[2] = 0x42; // 'B'
+    packet[3] = 0x41; // 'A'
+
+    // Opcode, space, flags (matching C# lines 576, 511, 512)
+    packet[4] = opcode;
+    packet[5] = space;
+    packet[6] = flags;
+
+    // Encode arguments based on opcode (matching C# SendCommand logic)
+    // Opcodes that need arguments:
+    // - GET/PUT (0/1): require args[0] (address), args[1] (size); size at 252, address at 256
+    //   (FILE space: args[0] (path), PUT also args[1] (size))
+    // - VGET/VPUT (2/3): require pairs of (size, address), 2 <= args <= 16 and multiple of 2
+    // - LS/MKDIR/RM/BOOT (4/5/6/9): require args[0] (path string)
+    // - MV (7): require args[0] (path1), args[1] (path2)
+    // - RESET/POWER_CYCLE/INFO/MENU_RESET/STREAM (8/10/11/12/13): no arguments
+    // - TIME (14): require second, minute, hour, day, month, year, weekday (see clock.rs)
+
+    match opcode {
+        0 | 1 if space == SPACE_FILE => {
+            // GET/PUT in FILE space: args[0] = path at bytes 8+, PUT also args[1] = size (hex)
+            // Size encoded at bytes 252-255 (big-endian, same field as the GET response size)
+            let arg_list = required_args(opcode, args, "string")?;
+
+            let path_bytes = arg_list[0].as_bytes();
+            let copy_len = std::cmp::min(path_bytes.len(), MAX_PATH_LEN); // Max 247 bytes (8 to 255)
+            packet[8..8+copy_len].copy_from_slice(&path_bytes[..copy_len]);
+
+            if opcode == 1 {
+                if arg_list.len() < 2 {
+                    return Err(CoreError::new(ErrorCode::ArgValidation,
+                        format!("Command: {} missing arg[1] uint", opcode)
+                    ));
+                }
+                let size = u32::from_str_radix(&arg_list[1], 16)
+                    .map_err(|e| CoreError::new(ErrorCode::ArgValidation, format!("Command: {} invalid arg[1]: {}", opcode, e)))?;
+                packet[252..256].copy_from_slice(&size.to_be_bytes());
+            }
+        }
+        0 | 1 => {
+            // GET/PUT: args[0] = address (hex string), args[1] = size (hex string)
+            // Size at bytes 252-255 and address at 256-259 (both big-endian uint32), the
+            // firmware's size/offset fields
+            let arg_list = required_args(opcode, args, "uint")?;
+            
+            if arg_list.len() < 2 {
+                return Err(CoreError::new(ErrorCode::ArgValidation,
+                    format!("Command: {} missing arg[1] uint", opcode)
+                ));
+            }
+            
+            // Parse address from hex string
+            let address = u32::from_str_radix(&arg_list[0], 16)
+                .map_err(|e| CoreError::new(ErrorCode::ArgValidation, format!("Command: {} invalid arg[0]: {}", opcode, e)))?;
+            
+            // Parse size from hex string
+            let size = u32::from_str_radix(&arg_list[1], 16)
+                .map_err(|e| CoreError::new(ErrorCode::ArgValidation, format!("Command: {} invalid arg[1]: {}", opcode, e)))?;
+
+            validate_address_range(space, address, size)?;
+            
+            // Size at bytes 252-255, where the RESPONSE echoes it, so the device knows the
+            // data phase length
+            packet[252..256].copy_from_slice(&size.to_be_bytes());
+
+            // Address at bytes 256-259
+            packet[256..260].copy_from_slice(&address.to_be_bytes());
+        }
+        2 | 3 => {
+            // VGET/VPUT: Multiple (size, address) pairs at bytes 32+
+            // C# format: args are (size0, address0, size1, address1, ...)
+            // Each pair encoded as: size (u8) at offset, address (uint32 big-endian) at offset+1..offset+4
+            // C#: "need 2 <= args <= 16 and a multiple of 2. Format: (size0, offset0), ..."
+            let arg_list = required_args(opcode, args, "uint")?;
+            
+            // Capacity is checked before anything is written, so no pair can spill
+            // past the region the firmware reads (see VGET_PAIRS_END)
+            if arg_list.len() < 2 || arg_list.len() > VGET_MAX_PAIRS * 2 || arg_list.len() % 2 != 0 {
+                return Err(CoreError::new(ErrorCode::ArgValidation,
+                    format!("Command: {} need 2 <= args <= {} and a multiple of 2. Format: (size0, offset0), ...",
+                        opcode, VGET_MAX_PAIRS * 2)
+                ));
+            }
+            
+            let num_pairs = arg_list.len() / 2;
+            let mut offset = VGET_PAIRS_OFFSET;
+            
+            for i in 0..num_pairs {
+                // Parse size (u8)
+                let size = u8::from_str_radix(&arg_list[i * 2], 16)
+                    .map_err(|e| CoreError::new(
+                        ErrorCode::ArgValidation,
+                        format!("Command: {} invalid size arg[{}]: {}", opcode, i * 2, e),
+                    ))?;
+                
+                // Parse address (uint32)
+                let address = u32::from_str_radix(&arg_list[i * 2 + 1], 16)
+                    .map_err(|e| CoreError::new(
+                        ErrorCode::ArgValidation,
+                        format!("Command: {} invalid address arg[{}]: {}", opcode, i * 2 + 1, e),
+                    ))?;
+                
+                // Encode: size (u8) at offset, address (uint32 big-endian) at offset+1..offset+4
+                // C# lines 57-60: size at offset, address bytes at offset+1 to offset+4
+                packet[offset] = size;
+                packet[offset + 1] = ((address >> 24) & 0xFF) as u8;
+                packet[offset + 2] = ((address >> 16) & 0xFF) as u8;
+                packet[offset + 3] = ((address >> 8) & 0xFF) as u8;
+                packet[offset + 4] = (address & 0xFF) as u8;
+                offset += VGET_PAIR_LEN;
+            }
+        }
+        4 | 5 | 6 | 9 => {
+            // LS/MKDIR/RM/BOOT: args[0] = path (string) at bytes 8+
+            // C#: Buffer.BlockCopy(Encoding.ASCII.GetBytes(source2), 0, numArray, 8, source2.Length)
+            let arg_list = required_args(opcode, args, "string")?;
+            
+            let path_bytes = arg_list[0].as_bytes();
+            let copy_len = std::cmp::min(path_bytes.len(), MAX_PATH_LEN); // Max 247 bytes (8 to 255)
+            if copy_len > 0 {
+                packet[8..8+copy_len].copy_from_slice(&path_bytes[..copy_len]);
+            }
+        }
+        7 => {
+            // MV: args[0] = path1 at bytes 8+, args[1] = path2 at bytes 256+
+            // C# line 16: path1 at bytes 8+, path2 at bytes 256+
+            let arg_list = required_args(opcode, args, "string")?;
+            if arg_list.len() < 2 {
+                return Err(CoreError::new(ErrorCode::ArgValidation,
+                    format!("Command: {} missing arg[1] string", opcode)
+                ));
+            }
+            
+            // Path1 at bytes 8+
+            let path1_bytes = arg_list[0].as_bytes();
+            let copy_len1 = std::cmp::min(path1_bytes.len(), MAX_PATH_LEN); // Max 247 bytes (8 to 255)
+            if copy_len1 > 0 {
+                packet[8..8+copy_len1].copy_from_slice(&path1_bytes[..copy_len1]);
+            }
+            
+            // Path2 at bytes 256+ (C#: Buffer.BlockCopy at offset 256, max 255 bytes)
+            let path2_bytes = arg_list[1].as_bytes();
+            let copy_len2 = std::cmp::min(path2_bytes.len(), MAX_MV_DEST_PATH_LEN);
+            if copy_len2 > 0 {
+                packet[256..256+copy_len2].copy_from_slice(&path2_bytes[..copy_len2]);
+            }
+        }
+        14 => {
+            // TIME: one byte per field at bytes 252+, except the year (big-endian u16)
+            let arg_list = required_args(opcode, args, "uint")?;
+            if arg_list.len() != 7 {
+                return Err(CoreError::new(ErrorCode::ArgValidation,
+                    format!("Command: {} needs 7 args: second, minute, hour, day, month, year, weekday", opcode)
+                ));
+            }
+            let mut offset = clock::TIME_FIELDS_OFFSET;
+            for (i, arg) in arg_list.iter().enumerate() {
+                let invalid = |e: String| CoreError::new(ErrorCode::ArgValidation,
+                    format!("Command: {} invalid arg[{}]: {}", opcode, i, e)
+                );
+                if i == 5 {
+                    let year = u16::from_str_radix(arg, 16).map_err(|e| invalid(e.to_string()))?;
+                    packet[offset..offset + 2].copy_from_slice(&year.to_be_bytes());
+                    offset += 2;
+                } else {
+                    packet[offset] = u8::from_str_radix(arg, 16).map_err(|e| invalid(e.to_string()))?;
+                    offset += 1;
+                }
+            }
+        }
+        8 | 10 | 11 | 12 | 13 => {
+            // RESET/POWER_CYCLE/INFO/MENU_RESET/STREAM: no arguments
+            // C# goto label_112 - no argument encoding needed
+        }
+        _ => {
+            // Unknown opcode, or RESPONSE (15) which only the device sends
+            return Err(validation::unhandled_opcode_error(opcode, space, flags));
+        }
+    }
+
+    Ok(packet)
+}
+
+/// Take the argument list of an opcode that requires arguments
+/// None and Some(empty) are rejected with the same "missing arg[0]" error
+fn required_args(opcode: u8, args: Option<Vec<String>>, kind: &str) -> Result<Vec<String>> {
+    match args {
+        Some(arg_list) if !arg_list.is_empty() => Ok(arg_list),
+        _ => Err(CoreError::new(ErrorCode::ArgValidation,
+            format!("Command: {} missing arg[0] {}", opcode, kind)
+        )),
+    }
+}
+
+/// Write a command packet and read back its RESPONSE (matching C# SendCommand I/O)
+/// Returns a zeroed packet without reading when the NORESP flag is set
+fn exchange(conn: &mut Connection, packet: &[u8]) -> Result<Vec<u8>> {
+    // Reads and listings leave the device untouched; anything else may change what
+    // a cached read would return
+    match packet[4] {
+        11 => {
+            if let Some(response) = conn.cache.lock().unwrap().get_info() {
+                return Ok(response);
+            }
+        }
+        0 | 2 | 4 => {}
+        _ => {
+            conn.cache.lock().unwrap().invalidate();
+            conn.ls_pages = None;
+        }
+    }
+    // RESET, BOOT, POWER_CYCLE and MENU_RESET leave scratch memory undefined
+    if matches!(packet[4], 8 | 9 | 10 | 12) {
+        conn.reservations.lock().unwrap().clear();
+    }
+
+    exchange_uncached(conn, packet)
+}
+
+/// exchange() without the cache lookup/invalidation, for commands that must reach the
+/// device (e.g. timed INFO); the reply is still cached and recorded
+pub(crate) fn exchange_uncached(conn: &mut Connection, packet: &[u8]) -> Result<Vec<u8>> {
+    let started = Instant::now();
+    let result = exchange_unrecorded(conn, packet);
+    if let (11, Ok(response)) = (packet[4], &result) {
+        conn.cache.lock().unwrap().put_info(response);
+    }
+
+    let mut log = conn.diagnostics.lock().unwrap();
+    // NORESP commands return a zeroed placeholder, not a real response
+    let response = match &result {
+        Ok(response) if packet[6] & NORESP_FLAG == 0 => Some(&response[..]),
+        _ => None,
+    };
+    log.record_exchange(packet, started.elapsed(), response, result.as_ref().err());
+    if let (11, Ok(response)) = (packet[4], &result) {
+        if let Ok(info) = parse_info_response(response.clone()) {
+            conn.session.lock().unwrap().observe(&info[2]);
+            log.record_info(info);
+        }
+    }
+    drop(log);
+
+    result
+}
+
+/// exchange() without the diagnostics bookkeeping
+/// Read-only commands whose RESPONSE times out are resent per the retry policy, after
+/// draining whatever part of the late reply has arrived. A garbled RESPONSE is followed
+/// by a drain (auto resync), and a read-only command is then resent once.
+fn exchange_unrecorded(conn: &mut Connection, packet: &[u8]) -> Result<Vec<u8>> {
+    let retries = conn.timeouts.retries_for(packet);
+    let mut attempt = 0;
+    let mut resynced = false;
+    loop {
+        if conn.abandoned.load(Ordering::SeqCst) {
+            return Err(cancelled_error());
+        }
+        match exchange_once(conn, packet) {
+            Err(e) if conn.auto_resync && is_garbled_response(&e.reason) => {
+                let drained = recover_framing_locked(conn, packet[4], &e)?;
+                if resynced || !timeouts::is_read_only(packet) {
+                    return Err(CoreError::new(e.code,
+                        format!("{} (resynchronized, {} stray bytes discarded)", e.reason, drained)
+                    ));
+                }
+                resynced = true;
+            }
+            Err(e) if e.code == ErrorCode::Timeout && attempt < retries => {
+                attempt += 1;
+                let drained = drain_input_locked(conn)?;
+                conn.diagnostics.lock().unwrap().record_warning(&format!(
+                    "Retrying opcode {} ({} of {}, {} stale bytes drained): {}",
+                    packet[4], attempt, retries, drained, e.reason
+                ));
+                std::thread::sleep(conn.timeouts.retry_backoff() * attempt);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// One send/receive of exchange_unrecorded()
+fn exchange_once(conn: &mut Connection, packet: &[u8]) -> Result<Vec<u8>> {
+    apply_timeout_locked(conn, packet)?;
+    send_packet_locked(conn, packet)?;
+
+    // If NORESP flag is set (like RESET opcode), don't wait for response
+    // (matching C# line 874: (flags & usbint_server_flags_e.NORESP) == usbint_server_flags_e.NONE)
+    if packet[6] & NORESP_FLAG != 0 {
+        return Ok(vec![0u8; PACKET_SIZE]); // Return empty response
+    }
+
+    receive_response_locked(conn, packet)
+}
+
+/// Write one command packet (matching C# _serial_port.Write(numArray, 0, count) where count = 512)
+/// Waits out the inter-command delay first, if one is configured
+pub(crate) fn send_packet_locked(conn: &mut Connection, packet: &[u8]) -> Result<()> {
+    let delay = conn.timeouts.inter_command_delay();
+    if let Some(gap) = conn.last_command_at.and_then(|at| delay.checked_sub(at.elapsed())) {
+        std::thread::sleep(gap);
+    }
+    conn.last_command_at = Some(Instant::now());
+    conn.transport.write_packet(packet)
+}
+
+/// Read and validate the RESPONSE to `packet` (already written)
+pub(crate) fn receive_response_locked(conn: &mut Connection, packet: &[u8]) -> Result<Vec<u8>> {
+    let (opcode, space, flags) = (packet[4], packet[5], packet[6]);
+
+    // Read response (matching C# _serial_port.Read)
+    // C# reads in a loop until 512 bytes are received: num5 += _serial_port.Read(numArray, num5 % 512, 512 - (num5 % 512))
+    // Response is also 512 bytes
+    let mut response = vec![0u8; PACKET_SIZE];
+    
+    // Read full 512-byte response (matching C# behavior)
+    conn.transport.read_packet(&mut response, conn.read_deadlines)?;
+    if conn.auto_resync && response[..4] != *b"USBA" {
+        realign_response_locked(conn, &mut response)?;
+    }
+
+    // Keep the raw reply (even an invalid one) for last_response() post-mortems
+    conn.last_response = Some(response.clone());
+
+    // Validate response magic header (matching C# validation at lines 697-698)
+    if response[0] != 0x55 || response[1] != 0x53 || response[2] != 0x42 || response[3] != 0x41 {
+        return Err(CoreError::new(ErrorCode::InvalidResponse,
+            format!("Invalid response magic header: {:02x} {:02x} {:02x} {:02x} (expected USBA)",
+                response[0], response[1], response[2], response[3])
+        ));
+    }
+    
+    // Validate response opcode (matching C# line 30: response[4] should be RESPONSE opcode = 15)
+    // C# checks: numArray[4] == usbint_server_opcode_e.RESPONSE
+    const RESPONSE_OPCODE: u8 = 15;
+    if response[4] != RESPONSE_OPCODE {
+        return Err(CoreError::new(ErrorCode::InvalidResponse,
+            format!("Response Error Request: {} space: {} flags: {} Response: {}",
+                opcode, space, flags, response[4])
+        ));
+    }
+
+    Ok(response)
+}
+
+/// GET `size` bytes from `space` on an already-locked port, including the data phase
+/// The RESPONSE carries the data size at bytes 252-255; the data follows in 512-byte
+/// blocks, or 64-byte ones with ConnectOptions.block_size 64
+pub(crate) fn get_locked(conn: &mut Connection, space: u8, address: u32, size: u32) -> Result<Vec<u8>> {
+    get_flagged_locked(conn, space, 0, address, size)
+}
+
+/// get_locked() with command flags on top of the connection's block size
+pub(crate) fn get_flagged_locked(conn: &mut Connection, space: u8, flags: u8, address: u32, size: u32) -> Result<Vec<u8>> {
+    if let Some(data) = conn.cache.lock().unwrap().get(space, address, size) {
+        return Ok(data);
+    }
+
+    let data = get_with_flags_locked(conn, space, conn.block_flags | flags, address, size)?;
+    conn.cache.lock().unwrap().put(space, address, size, &data);
+    Ok(data)
+}
+
+/// Uncached GET with explicit command flags (DATA64B switches the data phase to 64-byte blocks)
+pub(crate) fn get_with_flags_locked(conn: &mut Connection, space: u8, flags: u8, address: u32, size: u32) -> Result<Vec<u8>> {
+    let packet = build_packet(0, space, flags, Some(vec![format!("{:X}", address), format!("{:X}", size)]))?;
+    let response = exchange(conn, &packet)?;
+    let data_size = parse_get_response(response)?;
+
+    let mut data = read_data_locked(conn, data_size as usize, data_block_len(flags))?;
+    data.truncate(size as usize);
+    Ok(data)
+}
+
+/// VGET up to 8 (size, address) pairs from `space` in one exchange
+/// The data phase is the pairs' data back to back in 64-byte blocks; it is split per pair
+pub(crate) fn vget_locked(conn: &mut Connection, space: u8, pairs: &[(u8, u32)]) -> Result<Vec<Vec<u8>>> {
+    let packet = vget_packet(space, pairs)?;
+    exchange(conn, &packet)?;
+    let data = read_data_locked(conn, vget_data_len(pairs), data_block_len(DATA64B_FLAG))?;
+    Ok(split_vget_data(pairs, &data))
+}
+
+/// VGET command packet for up to 8 (size, address) pairs
+pub(crate) fn vget_packet(space: u8, pairs: &[(u8, u32)]) -> Result<Vec<u8>> {
+    vector_packet(2, space, pairs)
+}
+
+/// VGET/VPUT command packet: the pairs are hex-encoded for build_packet()
+fn vector_packet(opcode: u8, space: u8, pairs: &[(u8, u32)]) -> Result<Vec<u8>> {
+    let args = pairs.iter()
+        .flat_map(|&(size, address)| [format!("{:X}", size), format!("{:X}", address)])
+        .collect();
+    build_packet(opcode, space, DATA64B_FLAG, Some(args))
+}
 
-        // Build serial port with exact C# settings
-        // Note: serialport 4.x uses a builder pattern but DTR control may need platform-specific handling
-        let builder = serialport::new(&port_name, 9600)
-            .data_bits(serialport::DataBits::Eight)
-            .stop_bits(serialport::StopBits::One)
-            .parity(serialport::Parity::None)
-            .flow_control(serialport::FlowControl::None); // Handshake.None = no flow control!
+/// VPUT up to 8 (size, address) pairs to `space` in one exchange
+/// `data` is the pairs' data back to back; it goes out as zero-padded 64-byte blocks
+pub(crate) fn vput_locked(conn: &mut Connection, space: u8, pairs: &[(u8, u32)], data: &[u8]) -> Result<()> {
+    let packet = vector_packet(3, space, pairs)?;
+    let response = exchange(conn, &packet)?;
+    let block_len = data_block_len(DATA64B_FLAG);
+    let mut blocks = data.to_vec();
+    blocks.resize(data.len().div_ceil(block_len) * block_len, 0);
+    conn.transport.write_data(&blocks)?;
+    // As with PUT, a refused VPUT still takes its data phase
+    check_device_error(&response, "VPUT", &format!("space {} 0x{:X}", space, pairs[0].1))
+}
 
-        // Set timeouts (matching C# ReadTimeout/WriteTimeout = 5000ms)
-        // serialport 4.x uses timeout() for both read and write
-        let builder = builder.timeout(Duration::from_millis(5000));
+/// Total data phase length of a VGET
+pub(crate) fn vget_data_len(pairs: &[(u8, u32)]) -> usize {
+    pairs.iter().map(|&(size, _)| size as usize).sum()
+}
 
-        let port = builder.open()
-            .map_err(|e| NapiError::from_reason(
-                format!("Failed to open serial port {}: {}", port_name, e)
-            ))?;
+/// Split a VGET data phase back into one buffer per pair
+pub(crate) fn split_vget_data(pairs: &[(u8, u32)], data: &[u8]) -> Vec<Vec<u8>> {
+    let mut offset = 0;
+    pairs.iter().map(|&(size, _)| {
+        let chunk = data[offset..offset + size as usize].to_vec();
+        offset += size as usize;
+        chunk
+    }).collect()
+}
 
-        // Set DTR = true (matching C# DtrEnable = true)
-        // serialport 4.x: Use write_data_terminal_ready() or similar
-        // For now, DTR control may need platform-specific code or serialport 5.x
-        // Note: DTR control is optional - the device should work without explicit DTR setting
-        // We'll handle DTR in reset() method which is critical
-        
-        *port_guard = Some(port);
-        *self.port_name.lock().unwrap() = Some(port_name.clone());
+/// PUT `data` to `space` on an already-locked port, including the data phase
+pub(crate) fn put_locked(conn: &mut Connection, space: u8, address: u32, data: &[u8]) -> Result<()> {
+    put_flagged_locked(conn, space, 0, address, data)
+}
 
-        Ok(())
+/// put_locked() with command flags on top of the connection's block size
+pub(crate) fn put_flagged_locked(conn: &mut Connection, space: u8, flags: u8, address: u32, data: &[u8]) -> Result<()> {
+    let flags = conn.block_flags | flags;
+    let packet = build_packet(1, space, flags, Some(vec![format!("{:X}", address), format!("{:X}", data.len())]))?;
+    let response = exchange(conn, &packet)?;
+    check_put_size(conn, &response, data.len() as u32, space, &format!("space {} 0x{:X}", space, address))?;
+    // A refused memory PUT still takes its data phase, so the error is checked after it
+    if flags & DATA64B_FLAG != 0 {
+        let mut blocks = data.to_vec();
+        blocks.resize(data.len().div_ceil(64) * 64, 0);
+        conn.transport.write_data(&blocks)?;
+    } else {
+        write_data_locked(conn, data)?;
     }
+    check_device_error(&response, "PUT", &format!("space {} 0x{:X}", space, address))
+}
 
-    /// Disconnect from serial port
-    #[napi]
-    pub fn disconnect(&self) -> NapiResult<()> {
-        let mut port_guard = self.port.lock().unwrap();
-        
-        if let Some(_port) = port_guard.take() {
-            // Set DTR = false before closing (matching C# Disconnect())
-            // Note: DTR control may need platform-specific handling
-            // Port will be dropped (closed) here automatically
-        }
-        
-        *self.port_name.lock().unwrap() = None;
-        Ok(())
-    }
+/// Data phase block length for a command's flags
+pub(crate) fn data_block_len(flags: u8) -> usize {
+    if flags & DATA64B_FLAG != 0 { 64 } else { 512 }
+}
 
-    /// Reset device (matching C# Reset() method)
-    /// Sets DTR = false, waits 500ms
-    #[napi]
-    pub fn reset(&self) -> NapiResult<()> {
-        let mut port_guard = self.port.lock().unwrap();
-        
-        if let Some(_port) = port_guard.as_mut() {
-            // Reset device by setting DTR = false (matching C# Reset())
-            // serialport 4.x: DTR control may need platform-specific code
-            // For now, we'll skip DTR control and rely on RESET opcode
-            // TODO: Add platform-specific DTR control using native system calls
-            
-            drop(port_guard);
-            
-            // Wait 500ms (matching C# Thread.Sleep(500))
-            std::thread::sleep(Duration::from_millis(500));
-            
-            Ok(())
-        } else {
-            Err(NapiError::from_reason("Not connected - cannot reset"))
-        }
+/// Memory GET/PUT flags for ConnectOptions.block_size
+fn block_size_flags(block_size: Option<u32>) -> Result<u8> {
+    match block_size {
+        None | Some(512) => Ok(0),
+        Some(64) => Ok(DATA64B_FLAG),
+        Some(size) => Err(CoreError::new(ErrorCode::ArgValidation,
+            format!("Invalid block_size {}: must be 64 or 512", size)
+        )),
     }
+}
 
-    /// Send command packet (matching C# SendCommand method)
-    /// Packet format: 512 bytes
-    /// - Bytes 0-3: "USBA" magic header (0x55, 0x53, 0x42, 0x41)
-    /// - Byte 4: opcode
-    /// - Byte 5: space
-    /// - Byte 6: flags
-    /// - Bytes 7-511: arguments/padding (format depends on opcode)
-    #[napi]
-    pub fn send_command(
-        &self,
-        opcode: u8,
-        space: u8,
-        flags: u8,
-        args: Option<Vec<String>>, // Changed: args as string array for easier encoding
-    ) -> NapiResult<Vec<u8>> {
-        let mut port_guard = self.port.lock().unwrap();
-        
-        let port = port_guard.as_mut()
-            .ok_or_else(|| NapiError::from_reason("Not connected"))?;
+/// Read a data phase of `len` bytes (sent by the device as zero-padded `block_len`-byte blocks)
+pub(crate) fn read_data_locked(conn: &mut Connection, len: usize, block_len: usize) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(len);
+    read_data_with(conn, len, block_len, |block| data.extend_from_slice(block))?;
+    Ok(data)
+}
 
-        // Build 512-byte packet (matching C# byte[] numArray = new byte[512])
-        let mut packet = vec![0u8; 512];
+/// Read a data phase block by block, passing the unpadded bytes of each to `on_block`
+fn read_data_with(conn: &mut Connection, len: usize, block_len: usize, mut on_block: impl FnMut(&[u8])) -> Result<()> {
+    let mut buf = [0u8; 512];
+    let block = &mut buf[..block_len];
+    let mut remaining = len;
+    while remaining > 0 {
+        if conn.abandoned.load(Ordering::SeqCst) {
+            return Err(cancelled_error());
+        }
+        conn.transport.read_data(block, conn.read_deadlines)?;
+        let n = remaining.min(block_len);
+        on_block(&block[..n]);
+        remaining -= n;
+    }
+    Ok(())
+}
 
-        // Magic header "USBA" (matching C# lines 553, 482, 557, 853)
-        packet[0] = 0x55; // 'U'
-        packet[1] = 0x53; // 'S'
-        packet[2] = 0x42; // 'B'
-        packet[3] = 0x41; // 'A'
+/// Write a data phase, zero-padding the final block to 512 bytes
+fn write_data_locked(conn: &mut Connection, data: &[u8]) -> Result<()> {
+    let mut chunks = data.chunks(512);
+    write_data_with(conn, data.len(), |block| {
+        let chunk = chunks.next().unwrap_or_default();
+        block[..chunk.len()].copy_from_slice(chunk);
+    })
+}
 
-        // Opcode, space, flags (matching C# lines 576, 511, 512)
-        packet[4] = opcode;
-        packet[5] = space;
-        packet[6] = flags;
+/// Write a data phase of `len` bytes as zero-padded 512-byte blocks, letting `fill`
+/// write the unpadded bytes of each block
+/// Blocks go out in chunks of the size the connection's ChunkTuner currently allows,
+/// one flush per chunk, and each chunk's timing feeds back into that size.
+fn write_data_with(conn: &mut Connection, len: usize, mut fill: impl FnMut(&mut [u8])) -> Result<()> {
+    let mut remaining = len;
+    while remaining > 0 {
+        if conn.abandoned.load(Ordering::SeqCst) {
+            return Err(cancelled_error());
+        }
+        let blocks = conn.chunking.lock().unwrap().blocks() as usize;
+        let mut chunk = vec![0u8; blocks.min(remaining.div_ceil(512)) * 512];
+        for block in chunk.chunks_mut(512) {
+            let n = remaining.min(512);
+            fill(&mut block[..n]);
+            remaining -= n;
+        }
 
-        // Encode arguments based on opcode (matching C# SendCommand logic)
-        // Opcodes that need arguments:
-        // - GET/PUT (0/1): require args[0] (address), args[1] (size)
-        // - VGET/VPUT (2/3): require pairs of (size, address), 2 <= args <= 16 and multiple of 2
-        // - LS/MKDIR/RM/BOOT (4/5/6/9): require args[0] (path string)
-        // - MV (7): require args[0] (path1), args[1] (path2)
-        // - RESET/POWER_CYCLE/INFO/MENU_RESET/STREAM (8/10/11/12/13): no arguments
-
-        match opcode {
-            0 | 1 => {
-                // GET/PUT: args[0] = address (hex string), args[1] = size (hex string)
-                // Address encoded at bytes 252-255 (big-endian uint32)
-                // C#: num4 = (uint) args[0], encoded at bytes 252-255
-                let arg_list = args.ok_or_else(|| NapiError::from_reason(
-                    format!("Command: {} missing arg[0] uint", opcode)
-                ))?;
-                
-                if arg_list.len() < 2 {
-                    return Err(NapiError::from_reason(
-                        format!("Command: {} missing arg[1] uint", opcode)
-                    ));
-                }
-                
-                // Parse address from hex string
-                let address = u32::from_str_radix(&arg_list[0], 16)
-                    .map_err(|e| NapiError::from_reason(format!("Command: {} invalid arg[0]: {}", opcode, e)))?;
-                
-                // Parse size from hex string (stored but not encoded in packet for GET/PUT)
-                let _size = u32::from_str_radix(&arg_list[1], 16)
-                    .map_err(|e| NapiError::from_reason(format!("Command: {} invalid arg[1]: {}", opcode, e)))?;
-                
-                // Encode address at bytes 252-255 (big-endian, matching C# lines 636-638)
-                packet[252] = ((address >> 24) & 0xFF) as u8;
-                packet[253] = ((address >> 16) & 0xFF) as u8;
-                packet[254] = ((address >> 8) & 0xFF) as u8;
-                packet[255] = (address & 0xFF) as u8;
-            }
-            2 | 3 => {
-                // VGET/VPUT: Multiple (size, address) pairs at bytes 32+
-                // C# format: args are (size0, address0, size1, address1, ...)
-                // Each pair encoded as: size (u8) at offset, address (uint32 big-endian) at offset+1..offset+4
-                // C#: "need 2 <= args <= 16 and a multiple of 2. Format: (size0, offset0), ..."
-                let arg_list = args.ok_or_else(|| NapiError::from_reason(
-                    format!("Command: {} missing arguments", opcode)
-                ))?;
-                
-                if arg_list.len() < 2 || arg_list.len() > 16 || arg_list.len() % 2 != 0 {
-                    return Err(NapiError::from_reason(
-                        format!("Command: {} need 2 <= args <= 16 and a multiple of 2. Format: (size0, offset0), ...", opcode)
-                    ));
-                }
-                
-                let num_pairs = arg_list.len() / 2;
-                let mut offset = 32;
-                
-                for i in 0..num_pairs {
-                    // Parse size (u8)
-                    let size = u8::from_str_radix(&arg_list[i * 2], 16)
-                        .map_err(|e| NapiError::from_reason(format!("Command: {} invalid size arg[{}]: {}", opcode, i * 2, e)))?;
-                    
-                    // Parse address (uint32)
-                    let address = u32::from_str_radix(&arg_list[i * 2 + 1], 16)
-                        .map_err(|e| NapiError::from_reason(format!("Command: {} invalid address arg[{}]: {}", opcode, i * 2 + 1, e)))?;
-                    
-                    // Encode: size (u8) at offset, address (uint32 big-endian) at offset+1..offset+4
-                    // C# lines 57-60: size at offset, address bytes at offset+1 to offset+4
-                    packet[offset] = size;
-                    packet[offset + 1] = ((address >> 24) & 0xFF) as u8;
-                    packet[offset + 2] = ((address >> 16) & 0xFF) as u8;
-                    packet[offset + 3] = ((address >> 8) & 0xFF) as u8;
-                    packet[offset + 4] = (address & 0xFF) as u8;
-                    offset += 5;
-                    
-                    // Max 8 pairs (32 + 8*5 = 72 < 256, safe)
-                    if offset > 256 {
-                        break;
-                    }
-                }
-            }
-            4 | 5 | 6 | 9 => {
-                // LS/MKDIR/RM/BOOT: args[0] = path (string) at bytes 8+
-                // C#: Buffer.BlockCopy(Encoding.ASCII.GetBytes(source2), 0, numArray, 8, source2.Length)
-                let arg_list = args.ok_or_else(|| NapiError::from_reason(
-                    format!("Command: {} missing arg[0] string", opcode)
-                ))?;
-                
-                if arg_list.is_empty() {
-                    return Err(NapiError::from_reason(
-                        format!("Command: {} missing arg[0] string", opcode)
-                    ));
-                }
-                
-                let path_bytes = arg_list[0].as_bytes();
-                let copy_len = std::cmp::min(path_bytes.len(), 247); // Max 247 bytes (8 to 255)
-                if copy_len > 0 {
-                    packet[8..8+copy_len].copy_from_slice(&path_bytes[..copy_len]);
-                }
-            }
-            7 => {
-                // MV: args[0] = path1 at bytes 8+, args[1] = path2 at bytes 256+
-                // C# line 16: path1 at bytes 8+, path2 at bytes 256+
-                let arg_list = args.ok_or_else(|| NapiError::from_reason(
-                    format!("Command: {} missing arg[0] string", opcode)
-                ))?;
-                
-                if arg_list.len() < 1 {
-                    return Err(NapiError::from_reason(
-                        format!("Command: {} missing arg[0] string", opcode)
-                    ));
-                }
-                if arg_list.len() < 2 {
-                    return Err(NapiError::from_reason(
-                        format!("Command: {} missing arg[1] string", opcode)
-                    ));
-                }
-                
-                // Path1 at bytes 8+
-                let path1_bytes = arg_list[0].as_bytes();
-                let copy_len1 = std::cmp::min(path1_bytes.len(), 247); // Max 247 bytes (8 to 255)
-                if copy_len1 > 0 {
-                    packet[8..8+copy_len1].copy_from_slice(&path1_bytes[..copy_len1]);
-                }
-                
-                // Path2 at bytes 256+ (C#: Buffer.BlockCopy at offset 256, max 255 bytes)
-                let path2_bytes = arg_list[1].as_bytes();
-                let copy_len2 = std::cmp::min(path2_bytes.len(), 255);
-                if copy_len2 > 0 {
-                    packet[256..256+copy_len2].copy_from_slice(&path2_bytes[..copy_len2]);
-                }
-            }
-            8 | 10 | 11 | 12 | 13 => {
-                // RESET/POWER_CYCLE/INFO/MENU_RESET/STREAM: no arguments
-                // C# goto label_112 - no argument encoding needed
-            }
-            _ => {
-                // Unknown opcode
-                return Err(NapiError::from_reason(
-                    format!("Unhandled Command: {} space: {} flags: {}", opcode, space, flags)
-                ));
+        let started = Instant::now();
+        let written = conn.transport.write_data(&chunk);
+        let mut tuner = conn.chunking.lock().unwrap();
+        match written {
+            Ok(()) => tuner.record_success((chunk.len() / 512) as u32, started.elapsed()),
+            Err(e) => {
+                tuner.record_failure();
+                return Err(e);
             }
         }
+    }
+    Ok(())
+}
 
-        // Check NORESP flag (matching C# line 874: (flags & usbint_server_flags_e.NORESP) == usbint_server_flags_e.NONE)
-        const NORESP_FLAG: u8 = 64; // 0x40
-        let no_response = (flags & NORESP_FLAG) != 0;
-        
-        // Write packet (matching C# _serial_port.Write(numArray, 0, count) where count = 512)
-        port.write_all(&packet)
-            .map_err(|e| NapiError::from_reason(
-                format!("Write failed: {}", e)
-            ))?;
-
-        // Flush output to ensure data is sent (matching C# behavior)
-        port.flush()
-            .map_err(|e| NapiError::from_reason(
-                format!("Flush failed: {}", e)
-            ))?;
+fn cancelled_error() -> CoreError {
+    CoreError::new(ErrorCode::Cancelled, "Cancelled: disconnect in progress")
+}
 
-        // If NORESP flag is set (like RESET opcode), don't wait for response
-        if no_response {
-            return Ok(vec![0u8; 512]); // Return empty response
+/// Compare the size a PUT's RESPONSE acknowledges (bytes 252-255) with the size sent
+/// Firmware that doesn't echo it leaves the field zero, which is taken as agreement. On
+/// a mismatch no data phase is sent at all: the device is reset out of the one it is
+/// waiting for and resynced, a FILE PUT's partial file is removed, and "ShortWrite: ..."
+/// is returned. For FILE space `target` is the path.
+fn check_put_size(conn: &mut Connection, response: &[u8], expected: u32, space: u8, target: &str) -> Result<()> {
+    let actual = parse_get_response(response.to_vec())?;
+    if actual == 0 || actual == expected {
+        return Ok(());
+    }
+    let mut reason = format!(
+        "ShortWrite: PUT of {} expected {} bytes, device acknowledged {}", target, expected, actual
+    );
+    // The RESET opcode alone would be taken as data, so the lines are always pulsed
+    let reset = match conn.reset_strategy {
+        None | Some(ResetStrategy::Opcode) => ResetStrategy::DtrPulse,
+        Some(ResetStrategy::Full) => ResetStrategy::Combined,
+        Some(strategy) => strategy,
+    };
+    match resync_locked(conn, &ResyncOptions { reset: Some(reset) }) {
+        Ok(_) if space == SPACE_FILE => {
+            // Best effort: the short write is what gets reported
+            let _ = path_command_locked(conn, 6, "RM", vec![target.to_string()]);
         }
+        Ok(_) => {}
+        Err(e) => reason = format!("{} ({})", reason, e.reason),
+    }
+    Err(CoreError::new(ErrorCode::DeviceError, reason))
+}
 
-        // Read response (matching C# _serial_port.Read)
-        // C# reads in a loop until 512 bytes are received: num5 += _serial_port.Read(numArray, num5 % 512, 512 - (num5 % 512))
-        // Response is also 512 bytes
-        let mut response = vec![0u8; 512];
-        
-        // Read full 512-byte response (matching C# behavior)
-        // C# uses ReadTimeout = 5000ms and synchronous blocking Read()
-        // We'll read in a loop until we have exactly 512 bytes
-        let mut total_read = 0;
-        let start_time = std::time::Instant::now();
-        let timeout = Duration::from_millis(5000);
-        
-        while total_read < 512 {
-            // Check timeout (matching C# ReadTimeout behavior)
-            if start_time.elapsed() > timeout {
-                if total_read == 0 {
-                    return Err(NapiError::from_reason("Read timeout - no data received"));
-                }
-                // Partial read - device may have stopped responding
-                // Pad remaining bytes with zeros (C# doesn't explicitly handle this, but we'll be safe)
-                break;
-            }
-            
-            // Read remaining bytes (matching C#: Read(numArray, num5 % 512, 512 - (num5 % 512)))
-            let remaining = 512 - total_read;
-            match port.read(&mut response[total_read..total_read + remaining]) {
-                Ok(0) => {
-                    // EOF - connection closed
-                    if total_read == 0 {
-                        return Err(NapiError::from_reason("Connection closed during read"));
-                    }
-                    // Partial read - pad with zeros
-                    break;
-                }
-                Ok(n) => {
-                    total_read += n;
-                    // Continue reading until we have exactly 512 bytes
-                }
-                Err(e) => {
-                    // Check if it's a timeout or would-block
-                    if e.kind() == std::io::ErrorKind::TimedOut || e.kind() == std::io::ErrorKind::WouldBlock {
-                        // No data available yet - check our timeout and continue
-                        if start_time.elapsed() > timeout {
-                            if total_read == 0 {
-                                return Err(NapiError::from_reason("Read timeout - no data received"));
-                            }
-                            break;
-                        }
-                        // Wait a bit before retrying (10ms like before)
-                        std::thread::sleep(Duration::from_millis(10));
-                        continue;
-                    }
-                    return Err(NapiError::from_reason(
-                        format!("Read error: {}", e)
-                    ));
-                }
-            }
-        }
+/// Host filesystem error, prefixed so it can't be mistaken for a device error
+pub(crate) fn host_io_error(action: &str, path: &str, e: std::io::Error) -> CoreError {
+    CoreError::new(ErrorCode::IoError, format!("HostIoError: failed to {} {}: {}", action, path, e))
+}
 
-        // Validate response magic header (matching C# validation at lines 697-698)
-        if response[0] != 0x55 || response[1] != 0x53 || response[2] != 0x42 || response[3] != 0x41 {
-            return Err(NapiError::from_reason(
-                format!("Invalid response magic header: {:02x} {:02x} {:02x} {:02x} (expected USBA)",
-                    response[0], response[1], response[2], response[3])
-            ));
-        }
-        
-        // Validate response opcode (matching C# line 30: response[4] should be RESPONSE opcode = 15)
-        // C# checks: numArray[4] == usbint_server_opcode_e.RESPONSE
-        const RESPONSE_OPCODE: u8 = 15;
-        if response[4] != RESPONSE_OPCODE {
-            return Err(NapiError::from_reason(
-                format!("Response Error Request: {} space: {} flags: {} Response: {}",
-                    opcode, space, flags, response[4])
-            ));
-        }
+/// Temporary file a download to `host_path` is written to: ".<name>.part" in the same
+/// directory, so the final rename stays on one filesystem
+fn host_temp_path(host_path: &str) -> std::path::PathBuf {
+    let path = std::path::Path::new(host_path);
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    path.with_file_name(format!(".{}.part", name))
+}
 
-        Ok(response)
-    }
+/// INFO reply fields by name (see device_info() and parse_device_info())
+#[napi(object)]
+pub struct DeviceInfo {
+    /// Firmware version string, e.g. "1.11.0"
+    pub firmware_version: String,
+    /// Firmware revision number in uppercase hex (empty if the firmware reports 0)
+    pub version_string: String,
+    /// Path of the running ROM (the menu when no game is running)
+    pub rom_running: String,
+    /// Feature flags, e.g. ["FEAT_MSU1", "FEAT_CMD_UNLOCK"]
+    pub features: Vec<String>,
+}
 
-    /// Get port name
-    #[napi]
-    pub fn port_name(&self) -> Option<String> {
-        self.port_name.lock().unwrap().clone()
+impl DeviceInfo {
+    /// From parse_info_response()'s field list
+    fn from_fields(fields: Vec<String>) -> Self {
+        let mut fields = fields.into_iter();
+        let mut next = || fields.next().unwrap_or_default();
+        let (firmware_version, version_string, rom_running, flags) = (next(), next(), next(), next());
+        DeviceInfo {
+            firmware_version,
+            version_string,
+            rom_running,
+            features: flags.split('|').filter(|f| !f.is_empty()).map(String::from).collect(),
+        }
     }
+}
 
+/// parse_info_response() with named fields
+#[napi]
+pub fn parse_device_info(response: Vec<u8>) -> Result<DeviceInfo> {
+    parse_info_response(response).map(DeviceInfo::from_fields)
 }
 
 /// Parse INFO response (matching Core lines 911-934)
 /// Returns: [firmwareVersion, versionString, romRunning, flagString1, flagString2]
+/// See parse_device_info() for the same fields by name
 #[napi]
-pub fn parse_info_response(response: Vec<u8>) -> NapiResult<Vec<String>> {
+pub fn parse_info_response(response: Vec<u8>) -> Result<Vec<String>> {
     if response.len() < 512 {
-        return Err(NapiError::from_reason("Response too short"));
+        return Err(CoreError::new(ErrorCode::InvalidResponse, "Response too short"));
     }
 
     let mut result = Vec::new();
@@ -481,9 +2506,9 @@ pub fn parse_info_response(response: Vec<u8>) -> NapiResult<Vec<String>> {
 
 /// Parse GET response (returns data size as u32 from bytes 252-255)
 #[napi]
-pub fn parse_get_response(response: Vec<u8>) -> NapiResult<u32> {
+pub fn parse_get_response(response: Vec<u8>) -> Result<u32> {
     if response.len() < 256 {
-        return Err(NapiError::from_reason("Response too short"));
+        return Err(CoreError::new(ErrorCode::InvalidResponse, "Response too short"));
     }
 
     // GET response: Size at bytes 252-255 (big-endian uint32, matching C# line 675)
@@ -495,42 +2520,520 @@ pub fn parse_get_response(response: Vec<u8>) -> NapiResult<u32> {
     Ok(size)
 }
 
+/// Command flags byte (byte 6) of a RESPONSE packet, one field per bit
+/// Meaning depends on the opcode that was answered:
+/// - INFO: the byte is the feature set instead (see parse_info_response())
+/// - GET/PUT/VGET/VPUT: the request flags are echoed; `data64b` set means the data
+///   phase uses 64-byte blocks, `stream_burst` that a stream is active
+/// - BOOT/RESET/MENU_RESET: `skipreset`/`onlyreset` echo what was requested
+/// - other opcodes: no defined bits; check `raw` only for diagnostics
+#[napi(object)]
+pub struct ResponseFlags {
+    pub raw: u32,
+    pub skipreset: bool,
+    pub onlyreset: bool,
+    pub clrx: bool,
+    pub setx: bool,
+    pub stream_burst: bool,
+    pub noresp: bool,
+    #[napi(js_name = "data64b")]
+    pub data64b: bool,
+}
+
+/// Header fields of a RESPONSE packet
+#[napi(object)]
+pub struct ParsedResponse {
+    /// Byte 5: non-zero when the device reports a failure
+    pub error_code: u32,
+    pub flags: ResponseFlags,
+    /// Bytes 252-255: data phase size (GET/LS) or echoed size
+    pub size: u32,
+}
+
+/// Parse the header of a RESPONSE packet, including the flags byte
+#[napi]
+pub fn parse_response(response: Vec<u8>) -> Result<ParsedResponse> {
+    if response.len() < 256 {
+        return Err(CoreError::new(ErrorCode::InvalidResponse, "Response too short"));
+    }
+
+    let flags = response[6];
+    Ok(ParsedResponse {
+        error_code: response[5] as u32,
+        flags: ResponseFlags {
+            raw: flags as u32,
+            skipreset: (flags & 1) != 0,
+            onlyreset: (flags & 2) != 0,
+            clrx: (flags & 4) != 0,
+            setx: (flags & 8) != 0,
+            stream_burst: (flags & 16) != 0,
+            noresp: (flags & NORESP_FLAG) != 0,
+            data64b: (flags & 128) != 0,
+        },
+        size: parse_get_response(response)?,
+    })
+}
+
+/// One LS entry (see parse_ls_response())
+#[napi(object)]
+pub struct LsEntry {
+    /// 0 for a file, 1 for a directory
+    pub file_type: u32,
+    pub name: String,
+    /// Last modification, ms since the Unix epoch
+    /// Always None today: the firmware's LS entries are just type byte and name. Kept
+    /// so a file browser can sort by date once a firmware reports FAT timestamps (see
+    /// fat_timestamp_to_ms()).
+    pub modified_ms: Option<f64>,
+}
+
+/// Result of parse_ls_response()
+#[napi(object)]
+pub struct LsParseResult {
+    /// Entries found, without "." and ".."
+    pub entries: Vec<LsEntry>,
+    /// Bytes consumed: just past the terminator, at the start of block padding, or at the
+    /// start of an entry cut off by the end of the buffer (prepend buffer[cursor..] to the next block)
+    pub cursor: u32,
+    /// The 0xFF terminator was reached; no further blocks follow
+    pub terminated: bool,
+}
+
+/// Parse one or more concatenated LS data blocks
+/// A listing that neither terminates nor ends in padding continues in the next block.
+#[napi]
+pub fn parse_ls_response(response: Vec<u8>) -> LsParseResult {
+    let (entries, cursor, terminated) = parse_ls_response_internal(&response);
+    LsParseResult {
+        entries: entries.into_iter()
+            .map(|(file_type, name)| LsEntry { file_type: file_type as u32, name, modified_ms: None })
+            .collect(),
+        cursor: cursor as u32,
+        terminated,
+    }
+}
+
+/// FAT directory entry date and time words, as the SD card stores them
+/// date: bits 15-9 year since 1980, 8-5 month, 4-0 day;
+/// time: bits 15-11 hour, 10-5 minute, 4-0 seconds / 2
+#[napi(object)]
+pub struct FatTimestamp {
+    pub date: u32,
+    pub time: u32,
+}
+
+/// Days from 1970-01-01 to a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Inverse of days_from_civil(): (year, month, day)
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    (year_of_era + era * 400 + (month <= 2) as i64, month, day)
+}
+
+/// Convert a FAT date/time to ms since the Unix epoch (for `new Date(ms)`)
+/// FAT stores local time without a zone; it is read as UTC so sorting is stable.
+#[napi]
+pub fn fat_timestamp_to_ms(timestamp: FatTimestamp) -> Result<f64> {
+    let FatTimestamp { date, time } = timestamp;
+    let (year, month, day) = (1980 + ((date >> 9) & 0x7F) as i64, ((date >> 5) & 0x0F) as i64, (date & 0x1F) as i64);
+    let (hour, minute, second) = (((time >> 11) & 0x1F) as i64, ((time >> 5) & 0x3F) as i64, (time & 0x1F) as i64 * 2);
+    if date > 0xFFFF || time > 0xFFFF || !(1..=12).contains(&month) || day == 0 || hour > 23 || minute > 59 || second > 59 {
+        return Err(CoreError::new(ErrorCode::InvalidResponse, format!(
+            "Invalid FAT timestamp: date 0x{:04X} time 0x{:04X}", date, time
+        )));
+    }
+    let seconds = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second;
+    Ok((seconds * 1000) as f64)
+}
+
+/// Convert ms since the Unix epoch to a FAT date/time (UTC, rounded down to 2s)
+/// FAT can represent 1980-01-01 through 2107-12-31.
+#[napi]
+pub fn ms_to_fat_timestamp(ms: f64) -> Result<FatTimestamp> {
+    let out_of_range = || CoreError::new(ErrorCode::ArgValidation, format!(
+        "{} ms is outside the FAT timestamp range (1980-2107)", ms
+    ));
+    if !ms.is_finite() {
+        return Err(out_of_range());
+    }
+    let seconds = (ms / 1000.0).floor() as i64;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    if !(1980..=2107).contains(&year) {
+        return Err(out_of_range());
+    }
+    let second_of_day = seconds.rem_euclid(86_400);
+    Ok(FatTimestamp {
+        date: (((year - 1980) << 9) | (month << 5) | day) as u32,
+        time: (((second_of_day / 3600) << 11) | ((second_of_day / 60 % 60) << 5) | (second_of_day % 60 / 2)) as u32,
+    })
+}
+
+/// Whether LS data at `offset` is block padding: a 0 type byte with no name
+/// (type 0 is otherwise a regular file entry, so it can't be the terminator)
+fn is_ls_padding(response: &[u8], offset: usize) -> bool {
+    response.get(offset) == Some(&0) && response.get(offset + 1).is_none_or(|&b| b == 0)
+}
+
 /// Parse LS response and return as Vec of (type, filename) tuples
 /// Format: (type byte, filename null-terminated) pairs starting at byte 0
 /// C# format: List<(int, string)> where int is type (0=file, 1=dir) and string is filename
-/// Returns Vec of (type, filename) where type is 0 for file, 1 for dir
-fn parse_ls_response_internal(response: &[u8]) -> Vec<(u8, String)> {
+/// Returns (entries, cursor, terminated): see LsParseResult for the cursor
+fn parse_ls_response_internal(response: &[u8]) -> (Vec<(u8, String)>, usize, bool) {
     let mut files: Vec<(u8, String)> = Vec::new();
     let mut offset = 0;
     
-    while offset < response.len().min(512) {
-        if response[offset] == 0 || response[offset] == 0xFF {
-            break;
+    while offset < response.len() {
+        // 0xFF ends the listing; padding ends this block's data
+        if response[offset] == 0xFF {
+            return (files, offset + 1, true);
         }
-        
-        let file_type = response[offset];
-        offset += 1;
-        
-        if offset >= response.len() {
+        if is_ls_padding(response, offset) {
             break;
         }
         
-        let filename_start = offset;
-        while offset < response.len() && response[offset] != 0 {
-            offset += 1;
-        }
+        let file_type = response[offset];
+        let filename_start = offset + 1;
+        let filename_end = match response[filename_start.min(response.len())..].iter().position(|&b| b == 0) {
+            Some(len) => filename_start + len,
+            // Entry cut off by the end of the buffer: leave it for the next block
+            None => break,
+        };
         
-        if offset > filename_start {
-            let filename_bytes = &response[filename_start..offset];
+        if filename_end > filename_start {
+            let filename_bytes = &response[filename_start..filename_end];
             let filename = String::from_utf8_lossy(filename_bytes).to_string();
             if filename != "." && filename != ".." {
                 files.push((file_type, filename));
             }
         }
         
-        offset += 1;
+        offset = filename_end + 1;
     }
     
-    files
+    (files, offset, false)
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::{SimulatedFailure, SimulatorProfile};
+    use std::collections::VecDeque;
+    use std::io;
+
+    // Test binaries run without Node, so nothing provides the napi calls the event code
+    // links against; they are never reached, since tests register no JS listeners
+    #[no_mangle]
+    extern "C" fn napi_call_threadsafe_function() -> i32 {
+        unreachable!("napi call in a test binary")
+    }
+
+    #[no_mangle]
+    extern "C" fn napi_release_threadsafe_function() -> i32 {
+        unreachable!("napi call in a test binary")
+    }
+
+    #[test]
+    fn build_packet_with_empty_args() {
+        // Opcodes taking arguments reject an empty list; the others still build a packet
+        for opcode in 0..=14u8 {
+            let takes_args = !matches!(opcode, 8 | 10 | 11 | 12 | 13);
+            for args in [None, Some(Vec::new())] {
+                let name = format!("opcode {} with args {:?}", opcode, args);
+                match build_packet(opcode, SPACE_SNES, 0, args) {
+                    Ok(packet) => {
+                        assert!(!takes_args, "{} built a packet", name);
+                        assert_eq!(packet.len(), PACKET_SIZE, "{}", name);
+                        assert_eq!(&packet[..7], &[0x55, 0x53, 0x42, 0x41, opcode, SPACE_SNES, 0], "{}", name);
+                        assert!(packet[7..].iter().all(|&b| b == 0), "{}", name);
+                    }
+                    Err(error) => {
+                        assert!(takes_args, "{} failed: {}", name, error.reason);
+                        assert_eq!(error.code, ErrorCode::ArgValidation, "{}", name);
+                        assert!(error.reason.contains("missing arg[0]"), "{}: {}", name, error.reason);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn get_put_packet_layout() {
+        // Size at 252-255 and address at 256-259, both big-endian; nothing else past the header
+        for opcode in [0u8, 1] {
+            let packet = build_packet(opcode, SPACE_SNES, DATA64B_FLAG, Some(vec!["F51234".into(), "1A0".into()])).unwrap();
+            let mut expected = vec![0u8; PACKET_SIZE];
+            expected[..7].copy_from_slice(&[0x55, 0x53, 0x42, 0x41, opcode, SPACE_SNES, DATA64B_FLAG]);
+            expected[252..260].copy_from_slice(&[0x00, 0x00, 0x01, 0xA0, 0x00, 0xF5, 0x12, 0x34]);
+            assert_eq!(packet, expected, "opcode {}", opcode);
+        }
+    }
+
+    #[test]
+    fn vget_packet_holds_eight_pairs() {
+        let pairs: Vec<(u8, u32)> = (0..VGET_MAX_PAIRS as u32).map(|i| (0x10 + i as u8, 0xF50000 + i * 0x100)).collect();
+        let packet = vget_packet(SPACE_SNES, &pairs).unwrap();
+        assert_eq!(&packet[4..7], &[2, SPACE_SNES, DATA64B_FLAG]);
+        for (i, &(size, address)) in pairs.iter().enumerate() {
+            let offset = VGET_PAIRS_OFFSET + i * VGET_PAIR_LEN;
+            assert_eq!(packet[offset], size, "pair {} size", i);
+            assert_eq!(&packet[offset + 1..offset + 5], &address.to_be_bytes(), "pair {} address", i);
+        }
+        // The last pair ends at byte 72, well before the GET/PUT fields at 252
+        assert_eq!(VGET_PAIRS_OFFSET + VGET_MAX_PAIRS * VGET_PAIR_LEN, 72);
+        assert!(packet[72..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn vget_packet_rejects_a_ninth_pair() {
+        let pairs: Vec<(u8, u32)> = (0..VGET_MAX_PAIRS as u32 + 1).map(|i| (1, i)).collect();
+        let error = vget_packet(SPACE_SNES, &pairs).unwrap_err();
+        assert_eq!(error.code, ErrorCode::ArgValidation);
+        assert!(error.reason.contains("need 2 <= args <= 16"), "{}", error.reason);
+    }
+
+    #[test]
+    fn disconnect_stops_waiting_after_grace_period() {
+        let core = Usb2SnesCore::new();
+        let profile = SimulatorProfile { latency_ms: Some(300), ..Default::default() };
+        core.connect_simulated(Some(profile), None).unwrap();
+
+        let reader = core.clone();
+        let read = std::thread::spawn(move || {
+            reader.with_connection(|conn| get_locked(conn, SPACE_SNES, 0xF50000, 0x1000))
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        let started = Instant::now();
+        assert!(matches!(core.disconnect(Some(20)), Ok(DisconnectKind::Forced)));
+        assert!(started.elapsed() < Duration::from_millis(200), "disconnect() waited {:?}", started.elapsed());
+
+        let error = read.join().unwrap().unwrap_err();
+        assert_eq!(error.code, ErrorCode::Cancelled, "{}", error.reason);
+        assert!(!core.is_connected());
+    }
+
+    #[test]
+    fn info_fields_by_name() {
+        let core = Usb2SnesCore::new();
+        let profile = SimulatorProfile {
+            firmware_version: Some("1.10.3".into()),
+            rom_running: Some("/roms/smw.sfc".into()),
+            features: Some(vec!["FEAT_MSU1".into(), "FEAT_CMD_UNLOCK".into()]),
+            ..Default::default()
+        };
+        core.connect_simulated(Some(profile), None).unwrap();
+
+        let info = core.info().unwrap();
+        assert_eq!(info.firmware_version, "1.10.3");
+        assert_eq!(info.rom_running, "/roms/smw.sfc");
+        assert_eq!(info.features, ["FEAT_MSU1", "FEAT_CMD_UNLOCK"]);
+        assert!(!info.version_string.is_empty());
+    }
+
+    #[test]
+    fn mock_port_name_connects_the_simulator() {
+        let core = Usb2SnesCore::new();
+        core.connect("mock://".into()).unwrap();
+        assert!(core.is_connected());
+        assert_eq!(core.info().unwrap().rom_running, "/sd2snes/menu.bin");
+
+        core.with_connection(|conn| put_flagged_locked(conn, SPACE_SNES, 0, 0xF50010, &[1, 2, 3])).unwrap();
+        assert_eq!(core.get_memory_with(0xF50010, 3, None, 0, None).unwrap(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn simulator_reports_no_line_control() {
+        let core = Usb2SnesCore::new();
+        core.connect_simulated(None, None).unwrap();
+        let caps = core.capabilities().unwrap();
+        assert!(!caps.dtr_control && !caps.rts_control && !caps.signal_read && !caps.break_support);
+    }
+
+    #[test]
+    fn short_put_acknowledgement_sends_no_data() {
+        let core = Usb2SnesCore::new();
+        core.connect_simulated(None, None).unwrap();
+        let sim = core.with_connection(|conn| Ok(conn.simulator.clone().unwrap())).unwrap();
+
+        sim.lock().unwrap().fail_next(SimulatedFailure::ShortAck, 1);
+        let err = core.with_connection(|conn| put_locked(conn, SPACE_SNES, 0xF50000, &[7; 1024])).unwrap_err();
+        assert_eq!(err.reason, "ShortWrite: PUT of space 1 0xF50000 expected 1024 bytes, device acknowledged 512");
+        assert_eq!(core.get_memory_with(0xF50000, 1024, None, 0, None).unwrap(), vec![0; 1024]);
+
+        sim.lock().unwrap().fail_next(SimulatedFailure::ShortAck, 1);
+        let err = core.with_connection(|conn| put_file_locked(conn, "/short.sfc", &[7; 1024])).unwrap_err();
+        assert!(err.reason.starts_with("ShortWrite: PUT of /short.sfc"), "{}", err.reason);
+        assert_eq!(core.with_connection(|conn| lookup_entry_locked(conn, "/short.sfc")).unwrap(), None);
+
+        // The line is back in sync for the next PUT
+        core.with_connection(|conn| put_file_locked(conn, "/short.sfc", &[7; 1024])).unwrap();
+        assert_eq!(core.with_connection(|conn| get_file_locked(conn, "/short.sfc")).unwrap(), vec![7; 1024]);
+    }
+
+    #[test]
+    fn failed_download_keeps_the_existing_host_file() {
+        let core = Usb2SnesCore::new();
+        core.connect_simulated(None, None).unwrap();
+        core.with_connection(|conn| put_file_locked(conn, "/save.srm", &[2; 700])).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("usb2snes-download-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let host_path = dir.join("backup.srm");
+        let host = host_path.to_str().unwrap();
+        std::fs::write(&host_path, [1; 300]).unwrap();
+
+        let error = core.download_host_file("/missing.srm", host, |_, _| Ok(())).unwrap_err();
+        assert_ne!(error.code, ErrorCode::IoError, "{}", error.reason);
+        assert_eq!(std::fs::read(&host_path).unwrap(), [1; 300]);
+        assert!(!host_temp_path(host).exists());
+
+        assert_eq!(core.download_host_file("/save.srm", host, |_, _| Ok(())).unwrap(), 700);
+        assert_eq!(std::fs::read(&host_path).unwrap(), [2; 700]);
+        assert!(!host_temp_path(host).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn disconnect_is_graceful_when_idle() {
+        let core = Usb2SnesCore::new();
+        core.connect_simulated(None, None).unwrap();
+        assert!(matches!(core.disconnect(None), Ok(DisconnectKind::Graceful)));
+        assert!(!core.is_connected());
+    }
+
+    /// A device that answers LS with `listing`, sent as-is in 512-byte blocks
+    struct LsDevice {
+        listing: Vec<u8>,
+        input: VecDeque<u8>,
+    }
+
+    impl Transport for LsDevice {
+        fn write_packet(&mut self, packet: &[u8]) -> Result<()> {
+            assert_eq!(packet[4], 4, "LsDevice only answers LS");
+            let mut response = vec![0u8; PACKET_SIZE];
+            response[..5].copy_from_slice(b"USBA\x0F");
+            self.input.extend(response);
+            self.input.extend(&self.listing);
+            Ok(())
+        }
+
+        fn read_packet(&mut self, buf: &mut [u8], deadlines: ReadDeadlines) -> Result<()> {
+            self.read_data(buf, deadlines)
+        }
+
+        fn read_data(&mut self, block: &mut [u8], _: ReadDeadlines) -> Result<()> {
+            if self.input.len() < block.len() {
+                return Err(CoreError::new(ErrorCode::Timeout, "Timeout: read past the end of the listing"));
+            }
+            let len = block.len();
+            for (slot, byte) in block.iter_mut().zip(self.input.drain(..len)) {
+                *slot = byte;
+            }
+            Ok(())
+        }
+
+        fn write_data(&mut self, _: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_timeout(&mut self, _: Duration) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn drain_input(&mut self, _: Duration, _: Duration) -> Result<u32> {
+            let drained = self.input.len() as u32;
+            self.input.clear();
+            Ok(drained)
+        }
+    }
+
+    /// LS data for `entries`: type byte and NUL-terminated name each, then the 0xFF
+    /// terminator, zero-padded to whole blocks
+    fn ls_blocks(entries: &[(u8, String)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (file_type, name) in entries {
+            data.push(*file_type);
+            data.extend_from_slice(name.as_bytes());
+            data.push(0);
+        }
+        data.push(0xFF);
+        data.resize(data.len().div_ceil(512) * 512, 0);
+        data
+    }
+
+    /// list_dir_locked() against a device sending `listing`
+    fn list_from(listing: Vec<u8>) -> Vec<(u8, String)> {
+        let device = LsDevice { listing, input: VecDeque::new() };
+        let mut conn = Connection::new(
+            Box::new(device),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        );
+        let listing = list_dir_locked(&mut conn, "/").unwrap().unwrap();
+        assert_eq!(conn.transport.drain_input(Duration::ZERO, Duration::ZERO).unwrap(), 0, "listing left unread");
+        listing
+    }
+
+    #[test]
+    fn ls_reads_two_blocks() {
+        // 40 entries of 14 bytes: the terminator lands in the second block
+        let entries: Vec<(u8, String)> = (0..40).map(|i| ((i % 2) as u8, format!("file{:04}.sfc", i))).collect();
+        let listing = ls_blocks(&entries);
+        assert_eq!(listing.len(), 1024);
+        assert_eq!(list_from(listing), entries);
+    }
+
+    #[test]
+    fn ls_carries_a_name_across_block_boundaries() {
+        // Names long enough to run past the first block's end and over the second,
+        // with one entry's name split across the 512 and another across the 1024 boundary
+        let mut entries: Vec<(u8, String)> = (0..5).map(|i| (0, format!("{:03}-{}", i, "a".repeat(94)))).collect();
+        entries.push((1, format!("straddle-{}", "b".repeat(60))));
+        entries.extend((5..9).map(|i| (0, format!("{:03}-{}", i, "c".repeat(94)))));
+        entries.push((0, format!("second-straddle-{}", "d".repeat(60))));
+        entries.push((0, "last.sfc".to_string()));
+        let listing = ls_blocks(&entries);
+        assert_eq!(listing.len(), 1536);
+
+        // Each straddling name starts in one block and ends in the next
+        for (name, boundary) in [("straddle-", 512), ("second-straddle-", 1024)] {
+            let start = listing.windows(name.len()).position(|w| w == name.as_bytes()).unwrap();
+            let end = start + listing[start..].iter().position(|&b| b == 0).unwrap();
+            assert!(start < boundary && end > boundary, "{} spans {}..{}", name, start, end);
+        }
+        assert_eq!(list_from(listing), entries);
+    }
+
+    #[test]
+    fn ls_block_parse_reports_the_carried_tail() {
+        // The first block alone parses up to the start of the straddling entry
+        let entries: Vec<(u8, String)> = (0..5).map(|i| (0, format!("{:03}-{}", i, "a".repeat(94))))
+            .chain([(1, format!("straddle-{}", "b".repeat(60)))])
+            .collect();
+        let listing = ls_blocks(&entries);
+        let (parsed, cursor, terminated) = parse_ls_response_internal(&listing[..512]);
+        assert_eq!(parsed, entries[..5]);
+        assert_eq!(cursor, 5 * 100);
+        assert!(!terminated);
+        assert_eq!(listing[cursor], 1, "tail starts at the straddling entry's type byte");
+    }
+}
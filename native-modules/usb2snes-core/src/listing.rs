@@ -0,0 +1,204 @@
+// Directory listings with file sizes for the menu file browser
+// LS only carries a type and a name per entry. The firmware has no LS variant with
+// sizes, so the only way to learn a size is a FILE GET, whose RESPONSE carries it;
+// the data phase that follows still has to be read off the line. Probes therefore
+// cost a whole download each and are opt-in: only as many files as the caller allows
+// are probed, in listing order, and the rest get no size.
+
+use napi_derive::napi;
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::queue::Lane;
+
+use crate::{download_file_locked, list_dir_locked, normalize_path, LsEntry, Usb2SnesCore, LS_TYPE_DIR};
+
+/// Default of ListDirOptions.max_size_probes: no probes unless asked for
+const DEFAULT_MAX_SIZE_PROBES: u32 = 0;
+
+/// Options for list_dir_detailed()
+#[napi(object)]
+#[derive(Default)]
+pub struct ListDirOptions {
+    /// Most files whose size is probed (default 0, no probing); the first this many
+    /// files of the listing get a size, the rest none
+    pub max_size_probes: Option<u32>,
+}
+
+/// One entry of list_dir_detailed()
+#[napi(object)]
+pub struct DetailedEntry {
+    pub name: String,
+    pub is_directory: bool,
+    /// Size in bytes; unset for directories and for files that weren't probed
+    pub size: Option<u32>,
+}
+
+/// Kind of an ls() entry
+#[napi(string_enum = "lowercase")]
+pub enum EntryKind {
+    File,
+    Dir,
+}
+
+/// One entry of ls()
+#[napi(object)]
+pub struct DirEntry {
+    pub kind: EntryKind,
+    pub name: String,
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// List a directory as { kind: "file" | "dir", name } entries, without "." and ".."
+    /// The listing is read across as many 512-byte LS blocks as it takes, up to the
+    /// 0xFF terminator.
+    #[napi]
+    pub fn ls(&self, path: String) -> Result<Vec<DirEntry>> {
+        let path = normalize_path(&path)?;
+        let listing = self.with_connection_replayable(Lane::Normal, |conn| list_dir_locked(conn, &path))?.ok_or_else(|| {
+            CoreError::new(ErrorCode::DeviceError, format!("LS failed for {}: directory not found", path))
+        })?;
+        Ok(listing.into_iter()
+            .map(|(file_type, name)| DirEntry {
+                kind: if file_type == LS_TYPE_DIR { EntryKind::Dir } else { EntryKind::File },
+                name,
+            })
+            .collect())
+    }
+
+    /// List one page of a directory: entries `page * page_size` onwards, at most `page_size`
+    /// (0 = all). LS can't resume from an offset, so page 0 (or no page) reads the whole
+    /// listing, across as many 512-byte LS blocks as it takes, and keeps it on the
+    /// connection; later pages of the same path are cut from that listing without
+    /// another LS. Pages are therefore consistent with each other. The listing is read
+    /// again for page 0, for another path, and after any command that may change the
+    /// device or a reconnect.
+    #[napi]
+    pub fn ls_paged(&self, path: String, page_size: u32, page: Option<u32>) -> Result<Vec<LsEntry>> {
+        let path = normalize_path(&path)?;
+        let page = page.unwrap_or(0);
+        let (skip, take) = match page_size {
+            0 => (0, usize::MAX),
+            size => (page as usize * size as usize, size as usize),
+        };
+
+        self.with_connection_replayable(Lane::Normal, |conn| {
+            let kept = matches!(&conn.ls_pages, Some((listed, _)) if *listed == path);
+            if page == 0 || !kept {
+                let listing = list_dir_locked(conn, &path)?.ok_or_else(|| {
+                    CoreError::new(ErrorCode::DeviceError, format!("LS failed for {}: directory not found", path))
+                })?;
+                conn.ls_pages = Some((path.clone(), listing));
+            }
+            let (_, listing) = conn.ls_pages.as_ref().unwrap();
+            Ok(listing.iter()
+                .skip(skip)
+                .take(take)
+                .map(|(file_type, name)| LsEntry { file_type: *file_type as u32, name: name.clone(), modified_ms: None })
+                .collect())
+        })
+    }
+
+    /// List a directory, with the sizes of the first `options.max_size_probes` files
+    /// Each probe downloads the file (see the module comment), releasing the port
+    /// between probes so other commands aren't held up. A probe that fails leaves
+    /// that file's size unset.
+    #[napi]
+    pub fn list_dir_detailed(&self, path: String, options: Option<ListDirOptions>) -> Result<Vec<DetailedEntry>> {
+        let path = normalize_path(&path)?;
+        let max_probes = options.unwrap_or_default().max_size_probes.unwrap_or(DEFAULT_MAX_SIZE_PROBES);
+
+        let listing = self.with_connection_replayable(Lane::Normal, |conn| list_dir_locked(conn, &path))?.ok_or_else(|| {
+            CoreError::new(ErrorCode::DeviceError, format!("LS failed for {}: directory not found", path))
+        })?;
+        let mut probes_left = max_probes;
+        let mut entries = Vec::with_capacity(listing.len());
+        for (file_type, name) in listing {
+            let is_directory = file_type == LS_TYPE_DIR;
+            let size = if probes_left > 0 && !is_directory {
+                probes_left -= 1;
+                let full_path = if path == "/" { format!("/{}", name) } else { format!("{}/{}", path, name) };
+                self.with_connection(|conn| download_file_locked(conn, &full_path, |_| Ok(()))).ok()
+            } else {
+                None
+            };
+            entries.push(DetailedEntry { name, is_directory, size });
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_file(core: &Usb2SnesCore, path: &str) {
+        core.with_connection(|conn| {
+            conn.simulator.as_ref().unwrap().lock().unwrap().add_file(path, vec![0; 4]);
+            Ok(())
+        }).unwrap();
+    }
+
+    fn names(page: Vec<LsEntry>) -> Vec<String> {
+        page.into_iter().map(|entry| entry.name).collect()
+    }
+
+    #[test]
+    fn ls_paged_serves_later_pages_from_the_page_0_listing() {
+        let core = Usb2SnesCore::new();
+        core.connect_simulated(None, None).unwrap();
+        for name in ["a", "c", "e", "g"] {
+            add_file(&core, &format!("/roms/{}.sfc", name));
+        }
+
+        assert_eq!(names(core.ls_paged("/roms".into(), 2, None).unwrap()), ["a.sfc", "c.sfc"]);
+        // A file appearing between pages doesn't shift them
+        add_file(&core, "/roms/b.sfc");
+        assert_eq!(names(core.ls_paged("/roms".into(), 2, Some(1)).unwrap()), ["e.sfc", "g.sfc"]);
+        assert_eq!(names(core.ls_paged("/roms".into(), 2, Some(2)).unwrap()), Vec::<String>::new());
+
+        // Page 0 reads the directory again
+        assert_eq!(names(core.ls_paged("/roms".into(), 2, Some(0)).unwrap()), ["a.sfc", "b.sfc"]);
+        assert_eq!(names(core.ls_paged("/roms".into(), 2, Some(1)).unwrap()), ["c.sfc", "e.sfc"]);
+    }
+
+    #[test]
+    fn ls_paged_reads_again_for_another_path_or_after_a_change() {
+        let core = Usb2SnesCore::new();
+        core.connect_simulated(None, None).unwrap();
+        add_file(&core, "/roms/a.sfc");
+        add_file(&core, "/saves/a.srm");
+
+        assert_eq!(names(core.ls_paged("/roms".into(), 1, None).unwrap()), ["a.sfc"]);
+        // A later page of a path that wasn't listed lists it first
+        add_file(&core, "/saves/b.srm");
+        assert_eq!(names(core.ls_paged("/saves".into(), 1, Some(1)).unwrap()), ["b.srm"]);
+
+        // Commands that may change the device drop the kept listing
+        add_file(&core, "/saves/c.srm");
+        let mkdir = crate::build_packet(5, crate::SPACE_FILE, 0, Some(vec!["/tmp".into()])).unwrap();
+        core.with_connection(|conn| crate::exchange(conn, &mkdir)).unwrap();
+        assert_eq!(names(core.ls_paged("/saves".into(), 1, Some(2)).unwrap()), ["c.srm"]);
+    }
+
+    #[test]
+    fn list_dir_detailed_probes_only_when_asked() {
+        let core = Usb2SnesCore::new();
+        core.connect_simulated(None, None).unwrap();
+        for name in ["a.srm", "b.srm", "c.srm"] {
+            add_file(&core, &format!("/saves/{}", name));
+        }
+        add_file(&core, "/saves/a_old/d.srm");
+
+        let sizes = |max_size_probes| core.list_dir_detailed("/saves".into(), Some(ListDirOptions { max_size_probes }))
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.name, entry.size))
+            .collect::<Vec<_>>();
+        let unsized_listing = vec![("a.srm".into(), None), ("a_old".into(), None), ("b.srm".into(), None), ("c.srm".into(), None)];
+        assert_eq!(sizes(None), unsized_listing);
+        assert_eq!(sizes(Some(0)), unsized_listing);
+        // Over the cap, the first files are still probed; directories don't use it up
+        assert_eq!(sizes(Some(2)), [("a.srm".into(), Some(4)), ("a_old".into(), None), ("b.srm".into(), Some(4)), ("c.srm".into(), None)]);
+        assert_eq!(sizes(Some(16)), [("a.srm".into(), Some(4)), ("a_old".into(), None), ("b.srm".into(), Some(4)), ("c.srm".into(), Some(4))]);
+    }
+}
@@ -0,0 +1,159 @@
+// Command macros: fixed sequences of reads/writes/delays/boots run in one native call
+// Frame-timed tricks break when every step crosses the NAPI boundary, so the
+// whole macro runs with the connection locked and no JS between steps.
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+use crate::errors::{CoreError, ErrorCode, Result};
+use std::time::Duration;
+
+use crate::{
+    get_locked, normalize_path, path_command_locked, put_locked, validate_address_range, Usb2SnesCore,
+};
+
+/// Kind of a macro step
+#[napi(string_enum)]
+pub enum MacroStepKind {
+    /// GET `size` bytes from `space` at `address`
+    Read,
+    /// PUT `data` to `space` at `address`
+    Write,
+    /// Sleep for `ms` milliseconds
+    Delay,
+    /// BOOT the ROM at `path`
+    BootFile,
+}
+
+/// One macro step; which fields are required depends on `kind`
+#[napi(object)]
+pub struct MacroStep {
+    pub kind: MacroStepKind,
+    pub space: Option<u8>,
+    pub address: Option<u32>,
+    pub size: Option<u32>,
+    pub data: Option<Buffer>,
+    pub ms: Option<u32>,
+    pub path: Option<String>,
+}
+
+/// Outcome of a macro step
+#[napi(string_enum)]
+pub enum MacroStepStatus {
+    Ok,
+    Failed,
+    /// Not run because an earlier step failed
+    Skipped,
+}
+
+/// Per-step result of run_macro()
+#[napi(object)]
+pub struct MacroResult {
+    pub index: u32,
+    pub status: MacroStepStatus,
+    /// Bytes read (Read steps only)
+    pub data: Option<Buffer>,
+    pub error: Option<String>,
+}
+
+/// A step after validation, with everything it needs resolved
+enum ValidStep<'a> {
+    Read { space: u8, address: u32, size: u32 },
+    Write { space: u8, address: u32, data: &'a [u8] },
+    Delay(Duration),
+    BootFile(String),
+}
+
+fn missing(index: usize, field: &str) -> CoreError {
+    CoreError::new(ErrorCode::ArgValidation, format!("Invalid macro step {}: missing `{}`", index, field))
+}
+
+fn validate_step(index: usize, step: &MacroStep) -> Result<ValidStep<'_>> {
+    let invalid = |e: CoreError| CoreError::new(ErrorCode::ArgValidation, format!("Invalid macro step {}: {}", index, e.reason));
+    match step.kind {
+        MacroStepKind::Read => {
+            let space = step.space.ok_or_else(|| missing(index, "space"))?;
+            let address = step.address.ok_or_else(|| missing(index, "address"))?;
+            let size = step.size.filter(|&s| s > 0).ok_or_else(|| missing(index, "size"))?;
+            validate_address_range(space, address, size).map_err(invalid)?;
+            Ok(ValidStep::Read { space, address, size })
+        }
+        MacroStepKind::Write => {
+            let space = step.space.ok_or_else(|| missing(index, "space"))?;
+            let address = step.address.ok_or_else(|| missing(index, "address"))?;
+            let data = step.data.as_deref().filter(|d| !d.is_empty()).ok_or_else(|| missing(index, "data"))?;
+            validate_address_range(space, address, data.len() as u32).map_err(invalid)?;
+            Ok(ValidStep::Write { space, address, data })
+        }
+        MacroStepKind::Delay => {
+            let ms = step.ms.ok_or_else(|| missing(index, "ms"))?;
+            Ok(ValidStep::Delay(Duration::from_millis(ms as u64)))
+        }
+        MacroStepKind::BootFile => {
+            let path = step.path.as_deref().ok_or_else(|| missing(index, "path"))?;
+            Ok(ValidStep::BootFile(normalize_path(path).map_err(invalid)?))
+        }
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Run a fixed sequence of steps back-to-back with the connection held
+    /// The whole macro is validated before the first step runs. Once a step fails
+    /// the remaining steps are reported as Skipped; a failed step never throws.
+    #[napi]
+    pub fn run_macro(&self, steps: Vec<MacroStep>) -> Result<Vec<MacroResult>> {
+        let valid = steps.iter()
+            .enumerate()
+            .map(|(index, step)| validate_step(index, step))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.with_connection(|conn| {
+            let mut results = Vec::with_capacity(valid.len());
+            let mut failed = false;
+
+            for (index, step) in valid.into_iter().enumerate() {
+                let mut result = MacroResult {
+                    index: index as u32,
+                    status: MacroStepStatus::Skipped,
+                    data: None,
+                    error: None,
+                };
+                if failed {
+                    results.push(result);
+                    continue;
+                }
+
+                let outcome = match step {
+                    ValidStep::Read { space, address, size } => {
+                        get_locked(conn, space, address, size).map(|data| Some(data.into()))
+                    }
+                    ValidStep::Write { space, address, data } => {
+                        put_locked(conn, space, address, data).map(|_| None)
+                    }
+                    ValidStep::Delay(duration) => {
+                        std::thread::sleep(duration);
+                        Ok(None)
+                    }
+                    ValidStep::BootFile(path) => {
+                        path_command_locked(conn, 9, "BOOT", vec![path]).map(|_| None)
+                    }
+                };
+
+                match outcome {
+                    Ok(data) => {
+                        result.status = MacroStepStatus::Ok;
+                        result.data = data;
+                    }
+                    Err(e) => {
+                        result.status = MacroStepStatus::Failed;
+                        result.error = Some(e.reason);
+                        failed = true;
+                    }
+                }
+                results.push(result);
+            }
+
+            Ok(results)
+        })
+    }
+}
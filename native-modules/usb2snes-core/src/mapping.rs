@@ -0,0 +1,563 @@
+// SNES bus address <-> ROM file offset translation (LoROM/HiROM/ExHiROM)
+// The FxPak exposes the loaded ROM linearly at SNES space 0x000000, so bus
+// addresses from patch tools and cheat databases must be converted first.
+// snes_bus_to_fxpak() goes further and also resolves WRAM and SRAM bus addresses
+// to the FxPak's bases for them (see MemoryRegion::layout()).
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+use crate::errors::{CoreError, ErrorCode, Result};
+
+use crate::copier::copier_header_len;
+use crate::regions::MemoryRegion;
+use crate::{get_locked, put_locked, Usb2SnesCore, SPACE_SNES};
+
+/// Cartridge memory mapping mode
+#[allow(clippy::enum_variant_names)]
+#[napi]
+#[derive(Debug, PartialEq, Eq)]
+pub enum MemoryMapping {
+    LoRom,
+    HiRom,
+    ExHiRom,
+}
+
+/// A SNES bus address split into bank and 16-bit address
+#[napi(object)]
+pub struct SnesBusAddress {
+    pub bank: u32,
+    pub addr: u32,
+}
+
+/// Where a SNES bus address lands in the FxPak's SNES space
+#[napi(object)]
+pub struct FxPakAddress {
+    pub region: MemoryRegion,
+    /// Offset within the region
+    pub offset: u32,
+    /// SNES space address (region base + offset)
+    pub address: u32,
+}
+
+/// A ROM location given either as a linear file offset or as a bus address
+/// Exactly one of `offset` or `bank`/`addr` must be set; `mapping` applies to bus
+/// addresses and is auto-detected from the ROM header when omitted
+#[napi(object)]
+pub struct RomLocation {
+    pub offset: Option<u32>,
+    pub bank: Option<u32>,
+    pub addr: Option<u32>,
+    pub mapping: Option<MemoryMapping>,
+}
+
+/// Internal ROM header (the 32 bytes at bus $00:FFC0)
+#[napi(object)]
+pub struct RomHeader {
+    pub title: String,
+    pub mapping: MemoryMapping,
+    pub map_mode: u32,
+    pub rom_type: u32,
+    /// ROM size in KB (1 << size byte)
+    pub rom_size_kb: u32,
+    /// SRAM size in KB (0 if none)
+    pub sram_size_kb: u32,
+    pub region: u32,
+    pub version: u32,
+    pub checksum: u32,
+    pub complement: u32,
+    /// ROM file offset the header was found at
+    pub header_offset: u32,
+}
+
+/// ROM file offsets of the header for each mapping
+const HEADER_OFFSETS: [(u32, MemoryMapping); 3] = [
+    (0x007FC0, MemoryMapping::LoRom),
+    (0x00FFC0, MemoryMapping::HiRom),
+    (0x40FFC0, MemoryMapping::ExHiRom),
+];
+
+const HEADER_LEN: usize = 32;
+
+fn non_rom_error(bank: u32, addr: u32) -> CoreError {
+    CoreError::new(ErrorCode::ArgValidation, format!(
+        "${:02X}:{:04X} does not map to ROM (WRAM, registers or SRAM)", bank, addr
+    ))
+}
+
+/// Convert a SNES bus address to a linear ROM file offset
+/// Mirrors (e.g. FastROM banks $80+) resolve to the same offset; addresses that
+/// hit WRAM, I/O registers or SRAM are rejected rather than producing a bogus offset
+#[napi]
+pub fn snes_bus_to_rom_offset(mapping: MemoryMapping, bank: u32, addr: u32) -> Result<u32> {
+    if bank > 0xFF || addr > 0xFFFF {
+        return Err(CoreError::new(ErrorCode::ArgValidation, format!("Invalid bus address ${:X}:{:X}", bank, addr)));
+    }
+    // $7E-$7F is WRAM in every mapping
+    if bank == 0x7E || bank == 0x7F {
+        return Err(non_rom_error(bank, addr));
+    }
+
+    match mapping {
+        MemoryMapping::LoRom => {
+            // 32KB banks at $8000-$FFFF; $40-$6F/$C0-$EF lower halves mirror the upper half
+            let low = bank & 0x7F;
+            if addr < 0x8000 && !(0x40..0x70).contains(&low) {
+                return Err(non_rom_error(bank, addr));
+            }
+            Ok(low * 0x8000 + (addr & 0x7FFF))
+        }
+        MemoryMapping::HiRom => {
+            // 64KB banks at $C0-$FF (mirrored at $40-$7D, upper halves at $00-$3F/$80-$BF)
+            if bank & 0x40 == 0 && addr < 0x8000 {
+                return Err(non_rom_error(bank, addr));
+            }
+            Ok(((bank & 0x3F) << 16) | addr)
+        }
+        MemoryMapping::ExHiRom => {
+            // $C0-$FF = first 4MB, $40-$7D = second 4MB, $80-$BF/$00-$3F upper halves mirror them
+            if bank & 0x40 == 0 && addr < 0x8000 {
+                return Err(non_rom_error(bank, addr));
+            }
+            let upper = if bank & 0x80 == 0 { 0x400000 } else { 0 };
+            Ok(upper | ((bank & 0x3F) << 16) | addr)
+        }
+    }
+}
+
+/// WRAM offset of a bus address: $7E-$7F, or the low 8KB mirrored in banks $00-$3F/$80-$BF
+fn wram_offset(bank: u32, addr: u32) -> Option<u32> {
+    match bank {
+        0x7E | 0x7F => Some(((bank - 0x7E) << 16) | addr),
+        _ if bank & 0x40 == 0 && addr < 0x2000 => Some(addr),
+        _ => None,
+    }
+}
+
+/// SRAM offset of a bus address
+/// LoROM: 32KB pages at $70-$7D/$F0-$FF:0000-7FFF; HiROM/ExHiROM: 8KB pages at
+/// $20-$3F/$A0-$BF:6000-7FFF
+fn sram_offset(mapping: MemoryMapping, bank: u32, addr: u32) -> Option<u32> {
+    let low = bank & 0x7F;
+    match mapping {
+        MemoryMapping::LoRom if (0x70..0x80).contains(&low) && addr < 0x8000 => Some((low - 0x70) * 0x8000 + addr),
+        MemoryMapping::HiRom | MemoryMapping::ExHiRom if (0x20..0x40).contains(&low) && (0x6000..0x8000).contains(&addr) => {
+            Some((low - 0x20) * 0x2000 + addr - 0x6000)
+        }
+        _ => None,
+    }
+}
+
+/// Bus address of an SRAM offset (the inverse of sram_offset())
+/// LoROM pages 14-15 sit in banks $FE-$FF, since $7E-$7F are WRAM
+pub(crate) fn sram_offset_to_snes_bus(mapping: MemoryMapping, offset: u32) -> Option<SnesBusAddress> {
+    match mapping {
+        MemoryMapping::LoRom if offset < 0x80000 => {
+            let bank = 0x70 + (offset >> 15);
+            Some(SnesBusAddress { bank: if bank < 0x7E { bank } else { bank | 0x80 }, addr: offset & 0x7FFF })
+        }
+        MemoryMapping::HiRom | MemoryMapping::ExHiRom if offset < 0x40000 => {
+            Some(SnesBusAddress { bank: 0x20 + (offset >> 13), addr: 0x6000 + (offset & 0x1FFF) })
+        }
+        _ => None,
+    }
+}
+
+/// Convert a SNES bus address to the FxPak SNES-space address holding it
+/// WRAM (including the $0000-$1FFF mirror), SRAM and ROM are resolved; I/O registers
+/// and open bus are rejected. `mapping` decides the SRAM and ROM layouts.
+#[napi]
+pub fn snes_bus_to_fxpak(mapping: MemoryMapping, bank: u32, addr: u32) -> Result<FxPakAddress> {
+    if bank > 0xFF || addr > 0xFFFF {
+        return Err(CoreError::new(ErrorCode::ArgValidation, format!("Invalid bus address ${:X}:{:X}", bank, addr)));
+    }
+    let (region, offset) = if let Some(offset) = wram_offset(bank, addr) {
+        (MemoryRegion::Wram, offset)
+    } else if let Some(offset) = sram_offset(mapping, bank, addr) {
+        (MemoryRegion::Sram, offset)
+    } else if bank & 0x40 == 0 && addr < 0x8000 {
+        return Err(CoreError::new(ErrorCode::ArgValidation, format!(
+            "${:02X}:{:04X} is an I/O register or open bus, not memory the FxPak exposes", bank, addr
+        )));
+    } else {
+        (MemoryRegion::Rom, snes_bus_to_rom_offset(mapping, bank, addr)?)
+    };
+    let (_, address) = region.resolve(offset, 0)?;
+    Ok(FxPakAddress { region, offset, address })
+}
+
+/// Convert a linear ROM file offset to its canonical SNES bus address
+/// LoROM uses banks $00-$7D then the $FE-$FF FastROM mirror, HiROM uses $C0-$FF,
+/// ExHiROM uses $C0-$FF for the first 4MB and $40-$7D/$3E-$3F for the second
+#[napi]
+pub fn rom_offset_to_snes_bus(mapping: MemoryMapping, offset: u32) -> Result<SnesBusAddress> {
+    let out_of_range = || CoreError::new(ErrorCode::ArgValidation,
+        format!("ROM offset 0x{:X} is outside the {:?} address space", offset, mapping)
+    );
+
+    match mapping {
+        MemoryMapping::LoRom => {
+            if offset >= 0x400000 {
+                return Err(out_of_range());
+            }
+            let bank = offset >> 15;
+            Ok(SnesBusAddress {
+                bank: if bank < 0x7E { bank } else { bank | 0x80 },
+                addr: 0x8000 | (offset & 0x7FFF),
+            })
+        }
+        MemoryMapping::HiRom => {
+            if offset >= 0x400000 {
+                return Err(out_of_range());
+            }
+            Ok(SnesBusAddress { bank: 0xC0 | (offset >> 16), addr: offset & 0xFFFF })
+        }
+        MemoryMapping::ExHiRom => {
+            if offset >= 0x800000 {
+                return Err(out_of_range());
+            }
+            if offset < 0x400000 {
+                return Ok(SnesBusAddress { bank: 0xC0 | (offset >> 16), addr: offset & 0xFFFF });
+            }
+            let bank = 0x40 | ((offset >> 16) & 0x3F);
+            let addr = offset & 0xFFFF;
+            if bank < 0x7E {
+                Ok(SnesBusAddress { bank, addr })
+            } else if addr >= 0x8000 {
+                // $7E/$7F are WRAM; only the upper halves are reachable via $3E/$3F
+                Ok(SnesBusAddress { bank: bank & 0x3F, addr })
+            } else {
+                Err(out_of_range())
+            }
+        }
+    }
+}
+
+/// Parse a 32-byte internal header read from `header_offset`
+/// Returns the header and a plausibility score (higher is more likely the real header)
+fn parse_rom_header(bytes: &[u8], header_offset: u32, mapping: MemoryMapping) -> (RomHeader, u32) {
+    let title_bytes = &bytes[0..21];
+    let map_mode = bytes[0x15] as u32;
+    let complement = (bytes[0x1C] as u32) | ((bytes[0x1D] as u32) << 8);
+    let checksum = (bytes[0x1E] as u32) | ((bytes[0x1F] as u32) << 8);
+
+    let mut score = 0;
+    if complement ^ checksum == 0xFFFF {
+        score += 4;
+    }
+    let mode_matches = match mapping {
+        MemoryMapping::LoRom => map_mode & 0xEF == 0x20,
+        MemoryMapping::HiRom => map_mode & 0xEF == 0x21,
+        MemoryMapping::ExHiRom => map_mode & 0xEF == 0x25,
+    };
+    if mode_matches {
+        score += 2;
+    }
+    if title_bytes.iter().all(|&b| (0x20..0x7F).contains(&b)) {
+        score += 1;
+    }
+
+    let header = RomHeader {
+        title: String::from_utf8_lossy(title_bytes).trim_end().to_string(),
+        mapping,
+        map_mode,
+        rom_type: bytes[0x16] as u32,
+        rom_size_kb: 1u32.checked_shl(bytes[0x17] as u32).unwrap_or(0),
+        sram_size_kb: if bytes[0x18] == 0 { 0 } else { 1u32.checked_shl(bytes[0x18] as u32).unwrap_or(0) },
+        region: bytes[0x19] as u32,
+        version: bytes[0x1B] as u32,
+        checksum,
+        complement,
+        header_offset,
+    };
+    (header, score)
+}
+
+/// Identify the internal header of a ROM file, skipping a copier header if present
+/// (see copier.rs). Returns the header, with header_offset relative to the ROM, and
+/// the copier header length.
+pub(crate) fn rom_file_header(rom: &[u8]) -> Option<(RomHeader, usize)> {
+    let skip = copier_header_len(rom.len() as u64);
+    let rom = &rom[skip..];
+    let mut best: Option<(RomHeader, u32)> = None;
+    for (offset, mapping) in HEADER_OFFSETS {
+        let Some(bytes) = rom.get(offset as usize..offset as usize + HEADER_LEN) else {
+            continue;
+        };
+        let (header, score) = parse_rom_header(bytes, offset, mapping);
+        if best.as_ref().is_none_or(|(_, best_score)| score > *best_score) {
+            best = Some((header, score));
+        }
+    }
+    best.filter(|&(_, score)| score > 0).map(|(header, _)| (header, skip))
+}
+
+/// A copy of a ROM file with its internal header's checksum and complement recomputed
+/// The checksum is the 16-bit sum of the ROM bytes, taken with the checksum 0000 and
+/// the complement FFFF; a size that isn't a power of two is summed as mirrored up to
+/// the next one, as the hardware sees it (3MB counts its last 1MB twice). A copier
+/// header is kept and left out of the sum. Fails if no internal header is found.
+#[napi]
+pub fn fix_checksum(rom: Buffer) -> Result<Buffer> {
+    let mut rom = rom.to_vec();
+    fix_rom_checksum(&mut rom)?;
+    Ok(rom.into())
+}
+
+/// fix_checksum() in place; returns the new checksum
+pub(crate) fn fix_rom_checksum(rom: &mut [u8]) -> Result<u32> {
+    let (header, skip) = rom_file_header(rom)
+        .ok_or_else(|| CoreError::new(ErrorCode::ArgValidation, "No valid ROM header found"))?;
+    let rom = &mut rom[skip..];
+    let at = header.header_offset as usize;
+    rom[at + 0x1C..at + 0x20].copy_from_slice(&[0xFF, 0xFF, 0x00, 0x00]);
+
+    let checksum = mirrored_sum(rom, rom.len().next_power_of_two()) & 0xFFFF;
+    rom[at + 0x1C..at + 0x1E].copy_from_slice(&((checksum ^ 0xFFFF) as u16).to_le_bytes());
+    rom[at + 0x1E..at + 0x20].copy_from_slice(&(checksum as u16).to_le_bytes());
+    Ok(checksum)
+}
+
+/// Byte sum of `data` mirrored up to `len` (a power of two no smaller than it)
+/// The largest power-of-two part is kept and the rest mirrored up to the same size,
+/// recursively, the way the address decoding repeats a short ROM.
+fn mirrored_sum(data: &[u8], len: usize) -> u32 {
+    let sum = |bytes: &[u8]| bytes.iter().fold(0u32, |sum, &b| sum.wrapping_add(b as u32));
+    if data.is_empty() {
+        return 0;
+    }
+    let base = 1usize << (usize::BITS - 1 - data.len().leading_zeros());
+    let once = if base == data.len() {
+        sum(data)
+    } else {
+        sum(&data[..base]).wrapping_add(mirrored_sum(&data[base..], base))
+    };
+    let covered = if base == data.len() { base } else { 2 * base };
+    once.wrapping_mul((len / covered) as u32)
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Read and identify the internal header of the loaded ROM
+    /// Probes the LoROM, HiROM and ExHiROM header locations and keeps the most plausible
+    #[napi]
+    pub fn read_rom_header(&self) -> Result<RomHeader> {
+        let best = self.with_connection(|conn| {
+            let mut best: Option<(RomHeader, u32)> = None;
+            for (offset, mapping) in HEADER_OFFSETS {
+                let bytes = get_locked(conn, SPACE_SNES, offset, HEADER_LEN as u32)?;
+                if bytes.len() < HEADER_LEN {
+                    continue;
+                }
+                let (header, score) = parse_rom_header(&bytes, offset, mapping);
+                if best.as_ref().is_none_or(|(_, best_score)| score > *best_score) {
+                    best = Some((header, score));
+                }
+            }
+            Ok(best)
+        })?;
+
+        match best {
+            Some((header, score)) if score > 0 => Ok(header),
+            _ => Err(CoreError::new(ErrorCode::InvalidResponse, "No valid ROM header found")),
+        }
+    }
+
+    /// Read from the loaded ROM at a linear offset or bus address
+    #[napi]
+    pub fn read_rom(&self, location: RomLocation, size: u32) -> Result<Buffer> {
+        let offset = self.resolve_rom_location(location)?;
+
+        self.with_connection(|conn| get_locked(conn, SPACE_SNES, offset, size)).map(Buffer::from)
+    }
+
+    /// Write to the loaded ROM at a linear offset or bus address
+    #[napi]
+    pub fn write_rom(&self, location: RomLocation, data: Buffer) -> Result<()> {
+        let offset = self.resolve_rom_location(location)?;
+
+        self.with_connection(|conn| put_locked(conn, SPACE_SNES, offset, &data))
+    }
+
+    /// Read at a SNES bus address (WRAM, SRAM or ROM; see snes_bus_to_fxpak())
+    /// `mapping` is auto-detected from the ROM header when omitted and the address
+    /// isn't WRAM. The read must stay within the region the address falls in.
+    #[napi]
+    pub fn read_bus(&self, bank: u32, addr: u32, size: u32, mapping: Option<MemoryMapping>) -> Result<Buffer> {
+        let (space, address) = self.resolve_bus_address(bank, addr, size, mapping)?;
+        self.with_connection(|conn| get_locked(conn, space, address, size)).map(Buffer::from)
+    }
+
+    /// Write at a SNES bus address (WRAM, SRAM or ROM; see read_bus())
+    #[napi]
+    pub fn write_bus(&self, bank: u32, addr: u32, data: Buffer, mapping: Option<MemoryMapping>) -> Result<()> {
+        let (space, address) = self.resolve_bus_address(bank, addr, data.len() as u32, mapping)?;
+        self.with_connection(|conn| put_locked(conn, space, address, &data))
+    }
+
+    /// Bus address to (space, address), checking `size` bytes fit in its region
+    fn resolve_bus_address(&self, bank: u32, addr: u32, size: u32, mapping: Option<MemoryMapping>) -> Result<(u8, u32)> {
+        // WRAM is the same in every mapping, so skip reading the header for it
+        // (out-of-range addresses are still rejected by snes_bus_to_fxpak())
+        let mapping = match mapping {
+            Some(mapping) => mapping,
+            None if wram_offset(bank, addr).is_some() => MemoryMapping::LoRom,
+            None => self.read_rom_header()?.mapping,
+        };
+        let target = snes_bus_to_fxpak(mapping, bank, addr)?;
+        target.region.resolve(target.offset, size)
+    }
+
+    /// Turn a RomLocation into a linear ROM offset, detecting the mapping if needed
+    fn resolve_rom_location(&self, location: RomLocation) -> Result<u32> {
+        match (location.offset, location.bank, location.addr) {
+            (Some(offset), None, None) => Ok(offset),
+            (None, Some(bank), Some(addr)) => {
+                let mapping = match location.mapping {
+                    Some(mapping) => mapping,
+                    None => self.read_rom_header()?.mapping,
+                };
+                snes_bus_to_rom_offset(mapping, bank, addr)
+            }
+            _ => Err(CoreError::new(ErrorCode::ArgValidation,
+                "RomLocation needs either `offset` or both `bank` and `addr`"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bus_to_rom_offset() {
+        let cases = [
+            (MemoryMapping::LoRom, 0x00, 0x8000, 0x000000),
+            (MemoryMapping::LoRom, 0x80, 0x8000, 0x000000),
+            (MemoryMapping::LoRom, 0x01, 0x8000, 0x008000),
+            (MemoryMapping::LoRom, 0x7D, 0xFFFF, 0x3EFFFF),
+            (MemoryMapping::LoRom, 0x40, 0x0000, 0x200000),
+            (MemoryMapping::LoRom, 0xFE, 0x8000, 0x3F0000),
+            (MemoryMapping::HiRom, 0xC0, 0x0000, 0x000000),
+            (MemoryMapping::HiRom, 0xC1, 0x2345, 0x012345),
+            (MemoryMapping::HiRom, 0x40, 0x0000, 0x000000),
+            (MemoryMapping::HiRom, 0x00, 0x8000, 0x008000),
+            (MemoryMapping::HiRom, 0x3F, 0xFFFF, 0x3FFFFF),
+            (MemoryMapping::ExHiRom, 0xC0, 0x0000, 0x000000),
+            (MemoryMapping::ExHiRom, 0x80, 0x8000, 0x008000),
+            (MemoryMapping::ExHiRom, 0x40, 0x0000, 0x400000),
+            (MemoryMapping::ExHiRom, 0x00, 0x8000, 0x408000),
+            (MemoryMapping::ExHiRom, 0x3E, 0x8000, 0x7E8000),
+        ];
+        for (mapping, bank, addr, offset) in cases {
+            let name = format!("{:?} ${:02X}:{:04X}", mapping, bank, addr);
+            assert_eq!(snes_bus_to_rom_offset(mapping, bank, addr).ok(), Some(offset), "{}", name);
+        }
+    }
+
+    #[test]
+    fn bus_to_rom_offset_rejects_non_rom() {
+        let cases = [
+            (MemoryMapping::LoRom, 0x00, 0x7FFF),
+            (MemoryMapping::LoRom, 0x00, 0x2100),
+            (MemoryMapping::LoRom, 0x7E, 0x8000),
+            (MemoryMapping::LoRom, 0x7F, 0x0000),
+            (MemoryMapping::HiRom, 0x00, 0x7FFF),
+            (MemoryMapping::HiRom, 0x80, 0x6000),
+            (MemoryMapping::HiRom, 0x7E, 0x0000),
+            (MemoryMapping::ExHiRom, 0x00, 0x1000),
+            (MemoryMapping::ExHiRom, 0x7F, 0xFFFF),
+            (MemoryMapping::LoRom, 0x100, 0x8000),
+            (MemoryMapping::HiRom, 0xC0, 0x10000),
+        ];
+        for (mapping, bank, addr) in cases {
+            let name = format!("{:?} ${:02X}:{:04X}", mapping, bank, addr);
+            let error = snes_bus_to_rom_offset(mapping, bank, addr).expect_err(&name);
+            assert_eq!(error.code, ErrorCode::ArgValidation, "{}", name);
+        }
+    }
+
+    #[test]
+    fn rom_offset_to_bus() {
+        let cases = [
+            (MemoryMapping::LoRom, 0x000000, Some((0x00, 0x8000))),
+            (MemoryMapping::LoRom, 0x3EFFFF, Some((0x7D, 0xFFFF))),
+            (MemoryMapping::LoRom, 0x3F0000, Some((0xFE, 0x8000))),
+            (MemoryMapping::LoRom, 0x400000, None),
+            (MemoryMapping::HiRom, 0x012345, Some((0xC1, 0x2345))),
+            (MemoryMapping::HiRom, 0x400000, None),
+            (MemoryMapping::ExHiRom, 0x3FFFFF, Some((0xFF, 0xFFFF))),
+            (MemoryMapping::ExHiRom, 0x400000, Some((0x40, 0x0000))),
+            (MemoryMapping::ExHiRom, 0x7E8000, Some((0x3E, 0x8000))),
+            (MemoryMapping::ExHiRom, 0x7E0000, None),
+            (MemoryMapping::ExHiRom, 0x800000, None),
+        ];
+        for (mapping, offset, expected) in cases {
+            let name = format!("{:?} 0x{:06X}", mapping, offset);
+            let bus = rom_offset_to_snes_bus(mapping, offset).ok().map(|bus| (bus.bank, bus.addr));
+            assert_eq!(bus, expected, "{}", name);
+            if let Some((bank, addr)) = expected {
+                assert_eq!(snes_bus_to_rom_offset(mapping, bank, addr).ok(), Some(offset), "{} round trip", name);
+            }
+        }
+    }
+
+    #[test]
+    fn bus_to_fxpak() {
+        let cases = [
+            (MemoryMapping::LoRom, 0x7E, 0x0000, MemoryRegion::Wram, 0x00000, 0xF50000),
+            (MemoryMapping::HiRom, 0x7F, 0xFFFF, MemoryRegion::Wram, 0x1FFFF, 0xF6FFFF),
+            (MemoryMapping::LoRom, 0x00, 0x1234, MemoryRegion::Wram, 0x01234, 0xF51234),
+            (MemoryMapping::ExHiRom, 0x80, 0x1FFF, MemoryRegion::Wram, 0x01FFF, 0xF51FFF),
+            (MemoryMapping::LoRom, 0x70, 0x0000, MemoryRegion::Sram, 0x00000, 0xE00000),
+            (MemoryMapping::LoRom, 0x71, 0x0010, MemoryRegion::Sram, 0x08010, 0xE08010),
+            (MemoryMapping::LoRom, 0xF0, 0x7FFF, MemoryRegion::Sram, 0x07FFF, 0xE07FFF),
+            (MemoryMapping::HiRom, 0x20, 0x6000, MemoryRegion::Sram, 0x00000, 0xE00000),
+            (MemoryMapping::HiRom, 0x21, 0x6001, MemoryRegion::Sram, 0x02001, 0xE02001),
+            (MemoryMapping::ExHiRom, 0xA0, 0x7FFF, MemoryRegion::Sram, 0x01FFF, 0xE01FFF),
+            (MemoryMapping::LoRom, 0x00, 0x8000, MemoryRegion::Rom, 0x000000, 0x000000),
+            (MemoryMapping::LoRom, 0x70, 0x8000, MemoryRegion::Rom, 0x380000, 0x380000),
+            (MemoryMapping::HiRom, 0xC1, 0x0000, MemoryRegion::Rom, 0x010000, 0x010000),
+        ];
+        for (mapping, bank, addr, region, offset, address) in cases {
+            let name = format!("{:?} ${:02X}:{:04X}", mapping, bank, addr);
+            let target = snes_bus_to_fxpak(mapping, bank, addr).expect(&name);
+            assert_eq!((target.region, target.offset, target.address), (region, offset, address), "{}", name);
+        }
+    }
+
+    #[test]
+    fn bus_to_fxpak_rejects_registers_and_open_bus() {
+        let cases = [
+            (MemoryMapping::LoRom, 0x00, 0x2100),
+            (MemoryMapping::LoRom, 0x00, 0x4200),
+            (MemoryMapping::HiRom, 0x00, 0x6000),
+            (MemoryMapping::HiRom, 0x80, 0x2100),
+            (MemoryMapping::LoRom, 0x100, 0x0000),
+            (MemoryMapping::LoRom, 0x7E, 0x10000),
+        ];
+        for (mapping, bank, addr) in cases {
+            let name = format!("{:?} ${:02X}:{:04X}", mapping, bank, addr);
+            let error = snes_bus_to_fxpak(mapping, bank, addr).err().expect(&name);
+            assert_eq!(error.code, ErrorCode::ArgValidation, "{}", name);
+        }
+    }
+
+    #[test]
+    fn sram_offset_to_bus() {
+        let cases = [
+            (MemoryMapping::LoRom, 0x00000, Some((0x70, 0x0000))),
+            (MemoryMapping::LoRom, 0x68000, Some((0x7D, 0x0000))),
+            (MemoryMapping::LoRom, 0x70000, Some((0xFE, 0x0000))),
+            (MemoryMapping::LoRom, 0x80000, None),
+            (MemoryMapping::HiRom, 0x02000, Some((0x21, 0x6000))),
+            (MemoryMapping::ExHiRom, 0x3FFFF, Some((0x3F, 0x7FFF))),
+            (MemoryMapping::HiRom, 0x40000, None),
+        ];
+        for (mapping, offset, expected) in cases {
+            let name = format!("{:?} 0x{:05X}", mapping, offset);
+            let bus = sram_offset_to_snes_bus(mapping, offset).map(|bus| (bus.bank, bus.addr));
+            assert_eq!(bus, expected, "{}", name);
+        }
+    }
+}
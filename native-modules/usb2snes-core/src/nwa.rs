@@ -0,0 +1,432 @@
+// Emulator Network Access (NWA) backend
+// connect("nwa://host:port") drives an emulator implementing the NWA protocol (port
+// 0xBEEF unless given) over TCP instead of an FxPak. As with RetroArch, the backend is
+// a Transport that decodes the core's command packets: GET/PUT/VGET/VPUT on the SNES
+// space become CORE_MEMORY_READ/bCORE_MEMORY_WRITE on the memory domain holding the
+// address (WRAM, SRAM, CARTROM, VRAM, APURAM, CGRAM, OAM - the names the protocol
+// suggests for SNES emulators), BOOT becomes LOAD_GAME, RESET becomes EMULATION_RESET
+// and INFO is answered from EMULATOR_INFO and GAME_INFO. Domains are addressed by
+// offset, so unlike RetroArch no cartridge mapping is involved. Files, MENU and the
+// other spaces fail with "Unsupported: ..." before anything is sent.
+//
+// Requests are "NAME arg;arg;...\n" lines; a binary argument block (bNAME) follows as
+// 0x00, a big-endian u32 length and the bytes. Replies are either the same binary
+// block or an ASCII block: '\n', "key:value\n" lines and an empty line. An ASCII reply
+// with an "error" key is a failure, explained by its "reason".
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::regions::MemoryRegion;
+use crate::simulator::{be_u32, packet_pairs, packet_path, PendingPut};
+use crate::timeouts::ReadDeadlines;
+use crate::transport::{set_info_strings, ReplyQueue, Transport};
+use crate::{
+    DATA64B_FLAG, DEVICE_DISCONNECTED, MAX_PATH_LEN, NORESP_FLAG, ONLYRESET_FLAG, READ_TIMEOUT_MS, SPACE_SNES,
+};
+
+const URI_PREFIX: &str = "nwa://";
+
+/// First port the NWA protocol assigns to emulators
+const DEFAULT_PORT: u16 = 0xBEEF;
+
+/// Memory domains for the FxPak SNES-space regions
+const DOMAINS: [(MemoryRegion, &str); 7] = [
+    (MemoryRegion::Wram, "WRAM"),
+    (MemoryRegion::Sram, "SRAM"),
+    (MemoryRegion::Rom, "CARTROM"),
+    (MemoryRegion::Vram, "VRAM"),
+    (MemoryRegion::Apu, "APURAM"),
+    (MemoryRegion::Cgram, "CGRAM"),
+    (MemoryRegion::Oam, "OAM"),
+];
+
+/// Whether a port name is an NWA URI rather than a serial port
+pub(crate) fn is_nwa_uri(port_name: &str) -> bool {
+    port_name.starts_with(URI_PREFIX)
+}
+
+/// A reply: the binary block's bytes or the ASCII block's key/value pairs
+enum Reply {
+    Binary(Vec<u8>),
+    Ascii(Vec<(String, String)>),
+}
+
+impl Reply {
+    /// First value of `key` in an ASCII reply
+    fn get(&self, key: &str) -> Option<&str> {
+        match self {
+            Reply::Ascii(pairs) => pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str()),
+            Reply::Binary(_) => None,
+        }
+    }
+}
+
+/// An NWA emulator reached over TCP, seen as a Transport
+struct NwaTransport {
+    stream: TcpStream,
+    uri: String,
+    /// "<name> <version>" from EMULATOR_INFO, reported as the firmware by INFO
+    emulator: String,
+    /// Data phase expected from the core before the next command packet
+    pending_put: Option<PendingPut>,
+    /// Data phase bytes written by the core so far
+    input: Vec<u8>,
+    replies: ReplyQueue,
+}
+
+impl NwaTransport {
+    fn connect(uri: &str) -> Result<NwaTransport> {
+        let io_error = |e: String| CoreError::new(ErrorCode::IoError, format!("Failed to connect to {}: {}", uri, e));
+        let host = match &uri[URI_PREFIX.len()..] {
+            "" => format!("127.0.0.1:{}", DEFAULT_PORT),
+            host if host.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) => host.to_string(),
+            host => format!("{}:{}", host, DEFAULT_PORT),
+        };
+        let address = host.to_socket_addrs().map_err(|e| io_error(e.to_string()))?
+            .next()
+            .ok_or_else(|| io_error("host did not resolve".to_string()))?;
+        let timeout = Duration::from_millis(READ_TIMEOUT_MS);
+        let stream = TcpStream::connect_timeout(&address, timeout).map_err(|e| io_error(e.to_string()))?;
+        let _ = stream.set_nodelay(true);
+        stream.set_read_timeout(Some(timeout)).map_err(|e| io_error(e.to_string()))?;
+        stream.set_write_timeout(Some(timeout)).map_err(|e| io_error(e.to_string()))?;
+
+        let mut transport = NwaTransport {
+            stream,
+            uri: uri.to_string(),
+            emulator: String::new(),
+            pending_put: None,
+            input: Vec::new(),
+            replies: ReplyQueue::default(),
+        };
+        let info = transport.command("EMULATOR_INFO", &[])
+            .map_err(|e| CoreError::new(ErrorCode::NotConnected, format!(
+                "{}: no answer to EMULATOR_INFO ({}); is it an NWA emulator?", uri, e
+            )))?;
+        transport.emulator = [info.get("name"), info.get("version")]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+        Ok(transport)
+    }
+
+    /// Send a command (with a binary argument block if `block` is given) and read its reply
+    fn send(&mut self, command: &str, arguments: &[String], block: Option<&[u8]>) -> io::Result<Reply> {
+        let mut request = Vec::new();
+        if block.is_some() {
+            request.push(b'b');
+        }
+        request.extend_from_slice(command.as_bytes());
+        if !arguments.is_empty() {
+            request.push(b' ');
+            request.extend_from_slice(arguments.join(";").as_bytes());
+        }
+        request.push(b'\n');
+        if let Some(block) = block {
+            request.push(0);
+            request.extend_from_slice(&(block.len() as u32).to_be_bytes());
+            request.extend_from_slice(block);
+        }
+        self.stream.write_all(&request)?;
+        self.read_reply(command)
+    }
+
+    /// Send a command and read its reply, failing if it is an error reply
+    fn command(&mut self, command: &str, arguments: &[String]) -> io::Result<Reply> {
+        self.checked(command, arguments, None)
+    }
+
+    fn checked(&mut self, command: &str, arguments: &[String], block: Option<&[u8]>) -> io::Result<Reply> {
+        let reply = self.send(command, arguments, block)?;
+        if let Some(error) = reply.get("error") {
+            let reason = reply.get("reason").unwrap_or_default();
+            return Err(io::Error::other(format!("{} failed: {} {}", command, error, reason).trim_end().to_string()));
+        }
+        Ok(reply)
+    }
+
+    fn read_reply(&mut self, command: &str) -> io::Result<Reply> {
+        let mut kind = [0u8; 1];
+        self.read_exact(&mut kind, command)?;
+        match kind[0] {
+            0 => {
+                let mut len = [0u8; 4];
+                self.read_exact(&mut len, command)?;
+                let mut data = vec![0u8; u32::from_be_bytes(len) as usize];
+                self.read_exact(&mut data, command)?;
+                Ok(Reply::Binary(data))
+            }
+            b'\n' => {
+                let mut pairs = Vec::new();
+                loop {
+                    let line = self.read_line(command)?;
+                    if line.is_empty() {
+                        return Ok(Reply::Ascii(pairs));
+                    }
+                    let (key, value) = line.split_once(':').unwrap_or((line.as_str(), ""));
+                    pairs.push((key.to_string(), value.to_string()));
+                }
+            }
+            other => Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                "reply to {} starts with 0x{:02X}, not a binary or ASCII block", command, other
+            ))),
+        }
+    }
+
+    fn read_line(&mut self, command: &str) -> io::Result<String> {
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        loop {
+            self.read_exact(&mut byte, command)?;
+            if byte[0] == b'\n' {
+                return Ok(String::from_utf8_lossy(&line).to_string());
+            }
+            line.push(byte[0]);
+        }
+    }
+
+    /// read_exact(), with EOF and socket timeouts named after the command
+    fn read_exact(&mut self, buf: &mut [u8], command: &str) -> io::Result<()> {
+        self.stream.read_exact(buf).map_err(|e| match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+                io::Error::new(io::ErrorKind::TimedOut, format!("no reply to {}", command))
+            }
+            io::ErrorKind::UnexpectedEof => {
+                io::Error::new(io::ErrorKind::ConnectionReset, format!("connection closed during {}", command))
+            }
+            _ => e,
+        })
+    }
+
+    fn read_memory(&mut self, domain: &str, offset: u32, len: usize) -> io::Result<Vec<u8>> {
+        let arguments = [domain.to_string(), format!("${:X}", offset), format!("${:X}", len)];
+        match self.checked("CORE_MEMORY_READ", &arguments, None)? {
+            Reply::Binary(data) if data.len() == len => Ok(data),
+            Reply::Binary(data) => Err(io::Error::other(format!(
+                "CORE_MEMORY_READ {} ${:X} returned {} of {} bytes", domain, offset, data.len(), len
+            ))),
+            Reply::Ascii(_) => Err(io::Error::other(format!(
+                "CORE_MEMORY_READ {} ${:X} returned no data", domain, offset
+            ))),
+        }
+    }
+
+    fn write_memory(&mut self, domain: &str, offset: u32, data: &[u8]) -> io::Result<()> {
+        let arguments = [domain.to_string(), format!("${:X}", offset), format!("${:X}", data.len())];
+        self.checked("CORE_MEMORY_WRITE", &arguments, Some(data))?;
+        Ok(())
+    }
+
+    /// Split `size` bytes at an FxPak SNES-space `address` into (domain, offset, length)
+    /// ranges, one per memory region crossed
+    fn domain_ranges(address: u32, size: usize) -> io::Result<Vec<(&'static str, u32, usize)>> {
+        let mut ranges = Vec::new();
+        let mut offset = 0;
+        while offset < size {
+            let at = address + offset as u32;
+            let Some((base, region_size, domain)) = DOMAINS.iter()
+                .map(|&(region, domain)| {
+                    let (_, base, region_size) = region.layout();
+                    (base, region_size, domain)
+                })
+                .find(|&(base, region_size, _)| (base..base + region_size).contains(&at))
+            else {
+                return Err(unsupported(format!("SNES 0x{:06X} is in no NWA memory domain", at)));
+            };
+            let len = (size - offset).min((base + region_size - at) as usize);
+            ranges.push((domain, at - base, len));
+            offset += len;
+        }
+        Ok(ranges)
+    }
+
+    /// Answer one command packet, queueing its RESPONSE and data phase
+    fn handle_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        let (opcode, space, flags) = (packet[4], packet[5], packet[6]);
+        let mut response = ReplyQueue::response(flags);
+        let mut data = Vec::new();
+        let block_len = if flags & DATA64B_FLAG != 0 { 64 } else { 512 };
+
+        match opcode {
+            0..=3 if space != SPACE_SNES => {
+                return Err(unsupported(format!("NWA backend only reaches the SNES space (got space {})", space)));
+            }
+            0 | 1 => {
+                let address = be_u32(&packet[256..260]);
+                let size = match be_u32(&packet[252..256]) as usize {
+                    0 => block_len,
+                    size => size,
+                };
+                response[252..256].copy_from_slice(&(size as u32).to_be_bytes());
+                let ranges = Self::domain_ranges(address, size)?;
+                if opcode == 0 {
+                    for (domain, offset, len) in ranges {
+                        data.extend(self.read_memory(domain, offset, len)?);
+                    }
+                } else {
+                    self.pending_put = Some(PendingPut::Memory { space, address, size, block_len });
+                }
+            }
+            2 | 3 => {
+                let pairs = packet_pairs(packet);
+                let total: usize = pairs.iter().map(|&(size, _)| size as usize).sum();
+                response[252..256].copy_from_slice(&(total as u32).to_be_bytes());
+                for &(size, address) in &pairs {
+                    let ranges = Self::domain_ranges(address, size as usize)?;
+                    if opcode == 2 {
+                        for (domain, offset, len) in ranges {
+                            data.extend(self.read_memory(domain, offset, len)?);
+                        }
+                    }
+                }
+                if opcode == 3 {
+                    self.pending_put = Some(PendingPut::Vector { space, pairs });
+                }
+            }
+            // BOOT/MENU_RESET with ONLYRESET only reset, like RESET
+            8 => {
+                self.command("EMULATION_RESET", &[])?;
+            }
+            9 | 12 if flags & ONLYRESET_FLAG != 0 => {
+                self.command("EMULATION_RESET", &[])?;
+            }
+            9 => {
+                self.command("LOAD_GAME", &[packet_path(packet, 8, MAX_PATH_LEN)])?;
+            }
+            11 => {
+                // No game loaded is an error reply on some emulators: report no ROM
+                let game = match self.command("GAME_INFO", &[]) {
+                    Ok(info) => info.get("file").or(info.get("name")).unwrap_or_default().to_string(),
+                    Err(e) if e.kind() == io::ErrorKind::Other => String::new(),
+                    Err(e) => return Err(e),
+                };
+                let emulator = self.emulator.clone();
+                set_info_strings(&mut response, &emulator, &game);
+            }
+            _ => return Err(unsupported(format!("NWA backend has no equivalent of opcode {}", opcode))),
+        }
+
+        if flags & NORESP_FLAG != 0 {
+            return Ok(());
+        }
+        self.replies.push(response, data, block_len);
+        Ok(())
+    }
+
+    /// Write a completed data phase to memory
+    fn write_pending(&mut self, pending: PendingPut, data: &[u8]) -> io::Result<()> {
+        let ranges: Vec<(u32, usize)> = match pending {
+            PendingPut::Memory { address, size, .. } => vec![(address, size)],
+            PendingPut::Vector { pairs, .. } => pairs.iter().map(|&(size, address)| (address, size as usize)).collect(),
+            PendingPut::File { .. } => Vec::new(),
+        };
+        let mut offset = 0;
+        for (address, size) in ranges {
+            for (domain, domain_offset, len) in Self::domain_ranges(address, size)? {
+                self.write_memory(domain, domain_offset, &data[offset..offset + len])?;
+                offset += len;
+            }
+        }
+        Ok(())
+    }
+
+    /// A failed exchange with the emulator as a core error
+    /// A closed or reset connection means the emulator is gone: a disconnect.
+    fn error(&self, e: io::Error) -> CoreError {
+        match e.kind() {
+            io::ErrorKind::Unsupported => CoreError::new(ErrorCode::Unsupported, format!("Unsupported: {}", e)),
+            io::ErrorKind::TimedOut => CoreError::new(ErrorCode::Timeout, format!("NWA emulator at {}: {}", self.uri, e)),
+            io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::NotConnected => CoreError::new(ErrorCode::NotConnected,
+                format!("{}: NWA emulator at {} went away: {}", DEVICE_DISCONNECTED, self.uri, e)
+            ),
+            _ => CoreError::new(ErrorCode::DeviceError, format!("NWA emulator at {}: {}", self.uri, e)),
+        }
+    }
+}
+
+impl Transport for NwaTransport {
+    /// A command whose reply didn't come in time queues nothing, so read_packet()
+    /// reports the timeout and the core's retry policy applies as on hardware
+    fn write_packet(&mut self, packet: &[u8]) -> Result<()> {
+        self.pending_put = None;
+        self.input.clear();
+        match self.handle_packet(packet) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Ok(()),
+            Err(e) => Err(self.error(e)),
+        }
+    }
+
+    fn read_packet(&mut self, buf: &mut [u8], deadlines: ReadDeadlines) -> Result<()> {
+        self.replies.take(buf, deadlines, true)
+    }
+
+    fn read_data(&mut self, block: &mut [u8], deadlines: ReadDeadlines) -> Result<()> {
+        self.replies.take(block, deadlines, false)
+    }
+
+    /// Data nobody asked for (the data phase of a refused PUT) is dropped
+    fn write_data(&mut self, data: &[u8]) -> Result<()> {
+        let Some(pending) = &self.pending_put else {
+            return Ok(());
+        };
+        self.input.extend_from_slice(data);
+        if self.input.len() < pending.wire_len() {
+            return Ok(());
+        }
+        let pending = self.pending_put.take().unwrap();
+        let data = std::mem::take(&mut self.input);
+        self.write_pending(pending, &data).map_err(|e| self.error(e))
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.stream.set_read_timeout(Some(timeout))?;
+        self.stream.set_write_timeout(Some(timeout))
+    }
+
+    /// Drops queued replies and the rest of any reply that arrived late, so the next
+    /// command's reply isn't read from the middle of an old one
+    fn drain_input(&mut self, quiet: Duration, limit: Duration) -> Result<u32> {
+        let mut drained = self.replies.clear();
+        let _ = self.set_timeout(quiet);
+
+        let deadline = Instant::now() + limit;
+        let mut buf = [0u8; 4096];
+        while Instant::now() < deadline {
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    let _ = self.stream.shutdown(Shutdown::Both);
+                    return Err(CoreError::new(ErrorCode::NotConnected, format!(
+                        "{}: NWA emulator at {} closed the connection", DEVICE_DISCONNECTED, self.uri
+                    )));
+                }
+                Ok(n) => drained += n as u32,
+                Err(_) => break,
+            }
+        }
+        Ok(drained)
+    }
+
+    /// No control lines; accepted and ignored like on the simulator
+    fn set_dtr(&mut self, _level: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_rts(&mut self, _level: bool) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn unsupported(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, reason)
+}
+
+/// Open an NWA emulator's TCP port as the core's transport (see is_nwa_uri())
+pub(crate) fn open_nwa(uri: &str) -> Result<Box<dyn Transport>> {
+    Ok(Box::new(NwaTransport::connect(uri)?))
+}
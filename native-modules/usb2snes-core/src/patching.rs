@@ -0,0 +1,563 @@
+// ROM patching (IPS, BPS)
+// rhplay patches base ROMs before uploading them; doing it here avoids a slow JS copy
+// loop per record. An IPS patch is "PATCH", then records of a 3-byte big-endian offset
+// and a 2-byte size followed by that many bytes - or, with size 0, a 2-byte run length
+// and the byte to repeat (RLE) - then "EOF". Records past the end of the ROM extend it
+// (zero-filled up to the record). A 3-byte length after "EOF" (the truncation extension
+// written by Lunar IPS) cuts the patched ROM to that size.
+//
+// A BPS patch is "BPS1", the source, target and metadata sizes (variable-length
+// numbers), the metadata, then actions building the target front to back: SourceRead
+// (source bytes at the same offset), TargetRead (bytes from the patch), SourceCopy and
+// TargetCopy (bytes from a position relative to the previous copy of the same kind;
+// TargetCopy may overlap what it writes). It ends with the CRC32s of the source, the
+// target and the patch itself, so a wrong base ROM is caught before anything is written.
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::copier::{copier_header_len, COPIER_HEADER_LEN};
+
+const IPS_HEADER: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const BPS_HEADER: &[u8] = b"BPS1";
+
+/// Source, target and patch CRC32s
+const BPS_FOOTER_LEN: usize = 12;
+
+/// Largest BPS target accepted; well past any SNES ROM, and it bounds what a corrupt
+/// or hostile header can make bps_patch() allocate
+const BPS_MAX_TARGET: usize = 16 * 1024 * 1024;
+
+/// Shortest SourceCopy/TargetCopy create_bps() emits; shorter matches cost as much as the bytes
+const BPS_MIN_MATCH: usize = 4;
+
+/// Candidate positions create_bps() compares per hash bucket
+const BPS_MAX_CHAIN: usize = 64;
+
+const BPS_HASH_BITS: u32 = 16;
+
+const SOURCE_READ: usize = 0;
+const TARGET_READ: usize = 1;
+const SOURCE_COPY: usize = 2;
+const TARGET_COPY: usize = 3;
+
+/// Apply an IPS patch to a ROM, returning the patched copy
+/// Fails with "InvalidPatch: ..." for a missing header, a record cut short or a
+/// missing "EOF"; the ROM is left untouched either way.
+#[napi]
+pub fn apply_ips(rom: Buffer, patch: Buffer) -> Result<Buffer> {
+    Ok(ips_patch(&rom, &patch)?.into())
+}
+
+pub(crate) fn ips_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    if !patch.starts_with(IPS_HEADER) {
+        return Err(invalid_patch("missing \"PATCH\" header".to_string()));
+    }
+    let mut out = rom.to_vec();
+    let mut at = IPS_HEADER.len();
+    let field = |at: usize, len: usize, what: &str| -> Result<&[u8]> {
+        patch.get(at..at + len).ok_or_else(|| invalid_patch(format!(
+            "{} at patch offset 0x{:X} runs past the end of the patch", what, at
+        )))
+    };
+
+    loop {
+        let offset_field = field(at, 3, "record")?;
+        if offset_field == IPS_EOF {
+            at += 3;
+            break;
+        }
+        let offset = be_uint(offset_field);
+        let size = be_uint(field(at + 3, 2, "record size")?);
+        at += 5;
+
+        if size == 0 {
+            let run = be_uint(field(at, 2, "RLE run length")?);
+            let value = field(at + 2, 1, "RLE value")?[0];
+            at += 3;
+            grow(&mut out, offset + run);
+            out[offset..offset + run].fill(value);
+        } else {
+            let data = field(at, size, "record data")?;
+            at += size;
+            grow(&mut out, offset + size);
+            out[offset..offset + size].copy_from_slice(data);
+        }
+    }
+
+    // Truncation extension; anything else after EOF is ignored like other patchers do
+    if let Some(truncate) = patch.get(at..at + 3) {
+        out.truncate(be_uint(truncate));
+    }
+    Ok(out)
+}
+
+/// Zero-fill `rom` up to `len` bytes if it is shorter
+fn grow(rom: &mut Vec<u8>, len: usize) {
+    if rom.len() < len {
+        rom.resize(len, 0);
+    }
+}
+
+/// Apply an IPS or BPS patch, recognized by its header
+/// A BPS patch whose source doesn't match a ROM with a copier header (see copier.rs)
+/// is tried against the ROM without it. IPS patches apply to the ROM as given, since
+/// older ones were made against headered dumps.
+pub(crate) fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    if patch.starts_with(IPS_HEADER) {
+        return ips_patch(rom, patch);
+    }
+    if !patch.starts_with(BPS_HEADER) {
+        return Err(invalid_patch("not an IPS or BPS patch".to_string()));
+    }
+    match bps_patch(rom, patch) {
+        Err(e) if e.reason.starts_with("PatchSourceMismatch") && copier_header_len(rom.len() as u64) != 0 => {
+            bps_patch(&rom[COPIER_HEADER_LEN..], patch).map_err(|_| e)
+        }
+        result => result,
+    }
+}
+
+/// Apply a BPS patch to a source ROM, returning the target
+/// Fails with "InvalidPatch: ..." if the patch is malformed or its own CRC32 doesn't
+/// match, and with "PatchSourceMismatch: ..." if `source` isn't the ROM it was made
+/// from (wrong size or CRC32, e.g. a headered or different revision).
+#[napi]
+pub fn apply_bps(source: Buffer, patch: Buffer) -> Result<Buffer> {
+    Ok(bps_patch(&source, &patch)?.into())
+}
+
+/// Create a BPS patch turning `source` into `target`, with optional metadata
+/// (conventionally XML) stored in the patch
+#[napi]
+pub fn create_bps(source: Buffer, target: Buffer, metadata: Option<String>) -> Buffer {
+    bps_create(&source, &target, metadata.unwrap_or_default().as_bytes()).into()
+}
+
+pub(crate) fn bps_patch(source: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    if !patch.starts_with(BPS_HEADER) || patch.len() < BPS_HEADER.len() + BPS_FOOTER_LEN {
+        return Err(invalid_patch("missing \"BPS1\" header".to_string()));
+    }
+    let footer = patch.len() - BPS_FOOTER_LEN;
+    let crc = |at: usize| u32::from_le_bytes([patch[at], patch[at + 1], patch[at + 2], patch[at + 3]]);
+    let (source_crc, target_crc, patch_crc) = (crc(footer), crc(footer + 4), crc(footer + 8));
+    let actual = crc32fast::hash(&patch[..footer + 8]);
+    if actual != patch_crc {
+        return Err(invalid_patch(format!(
+            "patch CRC32 is {:08X}, expected {:08X} (corrupt or truncated patch)", actual, patch_crc
+        )));
+    }
+
+    let mut reader = BpsReader { patch: &patch[..footer], at: BPS_HEADER.len() };
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    reader.bytes(metadata_size)?;
+
+    let actual = crc32fast::hash(source);
+    if source.len() != source_size || actual != source_crc {
+        return Err(CoreError::new(ErrorCode::ArgValidation, format!(
+            "PatchSourceMismatch: source is {} bytes with CRC32 {:08X}, patch expects {} bytes with CRC32 {:08X}",
+            source.len(), actual, source_size, source_crc
+        )));
+    }
+
+    if target_size > BPS_MAX_TARGET {
+        return Err(invalid_patch(format!(
+            "{}-byte target is larger than the {}-byte limit", target_size, BPS_MAX_TARGET
+        )));
+    }
+
+    let mut target = Vec::with_capacity(target_size);
+    let (mut source_relative, mut target_relative) = (0usize, 0usize);
+    while reader.at < reader.patch.len() {
+        let action = reader.number()?;
+        let len = (action >> 2) + 1;
+        if target.len() + len > target_size {
+            return Err(invalid_patch(format!(
+                "action at patch offset 0x{:X} writes past the {}-byte target", reader.at, target_size
+            )));
+        }
+        match action & 3 {
+            SOURCE_READ => {
+                let out = target.len();
+                let bytes = source.get(out..out + len)
+                    .ok_or_else(|| invalid_patch(format!("SourceRead past the end of the source at 0x{:X}", out)))?;
+                target.extend_from_slice(bytes);
+            }
+            TARGET_READ => target.extend_from_slice(reader.bytes(len)?),
+            SOURCE_COPY => {
+                source_relative = reader.relative(source_relative)?;
+                let bytes = source.get(source_relative..source_relative + len)
+                    .ok_or_else(|| invalid_patch(format!("SourceCopy past the end of the source at 0x{:X}", source_relative)))?;
+                target.extend_from_slice(bytes);
+                source_relative += len;
+            }
+            _ => {
+                target_relative = reader.relative(target_relative)?;
+                if target_relative >= target.len() {
+                    return Err(invalid_patch(format!(
+                        "TargetCopy from 0x{:X}, which isn't written yet", target_relative
+                    )));
+                }
+                // Byte by byte: the copy may overlap what it writes
+                for _ in 0..len {
+                    target.push(target[target_relative]);
+                    target_relative += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(invalid_patch(format!("patch builds {} of {} target bytes", target.len(), target_size)));
+    }
+    let actual = crc32fast::hash(&target);
+    if actual != target_crc {
+        return Err(invalid_patch(format!("target CRC32 is {:08X}, expected {:08X}", actual, target_crc)));
+    }
+    Ok(target)
+}
+
+/// Reads BPS numbers and bytes from the action area of a patch (footer excluded)
+struct BpsReader<'a> {
+    patch: &'a [u8],
+    at: usize,
+}
+
+impl<'a> BpsReader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self.at.checked_add(len)
+            .and_then(|end| self.patch.get(self.at..end))
+            .ok_or_else(|| invalid_patch(format!("{} bytes at patch offset 0x{:X} run past the actions", len, self.at)))?;
+        self.at += len;
+        Ok(bytes)
+    }
+
+    /// A variable-length number: 7 bits per byte, low first, the last byte has bit 7 set
+    fn number(&mut self) -> Result<usize> {
+        let start = self.at;
+        let overflow = || invalid_patch(format!("number at patch offset 0x{:X} overflows", start));
+        let (mut value, mut shift) = (0usize, 1usize);
+        loop {
+            let byte = self.bytes(1)?[0];
+            let digit = (byte as usize & 0x7F).checked_mul(shift).ok_or_else(overflow)?;
+            value = value.checked_add(digit).ok_or_else(overflow)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(128).ok_or_else(overflow)?;
+            value = value.checked_add(shift).ok_or_else(overflow)?;
+        }
+    }
+
+    /// The new SourceCopy/TargetCopy position: a signed offset from `from`
+    fn relative(&mut self, from: usize) -> Result<usize> {
+        let data = self.number()?;
+        let distance = data >> 1;
+        let position = if data & 1 != 0 { from.checked_sub(distance) } else { from.checked_add(distance) };
+        position.ok_or_else(|| invalid_patch(format!("copy offset before the start at patch offset 0x{:X}", self.at)))
+    }
+}
+
+pub(crate) fn bps_create(source: &[u8], target: &[u8], metadata: &[u8]) -> Vec<u8> {
+    let mut patch = BPS_HEADER.to_vec();
+    bps_number(&mut patch, source.len());
+    bps_number(&mut patch, target.len());
+    bps_number(&mut patch, metadata.len());
+    patch.extend_from_slice(metadata);
+
+    let source_chains = MatchChains::new(source, source.len());
+    let mut target_chains = MatchChains::new(target, 0);
+    let (mut source_relative, mut target_relative) = (0usize, 0usize);
+    let mut literal_start = 0;
+    let mut out = 0;
+
+    while out < target.len() {
+        let same = source.get(out..).map_or(0, |rest| {
+            rest.iter().zip(&target[out..]).take_while(|(a, b)| a == b).count()
+        });
+        let (source_len, source_at) = source_chains.longest(source, target, out, usize::MAX);
+        let (target_len, target_at) = target_chains.longest(target, target, out, out);
+        let best = same.max(source_len).max(target_len);
+
+        if best < BPS_MIN_MATCH {
+            target_chains.insert(target, out);
+            out += 1;
+            continue;
+        }
+
+        if literal_start < out {
+            bps_number(&mut patch, ((out - literal_start - 1) << 2) | TARGET_READ);
+            patch.extend_from_slice(&target[literal_start..out]);
+        }
+        // SourceRead needs no offset, so it wins ties
+        let len = if same == best {
+            bps_number(&mut patch, ((same - 1) << 2) | SOURCE_READ);
+            same
+        } else if source_len == best {
+            bps_number(&mut patch, ((source_len - 1) << 2) | SOURCE_COPY);
+            bps_relative(&mut patch, source_relative, source_at);
+            source_relative = source_at + source_len;
+            source_len
+        } else {
+            bps_number(&mut patch, ((target_len - 1) << 2) | TARGET_COPY);
+            bps_relative(&mut patch, target_relative, target_at);
+            target_relative = target_at + target_len;
+            target_len
+        };
+        for at in out..out + len {
+            target_chains.insert(target, at);
+        }
+        out += len;
+        literal_start = out;
+    }
+    if literal_start < out {
+        bps_number(&mut patch, ((out - literal_start - 1) << 2) | TARGET_READ);
+        patch.extend_from_slice(&target[literal_start..out]);
+    }
+
+    patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+    patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+    let patch_crc = crc32fast::hash(&patch);
+    patch.extend_from_slice(&patch_crc.to_le_bytes());
+    patch
+}
+
+fn bps_number(patch: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let low = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            patch.push(0x80 | low);
+            return;
+        }
+        patch.push(low);
+        value -= 1;
+    }
+}
+
+fn bps_relative(patch: &mut Vec<u8>, from: usize, to: usize) {
+    if to >= from {
+        bps_number(patch, (to - from) << 1);
+    } else {
+        bps_number(patch, ((from - to) << 1) | 1);
+    }
+}
+
+/// Hash chains over the BPS_MIN_MATCH-byte sequences of a buffer, for finding copies
+struct MatchChains {
+    head: Vec<usize>,
+    previous: Vec<usize>,
+}
+
+impl MatchChains {
+    const NONE: usize = usize::MAX;
+
+    /// Chains over `data`, with its first `indexed` positions already inserted
+    fn new(data: &[u8], indexed: usize) -> Self {
+        let mut chains = MatchChains {
+            head: vec![Self::NONE; 1 << BPS_HASH_BITS],
+            previous: vec![Self::NONE; data.len()],
+        };
+        for at in 0..indexed {
+            chains.insert(data, at);
+        }
+        chains
+    }
+
+    fn hash(data: &[u8], at: usize) -> Option<usize> {
+        let key = data.get(at..at + BPS_MIN_MATCH)?;
+        let key = u32::from_le_bytes([key[0], key[1], key[2], key[3]]);
+        Some((key.wrapping_mul(0x9E37_79B1) >> (32 - BPS_HASH_BITS)) as usize)
+    }
+
+    fn insert(&mut self, data: &[u8], at: usize) {
+        if let Some(hash) = Self::hash(data, at) {
+            self.previous[at] = self.head[hash];
+            self.head[hash] = at;
+        }
+    }
+
+    /// Longest (length, position) in `data` (positions before `before`) matching `target` at `out`
+    fn longest(&self, data: &[u8], target: &[u8], out: usize, before: usize) -> (usize, usize) {
+        let Some(hash) = Self::hash(target, out) else {
+            return (0, 0);
+        };
+        let mut best = (0, 0);
+        let mut candidate = self.head[hash];
+        for _ in 0..BPS_MAX_CHAIN {
+            if candidate == Self::NONE {
+                break;
+            }
+            if candidate < before {
+                let len = data[candidate..].iter().zip(&target[out..]).take_while(|(a, b)| a == b).count();
+                if len > best.0 {
+                    best = (len, candidate);
+                }
+            }
+            candidate = self.previous[candidate];
+        }
+        best
+    }
+}
+
+fn be_uint(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |value, &byte| (value << 8) | byte as usize)
+}
+
+fn invalid_patch(reason: String) -> CoreError {
+    CoreError::new(ErrorCode::ArgValidation, format!("InvalidPatch: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A BPS patch from hand-written header fields and actions, with its CRC32 footer
+    fn sealed_bps(source: &[u8], target_size: usize, actions: &[u8], target_crc: u32) -> Vec<u8> {
+        let mut patch = BPS_HEADER.to_vec();
+        bps_number(&mut patch, source.len());
+        bps_number(&mut patch, target_size);
+        bps_number(&mut patch, 0);
+        patch.extend_from_slice(actions);
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&target_crc.to_le_bytes());
+        let patch_crc = crc32fast::hash(&patch);
+        patch.extend_from_slice(&patch_crc.to_le_bytes());
+        patch
+    }
+
+    /// An IPS patch from (offset, record body) pairs, with `after_eof` following "EOF"
+    fn ips(records: &[(usize, Vec<u8>)], after_eof: &[u8]) -> Vec<u8> {
+        let mut patch = IPS_HEADER.to_vec();
+        for (offset, data) in records {
+            patch.extend_from_slice(&offset.to_be_bytes()[5..]);
+            patch.extend_from_slice(data);
+        }
+        patch.extend_from_slice(IPS_EOF);
+        patch.extend_from_slice(after_eof);
+        patch
+    }
+
+    /// Record body: 2-byte size, then the bytes
+    fn literal(bytes: &[u8]) -> Vec<u8> {
+        [&(bytes.len() as u16).to_be_bytes()[..], bytes].concat()
+    }
+
+    /// Record body: size 0, 2-byte run length, the byte to repeat
+    fn rle(run: u16, value: u8) -> Vec<u8> {
+        [&[0, 0][..], &run.to_be_bytes(), &[value]].concat()
+    }
+
+    #[test]
+    fn ips_apply() {
+        let rom = vec![0u8; 8];
+        let cases: [(&str, Vec<u8>, Vec<u8>); 7] = [
+            ("no records", ips(&[], &[]), vec![0; 8]),
+            ("literal", ips(&[(2, literal(&[1, 2, 3]))], &[]), vec![0, 0, 1, 2, 3, 0, 0, 0]),
+            ("RLE", ips(&[(1, rle(4, 9))], &[]), vec![0, 9, 9, 9, 9, 0, 0, 0]),
+            ("literal then RLE over it", ips(&[(0, literal(&[5; 4])), (2, rle(3, 6))], &[]), vec![5, 5, 6, 6, 6, 0, 0, 0]),
+            ("extends past the end", ips(&[(10, literal(&[7, 7]))], &[]), vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 7, 7]),
+            ("RLE extends past the end", ips(&[(6, rle(4, 3))], &[]), vec![0, 0, 0, 0, 0, 0, 3, 3, 3, 3]),
+            ("truncation extension", ips(&[(0, literal(&[1]))], &[0, 0, 5]), vec![1, 0, 0, 0, 0]),
+        ];
+        for (name, patch, expected) in cases {
+            assert_eq!(ips_patch(&rom, &patch).ok(), Some(expected), "{}", name);
+        }
+    }
+
+    #[test]
+    fn ips_eof_handling() {
+        let rom = vec![0u8; 4];
+        // A record at offset 0x454F46 reads as "EOF" and ends the patch, as in other patchers
+        let mut eof_offset = ips(&[], &[]);
+        eof_offset.splice(5..5, [0x45, 0x4F, 0x46, 0, 1, 9]);
+        let cases = [
+            ("trailing bytes after EOF shorter than 3 are ignored", ips(&[(0, literal(&[1]))], &[0, 2]), vec![1, 0, 0, 0]),
+            ("bytes past the truncation length are ignored", ips(&[], &[0, 0, 2, 0xFF]), vec![0, 0]),
+            ("record offset spelling EOF", eof_offset, vec![0; 4]),
+        ];
+        for (name, patch, expected) in cases {
+            assert_eq!(ips_patch(&rom, &patch).ok(), Some(expected), "{}", name);
+        }
+
+        let cases = [
+            ("missing EOF", ips(&[(0, literal(&[1]))], &[])[..11].to_vec(), "InvalidPatch: record at patch offset 0xB"),
+            ("missing header", b"PATC".to_vec(), "InvalidPatch: missing \"PATCH\" header"),
+        ];
+        for (name, patch, expected) in cases {
+            let reason = ips_patch(&rom, &patch).unwrap_err().reason;
+            assert!(reason.starts_with(expected), "{}: {}", name, reason);
+        }
+    }
+
+    #[test]
+    fn ips_rejects_truncated_records() {
+        let rom = vec![0u8; 4];
+        let full = ips(&[(1, literal(&[1, 2, 3])), (0, rle(2, 7))], &[]);
+        let cases = [
+            ("offset", 7, "record at patch offset 0x5"),
+            ("record size", 9, "record size at patch offset 0x8"),
+            ("record data", 12, "record data at patch offset 0xA"),
+            ("RLE run length", 19, "RLE run length at patch offset 0x12"),
+            ("RLE value", 20, "RLE value at patch offset 0x14"),
+        ];
+        for (name, len, expected) in cases {
+            let reason = ips_patch(&rom, &full[..len]).unwrap_err().reason;
+            assert_eq!(reason, format!("InvalidPatch: {} runs past the end of the patch", expected), "{}", name);
+        }
+    }
+
+    #[test]
+    fn bps_round_trip() {
+        let source: Vec<u8> = (0..4096u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut modified = source.clone();
+        modified[100..108].copy_from_slice(b"MODIFIED");
+        let mut moved = source[2048..].to_vec();
+        moved.extend_from_slice(&source[..2048]);
+        let mut repeated = b"ABCD".repeat(300);
+        repeated.extend_from_slice(&source[..500]);
+
+        let cases: [(&str, Vec<u8>); 6] = [
+            ("identical", source.clone()),
+            ("modified", modified),
+            ("extended", [source.as_slice(), &[0xFF; 1000]].concat()),
+            ("moved halves", moved),
+            ("self-repeating", repeated),
+            ("empty", Vec::new()),
+        ];
+        for (name, target) in cases {
+            let patch = bps_create(&source, &target, b"<meta/>");
+            assert_eq!(bps_patch(&source, &patch).ok(), Some(target), "{}", name);
+        }
+    }
+
+    #[test]
+    fn bps_rejects() {
+        let source = vec![0x11; 64];
+        let target = [vec![0x22; 32], vec![0x11; 32]].concat();
+        let patch = bps_create(&source, &target, b"");
+        let mut corrupt = patch.clone();
+        corrupt[BPS_HEADER.len() + 3] ^= 0x01;
+        let mut other_source = source.clone();
+        other_source[10] = 0;
+        // TargetRead of 4 bytes into a 2-byte target
+        let overflowing = sealed_bps(&source, 2, &[(3 << 2 | TARGET_READ as u8) | 0x80, 1, 2, 3, 4], 0);
+        let oversized = sealed_bps(&source, BPS_MAX_TARGET + 1, &[], 0);
+
+        let cases: [(&str, &[u8], &[u8], &str); 5] = [
+            ("bad patch CRC", &source, &corrupt, "InvalidPatch: patch CRC32"),
+            ("wrong source CRC", &other_source, &patch, "PatchSourceMismatch: source is 64 bytes"),
+            ("wrong source size", &source[..63], &patch, "PatchSourceMismatch: source is 63 bytes"),
+            ("writes past the target", &source, &overflowing, "InvalidPatch: action at patch offset"),
+            ("oversized target", &source, &oversized, "InvalidPatch: 16777217-byte target"),
+        ];
+        for (name, source, patch, expected) in cases {
+            let reason = bps_patch(source, patch).unwrap_err().reason;
+            assert!(reason.starts_with(expected), "{}: {}", name, reason);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+// Batched memory reads over VGET, optionally pipelined
+// Polling many regions is dominated by round-trip latency. The firmware accepts the
+// next command packet while we are still reading the previous small response, so in
+// pipelined mode several VGETs are written ahead and their responses read in order.
+// With torn_read the batch is resampled until consistent (see torn.rs).
+
+use napi_derive::napi;
+use napi::bindgen_prelude::{Buffer, Either};
+use crate::errors::{CoreError, ErrorCode, Result};
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+use crate::queue::Lane;
+use crate::regions::MemoryRegion;
+use crate::torn::{read_stable_locked, StableReads, TornReadOptions, TornReadSettings};
+use crate::{
+    apply_timeout_locked, data_block_len, drain_input_locked, read_data_locked, receive_response_locked,
+    send_packet_locked, split_vget_data, validate_address_range, vget_data_len, vget_locked, vget_packet, Connection,
+    Usb2SnesCore, DATA64B_FLAG, SPACE_SNES, VGET_MAX_PAIRS,
+};
+
+/// Default number of VGET packets in flight when pipelining
+const DEFAULT_PIPELINE_DEPTH: u32 = 2;
+
+/// Upper bound on packets in flight (the device's receive buffer is small)
+const MAX_PIPELINE_DEPTH: u32 = 4;
+
+/// Largest size a single VGET pair can carry (the size field is one byte)
+pub(crate) const MAX_PAIR_SIZE: u32 = 0xFF;
+
+/// One read of read_multiple()
+#[napi(object)]
+pub struct ReadRequest {
+    pub region: MemoryRegion,
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// Options for read_multiple()
+#[napi(object)]
+#[derive(Default)]
+pub struct ReadMultipleOptions {
+    /// Write VGET packets ahead of their responses (default false)
+    /// Ignored while set_pipelining_allowed(false) is in effect
+    pub pipeline: Option<bool>,
+    /// VGET packets in flight when pipelining (default 2, at most 4)
+    pub pipeline_depth: Option<u32>,
+    /// Resample until the values are consistent (see torn.rs)
+    pub torn_read: Option<TornReadOptions>,
+}
+
+/// Only commands whose sole data phase comes from the device after its RESPONSE can be
+/// written ahead; anything with a host data phase (PUT/VPUT, FILE PUT) would interleave
+fn pipelinable(packet: &[u8]) -> bool {
+    packet[4] == 2 && packet[6] & DATA64B_FLAG != 0
+}
+
+/// Read the RESPONSE and data phase of a VGET already written
+fn receive_vget_locked(conn: &mut Connection, packet: &[u8], pairs: &[(u8, u32)]) -> Result<Vec<Vec<u8>>> {
+    receive_response_locked(conn, packet)?;
+    let data = read_data_locked(conn, vget_data_len(pairs), data_block_len(DATA64B_FLAG))?;
+    Ok(split_vget_data(pairs, &data))
+}
+
+/// Run the VGET batches with up to `depth` packets written ahead of their responses
+/// Responses arrive in request order, so the front of the in-flight queue is always
+/// the packet the next response belongs to. On any error the responses still in
+/// flight are drained before the error is returned, leaving the line in sync.
+fn vget_pipelined_locked(
+    conn: &mut Connection,
+    space: u8,
+    batches: &[Vec<(u8, u32)>],
+    depth: usize,
+) -> Result<Vec<Vec<Vec<u8>>>> {
+    let packets = batches.iter().map(|pairs| vget_packet(space, pairs)).collect::<Result<Vec<_>>>()?;
+    if depth < 2 || !packets.iter().all(|packet| pipelinable(packet)) {
+        return batches.iter().map(|pairs| vget_locked(conn, space, pairs)).collect();
+    }
+    apply_timeout_locked(conn, &packets[0])?;
+
+    let mut results = Vec::with_capacity(batches.len());
+    let mut in_flight: VecDeque<(usize, Instant)> = VecDeque::with_capacity(depth);
+    let mut next = 0;
+    while results.len() < batches.len() {
+        while next < packets.len() && in_flight.len() < depth {
+            let started = Instant::now();
+            if let Err(e) = send_packet_locked(conn, &packets[next]) {
+                conn.diagnostics.lock().unwrap().record_exchange(&packets[next], started.elapsed(), None, Some(&e));
+                return Err(abort_pipeline_locked(conn, &packets, batches, in_flight, e));
+            }
+            in_flight.push_back((next, started));
+            next += 1;
+        }
+
+        let (index, started) = in_flight.pop_front().unwrap();
+        let outcome = receive_vget_locked(conn, &packets[index], &batches[index]);
+        let response = conn.last_response.clone().filter(|_| outcome.is_ok());
+        conn.diagnostics.lock().unwrap()
+            .record_exchange(&packets[index], started.elapsed(), response.as_deref(), outcome.as_ref().err());
+        match outcome {
+            Ok(data) => results.push(data),
+            Err(e) => return Err(abort_pipeline_locked(conn, &packets, batches, in_flight, e)),
+        }
+    }
+    Ok(results)
+}
+
+/// Read (address, size) ranges as VGET pairs of at most 255 bytes, 8 to a packet, with
+/// up to `depth` packets in flight; one Vec per range
+fn read_ranges_locked(conn: &mut Connection, space: u8, ranges: &[(u32, u32)], depth: usize) -> Result<Vec<Vec<u8>>> {
+    // (range index, pair) for every chunk, then packed 8 to a packet
+    let mut chunks = Vec::new();
+    for (index, &(address, size)) in ranges.iter().enumerate() {
+        for chunk_offset in (0..size).step_by(MAX_PAIR_SIZE as usize) {
+            let chunk_size = (size - chunk_offset).min(MAX_PAIR_SIZE);
+            chunks.push((index, (chunk_size as u8, address + chunk_offset)));
+        }
+    }
+    let batches: Vec<Vec<(u8, u32)>> = chunks.chunks(VGET_MAX_PAIRS)
+        .map(|batch| batch.iter().map(|&(_, pair)| pair).collect())
+        .collect();
+    if batches.is_empty() {
+        return Ok(vec![Vec::new(); ranges.len()]);
+    }
+
+    let data = vget_pipelined_locked(conn, space, &batches, depth)?;
+    let mut out: Vec<Vec<u8>> = ranges.iter().map(|&(_, size)| Vec::with_capacity(size as usize)).collect();
+    for ((index, _), chunk) in chunks.iter().zip(data.into_iter().flatten()) {
+        out[*index].extend_from_slice(&chunk);
+    }
+    Ok(out)
+}
+
+/// Consume the responses of every packet still in flight, then hand back `error`
+/// If the line is already out of step (a response failed to parse), fall back to
+/// draining raw input until it goes quiet.
+fn abort_pipeline_locked(
+    conn: &mut Connection,
+    packets: &[Vec<u8>],
+    batches: &[Vec<(u8, u32)>],
+    in_flight: VecDeque<(usize, Instant)>,
+    error: CoreError,
+) -> CoreError {
+    let outstanding = in_flight.len();
+    let mut in_sync = true;
+    for (index, _) in in_flight {
+        if in_sync && receive_vget_locked(conn, &packets[index], &batches[index]).is_err() {
+            in_sync = false;
+        }
+    }
+    if !in_sync {
+        let _ = drain_input_locked(conn);
+    }
+
+    conn.diagnostics.lock().unwrap().record_warning(&format!(
+        "Pipelined read aborted with {} response(s) in flight: {}", outstanding, error.reason
+    ));
+    error
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Read several memory ranges using as few VGETs as possible
+    /// Ranges are split into VGET pairs of at most 255 bytes, 8 pairs per packet.
+    /// With `pipeline` set, up to `pipeline_depth` packets are written before the
+    /// first response is read. Returns one buffer per request, in order; with
+    /// `torn_read` they come as { data, strategy, retries, stable } (see torn.rs).
+    #[napi(ts_return_type = "Buffer[] | StableReads")]
+    pub fn read_multiple(
+        &self,
+        reads: Vec<ReadRequest>,
+        options: Option<ReadMultipleOptions>,
+    ) -> Result<Either<Vec<Buffer>, StableReads>> {
+        let options = options.unwrap_or_default();
+        let depth = if options.pipeline.unwrap_or(false) && self.pipelining_allowed.load(Ordering::SeqCst) {
+            options.pipeline_depth.unwrap_or(DEFAULT_PIPELINE_DEPTH).clamp(1, MAX_PIPELINE_DEPTH)
+        } else {
+            1
+        } as usize;
+        let torn_read = options.torn_read.map(TornReadSettings::new).transpose()?;
+
+        let mut space = None;
+        let mut ranges = Vec::with_capacity(reads.len());
+        for read in &reads {
+            let (read_space, address) = read.region.resolve(read.offset, read.size)?;
+            if space.is_some_and(|space| space != read_space) {
+                return Err(CoreError::new(ErrorCode::ArgValidation, "read_multiple: all regions must be in the same space"));
+            }
+            space = Some(read_space);
+            ranges.push((address, read.size));
+        }
+        let read = |conn: &mut Connection, ranges: &[(u32, u32)]| read_ranges_locked(conn, space.unwrap_or_default(), ranges, depth);
+
+        let Some(settings) = torn_read else {
+            let data = match space {
+                Some(_) if ranges.iter().any(|&(_, size)| size > 0) => {
+                    self.with_connection_in(Lane::Interactive, |conn| read(conn, &ranges))?
+                }
+                _ => vec![Vec::new(); ranges.len()],
+            };
+            return Ok(Either::A(data.into_iter().map(Buffer::from).collect()));
+        };
+        let space = space.unwrap_or(SPACE_SNES);
+        let (data, outcome) = self.with_connection_in(Lane::Interactive, |conn| {
+            read_stable_locked(conn, space, &ranges, settings, read)
+        })?;
+        Ok(Either::B(StableReads {
+            data: data.into_iter().map(Buffer::from).collect(),
+            strategy: outcome.strategy(),
+            retries: outcome.retries,
+            stable: outcome.stable,
+        }))
+    }
+
+    /// Read several small values by raw (address, size) in as few VGETs as possible
+    /// Each read is one VGET pair, packed 8 to a packet; the packets go out back to
+    /// back while holding the port. Zero-size reads return empty buffers without
+    /// being sent. Returns one buffer per read, in order.
+    #[napi]
+    pub fn read_many(&self, space: u8, reads: Vec<(u32, u8)>) -> Result<Vec<Buffer>> {
+        for &(address, size) in &reads {
+            validate_address_range(space, address, size as u32)?;
+        }
+        let pairs: Vec<(usize, (u8, u32))> = reads.iter().enumerate()
+            .filter(|(_, &(_, size))| size > 0)
+            .map(|(index, &(address, size))| (index, (size, address)))
+            .collect();
+        let batches: Vec<Vec<(u8, u32)>> = pairs.chunks(VGET_MAX_PAIRS)
+            .map(|batch| batch.iter().map(|&(_, pair)| pair).collect())
+            .collect();
+
+        let data = if batches.is_empty() {
+            Vec::new()
+        } else {
+            self.with_connection_in(Lane::Interactive, |conn| vget_pipelined_locked(conn, space, &batches, 1))?
+        };
+
+        let mut out = vec![Vec::new(); reads.len()];
+        for ((index, _), chunk) in pairs.iter().zip(data.into_iter().flatten()) {
+            out[*index] = chunk;
+        }
+        Ok(out.into_iter().map(Buffer::from).collect())
+    }
+
+    /// Allow or forbid pipelined reads on this core (allowed by default)
+    /// A kill switch for firmware that chokes on packets written ahead: while
+    /// forbidden, read_multiple() ignores its `pipeline` option.
+    #[napi]
+    pub fn set_pipelining_allowed(&self, allowed: bool) {
+        self.pipelining_allowed.store(allowed, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,168 @@
+// Typed opcode, space and flag values for send_command()
+// The enums are numeric on the JS side, so existing callers passing raw numbers keep
+// working while TypeScript gets names and autocompletion. An opcode or space outside
+// the enum is rejected by napi before the call runs. Flags are a bitmask, which napi
+// enums can't express, so send_command() and the command methods that take `flags`
+// (boot(), menu_reset(), get_memory(), put_memory()) take a number built from Flags
+// values and reject bits the firmware doesn't define; the methods also reject bits
+// that have no effect on their command. Address and size arguments may be given
+// as numbers or BigInts instead of hex strings; they are range-checked and encoded
+// here, so build_packet() still sees one argument format.
+
+use napi_derive::napi;
+use napi::bindgen_prelude::{BigInt, Either3};
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::{CLRX_FLAG, DATA64B_FLAG, NORESP_FLAG, ONLYRESET_FLAG, SETX_FLAG, SKIPRESET_FLAG, STREAM_BURST_FLAG};
+
+/// Request opcodes (RESPONSE, 15, is only sent by the device)
+#[napi]
+pub enum Opcode {
+    Get = 0,
+    Put = 1,
+    Vget = 2,
+    Vput = 3,
+    Ls = 4,
+    Mkdir = 5,
+    Rm = 6,
+    Mv = 7,
+    Reset = 8,
+    Boot = 9,
+    PowerCycle = 10,
+    Info = 11,
+    MenuReset = 12,
+    Stream = 13,
+    Time = 14,
+}
+
+/// Address spaces a command operates on
+#[napi]
+pub enum Space {
+    /// SD card files (paths instead of addresses)
+    File = 0,
+    /// SNES bus: ROM, SRAM, WRAM and the other FxPak-mapped ranges
+    Snes = 1,
+    Msu = 2,
+    Cmd = 3,
+    Config = 4,
+}
+
+/// Command flag bits; combine with `|` for send_command()'s `flags`
+#[napi]
+pub enum Flags {
+    None = 0x00,
+    /// BOOT/MENU_RESET: don't reset the SNES
+    SkipReset = 0x01,
+    /// BOOT/MENU_RESET: only reset, don't load
+    OnlyReset = 0x02,
+    /// CMD space: clear the execute bit
+    ClrX = 0x04,
+    /// CMD space: set the execute bit
+    SetX = 0x08,
+    /// STREAM: burst mode
+    StreamBurst = 0x10,
+    /// The device sends no RESPONSE packet
+    NoResp = 0x40,
+    /// The data phase uses 64-byte blocks instead of 512
+    Data64B = 0x80,
+}
+
+/// Every flag bit defined by the firmware
+const KNOWN_FLAGS: u8 = SKIPRESET_FLAG | ONLYRESET_FLAG | CLRX_FLAG | SETX_FLAG | STREAM_BURST_FLAG | NORESP_FLAG
+    | DATA64B_FLAG;
+
+/// Firmware names of the flag bits, lowest first
+const FLAG_NAMES: [(u8, &str); 7] = [
+    (SKIPRESET_FLAG, "SKIPRESET"), (ONLYRESET_FLAG, "ONLYRESET"), (CLRX_FLAG, "CLRX"), (SETX_FLAG, "SETX"),
+    (STREAM_BURST_FLAG, "STREAM_BURST"), (NORESP_FLAG, "NORESP"), (DATA64B_FLAG, "DATA64B"),
+];
+
+/// Flag bits as firmware names joined with '|', e.g. "SKIPRESET|DATA64B"
+pub(crate) fn flag_names(flags: u8) -> String {
+    FLAG_NAMES.iter()
+        .filter(|(bit, _)| flags & bit != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+/// Reject flag bits outside Flags
+pub(crate) fn check_flags(flags: u32) -> Result<u8> {
+    match u8::try_from(flags) {
+        Ok(flags) if flags & !KNOWN_FLAGS == 0 => Ok(flags),
+        _ => Err(CoreError::new(ErrorCode::ArgValidation, format!(
+            "InvalidCommand: flags 0x{:02X} set bits outside Flags (known bits 0x{:02X})",
+            flags, KNOWN_FLAGS
+        ))),
+    }
+}
+
+/// Check the `flags` of a command method: known bits, and only those in `allowed`
+pub(crate) fn method_flags(method: &str, flags: Option<u32>, allowed: u8) -> Result<u8> {
+    let flags = check_flags(flags.unwrap_or(0))?;
+    if flags & !allowed != 0 {
+        return Err(CoreError::new(ErrorCode::ArgValidation, format!(
+            "InvalidCommand: {}() takes no {} (allowed flags: {})",
+            method, flag_names(flags & !allowed), flag_names(allowed)
+        )));
+    }
+    Ok(flags)
+}
+
+/// One send_command() call; `opcode` and `space` are required
+#[napi(object)]
+pub struct CommandRequest {
+    pub opcode: Opcode,
+    pub space: Space,
+    /// Flags values or'd together (default none)
+    pub flags: Option<u32>,
+    /// Hex strings, or numbers/BigInts for addresses and sizes; paths are always strings
+    pub args: Option<Vec<CommandArg>>,
+    /// Read timeout for this call only, beating every ConnectOptions.timeouts value
+    pub timeout_ms: Option<u32>,
+}
+
+/// One send_command() argument: a path or hex string, or a number/BigInt address or size
+pub type CommandArg = Either3<String, f64, BigInt>;
+
+/// Whether argument `index` of a command is a path, which only a string can give
+fn is_path_arg(opcode: u8, space: u8, index: usize) -> bool {
+    match opcode {
+        0 | 1 => space == 0 && index == 0,
+        4 | 5 | 6 | 7 | 9 => true,
+        _ => false,
+    }
+}
+
+/// Encode send_command() arguments as the hex strings build_packet() parses
+/// Numbers must be whole and in 0..=0xFFFFFFFF; narrower fields (VGET sizes) are
+/// checked by build_packet() itself
+pub(crate) fn encode_args(opcode: u8, space: u8, args: Vec<CommandArg>) -> Result<Vec<String>> {
+    args.into_iter().enumerate().map(|(index, arg)| {
+        let value = match arg {
+            Either3::A(text) => return Ok(text),
+            Either3::B(number) if number.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&number) => number as u32,
+            Either3::B(number) => return Err(arg_error(opcode, index, &number.to_string())),
+            Either3::C(bigint) => match bigint.get_u64() {
+                (false, value, true) if value <= u32::MAX as u64 => value as u32,
+                (sign, value, _) => {
+                    let shown = if sign { format!("-{}", value) } else { format!("{}", value) };
+                    return Err(arg_error(opcode, index, &format!("{}n", shown)));
+                }
+            },
+        };
+        if is_path_arg(opcode, space, index) {
+            return Err(CoreError::new(
+                ErrorCode::ArgValidation,
+                format!("Command: {} arg[{}] is a path and must be a string, got {}", opcode, index, value),
+            ));
+        }
+        Ok(format!("{:X}", value))
+    }).collect()
+}
+
+fn arg_error(opcode: u8, index: usize, shown: &str) -> CoreError {
+    CoreError::new(
+        ErrorCode::ArgValidation,
+        format!("Command: {} arg[{}] {} is not a whole number in 0..=0xFFFFFFFF", opcode, index, shown),
+    )
+}
@@ -0,0 +1,170 @@
+// Command queue: the order in which concurrent callers get the port
+// The firmware is strictly request/response, so only one caller may talk to it at a
+// time; with_connection() holds the port for a whole call. The port mutex alone
+// decides nothing about who goes next when several callers wait (sync calls, *_async
+// jobs, watches, recordings, the WebSocket server), so callers queue here first. Each
+// call is in a lane - Interactive for memory access and INFO, Bulk for file transfers,
+// Normal for the rest - and the next turn goes to the highest lane with a waiter,
+// first come first served within it. A tracker poll waiting behind a multi-megabyte
+// upload therefore runs as soon as that upload's current call finishes, ahead of the
+// next queued file; and a waiter passed over MAX_SKIPS times goes next regardless, so
+// a steady stream of polls can't starve transfers either. A call that is running is
+// never interrupted: one GET/PUT and its data phase always stay together.
+
+use std::sync::{Condvar, Mutex};
+
+/// Turns a waiter may be passed over by higher lanes before it goes next anyway
+const MAX_SKIPS: u32 = 8;
+
+/// Queue lane of a call, lowest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Lane {
+    /// File transfers
+    Bulk,
+    Normal,
+    /// Memory reads/writes and INFO, typically polled
+    Interactive,
+}
+
+struct Waiter {
+    ticket: u64,
+    lane: Lane,
+    /// Turns granted to later arrivals while this one waited
+    skipped: u32,
+}
+
+#[derive(Default)]
+struct QueueState {
+    next_ticket: u64,
+    /// A turn is taken and not yet released
+    busy: bool,
+    waiting: Vec<Waiter>,
+}
+
+impl QueueState {
+    /// Add a waiter in `lane`, returning its ticket
+    fn enqueue(&mut self, lane: Lane) -> u64 {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        self.waiting.push(Waiter { ticket, lane, skipped: 0 });
+        ticket
+    }
+
+    /// Give `ticket` the turn, counting it against everyone who arrived earlier
+    fn take_turn(&mut self, ticket: u64) {
+        self.busy = true;
+        self.waiting.retain(|waiter| waiter.ticket != ticket);
+        for waiter in self.waiting.iter_mut().filter(|waiter| waiter.ticket < ticket) {
+            waiter.skipped += 1;
+        }
+    }
+
+    /// Ticket that gets the next turn
+    fn next_up(&self) -> Option<u64> {
+        let overdue = self.waiting.iter()
+            .filter(|waiter| waiter.skipped >= MAX_SKIPS)
+            .min_by_key(|waiter| waiter.ticket);
+        // Highest lane, and the earliest ticket within it
+        overdue
+            .or_else(|| self.waiting.iter().min_by_key(|waiter| (std::cmp::Reverse(waiter.lane), waiter.ticket)))
+            .map(|waiter| waiter.ticket)
+    }
+}
+
+/// Priority queue in front of a core's port (see with_connection_in())
+#[derive(Default)]
+pub(crate) struct CommandQueue {
+    state: Mutex<QueueState>,
+    turn: Condvar,
+}
+
+/// A turn at the port; the next waiter goes when it's dropped
+pub(crate) struct QueueTurn<'a> {
+    queue: &'a CommandQueue,
+}
+
+impl CommandQueue {
+    /// Wait for a turn in `lane`
+    pub(crate) fn wait_turn(&self, lane: Lane) -> QueueTurn<'_> {
+        let mut state = self.state.lock().unwrap();
+        let ticket = state.enqueue(lane);
+        while state.busy || state.next_up() != Some(ticket) {
+            state = self.turn.wait(state).unwrap();
+        }
+        state.take_turn(ticket);
+        QueueTurn { queue: self }
+    }
+
+    /// Calls waiting for a turn, not counting the one that has it
+    pub(crate) fn depth(&self) -> u32 {
+        self.state.lock().unwrap().waiting.len() as u32
+    }
+}
+
+impl Drop for QueueTurn<'_> {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().busy = false;
+        self.queue.turn.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn next_up_prefers_higher_lanes_then_earlier_tickets() {
+        let mut state = QueueState::default();
+        let bulk = state.enqueue(Lane::Bulk);
+        let normal = state.enqueue(Lane::Normal);
+        let interactive = state.enqueue(Lane::Interactive);
+        let later_interactive = state.enqueue(Lane::Interactive);
+
+        for expected in [interactive, later_interactive, normal, bulk] {
+            assert_eq!(state.next_up(), Some(expected));
+            state.take_turn(expected);
+        }
+        assert_eq!(state.next_up(), None);
+    }
+
+    #[test]
+    fn bulk_passed_over_max_skips_times_goes_next() {
+        let mut state = QueueState::default();
+        let bulk = state.enqueue(Lane::Bulk);
+        for skip in 0..MAX_SKIPS {
+            let poll = state.enqueue(Lane::Interactive);
+            assert_eq!(state.next_up(), Some(poll), "poll {} overtakes", skip);
+            state.take_turn(poll);
+        }
+        let poll = state.enqueue(Lane::Interactive);
+        assert_eq!(state.next_up(), Some(bulk));
+        state.take_turn(bulk);
+        assert_eq!(state.next_up(), Some(poll));
+    }
+
+    #[test]
+    fn interactive_waiter_overtakes_bulk() {
+        let queue = Arc::new(CommandQueue::default());
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let turn = queue.wait_turn(Lane::Normal);
+
+        let mut threads = Vec::new();
+        for (lane, waiting) in [(Lane::Bulk, 1), (Lane::Interactive, 2)] {
+            let (waiter_queue, waiter_order) = (queue.clone(), order.clone());
+            threads.push(std::thread::spawn(move || {
+                let _turn = waiter_queue.wait_turn(lane);
+                waiter_order.lock().unwrap().push(lane);
+            }));
+            while queue.depth() < waiting {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+        drop(turn);
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), [Lane::Interactive, Lane::Bulk]);
+    }
+}
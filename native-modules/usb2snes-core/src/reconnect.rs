@@ -0,0 +1,277 @@
+// Automatic reconnect: bring a lost connection back without the caller's help
+// Opt-in with enable_auto_reconnect(). When the device goes away (a DeviceDisconnected
+// failure, or the liveness monitor of on()) or a read or write on the port fails with
+// IoError, the connection is torn down and a supervisor thread reconnects with the
+// port and options of the last connect_with_options(): it waits (initial_delay_ms,
+// doubling up to max_delay_ms), looks the port up again - by USB serial number, as a
+// re-plugged FxPak may come back under another name - and connects. Idempotent reads
+// (memory and file reads, INFO, LS) issued meanwhile wait for it instead of failing,
+// and one that failed because the link dropped is run again once it's back; writes
+// and other commands fail fast as before. Progress is reported to on() listeners as
+// "reconnecting" (before each attempt), "reconnected" and "reconnectFailed".
+// disconnect() and a new connect stop a reconnect in progress.
+
+use napi_derive::napi;
+use serialport::SerialPortType;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::events::ConnectionEvent;
+use crate::queue::Lane;
+use crate::{open_serial_port, ConnectOptions, Connection, DisconnectKind, Usb2SnesCore};
+
+const DEFAULT_INITIAL_DELAY_MS: u32 = 250;
+const DEFAULT_MAX_DELAY_MS: u32 = 8000;
+const DEFAULT_QUEUE_TIMEOUT_MS: u32 = 10_000;
+
+/// How often waiting reads and the backoff sleep check for a change
+const RECONNECT_POLL_MS: u64 = 10;
+
+/// IoError reasons of a failed read or write on the port (see transport.rs); other
+/// IoErrors (host files, control lines) say nothing about the link
+const LINK_IO_ERRORS: &[&str] = &["Read error: ", "Write failed: ", "Flush failed: "];
+
+/// Options for enable_auto_reconnect()
+#[napi(object)]
+#[derive(Clone, Default)]
+pub struct ReconnectOptions {
+    /// Wait before the first attempt (default 250ms); doubles after each failed one
+    pub initial_delay_ms: Option<u32>,
+    /// Upper bound of the wait between attempts (default 8000ms)
+    pub max_delay_ms: Option<u32>,
+    /// Give up after this many attempts (default: never)
+    pub max_attempts: Option<u32>,
+    /// How long an idempotent read waits for a reconnect in progress (default 10000ms)
+    pub queue_timeout_ms: Option<u32>,
+}
+
+/// What the last connect_with_options() connected to
+#[derive(Clone)]
+struct ReconnectTarget {
+    port_name: String,
+    /// USB serial number of the port, to find it again under another name
+    serial_number: Option<String>,
+    options: ConnectOptions,
+}
+
+/// Auto-reconnect settings of one core and the reconnect in progress
+#[derive(Default)]
+pub(crate) struct Supervisor {
+    options: Option<ReconnectOptions>,
+    target: Option<ReconnectTarget>,
+    /// Cancel flag of the running supervisor thread
+    running: Option<Arc<AtomicBool>>,
+}
+
+/// USB serial number of a serial port, if it is a USB port that has one
+fn usb_serial_number(port_name: &str) -> Option<String> {
+    if port_name.contains("://") {
+        return None;
+    }
+    serialport::available_ports().ok()?
+        .into_iter()
+        .find(|port| port.port_name == port_name)
+        .and_then(|port| match port.port_type {
+            SerialPortType::UsbPort(usb) => usb.serial_number,
+            _ => None,
+        })
+}
+
+impl Supervisor {
+    pub(crate) fn forget_target(&mut self) {
+        self.target = None;
+    }
+}
+
+impl ReconnectTarget {
+    /// Current name of the target's port
+    fn locate(&self) -> String {
+        let Some(serial_number) = &self.serial_number else {
+            return self.port_name.clone();
+        };
+        serialport::available_ports().ok()
+            .and_then(|ports| ports.into_iter().find(|port| matches!(
+                &port.port_type, SerialPortType::UsbPort(usb) if usb.serial_number.as_ref() == Some(serial_number)
+            )))
+            .map_or_else(|| self.port_name.clone(), |port| port.port_name)
+    }
+}
+
+fn is_link_io_error(error: &CoreError) -> bool {
+    error.code == ErrorCode::IoError && LINK_IO_ERRORS.iter().any(|reason| error.reason.contains(reason))
+}
+
+impl Usb2SnesCore {
+    /// Remember what connect_with_options() connected to, for a later reconnect
+    pub(crate) fn set_reconnect_target(&self, port_name: &str, options: ConnectOptions) {
+        let mut supervisor = self.supervisor.lock().unwrap();
+        // Finding the serial number enumerates the ports; only worth it when enabled
+        let serial_number = supervisor.options.as_ref().and_then(|_| usb_serial_number(port_name));
+        supervisor.target = Some(ReconnectTarget { port_name: port_name.to_string(), serial_number, options });
+    }
+
+    /// Stop a reconnect in progress (disconnect() and new connects)
+    pub(crate) fn cancel_reconnect(&self) {
+        if let Some(cancel) = self.supervisor.lock().unwrap().running.take() {
+            cancel.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Tear down a connection whose port failed with IoError, if auto-reconnect is on,
+    /// and start reconnecting
+    pub(crate) fn supervise_failure(&self, port_guard: &mut Option<Connection>, error: &CoreError) {
+        if !is_link_io_error(error) || self.supervisor.lock().unwrap().options.is_none() {
+            return;
+        }
+        if port_guard.take().is_none() {
+            return;
+        }
+        let port = self.port_name.lock().unwrap().take();
+        self.reservations.lock().unwrap().clear();
+        self.emit_disconnected(port, Some(error), DisconnectKind::Forced);
+        self.begin_reconnect();
+    }
+
+    /// Start the supervisor thread after the connection was lost, if enabled
+    pub(crate) fn begin_reconnect(&self) {
+        let mut supervisor = self.supervisor.lock().unwrap();
+        if supervisor.running.is_some() {
+            return;
+        }
+        let (Some(options), Some(target)) = (&supervisor.options, &supervisor.target) else {
+            return;
+        };
+        let (options, target) = (options.clone(), target.clone());
+        let cancel = Arc::new(AtomicBool::new(false));
+        supervisor.running = Some(cancel.clone());
+        let core = self.clone();
+        std::thread::spawn(move || core.reconnect_loop(target, options, &cancel));
+    }
+
+    pub(crate) fn is_reconnect_running(&self) -> bool {
+        self.supervisor.lock().unwrap().running.is_some()
+    }
+
+    /// Body of the supervisor thread; returns once connected, given up or cancelled
+    fn reconnect_loop(&self, target: ReconnectTarget, options: ReconnectOptions, cancel: &AtomicBool) {
+        let max_delay = options.max_delay_ms.unwrap_or(DEFAULT_MAX_DELAY_MS);
+        let mut delay = options.initial_delay_ms.unwrap_or(DEFAULT_INITIAL_DELAY_MS).min(max_delay);
+        let mut attempt = 0;
+        let mut last_error = None;
+        loop {
+            if options.max_attempts.is_some_and(|max| attempt >= max) {
+                self.finish_reconnect(cancel);
+                self.emit_attempt_event(ConnectionEvent::ReconnectFailed, Some(target.port_name.clone()),
+                    last_error.as_ref(), Some((attempt, delay)));
+                return;
+            }
+            if attempt > 0 {
+                delay = delay.saturating_mul(2).min(max_delay);
+            }
+            attempt += 1;
+            self.emit_attempt_event(ConnectionEvent::Reconnecting, Some(target.port_name.clone()),
+                last_error.as_ref(), Some((attempt, delay)));
+
+            let wake = Instant::now() + Duration::from_millis(delay as u64);
+            while Instant::now() < wake && !cancel.load(Ordering::SeqCst) {
+                std::thread::sleep(Duration::from_millis(RECONNECT_POLL_MS));
+            }
+
+            let port_name = target.locate();
+            let mut port_guard = self.port.lock().unwrap();
+            // Cancelled, or someone else connected in the meantime
+            if cancel.load(Ordering::SeqCst) || port_guard.is_some() {
+                drop(port_guard);
+                self.finish_reconnect(cancel);
+                return;
+            }
+            let connected = open_serial_port(&port_name).and_then(|transport| {
+                self.attach_port(&mut port_guard, transport, port_name.clone(), target.options.clone(), |_| {})
+            });
+            drop(port_guard);
+            match connected {
+                Ok(()) => {
+                    self.finish_reconnect(cancel);
+                    self.emit_attempt_event(ConnectionEvent::Reconnected, Some(port_name), None, Some((attempt, delay)));
+                    return;
+                }
+                Err(e) => {
+                    self.diagnostics.lock().unwrap().record_error(&e);
+                    last_error = Some(e);
+                }
+            }
+        }
+    }
+
+    /// Mark the supervisor thread owning `cancel` as done
+    fn finish_reconnect(&self, cancel: &AtomicBool) {
+        let mut supervisor = self.supervisor.lock().unwrap();
+        if supervisor.running.as_deref().is_some_and(|running| std::ptr::eq(running, cancel)) {
+            supervisor.running = None;
+        }
+    }
+
+    /// Wait for a reconnect in progress, up to queue_timeout_ms
+    fn await_reconnect(&self) {
+        let timeout = {
+            let supervisor = self.supervisor.lock().unwrap();
+            match (&supervisor.running, &supervisor.options) {
+                (Some(_), Some(options)) => options.queue_timeout_ms.unwrap_or(DEFAULT_QUEUE_TIMEOUT_MS),
+                _ => return,
+            }
+        };
+        let deadline = Instant::now() + Duration::from_millis(timeout as u64);
+        while self.is_reconnect_running() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(RECONNECT_POLL_MS));
+        }
+    }
+
+    /// with_connection_in() for commands that are safe to run twice
+    /// Waits for a reconnect in progress, and runs `f` again after one that its own
+    /// failure started.
+    pub(crate) fn with_connection_replayable<T>(&self, lane: Lane, mut f: impl FnMut(&mut Connection) -> Result<T>) -> Result<T> {
+        self.await_reconnect();
+        match self.with_connection_in(lane, &mut f) {
+            Err(_) if self.is_reconnect_running() => {
+                self.await_reconnect();
+                self.with_connection_in(lane, f)
+            }
+            result => result,
+        }
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Reconnect on its own when the connection is lost (see ReconnectOptions)
+    /// Applies to connections made with connect()/connect_with_options(), including
+    /// one already open. Calling it again replaces the options.
+    #[napi]
+    pub fn enable_auto_reconnect(&self, options: Option<ReconnectOptions>) {
+        let current = self.port_name.lock().unwrap().clone();
+        let mut supervisor = self.supervisor.lock().unwrap();
+        supervisor.options = Some(options.unwrap_or_default());
+        // The target was recorded without its serial number while disabled
+        if let Some(target) = supervisor.target.as_mut() {
+            if current.as_deref() == Some(target.port_name.as_str()) && target.serial_number.is_none() {
+                target.serial_number = usb_serial_number(&target.port_name);
+            }
+        }
+    }
+
+    /// Turn auto-reconnect off, stopping a reconnect in progress
+    /// Returns false if it wasn't on
+    #[napi]
+    pub fn disable_auto_reconnect(&self) -> bool {
+        self.cancel_reconnect();
+        self.supervisor.lock().unwrap().options.take().is_some()
+    }
+
+    /// Whether a reconnect is in progress
+    #[napi]
+    pub fn is_reconnecting(&self) -> bool {
+        self.is_reconnect_running()
+    }
+}
@@ -0,0 +1,161 @@
+// Memory recordings: sample a region on an interval and append it to a host file
+// Each recording runs on its own thread and takes the port per sample like any other
+// command, so it interleaves with foreground work instead of blocking it. The file
+// is plain text, one "unix_ms hexbytes" line per sample, flushed at least once a
+// second so a crash loses little of the capture.
+
+use napi_derive::napi;
+use crate::errors::{CoreError, ErrorCode, Result};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::queue::Lane;
+use crate::regions::MemoryRegion;
+use crate::{get_with_flags_locked, host_io_error, Usb2SnesCore};
+
+/// Longest time between file flushes
+const RECORD_FLUSH_MS: u64 = 1000;
+
+/// Granularity at which a sleeping recorder notices stop_record()
+const RECORD_STOP_POLL_MS: u64 = 20;
+
+/// What record_memory() samples and where it writes
+#[napi(object)]
+pub struct RecordOptions {
+    pub region: MemoryRegion,
+    /// Offset within the region
+    pub offset: u32,
+    /// Bytes per sample
+    pub size: u32,
+    /// Time between the starts of two samples
+    pub interval_ms: u32,
+    /// Host file the samples are appended to (created if missing)
+    pub host_path: String,
+}
+
+/// Returned by record_memory(); pass to stop_record()
+#[napi(object)]
+pub struct RecordHandle {
+    pub id: u32,
+    pub host_path: String,
+}
+
+/// Result of stop_record()
+#[napi(object)]
+pub struct RecordSummary {
+    /// Samples written to the file
+    pub samples: u32,
+    /// Sample reads that failed (written to the file as "unix_ms ERROR reason")
+    pub errors: u32,
+}
+
+struct Recording {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Result<RecordSummary>>,
+}
+
+/// Running recordings of one core, by handle id
+#[derive(Default)]
+pub(crate) struct Recordings {
+    next_id: u32,
+    running: HashMap<u32, Recording>,
+}
+
+pub(crate) fn unix_millis() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0)
+}
+
+impl Usb2SnesCore {
+    /// Body of a recording thread; returns when `stop` is set or the file can't be written
+    fn record_loop(
+        &self,
+        space: u8,
+        address: u32,
+        size: u32,
+        interval: Duration,
+        host_path: &str,
+        stop: &AtomicBool,
+    ) -> Result<RecordSummary> {
+        let file = OpenOptions::new().create(true).append(true).open(host_path)
+            .map_err(|e| host_io_error("open", host_path, e))?;
+        let mut writer = BufWriter::new(file);
+        let mut summary = RecordSummary { samples: 0, errors: 0 };
+        let mut last_flush = Instant::now();
+
+        while !stop.load(Ordering::SeqCst) {
+            let started = Instant::now();
+            let line = match self.with_connection_in(Lane::Interactive, |conn| get_with_flags_locked(conn, space, conn.block_flags, address, size)) {
+                Ok(data) => {
+                    summary.samples += 1;
+                    let hex: String = data.iter().map(|b| format!("{:02X}", b)).collect();
+                    format!("{} {}\n", unix_millis(), hex)
+                }
+                Err(e) => {
+                    summary.errors += 1;
+                    format!("{} ERROR {}\n", unix_millis(), e.reason)
+                }
+            };
+            writer.write_all(line.as_bytes()).map_err(|e| host_io_error("write", host_path, e))?;
+            if last_flush.elapsed() >= Duration::from_millis(RECORD_FLUSH_MS) {
+                writer.flush().map_err(|e| host_io_error("flush", host_path, e))?;
+                last_flush = Instant::now();
+            }
+
+            while !stop.load(Ordering::SeqCst) && started.elapsed() < interval {
+                std::thread::sleep((interval - started.elapsed()).min(Duration::from_millis(RECORD_STOP_POLL_MS)));
+            }
+        }
+
+        writer.flush().map_err(|e| host_io_error("flush", host_path, e))?;
+        Ok(summary)
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Sample `size` bytes at `offset` within a region every `interval_ms` and append
+    /// each sample to `host_path` as a "unix_ms hexbytes" line
+    /// Runs in the background until stop_record(). Failed reads are written as
+    /// "unix_ms ERROR reason" lines and recording continues.
+    #[napi]
+    pub fn record_memory(&self, options: RecordOptions) -> Result<RecordHandle> {
+        let RecordOptions { region, offset, size, interval_ms, host_path } = options;
+        let (space, address) = region.resolve(offset, size)?;
+        if size == 0 || interval_ms == 0 {
+            return Err(CoreError::new(ErrorCode::ArgValidation, "record_memory: size and interval_ms must be non-zero"));
+        }
+        // Fail fast on an unwritable path instead of inside the thread
+        OpenOptions::new().create(true).append(true).open(&host_path)
+            .map_err(|e| host_io_error("open", &host_path, e))?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let core = self.clone();
+        let thread_stop = stop.clone();
+        let thread_path = host_path.clone();
+        let interval = Duration::from_millis(interval_ms as u64);
+        let thread = std::thread::spawn(move || {
+            core.record_loop(space, address, size, interval, &thread_path, &thread_stop)
+        });
+
+        let mut recordings = self.recordings.lock().unwrap();
+        recordings.next_id += 1;
+        let id = recordings.next_id;
+        recordings.running.insert(id, Recording { stop, thread });
+        Ok(RecordHandle { id, host_path })
+    }
+
+    /// Stop a recording, flush its file and report how it went
+    #[napi]
+    pub fn stop_record(&self, handle: RecordHandle) -> Result<RecordSummary> {
+        let recording = self.recordings.lock().unwrap().running.remove(&handle.id)
+            .ok_or_else(|| CoreError::new(ErrorCode::ArgValidation, format!("stop_record: no recording with id {}", handle.id)))?;
+        recording.stop.store(true, Ordering::SeqCst);
+        recording.thread.join()
+            .map_err(|_| CoreError::new(ErrorCode::DeviceError, "stop_record: recording thread panicked"))?
+    }
+}
@@ -0,0 +1,84 @@
+// Named SNES memory regions
+// The FxPak exposes everything through SNES space at fixed bases; callers pick a
+// region and an offset within it instead of hand-computing space/address pairs.
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+use crate::errors::{CoreError, ErrorCode, Result};
+
+use crate::{get_locked, put_locked, Usb2SnesCore, SPACE_SNES};
+
+/// A memory region of the running SNES as exposed by the FxPak
+#[napi]
+#[derive(Debug, PartialEq, Eq)]
+pub enum MemoryRegion {
+    Wram,
+    Sram,
+    Rom,
+    Vram,
+    Apu,
+    Cgram,
+    Oam,
+}
+
+impl MemoryRegion {
+    /// (space, base address, size) of the region
+    pub(crate) fn layout(self) -> (u8, u32, u32) {
+        match self {
+            MemoryRegion::Rom => (SPACE_SNES, 0x000000, 0xE00000),
+            MemoryRegion::Sram => (SPACE_SNES, 0xE00000, 0x100000),
+            MemoryRegion::Wram => (SPACE_SNES, 0xF50000, 0x20000),
+            MemoryRegion::Vram => (SPACE_SNES, 0xF70000, 0x10000),
+            MemoryRegion::Apu => (SPACE_SNES, 0xF80000, 0x10000),
+            MemoryRegion::Cgram => (SPACE_SNES, 0xF90000, 0x200),
+            MemoryRegion::Oam => (SPACE_SNES, 0xF90200, 0x220),
+        }
+    }
+
+    /// Resolve `offset`/`size` within the region to a (space, address) pair
+    pub(crate) fn resolve(self, offset: u32, size: u32) -> Result<(u8, u32)> {
+        let (space, base, region_size) = self.layout();
+        if (offset as u64) + (size as u64) > region_size as u64 {
+            return Err(CoreError::new(ErrorCode::ArgValidation, format!(
+                "AddressOutOfRange: offset 0x{:X} + 0x{:X} bytes exceeds {:?} (0x{:X} bytes)",
+                offset, size, self, region_size
+            )));
+        }
+        Ok((space, base + offset))
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Read `size` bytes at `offset` within a memory region
+    #[napi]
+    pub fn read(&self, region: MemoryRegion, offset: u32, size: u32) -> Result<Buffer> {
+        let (space, address) = region.resolve(offset, size)?;
+        self.with_connection(|conn| get_locked(conn, space, address, size)).map(Buffer::from)
+    }
+
+    /// Write `data` at `offset` within a memory region
+    #[napi]
+    pub fn write(&self, region: MemoryRegion, offset: u32, data: Buffer) -> Result<()> {
+        let (space, address) = region.resolve(offset, data.len() as u32)?;
+        self.with_connection(|conn| put_locked(conn, space, address, &data))
+    }
+
+    /// Read WRAM at `offset` from $7E:0000 (read(Wram, ...))
+    #[napi]
+    pub fn read_wram(&self, offset: u32, size: u32) -> Result<Buffer> {
+        self.read(MemoryRegion::Wram, offset, size)
+    }
+
+    /// Read cartridge SRAM at `offset` from its start (read(Sram, ...))
+    #[napi]
+    pub fn read_sram(&self, offset: u32, size: u32) -> Result<Buffer> {
+        self.read(MemoryRegion::Sram, offset, size)
+    }
+
+    /// Read VRAM at byte `offset` (read(Vram, ...)); word address N is byte offset 2N
+    #[napi]
+    pub fn read_vram(&self, offset: u32, size: u32) -> Result<Buffer> {
+        self.read(MemoryRegion::Vram, offset, size)
+    }
+}
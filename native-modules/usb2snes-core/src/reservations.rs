@@ -0,0 +1,111 @@
+// Named scratch-region reservations for tool-assisted writes
+// Features that inject code or data into free memory claim their bytes here so two
+// of them can't stomp the same range. Purely host-side bookkeeping: the device knows
+// nothing about it. Claims are dropped on disconnect, BOOT and any reset, since the
+// memory they describe no longer holds what their owners put there.
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::queue::Lane;
+use std::collections::BTreeMap;
+
+use crate::{get_locked, put_locked, validate_address_range, Usb2SnesCore};
+
+/// One reserved range (see reserve_region() and reserved_regions())
+#[napi(object)]
+#[derive(Clone)]
+pub struct RegionReservation {
+    pub name: String,
+    pub space: u8,
+    pub address: u32,
+    pub size: u32,
+}
+
+/// Claims of one core, by name
+#[derive(Default)]
+pub(crate) struct Reservations {
+    claims: BTreeMap<String, RegionReservation>,
+}
+
+impl Reservations {
+    /// Drop every claim (disconnect, BOOT, reset)
+    pub(crate) fn clear(&mut self) {
+        self.claims.clear();
+    }
+
+    /// (space, address) of `len` bytes at `offset` within the claim `name`
+    fn resolve(&self, name: &str, offset: u32, len: u32) -> Result<(u8, u32)> {
+        let claim = self.claims.get(name).ok_or_else(|| CoreError::new(ErrorCode::ArgValidation, format!(
+            "UnknownRegion: no reservation named \"{}\"", name
+        )))?;
+        if (offset as u64) + (len as u64) > claim.size as u64 {
+            return Err(CoreError::new(ErrorCode::ArgValidation, format!(
+                "AddressOutOfRange: offset 0x{:X} + 0x{:X} bytes exceeds reservation \"{}\" (0x{:X} bytes)",
+                offset, len, name, claim.size
+            )));
+        }
+        Ok((claim.space, claim.address + offset))
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Claim `size` bytes at `address` in `space` exclusively for `name`
+    /// Fails with "RegionConflict: ..." naming the owner if the name is taken or
+    /// the range overlaps another claim.
+    #[napi]
+    pub fn reserve_region(&self, reservation: RegionReservation) -> Result<()> {
+        let RegionReservation { name, space, address, size } = reservation;
+        if size == 0 {
+            return Err(CoreError::new(ErrorCode::ArgValidation, "reserve_region: size must be non-zero"));
+        }
+        validate_address_range(space, address, size)?;
+
+        let mut reservations = self.reservations.lock().unwrap();
+        if reservations.claims.contains_key(&name) {
+            return Err(CoreError::new(ErrorCode::DeviceBusy, format!(
+                "RegionConflict: \"{}\" is already reserved", name
+            )));
+        }
+        let end = address as u64 + size as u64;
+        if let Some(owner) = reservations.claims.values().find(|claim| {
+            claim.space == space && (address as u64) < claim.address as u64 + claim.size as u64
+                && (claim.address as u64) < end
+        }) {
+            return Err(CoreError::new(ErrorCode::DeviceBusy, format!(
+                "RegionConflict: 0x{:X} + 0x{:X} bytes overlaps \"{}\" (0x{:X} + 0x{:X} bytes)",
+                address, size, owner.name, owner.address, owner.size
+            )));
+        }
+
+        reservations.claims.insert(name.clone(), RegionReservation { name, space, address, size });
+        Ok(())
+    }
+
+    /// Write `data` at `offset` within the reservation `name`
+    #[napi]
+    pub fn write_region(&self, name: String, offset: u32, data: Buffer) -> Result<()> {
+        let (space, address) = self.reservations.lock().unwrap().resolve(&name, offset, data.len() as u32)?;
+        self.with_connection_in(Lane::Interactive, |conn| put_locked(conn, space, address, &data))
+    }
+
+    /// Read `len` bytes at `offset` within the reservation `name`
+    #[napi]
+    pub fn read_region(&self, name: String, offset: u32, len: u32) -> Result<Buffer> {
+        let (space, address) = self.reservations.lock().unwrap().resolve(&name, offset, len)?;
+        self.with_connection_in(Lane::Interactive, |conn| get_locked(conn, space, address, len)).map(Buffer::from)
+    }
+
+    /// Free the reservation `name`; returns false if there was none
+    #[napi]
+    pub fn release_region(&self, name: String) -> bool {
+        self.reservations.lock().unwrap().claims.remove(&name).is_some()
+    }
+
+    /// Current reservations, by name
+    #[napi]
+    pub fn reserved_regions(&self) -> Vec<RegionReservation> {
+        self.reservations.lock().unwrap().claims.values().cloned().collect()
+    }
+}
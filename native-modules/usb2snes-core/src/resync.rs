@@ -0,0 +1,298 @@
+// Protocol resynchronization: get back in step after a RESPONSE arrives out of step
+// A glitch on the line (a dropped or extra byte, a data phase nobody read) leaves the
+// core reading in the middle of the device's output, and every later RESPONSE then
+// fails the USBA check until the cable is replugged. With auto resync (on unless
+// ConnectOptions.auto_resync is false) a RESPONSE without the USBA magic is first
+// scanned for the real header: if the packet starts further in, the rest of it is read
+// and the command carries on. Otherwise the input is drained until the line is quiet,
+// so the next command starts on a packet boundary; the command itself fails, except
+// reads (GET, LS, INFO), which are sent once more. resync() does the same on demand and
+// can reset the device first (DTR pulse and/or RESET with NORESP) for a device that is
+// itself stuck. One case neither can see: a garbled RESPONSE to a PUT leaves open
+// whether the device is waiting for the data phase, in which case the next commands
+// are taken as that data and time out until it is complete.
+
+use napi_derive::napi;
+use std::time::Duration;
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::{
+    drain_input_locked, reset_locked, verify_fxpak_locked, Connection, ResetStrategy, PACKET_SIZE, RESET_WAIT_MS,
+    VERIFY_TIMEOUT_MS,
+};
+
+/// Magic and opcode that start every RESPONSE
+pub(crate) const RESPONSE_HEADER: &[u8] = b"USBA\x0f";
+
+/// Options for resync()
+#[napi(object)]
+#[derive(Default)]
+pub struct ResyncOptions {
+    /// Reset the device between two drains (DtrPulse, RtsPulse, Opcode or Combined;
+    /// Full reopens the port, use reset() for that). Default: no reset
+    pub reset: Option<ResetStrategy>,
+}
+
+/// Whether a failure means the RESPONSE framing was off, not the request
+pub(crate) fn is_garbled_response(reason: &str) -> bool {
+    reason.starts_with("Invalid response magic header") || reason.starts_with("Response Error Request")
+}
+
+/// Find the RESPONSE header inside `response`, a packet read out of step, and read the
+/// rest of the packet after it; returns the stray bytes skipped, None if there is none
+pub(crate) fn realign_response_locked(conn: &mut Connection, response: &mut Vec<u8>) -> Result<Option<usize>> {
+    // The whole magic must be there; a lone 'U' at the end is as likely to be noise
+    let found = (1..=PACKET_SIZE - 4).find(|&offset| {
+        let candidate = &response[offset..];
+        let len = candidate.len().min(RESPONSE_HEADER.len());
+        candidate[..len] == RESPONSE_HEADER[..len]
+    });
+    let Some(offset) = found else {
+        return Ok(None);
+    };
+    let mut tail = vec![0u8; offset];
+    match conn.transport.read_data(&mut tail, conn.read_deadlines) {
+        Ok(()) => {}
+        // What looked like a header was data after all
+        Err(e) if e.code == ErrorCode::Timeout => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    response.drain(..offset);
+    response.extend_from_slice(&tail);
+    conn.diagnostics.lock().unwrap().record_warning(&format!(
+        "Resync: RESPONSE started {} bytes late; skipped the stray bytes", offset
+    ));
+    Ok(Some(offset))
+}
+
+/// Drain the input after a garbled RESPONSE to `opcode`; returns the bytes discarded
+pub(crate) fn recover_framing_locked(conn: &mut Connection, opcode: u8, error: &CoreError) -> Result<u32> {
+    let drained = drain_input_locked(conn)?;
+    conn.diagnostics.lock().unwrap().record_warning(&format!(
+        "Resync after opcode {} ({} stray bytes drained): {}", opcode, drained, error.reason
+    ));
+    Ok(drained)
+}
+
+/// resync() on an already-locked port; returns the stray bytes discarded
+pub(crate) fn resync_locked(conn: &mut Connection, options: &ResyncOptions) -> Result<u32> {
+    if options.reset == Some(ResetStrategy::Full) {
+        return Err(CoreError::new(ErrorCode::ArgValidation,
+            "Invalid resync options: Full reopens the port, use reset()"
+        ));
+    }
+    let mut drained = drain_input_locked(conn)?;
+    if let Some(strategy) = options.reset {
+        conn.reservations.lock().unwrap().clear();
+        reset_locked(conn, strategy).map_err(|e| CoreError::new(e.code,
+            format!("Resync failed to reset ({:?}): {}", strategy, e.reason)
+        ))?;
+        std::thread::sleep(Duration::from_millis(RESET_WAIT_MS));
+        // Whatever the device sent while resetting
+        drained += drain_input_locked(conn)?;
+    }
+    let timeout = Duration::from_millis(VERIFY_TIMEOUT_MS);
+    verify_fxpak_locked(conn, timeout).map_err(|e| CoreError::new(e.code,
+        format!("Resync failed after discarding {} bytes: {}", drained, e.reason)
+    ))?;
+    Ok(drained)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeouts::ReadDeadlines;
+    use crate::transport::Transport;
+    use std::collections::VecDeque;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    /// What the device saw, in order
+    #[derive(Debug, PartialEq, Eq)]
+    enum Seen {
+        Dtr(bool),
+        Rts(bool),
+        /// A command packet: (opcode, flags)
+        Command(u8, u8),
+    }
+
+    /// A device that answers INFO and babbles `noise` bytes whenever it is reset
+    #[derive(Default)]
+    struct Device {
+        input: VecDeque<u8>,
+        seen: Vec<Seen>,
+        noise: usize,
+    }
+
+    struct FakeTransport(Arc<Mutex<Device>>);
+
+    impl Transport for FakeTransport {
+        fn write_packet(&mut self, packet: &[u8]) -> Result<()> {
+            let mut device = self.0.lock().unwrap();
+            device.seen.push(Seen::Command(packet[4], packet[6]));
+            match packet[4] {
+                11 => {
+                    let mut response = vec![0u8; PACKET_SIZE];
+                    response[..5].copy_from_slice(RESPONSE_HEADER);
+                    device.input.extend(response);
+                }
+                8 => {
+                    let noise = device.noise;
+                    device.input.extend(std::iter::repeat_n(0xAA, noise));
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+
+        fn read_packet(&mut self, buf: &mut [u8], deadlines: ReadDeadlines) -> Result<()> {
+            self.read_data(buf, deadlines)
+        }
+
+        fn read_data(&mut self, block: &mut [u8], _: ReadDeadlines) -> Result<()> {
+            let mut device = self.0.lock().unwrap();
+            if device.input.len() < block.len() {
+                return Err(CoreError::new(ErrorCode::Timeout, "Timeout: nothing to read"));
+            }
+            let len = block.len();
+            for (slot, byte) in block.iter_mut().zip(device.input.drain(..len)) {
+                *slot = byte;
+            }
+            Ok(())
+        }
+
+        fn write_data(&mut self, _: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_timeout(&mut self, _: Duration) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn drain_input(&mut self, _: Duration, _: Duration) -> Result<u32> {
+            let mut device = self.0.lock().unwrap();
+            let drained = device.input.len() as u32;
+            device.input.clear();
+            Ok(drained)
+        }
+
+        fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+            let mut device = self.0.lock().unwrap();
+            device.seen.push(Seen::Dtr(level));
+            if level {
+                let noise = device.noise;
+                device.input.extend(std::iter::repeat_n(0xAA, noise));
+            }
+            Ok(())
+        }
+
+        fn set_rts(&mut self, level: bool) -> io::Result<()> {
+            let mut device = self.0.lock().unwrap();
+            device.seen.push(Seen::Rts(level));
+            if level {
+                let noise = device.noise;
+                device.input.extend(std::iter::repeat_n(0xAA, noise));
+            }
+            Ok(())
+        }
+    }
+
+    /// A connection to a fake device with `stray` bytes waiting in its input
+    fn connect(stray: usize, noise: usize) -> (Connection, Arc<Mutex<Device>>) {
+        let device = Arc::new(Mutex::new(Device {
+            input: std::iter::repeat_n(0x55, stray).collect(),
+            noise,
+            ..Default::default()
+        }));
+        let conn = Connection::new(
+            Box::new(FakeTransport(device.clone())),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        );
+        (conn, device)
+    }
+
+    /// resync() with `reset`: (bytes discarded, what the device saw)
+    fn resync_with(reset: Option<ResetStrategy>) -> (u32, Vec<Seen>) {
+        let (mut conn, device) = connect(7, 3);
+        let drained = resync_locked(&mut conn, &ResyncOptions { reset }).unwrap();
+        let seen = std::mem::take(&mut device.lock().unwrap().seen);
+        (drained, seen)
+    }
+
+    const INFO: Seen = Seen::Command(11, 0);
+    const RESET: Seen = Seen::Command(8, crate::NORESP_FLAG);
+
+    #[test]
+    fn resync_without_reset_drains_then_verifies() {
+        assert_eq!(resync_with(None), (7, vec![INFO]));
+    }
+
+    #[test]
+    fn resync_with_dtr_pulse() {
+        assert_eq!(resync_with(Some(ResetStrategy::DtrPulse)), (10, vec![Seen::Dtr(false), Seen::Dtr(true), INFO]));
+    }
+
+    #[test]
+    fn resync_with_rts_pulse() {
+        assert_eq!(resync_with(Some(ResetStrategy::RtsPulse)), (10, vec![Seen::Rts(false), Seen::Rts(true), INFO]));
+    }
+
+    #[test]
+    fn resync_with_reset_opcode() {
+        assert_eq!(resync_with(Some(ResetStrategy::Opcode)), (10, vec![RESET, INFO]));
+    }
+
+    #[test]
+    fn resync_with_combined_reset() {
+        let seen = vec![Seen::Dtr(false), Seen::Rts(false), Seen::Dtr(true), Seen::Rts(true), RESET, INFO];
+        // DTR, RTS and the opcode each leave their noise behind
+        assert_eq!(resync_with(Some(ResetStrategy::Combined)), (16, seen));
+    }
+
+    #[test]
+    fn resync_rejects_full_reset() {
+        let (mut conn, device) = connect(7, 3);
+        let error = resync_locked(&mut conn, &ResyncOptions { reset: Some(ResetStrategy::Full) }).unwrap_err();
+        assert_eq!(error.code, ErrorCode::ArgValidation);
+        // Nothing was drained or sent
+        let device = device.lock().unwrap();
+        assert!(device.seen.is_empty());
+        assert_eq!(device.input.len(), 7);
+    }
+
+    #[test]
+    fn realign_skips_stray_bytes_before_the_header() {
+        let (mut conn, device) = connect(0, 0);
+        // The RESPONSE started 3 bytes late: its last 3 bytes are still waiting
+        let mut response = vec![0xFF; 3];
+        response.extend_from_slice(RESPONSE_HEADER);
+        response.resize(PACKET_SIZE, 0);
+        device.lock().unwrap().input.extend([1, 2, 3]);
+
+        assert_eq!(realign_response_locked(&mut conn, &mut response).unwrap(), Some(3));
+        assert_eq!(&response[..5], RESPONSE_HEADER);
+        assert_eq!(&response[PACKET_SIZE - 3..], &[1, 2, 3]);
+    }
+
+    #[test]
+    fn realign_without_a_header() {
+        let (mut conn, _) = connect(0, 0);
+        let mut response = vec![0xFF; PACKET_SIZE];
+        assert_eq!(realign_response_locked(&mut conn, &mut response).unwrap(), None);
+    }
+
+    #[test]
+    fn realign_ignores_a_header_that_was_data() {
+        // What looks like a header near the end, but the rest of the "packet" never comes
+        let (mut conn, _) = connect(0, 0);
+        let mut response = vec![0xFF; PACKET_SIZE];
+        response[PACKET_SIZE - 5..].copy_from_slice(RESPONSE_HEADER);
+        assert_eq!(realign_response_locked(&mut conn, &mut response).unwrap(), None);
+        assert_eq!(&response[PACKET_SIZE - 5..], RESPONSE_HEADER);
+    }
+}
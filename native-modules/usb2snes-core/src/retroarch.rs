@@ -0,0 +1,373 @@
+// RetroArch network command backend
+// connect("retroarch://host:port") drives an emulator through RetroArch's UDP network
+// commands (network_cmd_enable, port 55355 unless given) instead of an FxPak. The
+// backend is a Transport that decodes the core's command packets: GET/PUT/VGET/VPUT on
+// the SNES space become READ_CORE_MEMORY/WRITE_CORE_MEMORY, INFO is answered from
+// VERSION and GET_STATUS, and RESET becomes RESET, so get_memory()/put_memory() and
+// everything built on them work unchanged. FxPak SNES-space addresses are translated to
+// the bus addresses RetroArch's memory map uses: WRAM directly, ROM and SRAM with the
+// mapping read from the cartridge header (once per command, since the loaded game can
+// change). Files, BOOT/MENU, the other spaces and memory RetroArch can't reach (VRAM,
+// APU, ...) fail with "Unsupported: ..." before anything is sent.
+
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::mapping::{rom_offset_to_snes_bus, sram_offset_to_snes_bus, MemoryMapping};
+use crate::regions::MemoryRegion;
+use crate::simulator::{be_u32, packet_pairs, PendingPut};
+use crate::timeouts::ReadDeadlines;
+use crate::transport::{set_info_strings, ReplyQueue, Transport};
+use crate::{DATA64B_FLAG, DEVICE_DISCONNECTED, NORESP_FLAG, READ_TIMEOUT_MS, SPACE_SNES};
+
+const URI_PREFIX: &str = "retroarch://";
+
+/// RetroArch's default network_cmd_port
+const DEFAULT_PORT: u16 = 55355;
+
+/// Largest READ/WRITE_CORE_MEMORY; chunks also stop at 512-byte boundaries of the
+/// FxPak address, so a chunk never spans two banks of any mapping
+const CHUNK_LEN: usize = 512;
+
+/// Bus address of WRAM ($7E:0000)
+const WRAM_BUS_BASE: u32 = 0x7E0000;
+
+/// Bus address of the internal ROM header ($00:FFC0) and its map mode byte
+const HEADER_BUS_ADDRESS: u32 = 0x00FFC0;
+const HEADER_MAP_MODE: usize = 0x15;
+
+/// Whether a port name is a RetroArch network command URI rather than a serial port
+pub(crate) fn is_retroarch_uri(port_name: &str) -> bool {
+    port_name.starts_with(URI_PREFIX)
+}
+
+/// RetroArch reached over UDP network commands, seen as a Transport
+struct RetroArchTransport {
+    socket: UdpSocket,
+    uri: String,
+    version: String,
+    timeout: Duration,
+    /// Mapping of the running game, read on first need within a command
+    mapping: Option<MemoryMapping>,
+    /// Data phase expected from the core before the next command packet
+    pending_put: Option<PendingPut>,
+    /// Data phase bytes written by the core so far
+    input: Vec<u8>,
+    replies: ReplyQueue,
+}
+
+impl RetroArchTransport {
+    fn connect(uri: &str) -> Result<RetroArchTransport> {
+        let io_error = |e: String| CoreError::new(ErrorCode::IoError, format!("Failed to connect to {}: {}", uri, e));
+        let host = match &uri[URI_PREFIX.len()..] {
+            "" => format!("127.0.0.1:{}", DEFAULT_PORT),
+            host if host.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) => host.to_string(),
+            host => format!("{}:{}", host, DEFAULT_PORT),
+        };
+        let address = host.to_socket_addrs().map_err(|e| io_error(e.to_string()))?
+            .next()
+            .ok_or_else(|| io_error("host did not resolve".to_string()))?;
+        let local = if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(local).map_err(|e| io_error(e.to_string()))?;
+        socket.connect(address).map_err(|e| io_error(e.to_string()))?;
+
+        let mut transport = RetroArchTransport {
+            socket,
+            uri: uri.to_string(),
+            version: String::new(),
+            timeout: Duration::from_millis(READ_TIMEOUT_MS),
+            mapping: None,
+            pending_put: None,
+            input: Vec::new(),
+            replies: ReplyQueue::default(),
+        };
+        transport.version = transport.command("VERSION", |reply| reply.starts_with(|c: char| c.is_ascii_digit()))
+            .map_err(|e| CoreError::new(ErrorCode::NotConnected, format!(
+                "{}: RetroArch did not answer VERSION ({}); is network_cmd_enable on?", uri, e
+            )))?;
+        Ok(transport)
+    }
+
+    /// Send a command and wait for its reply
+    /// Datagrams `is_reply` rejects (late replies to commands that timed out) are skipped.
+    fn command(&mut self, command: &str, is_reply: impl Fn(&str) -> bool) -> io::Result<String> {
+        self.socket.send(command.as_bytes())?;
+        let deadline = Instant::now() + self.timeout;
+        let mut buf = vec![0u8; 65536];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, format!("no reply to {}", command)));
+            }
+            self.socket.set_read_timeout(Some(remaining))?;
+            let n = match self.socket.recv(&mut buf) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => continue,
+                Err(e) => return Err(e),
+            };
+            let reply = String::from_utf8_lossy(&buf[..n]).trim_end().to_string();
+            if is_reply(&reply) {
+                return Ok(reply);
+            }
+        }
+    }
+
+    /// Fields of a READ/WRITE_CORE_MEMORY reply after the echoed address
+    /// A "-1 <message>" reply (e.g. memory the running core doesn't expose) is an error.
+    fn memory_command(&mut self, name: &str, address: u32, arguments: &str) -> io::Result<Vec<String>> {
+        let reply = self.command(&format!("{} {:X} {}", name, address, arguments), |reply| {
+            let mut fields = reply.split_whitespace();
+            fields.next() == Some(name)
+                && fields.next().and_then(|echo| u32::from_str_radix(echo, 16).ok()) == Some(address)
+        })?;
+        let fields: Vec<String> = reply.split_whitespace().skip(2).map(str::to_string).collect();
+        if fields.first().map(String::as_str) == Some("-1") {
+            return Err(io::Error::other(format!("{} ${:06X} failed: {}", name, address, fields[1..].join(" "))));
+        }
+        Ok(fields)
+    }
+
+    fn read_memory(&mut self, address: u32, len: usize) -> io::Result<Vec<u8>> {
+        let fields = self.memory_command("READ_CORE_MEMORY", address, &len.to_string())?;
+        let data = fields.iter()
+            .map(|byte| u8::from_str_radix(byte, 16))
+            .collect::<std::result::Result<Vec<u8>, _>>()
+            .map_err(|_| io::Error::other(format!("malformed READ_CORE_MEMORY reply for ${:06X}", address)))?;
+        if data.len() != len {
+            return Err(io::Error::other(format!(
+                "READ_CORE_MEMORY ${:06X} returned {} of {} bytes", address, data.len(), len
+            )));
+        }
+        Ok(data)
+    }
+
+    fn write_memory(&mut self, address: u32, data: &[u8]) -> io::Result<()> {
+        let bytes: Vec<String> = data.iter().map(|byte| format!("{:02X}", byte)).collect();
+        self.memory_command("WRITE_CORE_MEMORY", address, &bytes.join(" "))?;
+        Ok(())
+    }
+
+    /// Mapping of the running game, from the map mode byte of its header
+    fn mapping(&mut self) -> io::Result<MemoryMapping> {
+        if let Some(mapping) = self.mapping {
+            return Ok(mapping);
+        }
+        let header = self.read_memory(HEADER_BUS_ADDRESS, HEADER_MAP_MODE + 1)?;
+        let mapping = match header[HEADER_MAP_MODE] & 0xEF {
+            0x21 => MemoryMapping::HiRom,
+            0x25 => MemoryMapping::ExHiRom,
+            _ => MemoryMapping::LoRom,
+        };
+        self.mapping = Some(mapping);
+        Ok(mapping)
+    }
+
+    /// Bus address of an FxPak SNES-space address (None if RetroArch can't reach it)
+    fn bus_address(&mut self, address: u32) -> io::Result<Option<u32>> {
+        let within = |region: MemoryRegion| {
+            let (_, base, size) = region.layout();
+            (base..base + size).contains(&address).then(|| address - base)
+        };
+        if let Some(offset) = within(MemoryRegion::Wram) {
+            return Ok(Some(WRAM_BUS_BASE + offset));
+        }
+        let bus = if let Some(offset) = within(MemoryRegion::Sram) {
+            sram_offset_to_snes_bus(self.mapping()?, offset)
+        } else if let Some(offset) = within(MemoryRegion::Rom) {
+            rom_offset_to_snes_bus(self.mapping()?, offset).ok()
+        } else {
+            None
+        };
+        Ok(bus.map(|bus| (bus.bank << 16) | bus.addr))
+    }
+
+    /// Split `size` bytes at `address` into (bus address, length) chunks
+    /// Fails with "Unsupported: ..." if any byte has no bus address.
+    fn bus_chunks(&mut self, address: u32, size: usize) -> io::Result<Vec<(u32, usize)>> {
+        let mut chunks = Vec::new();
+        let mut offset = 0;
+        while offset < size {
+            let at = address + offset as u32;
+            let len = (size - offset).min(CHUNK_LEN - at as usize % CHUNK_LEN);
+            match self.bus_address(at)? {
+                Some(bus) => chunks.push((bus, len)),
+                None => return Err(unsupported(format!("SNES 0x{:06X} is not in RetroArch's memory map", at))),
+            }
+            offset += len;
+        }
+        Ok(chunks)
+    }
+
+    /// Answer one command packet, queueing its RESPONSE and data phase
+    fn handle_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        let (opcode, space, flags) = (packet[4], packet[5], packet[6]);
+        let mut response = ReplyQueue::response(flags);
+        let mut data = Vec::new();
+        let block_len = if flags & DATA64B_FLAG != 0 { 64 } else { 512 };
+
+        match opcode {
+            0..=3 if space != SPACE_SNES => {
+                return Err(unsupported(format!("RetroArch backend only reaches the SNES space (got space {})", space)));
+            }
+            0 | 1 => {
+                let address = be_u32(&packet[256..260]);
+                let size = match be_u32(&packet[252..256]) as usize {
+                    0 => block_len,
+                    size => size,
+                };
+                response[252..256].copy_from_slice(&(size as u32).to_be_bytes());
+                let chunks = self.bus_chunks(address, size)?;
+                if opcode == 0 {
+                    for (bus, len) in chunks {
+                        data.extend(self.read_memory(bus, len)?);
+                    }
+                } else {
+                    self.pending_put = Some(PendingPut::Memory { space, address, size, block_len });
+                }
+            }
+            2 | 3 => {
+                let pairs = packet_pairs(packet);
+                let total: usize = pairs.iter().map(|&(size, _)| size as usize).sum();
+                response[252..256].copy_from_slice(&(total as u32).to_be_bytes());
+                for &(size, address) in &pairs {
+                    let chunks = self.bus_chunks(address, size as usize)?;
+                    if opcode == 2 {
+                        for (bus, len) in chunks {
+                            data.extend(self.read_memory(bus, len)?);
+                        }
+                    }
+                }
+                if opcode == 3 {
+                    self.pending_put = Some(PendingPut::Vector { space, pairs });
+                }
+            }
+            8 => self.socket.send(b"RESET").map(|_| ())?,
+            11 => {
+                let status = self.command("GET_STATUS", |reply| reply.starts_with("GET_STATUS"))?;
+                // "GET_STATUS PLAYING super_nes,<game>,crc32=<crc>" or "GET_STATUS CONTENTLESS"
+                let game = status.split_once(',')
+                    .map(|(_, rest)| rest.rsplit_once(",crc32=").map_or(rest, |(game, _)| game))
+                    .unwrap_or_default();
+                set_info_strings(&mut response, &format!("RetroArch {}", self.version), game);
+            }
+            _ => return Err(unsupported(format!("RetroArch backend has no equivalent of opcode {}", opcode))),
+        }
+
+        if flags & NORESP_FLAG != 0 {
+            return Ok(());
+        }
+        self.replies.push(response, data, block_len);
+        Ok(())
+    }
+
+    /// Write a completed data phase to memory
+    fn write_pending(&mut self, pending: PendingPut, data: &[u8]) -> io::Result<()> {
+        let ranges: Vec<(u32, usize)> = match pending {
+            PendingPut::Memory { address, size, .. } => vec![(address, size)],
+            PendingPut::Vector { pairs, .. } => pairs.iter().map(|&(size, address)| (address, size as usize)).collect(),
+            PendingPut::File { .. } => Vec::new(),
+        };
+        let mut offset = 0;
+        for (address, size) in ranges {
+            // Already checked when the PUT/VPUT was answered, with the same mapping
+            let chunks = self.bus_chunks(address, size)?;
+            for (bus, len) in chunks {
+                self.write_memory(bus, &data[offset..offset + len])?;
+                offset += len;
+            }
+        }
+        Ok(())
+    }
+
+    /// A failed exchange with RetroArch as a core error
+    /// A refused datagram means nothing listens on the port any more: a disconnect.
+    fn error(&self, e: io::Error) -> CoreError {
+        match e.kind() {
+            io::ErrorKind::Unsupported => CoreError::new(ErrorCode::Unsupported, format!("Unsupported: {}", e)),
+            io::ErrorKind::TimedOut => CoreError::new(ErrorCode::Timeout, format!("RetroArch at {}: {}", self.uri, e)),
+            io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset => CoreError::new(ErrorCode::NotConnected,
+                format!("{}: RetroArch at {} stopped answering: {}", DEVICE_DISCONNECTED, self.uri, e)
+            ),
+            _ => CoreError::new(ErrorCode::DeviceError, format!("RetroArch at {}: {}", self.uri, e)),
+        }
+    }
+}
+
+impl Transport for RetroArchTransport {
+    /// A command whose reply didn't come in time queues nothing, so read_packet()
+    /// reports the timeout and the core's retry policy applies as on hardware
+    fn write_packet(&mut self, packet: &[u8]) -> Result<()> {
+        self.mapping = None;
+        self.pending_put = None;
+        self.input.clear();
+        match self.handle_packet(packet) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Ok(()),
+            Err(e) => Err(self.error(e)),
+        }
+    }
+
+    fn read_packet(&mut self, buf: &mut [u8], deadlines: ReadDeadlines) -> Result<()> {
+        self.replies.take(buf, deadlines, true)
+    }
+
+    fn read_data(&mut self, block: &mut [u8], deadlines: ReadDeadlines) -> Result<()> {
+        self.replies.take(block, deadlines, false)
+    }
+
+    /// Data nobody asked for (the data phase of a refused PUT) is dropped
+    fn write_data(&mut self, data: &[u8]) -> Result<()> {
+        let Some(pending) = &self.pending_put else {
+            return Ok(());
+        };
+        self.input.extend_from_slice(data);
+        if self.input.len() < pending.wire_len() {
+            return Ok(());
+        }
+        let pending = self.pending_put.take().unwrap();
+        let data = std::mem::take(&mut self.input);
+        self.write_pending(pending, &data).map_err(|e| self.error(e))
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    /// Drops queued replies and any late datagrams still arriving
+    fn drain_input(&mut self, quiet: Duration, limit: Duration) -> Result<u32> {
+        let mut drained = self.replies.clear();
+        self.timeout = quiet;
+
+        let deadline = Instant::now() + limit;
+        let mut buf = vec![0u8; 65536];
+        let _ = self.socket.set_read_timeout(Some(quiet));
+        while Instant::now() < deadline {
+            match self.socket.recv(&mut buf) {
+                Ok(n) => drained += n as u32,
+                Err(_) => break,
+            }
+        }
+        Ok(drained)
+    }
+
+    /// No control lines; accepted and ignored like on the simulator
+    fn set_dtr(&mut self, _level: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_rts(&mut self, _level: bool) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn unsupported(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, reason)
+}
+
+/// Open RetroArch's network command port as the core's transport (see is_retroarch_uri())
+pub(crate) fn open_retroarch(uri: &str) -> Result<Box<dyn Transport>> {
+    Ok(Box::new(RetroArchTransport::connect(uri)?))
+}
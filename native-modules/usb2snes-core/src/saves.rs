@@ -0,0 +1,285 @@
+// Per-game SRAM isolation
+// The firmware keeps a game's SRAM in <saves dir>/<ROM name>.srm, so every hack uploaded
+// under the same file name shares - and overwrites - one save. activate_game_save()
+// gives each game, identified by a caller-chosen key, a save of its own: the save in
+// the ROM's slot is moved into a store directory under the key of the game it belongs
+// to, and the incoming game's stored save (if any) is moved into the slot. Which key
+// owns each slot is recorded in a manifest in the store, on the SD card itself, so it
+// survives restarts and works from any host. A slot save with no recorded owner (made
+// before isolation was used) is kept as "<slot>.unclaimed.<ms>.srm", never handed to
+// a game. If the running game uses the slot, the device is sent to the menu first: the
+// firmware writes SRAM out on the way, and otherwise its next periodic write would
+// land in the other game's save.
+
+use napi_derive::napi;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::journal::JournalOp;
+use crate::{
+    get_file_locked, info_locked, lookup_entry_locked, menu_reset_locked, normalize_path, path_command_locked,
+    put_file_atomic_locked, split_path, Connection, Usb2SnesCore,
+};
+
+/// Where the firmware keeps .srm files
+const DEFAULT_SAVES_DIR: &str = "/sd2snes/saves";
+
+/// Where isolated saves and the manifest are kept
+const DEFAULT_STORE_DIR: &str = "/sd2snes/saves/isolated";
+
+/// Manifest of slot owners in the store: one "<slot file name>\t<key>" line per slot
+const MANIFEST_NAME: &str = "slots.txt";
+
+const SAVE_EXTENSION: &str = ".srm";
+
+/// Time the firmware needs after MENU_RESET to write SRAM out and reach the menu
+const MENU_SETTLE_MS: u64 = 1000;
+
+/// Options for activate_game_save() and game_save_owners()
+#[napi(object)]
+pub struct GameSaveOptions {
+    /// Directory the firmware keeps .srm files in (default "/sd2snes/saves")
+    pub saves_dir: Option<String>,
+    /// Directory for isolated saves and their manifest (default "/sd2snes/saves/isolated")
+    pub store_dir: Option<String>,
+}
+
+/// Result of activate_game_save()
+#[napi(object)]
+pub struct GameSaveSwitch {
+    /// The ROM's save slot (<saves dir>/<ROM name>.srm)
+    pub slot: String,
+    /// Key the manifest recorded for the slot before; unset if none was
+    pub previous_key: Option<String>,
+    /// Where the save previously in the slot was moved; unset if the slot was empty
+    /// or already belonged to this game
+    pub stashed_to: Option<String>,
+    /// The game's stored save was moved into the slot
+    pub restored: bool,
+    /// The running game used the slot, so the device was sent to the menu first
+    pub returned_to_menu: bool,
+}
+
+/// A slot and the key of the game whose save is in it
+#[napi(object)]
+pub struct GameSaveOwner {
+    pub slot: String,
+    pub key: String,
+}
+
+/// Resolved saves and store directories
+struct SaveDirs {
+    saves: String,
+    store: String,
+}
+
+impl SaveDirs {
+    fn new(options: Option<GameSaveOptions>) -> Result<Self> {
+        let (saves, store) = match options {
+            Some(options) => (options.saves_dir, options.store_dir),
+            None => (None, None),
+        };
+        Ok(SaveDirs {
+            saves: normalize_path(saves.as_deref().unwrap_or(DEFAULT_SAVES_DIR))?,
+            store: normalize_path(store.as_deref().unwrap_or(DEFAULT_STORE_DIR))?,
+        })
+    }
+
+    fn manifest(&self) -> String {
+        format!("{}/{}", self.store, MANIFEST_NAME)
+    }
+
+    /// Where the stored save of `key` lives
+    fn stored(&self, key: &str) -> String {
+        format!("{}/{}{}", self.store, encode_key(key), SAVE_EXTENSION)
+    }
+}
+
+/// A key as a file name: lowercase ASCII letters, digits, '-', '_' and '.' are kept and
+/// every other byte becomes %XX. Distinct keys get distinct names even on FAT, which
+/// ignores case: an uppercase letter only ever appears as a hex digit after '%'.
+fn encode_key(key: &str) -> String {
+    key.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// File name of the save slot the firmware uses for a ROM: its name with .srm for the extension
+fn slot_name(rom_path: &str) -> String {
+    let (_, name) = split_path(rom_path);
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    format!("{}{}", stem, SAVE_EXTENSION)
+}
+
+/// Slot owners from the manifest; a missing manifest means no slot has a recorded owner
+fn read_manifest_locked(conn: &mut Connection, dirs: &SaveDirs) -> Result<Vec<(String, String)>> {
+    let manifest = dirs.manifest();
+    if lookup_entry_locked(conn, &manifest)?.is_none() {
+        return Ok(Vec::new());
+    }
+    let text = String::from_utf8_lossy(&get_file_locked(conn, &manifest)?).to_string();
+    Ok(text.lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(slot, key)| (slot.to_string(), key.to_string()))
+        .collect())
+}
+
+fn write_manifest_locked(conn: &mut Connection, dirs: &SaveDirs, owners: &[(String, String)]) -> Result<()> {
+    let text: String = owners.iter().map(|(slot, key)| format!("{}\t{}\n", slot, key)).collect();
+    put_file_atomic_locked(conn, &dirs.manifest(), text.as_bytes())
+}
+
+/// MV on the card, journaled like rename()
+fn move_locked(conn: &mut Connection, from: &str, to: &str) -> Result<()> {
+    path_command_locked(conn, 7, "MV", vec![from.to_string(), to.to_string()])?;
+    conn.journal.lock().unwrap().record(JournalOp::Move, from, Some(to), None, None);
+    Ok(())
+}
+
+fn remove_locked(conn: &mut Connection, path: &str) -> Result<()> {
+    path_command_locked(conn, 6, "RM", vec![path.to_string()])?;
+    conn.journal.lock().unwrap().record(JournalOp::Remove, path, None, None, None);
+    Ok(())
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Give the game `key` its own save in the slot of `rom_path` before booting it
+    /// The save now in the slot is stored under its owner's key (replacing that key's
+    /// older stored save) and `key`'s stored save, if any, takes its place; a game with
+    /// none starts fresh. Nothing moves if the slot already belongs to `key`. Keys are
+    /// percent-encoded into file names in the store (anything but lowercase letters,
+    /// digits, '-', '_' and '.' becomes %XX); they can't contain tabs or line breaks.
+    #[napi]
+    pub fn activate_game_save(&self, rom_path: String, key: String, options: Option<GameSaveOptions>) -> Result<GameSaveSwitch> {
+        if key.is_empty() || key.contains(['\t', '\n', '\r']) {
+            return Err(CoreError::new(ErrorCode::ArgValidation, format!(
+                "Invalid save key {:?}: must be non-empty without tabs or line breaks", key
+            )));
+        }
+        let rom_path = normalize_path(&rom_path)?;
+        let dirs = SaveDirs::new(options)?;
+        let slot_file = slot_name(&rom_path);
+        let slot = format!("{}/{}", dirs.saves, slot_file);
+        normalize_path(&dirs.stored(&key))?;
+
+        let owners = self.with_connection(|conn| read_manifest_locked(conn, &dirs))?;
+        let previous_key = owners.iter()
+            .find(|(owned, _)| owned.eq_ignore_ascii_case(&slot_file))
+            .map(|(_, owner)| owner.clone());
+        let mut switch = GameSaveSwitch {
+            slot: slot.clone(),
+            previous_key,
+            stashed_to: None,
+            restored: false,
+            returned_to_menu: false,
+        };
+        if switch.previous_key.as_deref() == Some(key.as_str()) {
+            return Ok(switch);
+        }
+
+        let running = self.with_connection(info_locked)?.get(2).cloned().unwrap_or_default();
+        if !running.is_empty() && slot_name(&running).eq_ignore_ascii_case(&slot_file) {
+            self.with_connection(|conn| menu_reset_locked(conn, 0))?;
+            std::thread::sleep(Duration::from_millis(MENU_SETTLE_MS));
+            switch.returned_to_menu = true;
+        }
+        self.mkdir_p(dirs.store.clone())?;
+
+        self.with_connection(|conn| {
+            // Re-read under this hold: another host may have switched in the meantime
+            let mut owners = read_manifest_locked(conn, &dirs)?;
+            let owner_index = owners.iter().position(|(owned, _)| owned.eq_ignore_ascii_case(&slot_file));
+            let owner = owner_index.map(|i| owners[i].1.clone());
+            switch.previous_key = owner.clone();
+
+            if lookup_entry_locked(conn, &slot)?.is_some() {
+                let stash = match &owner {
+                    Some(owner) => dirs.stored(owner),
+                    None => {
+                        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+                        let stem = slot_file.trim_end_matches(SAVE_EXTENSION);
+                        format!("{}/{}.unclaimed.{}{}", dirs.store, stem, now, SAVE_EXTENSION)
+                    }
+                };
+                // MV won't replace an existing file on FAT
+                if lookup_entry_locked(conn, &stash)?.is_some() {
+                    remove_locked(conn, &stash)?;
+                }
+                move_locked(conn, &slot, &stash)?;
+                switch.stashed_to = Some(stash);
+            }
+
+            let stored = dirs.stored(&key);
+            if lookup_entry_locked(conn, &stored)?.is_some() {
+                move_locked(conn, &stored, &slot)?;
+                switch.restored = true;
+            }
+
+            match owner_index {
+                Some(i) => owners[i].1 = key.clone(),
+                None => owners.push((slot_file.clone(), key.clone())),
+            }
+            write_manifest_locked(conn, &dirs, &owners)
+        })?;
+        Ok(switch)
+    }
+
+    /// Slots whose save belongs to a known game, from the store's manifest
+    #[napi]
+    pub fn game_save_owners(&self, options: Option<GameSaveOptions>) -> Result<Vec<GameSaveOwner>> {
+        let dirs = SaveDirs::new(options)?;
+        let owners = self.with_connection(|conn| read_manifest_locked(conn, &dirs))?;
+        Ok(owners.into_iter()
+            .map(|(slot, key)| GameSaveOwner { slot: format!("{}/{}", dirs.saves, slot), key })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slot_names() {
+        let cases = [
+            ("/roms/Super Mario World.sfc", "Super Mario World.srm"),
+            ("/hack.v1.2.smc", "hack.v1.2.srm"),
+            ("/roms/noextension", "noextension.srm"),
+            ("/a/b/c/game.SFC", "game.srm"),
+        ];
+        for (rom, slot) in cases {
+            assert_eq!(slot_name(rom), slot, "{}", rom);
+        }
+    }
+
+    #[test]
+    fn stored_names() {
+        let dirs = SaveDirs::new(None).unwrap();
+        let cases = [
+            ("smw-hack_12.3", "smw-hack_12.3"),
+            ("Game", "%47ame"),
+            ("a/b", "a%2Fb"),
+            ("a_b", "a_b"),
+            ("100%", "100%25"),
+            ("kaizo: part 2?", "kaizo%3A%20part%202%3F"),
+            ("é", "%C3%A9"),
+        ];
+        for (key, name) in cases {
+            assert_eq!(dirs.stored(key), format!("{}/{}.srm", DEFAULT_STORE_DIR, name), "{}", key);
+        }
+    }
+
+    #[test]
+    fn stored_names_never_collide_on_fat() {
+        let dirs = SaveDirs::new(None).unwrap();
+        let keys = ["game", "Game", "GAME", "a/b", "a_b", "a:b", "a%2Fb", "a b", "a%20b", "x\u{1}", "x_"];
+        let mut names: Vec<String> = keys.iter().map(|key| dirs.stored(key).to_ascii_lowercase()).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), keys.len(), "{:?}", names);
+    }
+}
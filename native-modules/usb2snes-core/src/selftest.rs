@@ -0,0 +1,224 @@
+// Startup self-test ("is your setup actually working?")
+// Runs a fixed suite of protocol checks against the connected device and reports
+// each one separately. Nothing is written unless the caller opts in, and the
+// write check removes its scratch files even when it fails.
+
+use napi_derive::napi;
+use crate::errors::{CoreError, ErrorCode, Result};
+use std::time::Instant;
+
+use crate::regions::MemoryRegion;
+use crate::{
+    get_file_locked, get_locked, get_with_flags_locked, info_locked, list_dir_locked, path_command_locked,
+    put_file_locked, vget_locked, Connection, Usb2SnesCore, DATA64B_FLAG,
+};
+
+/// Scratch directory used by the write check
+const SCRATCH_DIR: &str = "/.usb2snes-selftest";
+
+/// File written, read back and removed by the write check
+const SCRATCH_FILE: &str = "/.usb2snes-selftest/selftest.bin";
+
+/// Bytes read by the WRAM and DATA64B checks
+const PROBE_LEN: u32 = 16;
+
+/// Options for self_test()
+#[napi(object)]
+#[derive(Default)]
+pub struct SelfTestOptions {
+    /// Also GET with the DATA64B flag (64-byte data blocks) (default false)
+    #[napi(js_name = "probeData64b")]
+    pub probe_data64b: Option<bool>,
+    /// Create, verify and delete a small file in /.usb2snes-selftest (default false)
+    pub allow_writes: Option<bool>,
+}
+
+/// Checks run by self_test(), in order
+#[napi(string_enum)]
+#[derive(Debug)]
+pub enum SelfTestCheckKind {
+    Info,
+    WramRead,
+    VgetMultiPair,
+    LsRoot,
+    Data64bProbe,
+    WriteRoundTrip,
+}
+
+#[napi(string_enum)]
+pub enum SelfTestStatus {
+    Pass,
+    Fail,
+    Skipped,
+}
+
+/// Outcome of one check
+#[napi(object)]
+pub struct SelfTestCheck {
+    pub check: SelfTestCheckKind,
+    pub status: SelfTestStatus,
+    pub elapsed_ms: u32,
+    /// Failure reason, or why the check was skipped
+    pub error: Option<String>,
+}
+
+/// Result of self_test()
+#[napi(object)]
+pub struct SelfTestReport {
+    pub checks: Vec<SelfTestCheck>,
+    /// No check failed (skipped checks don't count against it)
+    pub passed: bool,
+    /// e.g. "4 passed, 0 failed, 2 skipped"
+    pub summary: String,
+}
+
+impl SelfTestCheck {
+    /// One line for diagnostic_snapshot()
+    pub(crate) fn describe(&self) -> String {
+        let status = match self.status {
+            SelfTestStatus::Pass => "pass",
+            SelfTestStatus::Fail => "FAIL",
+            SelfTestStatus::Skipped => "skipped",
+        };
+        match &self.error {
+            Some(error) => format!("{:?} {} {}ms: {}", self.check, status, self.elapsed_ms, error),
+            None => format!("{:?} {} {}ms", self.check, status, self.elapsed_ms),
+        }
+    }
+}
+
+fn check_len(what: &str, data: &[u8], expected: usize) -> Result<()> {
+    if data.len() != expected {
+        return Err(CoreError::new(ErrorCode::InvalidResponse, format!(
+            "{} returned {} byte(s), expected {}", what, data.len(), expected
+        )));
+    }
+    Ok(())
+}
+
+/// Create, read back and delete a scratch file; cleanup runs whatever the outcome
+fn write_round_trip_locked(conn: &mut Connection) -> Result<()> {
+    let pattern: Vec<u8> = (0..64u8).map(|i| i.wrapping_mul(37) ^ 0xA5).collect();
+
+    let created_dir = list_dir_locked(conn, SCRATCH_DIR)?.is_none();
+    if created_dir {
+        path_command_locked(conn, 5, "MKDIR", vec![SCRATCH_DIR.to_string()])?;
+    }
+
+    let outcome = put_file_locked(conn, SCRATCH_FILE, &pattern)
+        .and_then(|_| get_file_locked(conn, SCRATCH_FILE))
+        .and_then(|readback| {
+            if readback == pattern {
+                Ok(())
+            } else {
+                Err(CoreError::new(ErrorCode::DeviceError, format!(
+                    "scratch file read back differs ({} byte(s) written, {} read)", pattern.len(), readback.len()
+                )))
+            }
+        });
+
+    // The file may not exist if the PUT failed early; only the directory removal must succeed
+    let _ = path_command_locked(conn, 6, "RM", vec![SCRATCH_FILE.to_string()]);
+    let cleanup = if created_dir {
+        path_command_locked(conn, 6, "RM", vec![SCRATCH_DIR.to_string()]).map(|_| ())
+    } else {
+        Ok(())
+    };
+
+    match (outcome, cleanup) {
+        (Err(e), Err(cleanup)) => Err(CoreError::new(e.code, format!(
+            "{} (cleanup also failed: {})", e.reason, cleanup.reason
+        ))),
+        (Err(e), _) | (Ok(()), Err(e)) => Err(e),
+        (Ok(()), Ok(())) => Ok(()),
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Run a non-destructive protocol check suite against the connected device
+    /// INFO, a WRAM read, a two-pair VGET and an LS of "/" always run; the DATA64B
+    /// probe and the write check are opt-in. Check failures don't throw: they are
+    /// reported per check, and everything after a failed INFO is skipped.
+    /// The report is also kept for diagnostic_snapshot().
+    #[napi]
+    pub fn self_test(&self, options: Option<SelfTestOptions>) -> Result<SelfTestReport> {
+        let options = options.unwrap_or_default();
+        let (_, wram_base, _) = MemoryRegion::Wram.layout();
+
+        let plan = [
+            (SelfTestCheckKind::Info, true),
+            (SelfTestCheckKind::WramRead, true),
+            (SelfTestCheckKind::VgetMultiPair, true),
+            (SelfTestCheckKind::LsRoot, true),
+            (SelfTestCheckKind::Data64bProbe, options.probe_data64b.unwrap_or(false)),
+            (SelfTestCheckKind::WriteRoundTrip, options.allow_writes.unwrap_or(false)),
+        ];
+
+        let mut checks = Vec::with_capacity(plan.len());
+        let mut info_failed = false;
+        for (check, enabled) in plan {
+            if !enabled || info_failed {
+                checks.push(SelfTestCheck {
+                    check,
+                    status: SelfTestStatus::Skipped,
+                    elapsed_ms: 0,
+                    error: Some(if info_failed { "INFO failed" } else { "not enabled" }.to_string()),
+                });
+                continue;
+            }
+
+            let started = Instant::now();
+            let outcome = match check {
+                SelfTestCheckKind::Info => self.with_connection(|conn| {
+                    // A cached INFO would prove nothing about the line right now
+                    conn.cache.lock().unwrap().invalidate();
+                    info_locked(conn).map(|_| ())
+                }),
+                SelfTestCheckKind::WramRead => self.with_connection(|conn| {
+                    let (space, address) = MemoryRegion::Wram.resolve(0, PROBE_LEN)?;
+                    check_len("GET", &get_locked(conn, space, address, PROBE_LEN)?, PROBE_LEN as usize)
+                }),
+                SelfTestCheckKind::VgetMultiPair => self.with_connection(|conn| {
+                    let pairs = [(PROBE_LEN as u8, wram_base), (PROBE_LEN as u8, wram_base + 0x100)];
+                    let (space, _, _) = MemoryRegion::Wram.layout();
+                    let data = vget_locked(conn, space, &pairs)?;
+                    data.iter().try_for_each(|chunk| check_len("VGET pair", chunk, PROBE_LEN as usize))
+                }),
+                SelfTestCheckKind::LsRoot => self.with_connection(|conn| match list_dir_locked(conn, "/")? {
+                    Some(_) => Ok(()),
+                    None => Err(CoreError::new(ErrorCode::DeviceError, "LS of / reported an error")),
+                }),
+                SelfTestCheckKind::Data64bProbe => self.with_connection(|conn| {
+                    let (space, address) = MemoryRegion::Wram.resolve(0, PROBE_LEN)?;
+                    let data = get_with_flags_locked(conn, space, DATA64B_FLAG, address, PROBE_LEN)?;
+                    check_len("DATA64B GET", &data, PROBE_LEN as usize)
+                }),
+                SelfTestCheckKind::WriteRoundTrip => self.with_connection(write_round_trip_locked),
+            };
+
+            if matches!(check, SelfTestCheckKind::Info) && outcome.is_err() {
+                info_failed = true;
+            }
+            checks.push(SelfTestCheck {
+                check,
+                status: if outcome.is_ok() { SelfTestStatus::Pass } else { SelfTestStatus::Fail },
+                elapsed_ms: started.elapsed().as_millis() as u32,
+                error: outcome.err().map(|e| e.reason),
+            });
+        }
+
+        let count = |status: fn(&SelfTestStatus) -> bool| checks.iter().filter(|c| status(&c.status)).count();
+        let passed = count(|s| matches!(s, SelfTestStatus::Pass));
+        let failed = count(|s| matches!(s, SelfTestStatus::Fail));
+        let skipped = count(|s| matches!(s, SelfTestStatus::Skipped));
+        let report = SelfTestReport {
+            passed: failed == 0,
+            summary: format!("{} passed, {} failed, {} skipped", passed, failed, skipped),
+            checks,
+        };
+
+        self.diagnostics.lock().unwrap().record_self_test(&report);
+        Ok(report)
+    }
+}
@@ -0,0 +1,578 @@
+// WebSocket server speaking the usb2snes/QUsb2Snes protocol
+// Lets existing clients (trackers, Crowd Control, patchers) use the device this core
+// is connected to, so no separate QUsb2Snes instance is needed. Each client gets its
+// own thread; every request takes the port like any other command, so clients and
+// the JS side interleave per command. Requests are JSON text frames
+// {"Opcode", "Space", "Flags", "Operands"}; replies are {"Results": [...]} text
+// frames, and data phases (GetAddress/GetFile replies, PutAddress/PutFile payloads)
+// are binary frames. As in QUsb2Snes a failed request closes that client's socket.
+// Supported: DeviceList, Attach, Name, AppVersion, Info, GetAddress, PutAddress,
+// GetFile, PutFile, List, MakeDir, Remove, Rename, Boot, Menu, Reset.
+
+use napi_derive::napi;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::CloseFrame;
+use tungstenite::{Message, WebSocket};
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::json::{json_string, parse_json, Json};
+use crate::queue::Lane;
+use crate::vectors::vget_ranges_locked;
+use crate::{
+    build_packet, exchange, get_file_locked, get_locked, info_locked, list_dir_locked, normalize_path,
+    path_command_locked, put_file_locked, put_locked, validate_address_range, Usb2SnesCore, LS_TYPE_DIR,
+    NORESP_FLAG, SPACE_SNES,
+};
+
+/// QUsb2Snes' port; legacy usb2snes clients use 8080
+const DEFAULT_SERVER_PORT: u16 = 23074;
+
+/// How often the accept loop and idle clients check for stop_server()
+const SERVER_POLL_MS: u64 = 50;
+
+/// Time a new client gets to complete the WebSocket handshake
+const HANDSHAKE_TIMEOUT_MS: u64 = 5000;
+
+/// Longest close reason a WebSocket close frame can carry
+const MAX_CLOSE_REASON: usize = 123;
+
+/// Largest PutFile a client may announce; the payload is buffered in memory until it
+/// is complete, so this bounds what one client can make the server hold
+const MAX_PUT_FILE_SIZE: u32 = 64 * 1024 * 1024;
+
+/// Options for start_server()
+#[napi(object)]
+#[derive(Default)]
+pub struct ServerOptions {
+    /// Interface to listen on (default "127.0.0.1"; "0.0.0.0" exposes the device to the network)
+    pub host: Option<String>,
+    /// TCP port (default 23074, QUsb2Snes'; 0 picks a free one)
+    pub port: Option<u32>,
+}
+
+/// The running server of one core
+pub(crate) struct WsServer {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// A {"Results": [...]} reply
+fn results_message(results: &[String]) -> Message {
+    let items: Vec<String> = results.iter().map(|result| json_string(result)).collect();
+    Message::Text(format!("{{\"Results\":[{}]}}", items.join(",")))
+}
+
+fn protocol_error(reason: impl Into<String>) -> CoreError {
+    CoreError::new(ErrorCode::ArgValidation, reason)
+}
+
+/// One client request
+struct Request {
+    opcode: String,
+    space: Option<String>,
+    operands: Vec<String>,
+}
+
+impl Request {
+    fn parse(text: &str) -> Result<Request> {
+        let Some(Json::Object(fields)) = parse_json(text) else {
+            return Err(protocol_error(format!("Malformed request: {}", text)));
+        };
+        let mut request = Request { opcode: String::new(), space: None, operands: Vec::new() };
+        for (key, value) in fields {
+            match (key.as_str(), value) {
+                ("Opcode", Json::Str(opcode)) => request.opcode = opcode,
+                ("Space", Json::Str(space)) => request.space = Some(space),
+                ("Operands", Json::Array(items)) => {
+                    request.operands = items.into_iter()
+                        .map(|item| match item {
+                            Json::Str(operand) => Ok(operand),
+                            _ => Err(protocol_error("Operands must be strings")),
+                        })
+                        .collect::<Result<_>>()?;
+                }
+                // Flags only matter to commands this server doesn't forward raw
+                _ => {}
+            }
+        }
+        if request.opcode.is_empty() {
+            return Err(protocol_error("Request without Opcode"));
+        }
+        Ok(request)
+    }
+
+    fn operand(&self, index: usize) -> Result<&str> {
+        self.operands.get(index).map(String::as_str)
+            .ok_or_else(|| protocol_error(format!("{}: missing operand {}", self.opcode, index)))
+    }
+
+    /// The memory space of GetAddress/PutAddress (SNES unless given)
+    fn memory_space(&self) -> Result<u8> {
+        match self.space.as_deref().unwrap_or("SNES") {
+            "SNES" => Ok(SPACE_SNES),
+            "MSU" => Ok(2),
+            "CMD" => Ok(3),
+            "CONFIG" => Ok(4),
+            other => Err(protocol_error(format!("{}: unsupported space {}", self.opcode, other))),
+        }
+    }
+
+    /// Hex (address, size) operand pairs, validated for `space`
+    fn address_pairs(&self, space: u8) -> Result<Vec<(u32, u32)>> {
+        if self.operands.is_empty() || !self.operands.len().is_multiple_of(2) {
+            return Err(protocol_error(format!("{}: operands must be address/size pairs", self.opcode)));
+        }
+        let hex = |text: &str| u32::from_str_radix(text.trim_start_matches("0x"), 16)
+            .map_err(|_| protocol_error(format!("{}: {} is not a hex number", self.opcode, text)));
+        self.operands.chunks(2)
+            .map(|pair| {
+                let (address, size) = (hex(&pair[0])?, hex(&pair[1])?);
+                validate_address_range(space, address, size)?;
+                Ok((address, size))
+            })
+            .collect()
+    }
+}
+
+/// Binary data a PutAddress/PutFile is still waiting for
+enum PendingPut {
+    Memory { space: u8, pairs: Vec<(u32, u32)>, data: Vec<u8> },
+    File { path: String, size: usize, data: Vec<u8> },
+}
+
+impl PendingPut {
+    fn expected(&self) -> usize {
+        match self {
+            PendingPut::Memory { pairs, .. } => pairs.iter().map(|&(_, size)| size as usize).sum(),
+            PendingPut::File { size, .. } => *size,
+        }
+    }
+
+    fn data(&mut self) -> &mut Vec<u8> {
+        match self {
+            PendingPut::Memory { data, .. } | PendingPut::File { data, .. } => data,
+        }
+    }
+
+    fn write(self, core: &Usb2SnesCore) -> Result<()> {
+        match self {
+            PendingPut::Memory { space, pairs, data } => core.with_connection_in(Lane::Interactive, |conn| {
+                let mut offset = 0;
+                for (address, size) in pairs {
+                    put_locked(conn, space, address, &data[offset..offset + size as usize])?;
+                    offset += size as usize;
+                }
+                Ok(())
+            }),
+            PendingPut::File { path, data, .. } => core.with_connection_in(Lane::Bulk, |conn| put_file_locked(conn, &path, &data)),
+        }
+    }
+}
+
+/// Per-client protocol state
+#[derive(Default)]
+struct ClientSession {
+    attached: bool,
+    pending: Option<PendingPut>,
+}
+
+impl ClientSession {
+    /// Handle a text frame; returns the frames to send back
+    fn request(&mut self, core: &Usb2SnesCore, text: &str) -> Result<Vec<Message>> {
+        if self.pending.is_some() {
+            return Err(protocol_error("Request sent while a Put is still waiting for its data"));
+        }
+        let request = Request::parse(text)?;
+        match request.opcode.as_str() {
+            "DeviceList" => return Ok(vec![results_message(&device_name(core).into_iter().collect::<Vec<_>>())]),
+            "AppVersion" => return Ok(vec![results_message(&[format!("usb2snes-core-{}", env!("CARGO_PKG_VERSION"))])]),
+            "Name" => return Ok(Vec::new()),
+            "Attach" => {
+                let wanted = request.operand(0)?;
+                if device_name(core).as_deref() != Some(wanted) {
+                    return Err(CoreError::new(ErrorCode::NotConnected, format!("Attach: no device named {}", wanted)));
+                }
+                self.attached = true;
+                return Ok(Vec::new());
+            }
+            _ if !self.attached => {
+                return Err(CoreError::new(ErrorCode::NotConnected, format!("{}: Attach to a device first", request.opcode)));
+            }
+            _ => {}
+        }
+
+        match request.opcode.as_str() {
+            "Info" => {
+                let info = core.with_connection_in(Lane::Interactive, info_locked)?;
+                Ok(vec![results_message(&info)])
+            }
+            "GetAddress" => {
+                let space = request.memory_space()?;
+                let pairs = request.address_pairs(space)?;
+                let data = core.with_connection_in(Lane::Interactive, |conn| match pairs[..] {
+                    [(address, size)] => get_locked(conn, space, address, size),
+                    _ => vget_ranges_locked(conn, space, &pairs).map(|parts| parts.concat()),
+                })?;
+                Ok(vec![Message::Binary(data)])
+            }
+            "PutAddress" => {
+                let space = request.memory_space()?;
+                let pairs = request.address_pairs(space)?;
+                self.expect(core, PendingPut::Memory { space, pairs, data: Vec::new() })
+            }
+            "GetFile" => {
+                let path = normalize_path(request.operand(0)?)?;
+                let data = core.with_connection_in(Lane::Bulk, |conn| get_file_locked(conn, &path))?;
+                Ok(vec![results_message(&[format!("{:X}", data.len())]), Message::Binary(data)])
+            }
+            "PutFile" => {
+                let path = normalize_path(request.operand(0)?)?;
+                let size = u32::from_str_radix(request.operand(1)?, 16)
+                    .map_err(|_| protocol_error(format!("PutFile: {} is not a hex size", request.operands[1])))?;
+                if size > MAX_PUT_FILE_SIZE {
+                    return Err(protocol_error(format!(
+                        "PutFile: {} bytes is over the {}-byte limit", size, MAX_PUT_FILE_SIZE
+                    )));
+                }
+                self.expect(core, PendingPut::File { path, size: size as usize, data: Vec::new() })
+            }
+            "List" => {
+                let path = normalize_path(request.operand(0)?)?;
+                let entries = core.with_connection(|conn| list_dir_locked(conn, &path))?
+                    .ok_or_else(|| CoreError::new(ErrorCode::DeviceError, format!("List: {} not found", path)))?;
+                // The protocol's types are "0" for a directory and "1" for a file
+                let results: Vec<String> = entries.into_iter()
+                    .flat_map(|(file_type, name)| [if file_type == LS_TYPE_DIR { "0" } else { "1" }.to_string(), name])
+                    .collect();
+                Ok(vec![results_message(&results)])
+            }
+            "Boot" | "MakeDir" | "Remove" => {
+                let (opcode, name) = match request.opcode.as_str() {
+                    "Boot" => (9, "BOOT"),
+                    "MakeDir" => (5, "MKDIR"),
+                    _ => (6, "RM"),
+                };
+                let path = normalize_path(request.operand(0)?)?;
+                core.with_connection(|conn| path_command_locked(conn, opcode, name, vec![path]))?;
+                Ok(Vec::new())
+            }
+            "Rename" => {
+                let from = normalize_path(request.operand(0)?)?;
+                let to = normalize_path(request.operand(1)?)?;
+                core.with_connection(|conn| path_command_locked(conn, 7, "MV", vec![from, to]))?;
+                Ok(Vec::new())
+            }
+            // MENU_RESET / RESET opcodes, like QUsb2Snes (no DTR pulse)
+            "Menu" | "Reset" => {
+                let opcode = if request.opcode == "Menu" { 12 } else { 8 };
+                let packet = build_packet(opcode, SPACE_SNES, NORESP_FLAG, None)?;
+                core.with_connection(|conn| exchange(conn, &packet))?;
+                Ok(Vec::new())
+            }
+            other => Err(CoreError::new(ErrorCode::Unsupported, format!("Unsupported opcode {}", other))),
+        }
+    }
+
+    /// Wait for the binary payload of a Put (written at once if it is empty)
+    fn expect(&mut self, core: &Usb2SnesCore, put: PendingPut) -> Result<Vec<Message>> {
+        if put.expected() == 0 {
+            put.write(core)?;
+        } else {
+            self.pending = Some(put);
+        }
+        Ok(Vec::new())
+    }
+
+    /// Handle a binary frame: part of a Put's payload
+    fn data(&mut self, core: &Usb2SnesCore, bytes: Vec<u8>) -> Result<()> {
+        let Some(put) = self.pending.as_mut() else {
+            return Err(protocol_error("Binary data without a PutAddress/PutFile"));
+        };
+        let expected = put.expected();
+        let received = put.data().len() + bytes.len();
+        if received > expected {
+            return Err(protocol_error(format!("Put sent {} bytes, {} were announced", received, expected)));
+        }
+        put.data().extend_from_slice(&bytes);
+        if put.data().len() == expected {
+            self.pending.take().unwrap().write(core)?;
+        }
+        Ok(())
+    }
+}
+
+/// The device name clients see in DeviceList and pass to Attach: the port name
+fn device_name(core: &Usb2SnesCore) -> Option<String> {
+    if !core.is_connected() {
+        return None;
+    }
+    core.port_name.lock().unwrap().clone()
+}
+
+fn is_timeout(error: &tungstenite::Error) -> bool {
+    matches!(error, tungstenite::Error::Io(e)
+        if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut)
+}
+
+/// Close a client's socket with `error` as the reason
+fn close_with_error(socket: &mut WebSocket<TcpStream>, error: &CoreError) {
+    let mut reason = error.reason.clone();
+    while reason.len() > MAX_CLOSE_REASON {
+        reason.pop();
+    }
+    let _ = socket.close(Some(CloseFrame { code: CloseCode::Error, reason: reason.into() }));
+    let _ = socket.flush();
+}
+
+impl Usb2SnesCore {
+    /// Body of a client thread; returns when the client leaves, fails a request or the server stops
+    fn serve_client(&self, stream: TcpStream, stop: &AtomicBool) {
+        let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let _ = stream.set_read_timeout(Some(Duration::from_millis(HANDSHAKE_TIMEOUT_MS)));
+        let Ok(mut socket) = tungstenite::accept(stream) else {
+            return;
+        };
+        let _ = socket.get_ref().set_read_timeout(Some(Duration::from_millis(SERVER_POLL_MS)));
+
+        let mut session = ClientSession::default();
+        while !stop.load(Ordering::SeqCst) {
+            let result = match socket.read() {
+                Ok(Message::Text(text)) => session.request(self, &text),
+                Ok(Message::Binary(bytes)) => session.data(self, bytes).map(|_| Vec::new()),
+                Ok(_) => Ok(Vec::new()),
+                Err(e) if is_timeout(&e) => continue,
+                Err(_) => return,
+            };
+            match result {
+                Ok(replies) => {
+                    for reply in replies {
+                        if socket.send(reply).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.diagnostics.lock().unwrap().record_warning(&format!(
+                        "WebSocket client {} closed: {}", peer, e.reason
+                    ));
+                    close_with_error(&mut socket, &e);
+                    return;
+                }
+            }
+        }
+        let _ = socket.close(None);
+        let _ = socket.flush();
+    }
+
+    /// Body of the accept thread; stops the client threads when `stop` is set
+    fn accept_loop(&self, listener: TcpListener, stop: Arc<AtomicBool>) {
+        let mut clients: Vec<JoinHandle<()>> = Vec::new();
+        while !stop.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let _ = stream.set_nonblocking(false);
+                    let core = self.clone();
+                    let client_stop = stop.clone();
+                    clients.push(std::thread::spawn(move || core.serve_client(stream, &client_stop)));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(SERVER_POLL_MS));
+                }
+                Err(e) => {
+                    self.diagnostics.lock().unwrap().record_warning(&format!("WebSocket accept failed: {}", e));
+                    std::thread::sleep(Duration::from_millis(SERVER_POLL_MS));
+                }
+            }
+            clients.retain(|client| !client.is_finished());
+        }
+        for client in clients {
+            let _ = client.join();
+        }
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Start a usb2snes/QUsb2Snes-compatible WebSocket server for this core's device
+    /// Clients see the connected device under its port name in DeviceList. Listens on
+    /// 127.0.0.1:23074 unless `options` say otherwise; returns the port listened on.
+    /// Runs until stop_server(), across disconnects and reconnects of the core.
+    #[napi]
+    pub fn start_server(&self, options: Option<ServerOptions>) -> Result<u32> {
+        let options = options.unwrap_or_default();
+        let mut server = self.server.lock().unwrap();
+        if server.is_some() {
+            return Err(CoreError::new(ErrorCode::DeviceBusy, "start_server: the server is already running"));
+        }
+        let host = options.host.unwrap_or_else(|| "127.0.0.1".to_string());
+        let port = match options.port {
+            Some(port) => u16::try_from(port)
+                .map_err(|_| CoreError::new(ErrorCode::ArgValidation, format!("start_server: invalid port {}", port)))?,
+            None => DEFAULT_SERVER_PORT,
+        };
+
+        let io_error = |e: std::io::Error| CoreError::new(ErrorCode::IoError,
+            format!("start_server: cannot listen on {}:{}: {}", host, port, e)
+        );
+        let listener = TcpListener::bind((host.as_str(), port)).map_err(io_error)?;
+        listener.set_nonblocking(true).map_err(io_error)?;
+        let bound = listener.local_addr().map_err(io_error)?.port();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let core = self.clone();
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || core.accept_loop(listener, thread_stop));
+        *server = Some(WsServer { stop, thread });
+        Ok(bound as u32)
+    }
+
+    /// Stop the WebSocket server, closing every client connection
+    /// Returns false if it wasn't running
+    #[napi]
+    pub fn stop_server(&self) -> Result<bool> {
+        let Some(server) = self.server.lock().unwrap().take() else {
+            return Ok(false);
+        };
+        server.stop.store(true, Ordering::SeqCst);
+        server.thread.join()
+            .map_err(|_| CoreError::new(ErrorCode::DeviceError, "stop_server: server thread panicked"))?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A core connected to the simulator and a session attached to it
+    fn attached() -> (Usb2SnesCore, ClientSession) {
+        let core = Usb2SnesCore::new();
+        core.connect_simulated(None, None).unwrap();
+        let mut session = ClientSession::default();
+        session.request(&core, r#"{"Opcode":"Attach","Operands":["simulator"]}"#).unwrap();
+        (core, session)
+    }
+
+    fn binary(messages: Vec<Message>) -> Vec<u8> {
+        match &messages[..] {
+            [Message::Binary(data)] => data.clone(),
+            _ => panic!("expected one binary frame"),
+        }
+    }
+
+    #[test]
+    fn parse_requests() {
+        let request = Request::parse(r#"{"Opcode":"GetAddress","Space":"SNES","Flags":["X"],"Operands":["F50000","10"]}"#).unwrap();
+        assert_eq!((request.opcode.as_str(), request.space.as_deref()), ("GetAddress", Some("SNES")));
+        assert_eq!(request.operands, ["F50000", "10"]);
+
+        let cases = [
+            ("not JSON", "GetAddress", "Malformed request"),
+            ("not an object", "[1]", "Malformed request"),
+            ("no opcode", r#"{"Space":"SNES"}"#, "Request without Opcode"),
+            ("number operand", r#"{"Opcode":"Boot","Operands":[1]}"#, "Operands must be strings"),
+        ];
+        for (name, text, expected) in cases {
+            let reason = Request::parse(text).err().expect(name).reason;
+            assert!(reason.starts_with(expected), "{}: {}", name, reason);
+        }
+    }
+
+    #[test]
+    fn address_pairs() {
+        let request = |operands: &[&str]| Request {
+            opcode: "GetAddress".to_string(),
+            space: None,
+            operands: operands.iter().map(|operand| operand.to_string()).collect(),
+        };
+        assert_eq!(request(&["F50000", "10", "0xE00000", "2"]).address_pairs(SPACE_SNES).unwrap(), [(0xF50000, 0x10), (0xE00000, 2)]);
+
+        let cases: [(&str, &[&str]); 4] = [
+            ("no operands", &[]),
+            ("odd operand count", &["F50000", "10", "F50010"]),
+            ("not hex", &["F5000G", "10"]),
+            ("past the end of the space", &["FFFFFF", "10"]),
+        ];
+        for (name, operands) in cases {
+            assert!(request(operands).address_pairs(SPACE_SNES).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn commands_need_attach() {
+        let core = Usb2SnesCore::new();
+        core.connect_simulated(None, None).unwrap();
+        let mut session = ClientSession::default();
+
+        let devices = session.request(&core, r#"{"Opcode":"DeviceList"}"#).unwrap();
+        assert!(matches!(&devices[..], [Message::Text(text)] if text == r#"{"Results":["simulator"]}"#));
+        let error = session.request(&core, r#"{"Opcode":"Info"}"#).unwrap_err();
+        assert_eq!(error.reason, "Info: Attach to a device first");
+        let error = session.request(&core, r#"{"Opcode":"Attach","Operands":["COM9"]}"#).unwrap_err();
+        assert_eq!(error.reason, "Attach: no device named COM9");
+
+        session.request(&core, r#"{"Opcode":"Attach","Operands":["simulator"]}"#).unwrap();
+        let info = session.request(&core, r#"{"Opcode":"Info"}"#).unwrap();
+        assert!(matches!(&info[..], [Message::Text(text)] if text.contains("/sd2snes/menu.bin")));
+    }
+
+    #[test]
+    fn get_address_single_and_multiple_pairs() {
+        let (core, mut session) = attached();
+        let wram: Vec<u8> = (0..32).collect();
+        core.with_connection(|conn| put_locked(conn, SPACE_SNES, 0xF50000, &wram)).unwrap();
+
+        let single = session.request(&core, r#"{"Opcode":"GetAddress","Space":"SNES","Operands":["F50004","8"]}"#).unwrap();
+        assert_eq!(binary(single), wram[4..12]);
+        let multiple = session.request(&core, r#"{"Opcode":"GetAddress","Operands":["F50010","4","F50000","2"]}"#).unwrap();
+        assert_eq!(binary(multiple), [16, 17, 18, 19, 0, 1]);
+    }
+
+    #[test]
+    fn put_address_split_across_frames() {
+        let (core, mut session) = attached();
+        session.request(&core, r#"{"Opcode":"PutAddress","Operands":["F50000","3","F50100","3"]}"#).unwrap();
+        let error = session.request(&core, r#"{"Opcode":"Info"}"#).unwrap_err();
+        assert!(error.reason.starts_with("Request sent while a Put"), "{}", error.reason);
+
+        session.data(&core, vec![1, 2]).unwrap();
+        session.data(&core, vec![3, 4]).unwrap();
+        assert_eq!(core.get_memory_with(0xF50000, 3, None, 0, None).unwrap(), [0, 0, 0], "written only when complete");
+        session.data(&core, vec![5, 6]).unwrap();
+        assert_eq!(core.get_memory_with(0xF50000, 3, None, 0, None).unwrap(), [1, 2, 3]);
+        assert_eq!(core.get_memory_with(0xF50100, 3, None, 0, None).unwrap(), [4, 5, 6]);
+        assert!(session.pending.is_none());
+    }
+
+    #[test]
+    fn over_long_put_is_refused() {
+        let (core, mut session) = attached();
+        session.request(&core, r#"{"Opcode":"PutAddress","Operands":["F50000","4"]}"#).unwrap();
+        session.data(&core, vec![1, 2]).unwrap();
+        let error = session.data(&core, vec![3, 4, 5]).unwrap_err();
+        assert_eq!(error.reason, "Put sent 5 bytes, 4 were announced");
+        assert_eq!(core.get_memory_with(0xF50000, 4, None, 0, None).unwrap(), [0; 4]);
+
+        let error = ClientSession::default().data(&core, vec![1]).unwrap_err();
+        assert_eq!(error.reason, "Binary data without a PutAddress/PutFile");
+    }
+
+    #[test]
+    fn put_file_size_is_capped_before_buffering() {
+        let (core, mut session) = attached();
+        let text = format!(r#"{{"Opcode":"PutFile","Operands":["/big.pcm","{:X}"]}}"#, MAX_PUT_FILE_SIZE + 1);
+        let error = session.request(&core, &text).unwrap_err();
+        assert!(error.reason.starts_with("PutFile: 67108865 bytes is over"), "{}", error.reason);
+        assert!(session.pending.is_none());
+
+        session.request(&core, r#"{"Opcode":"PutFile","Operands":["/small.sfc","3"]}"#).unwrap();
+        session.data(&core, vec![7, 8, 9]).unwrap();
+        let data = core.with_connection(|conn| get_file_locked(conn, "/small.sfc")).unwrap();
+        assert_eq!(data, [7, 8, 9]);
+    }
+}
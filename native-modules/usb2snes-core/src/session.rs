@@ -0,0 +1,102 @@
+// Game session tracking from INFO's romRunning
+// Memory addresses only mean something while the game that owns them is running.
+// Every INFO that already goes over the wire (no extra traffic) is checked for a
+// romRunning change: returning to the menu pauses game-scoped polling and queues a
+// GameExited event, booting a ROM queues GameStarted. Polling stays paused until the
+// caller calls resume_watches(), since only it knows whether its addresses still apply.
+
+use napi_derive::napi;
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::Usb2SnesCore;
+
+/// Events kept until game_events() collects them
+const MAX_EVENTS: usize = 16;
+
+#[napi(string_enum)]
+pub enum GameEventKind {
+    /// romRunning changed to the menu (MENU_RESET, crash back to menu, ...)
+    GameExited,
+    /// romRunning changed to a ROM
+    GameStarted,
+}
+
+/// A romRunning transition seen in INFO
+#[napi(object)]
+pub struct GameEvent {
+    pub kind: GameEventKind,
+    /// The ROM that exited or started
+    pub rom: String,
+    pub at_ms: f64,
+}
+
+#[derive(Default)]
+pub(crate) struct GameSession {
+    /// romRunning of the last INFO (None before the first one)
+    rom_running: Option<String>,
+    paused: bool,
+    events: VecDeque<GameEvent>,
+}
+
+/// Whether romRunning names the firmware menu rather than a game
+fn is_menu(rom_running: &str) -> bool {
+    let name = rom_running.rsplit('/').next().unwrap_or_default().to_ascii_lowercase();
+    rom_running.is_empty() || name == "menu.bin" || name == "m3nu.bin"
+}
+
+impl GameSession {
+    /// Look at the romRunning field of an INFO reply
+    pub(crate) fn observe(&mut self, rom_running: &str) {
+        let previous = self.rom_running.replace(rom_running.to_string());
+        let previous = match previous {
+            Some(previous) if previous != rom_running => previous,
+            // First INFO or no change
+            _ => return,
+        };
+
+        if is_menu(rom_running) {
+            if !is_menu(&previous) {
+                self.paused = true;
+                self.push(GameEventKind::GameExited, previous);
+            }
+        } else {
+            // A different game (or the same path rebooted from the menu) is a new session
+            if !is_menu(&previous) {
+                self.paused = true;
+                self.push(GameEventKind::GameExited, previous);
+            }
+            self.push(GameEventKind::GameStarted, rom_running.to_string());
+        }
+    }
+
+    fn push(&mut self, kind: GameEventKind, rom: String) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        let at_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as f64).unwrap_or(0.0);
+        self.events.push_back(GameEvent { kind, rom, at_ms });
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Collect the GameExited/GameStarted events seen since the last call, oldest first
+    #[napi]
+    pub fn game_events(&self) -> Vec<GameEvent> {
+        self.session.lock().unwrap().events.drain(..).collect()
+    }
+
+    /// Whether game-scoped polling is paused because the game exited
+    /// Watch loops should check this before reading game memory.
+    #[napi]
+    pub fn watches_paused(&self) -> bool {
+        self.session.lock().unwrap().paused
+    }
+
+    /// Re-arm game-scoped polling after a GameExited
+    #[napi]
+    pub fn resume_watches(&self) {
+        self.session.lock().unwrap().paused = false;
+    }
+}
@@ -0,0 +1,755 @@
+// Simulated FxPak for UI development and end-to-end tests without hardware
+// connect_simulated() attaches the core to an in-process device instead of a serial
+// port. The device decodes the same 512-byte packets the firmware does (INFO, LS/MKDIR/
+// RM/MV/BOOT over a virtual filesystem, GET/PUT/VGET/VPUT over in-memory address
+// spaces, STREAM as one block of WRAM per frame until the next command, TIME is
+// acknowledged, a routine armed in CMD space counts as run at once), so every
+// high-level call runs through the real encoder, timeouts and data-phase code.
+// simulator_control() lets scripts mutate memory and files while connected and inject
+// latency and failures into upcoming commands. This is the MockUsb2Snes backend: JS
+// code that only knows connect() selects it with a "mock://" port name.
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::transport::SerialTransport;
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::cmd::CMD_EXEC_ADDRESS;
+use crate::{
+    normalize_path, validate_address_range, ConnectOptions, Connection, Usb2SnesCore, DATA64B_FLAG,
+    LS_TYPE_DIR, MAX_MV_DEST_PATH_LEN, MAX_PATH_LEN, NORESP_FLAG, ONLYRESET_FLAG, PACKET_SIZE, READ_TIMEOUT_MS, SPACE_CMD,
+    SPACE_FILE, SPACE_SNES, VGET_MAX_PAIRS, VGET_PAIRS_OFFSET, VGET_PAIR_LEN,
+};
+
+/// Port name reported while connected to the simulator
+pub(crate) const SIMULATOR_PORT_NAME: &str = "simulator";
+
+/// Port names connect() hands to the simulator (anything after it is ignored)
+const MOCK_URI_PREFIX: &str = "mock://";
+
+/// Defaults of SimulatorProfile
+const DEFAULT_FIRMWARE_VERSION: &str = "1.11.0";
+const DEFAULT_REVISION: u32 = 0x1100;
+const MENU_PATH: &str = "/sd2snes/menu.bin";
+
+/// Size of each simulated address space (SNES is the full 24-bit window)
+const SNES_SPACE_LEN: usize = 0x100_0000;
+const OTHER_SPACE_LEN: usize = 0x1_0000;
+
+/// INFO feature bits, in the order parse_info_response() decodes them
+pub(crate) const FEATURE_NAMES: [&str; 8] = [
+    "FEAT_DSPX", "FEAT_ST0010", "FEAT_SRTC", "FEAT_MSU1", "FEAT_213F", "FEAT_CMD_UNLOCK", "FEAT_USB1", "FEAT_DMA1",
+];
+
+/// How a simulated read waits for a response that isn't due yet
+const SIM_POLL_MS: u64 = 1;
+
+/// Junk bytes sent ahead of the RESPONSE by FailNext with StrayBytes
+const SIM_STRAY_BYTES: usize = 3;
+
+/// Time between two STREAM frames (one NTSC frame)
+const SIM_FRAME_MS: u64 = 16;
+
+/// Where STREAM frames are read from (start of WRAM)
+const SIM_STREAM_ADDRESS: u32 = 0xF5_0000;
+
+/// What the simulated device reports at connect time
+#[napi(object)]
+#[derive(Default)]
+pub struct SimulatorProfile {
+    /// Firmware version string reported by INFO (default "1.11.0")
+    pub firmware_version: Option<String>,
+    /// ROM running at connect time (default the menu, "/sd2snes/menu.bin")
+    pub rom_running: Option<String>,
+    /// Feature flags reported by INFO, e.g. ["FEAT_MSU1"] (default none)
+    pub features: Option<Vec<String>>,
+    /// Delay before every RESPONSE (default 0)
+    pub latency_ms: Option<u32>,
+}
+
+/// Kind of a simulator_control() call
+#[napi(string_enum)]
+pub enum SimulatorAction {
+    /// Write `data` to `space` (default SNES) at `address`
+    SetMemory,
+    /// Create or replace the file `path` with `data`, creating parent directories
+    AddFile,
+    /// Create the directory `path` and its parents
+    AddDirectory,
+    /// Remove the file or directory `path` (and everything under it)
+    RemoveFile,
+    /// Make the next `count` commands fail as `failure`
+    FailNext,
+    /// Delay the RESPONSE of the next `count` commands by an extra `ms`
+    DelayNext,
+    /// Delay every RESPONSE by `ms` from now on
+    SetLatency,
+    /// Change the ROM INFO reports as running, as if it had been booted
+    SetRomRunning,
+}
+
+/// How FailNext makes a command fail
+#[napi(string_enum)]
+#[derive(Debug)]
+pub enum SimulatedFailure {
+    /// RESPONSE with a non-zero error byte; the command has no effect
+    DeviceError,
+    /// RESPONSE with a corrupted magic header (the command still runs)
+    GarbledResponse,
+    /// No RESPONSE at all, so the caller times out (the command still runs)
+    NoResponse,
+    /// A few stray bytes ahead of the RESPONSE, as after a line glitch
+    StrayBytes,
+    /// A PUT's RESPONSE acknowledges half the size sent, and the device waits for a
+    /// data phase of that size
+    ShortAck,
+}
+
+/// One simulator_control() call; which fields are required depends on `action`
+#[napi(object)]
+pub struct SimulatorControl {
+    pub action: SimulatorAction,
+    pub space: Option<u8>,
+    pub address: Option<u32>,
+    pub data: Option<Buffer>,
+    pub path: Option<String>,
+    /// Commands affected by FailNext/DelayNext (default 1)
+    pub count: Option<u32>,
+    pub ms: Option<u32>,
+    /// FailNext: how the commands fail (default DeviceError)
+    pub failure: Option<SimulatedFailure>,
+}
+
+/// Host data phase the device is waiting for after a PUT/VPUT RESPONSE
+pub(crate) enum PendingPut {
+    File { path: String, size: usize },
+    /// `block_len` is 64 for a DATA64B PUT
+    Memory { space: u8, address: u32, size: usize, block_len: usize },
+    Vector { space: u8, pairs: Vec<(u8, u32)> },
+}
+
+impl PendingPut {
+    /// Bytes the host sends for this data phase (zero-padded to whole blocks)
+    pub(crate) fn wire_len(&self) -> usize {
+        match self {
+            PendingPut::File { size, .. } => size.div_ceil(512) * 512,
+            PendingPut::Memory { size, block_len, .. } => size.div_ceil(*block_len) * block_len,
+            PendingPut::Vector { pairs, .. } => {
+                let len: usize = pairs.iter().map(|&(size, _)| size as usize).sum();
+                len.div_ceil(64) * 64
+            }
+        }
+    }
+}
+
+/// Everything the simulated device knows; shared by its port and simulator_control()
+pub(crate) struct SimState {
+    /// Virtual SD card by normalized path; None is a directory
+    files: BTreeMap<String, Option<Vec<u8>>>,
+    /// Address spaces, allocated on first use
+    memory: HashMap<u8, Vec<u8>>,
+    rom_running: String,
+    firmware_version: String,
+    features: u8,
+    latency: Duration,
+    /// Extra delay of each upcoming command, front first
+    delays: VecDeque<Duration>,
+    /// Failure of each upcoming command, front first
+    failures: VecDeque<SimulatedFailure>,
+    /// Bytes written by the host and not yet consumed
+    input: Vec<u8>,
+    /// Data phase expected from the host before the next command packet
+    pending_put: Option<(PendingPut, bool)>,
+    /// Bytes for the host and when they may be read
+    output: VecDeque<u8>,
+    ready_at: Instant,
+    /// Running STREAM: frame length and when the next frame is due
+    stream: Option<(usize, Instant)>,
+}
+
+/// Path of a packet field: NUL-terminated ASCII starting at `offset`
+pub(crate) fn packet_path(packet: &[u8], offset: usize, limit: usize) -> String {
+    let field = &packet[offset..(offset + limit).min(packet.len())];
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).to_string()
+}
+
+pub(crate) fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// VGET/VPUT pairs of a packet (a zero size ends the list)
+pub(crate) fn packet_pairs(packet: &[u8]) -> Vec<(u8, u32)> {
+    (0..VGET_MAX_PAIRS)
+        .map(|i| VGET_PAIRS_OFFSET + i * VGET_PAIR_LEN)
+        .map(|at| (packet[at], be_u32(&packet[at + 1..at + 5])))
+        .take_while(|&(size, _)| size != 0)
+        .collect()
+}
+
+/// Normalize a path from a packet the way the firmware would see it ("" is the root)
+fn sim_path(path: &str) -> String {
+    normalize_path(path).unwrap_or_else(|_| path.to_string())
+}
+
+fn parent_of(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
+
+impl SimState {
+    fn new(profile: SimulatorProfile) -> Result<Self> {
+        let mut features = 0u8;
+        for name in profile.features.unwrap_or_default() {
+            let bit = FEATURE_NAMES.iter().position(|f| *f == name).ok_or_else(|| CoreError::new(ErrorCode::ArgValidation,
+                format!("connect_simulated: unknown feature {}", name)
+            ))?;
+            features |= 1 << bit;
+        }
+
+        let mut state = SimState {
+            files: BTreeMap::new(),
+            memory: HashMap::new(),
+            rom_running: profile.rom_running.unwrap_or_else(|| MENU_PATH.to_string()),
+            firmware_version: profile.firmware_version.unwrap_or_else(|| DEFAULT_FIRMWARE_VERSION.to_string()),
+            features,
+            latency: Duration::from_millis(profile.latency_ms.unwrap_or(0) as u64),
+            delays: VecDeque::new(),
+            failures: VecDeque::new(),
+            input: Vec::new(),
+            pending_put: None,
+            output: VecDeque::new(),
+            ready_at: Instant::now(),
+            stream: None,
+        };
+        state.add_file(MENU_PATH, Vec::new());
+        Ok(state)
+    }
+
+    fn space_mut(&mut self, space: u8) -> &mut Vec<u8> {
+        self.memory.entry(space).or_insert_with(|| {
+            vec![0u8; if space == SPACE_SNES { SNES_SPACE_LEN } else { OTHER_SPACE_LEN }]
+        })
+    }
+
+    /// Read memory; bytes past the end of the space read as zero
+    fn read_memory(&mut self, space: u8, address: u32, len: usize) -> Vec<u8> {
+        let memory = self.space_mut(space);
+        (0..len).map(|i| memory.get(address as usize + i).copied().unwrap_or(0)).collect()
+    }
+
+    /// Write memory; bytes past the end of the space are dropped
+    fn write_memory(&mut self, space: u8, address: u32, data: &[u8]) {
+        let memory = self.space_mut(space);
+        let start = (address as usize).min(memory.len());
+        let end = (start + data.len()).min(memory.len());
+        memory[start..end].copy_from_slice(&data[..end - start]);
+    }
+
+    fn add_directory(&mut self, path: &str) {
+        let mut current = String::new();
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            current.push('/');
+            current.push_str(component);
+            self.files.entry(current.clone()).or_insert(None);
+        }
+    }
+
+    pub(crate) fn add_file(&mut self, path: &str, data: Vec<u8>) {
+        self.add_directory(parent_of(path));
+        self.files.insert(path.to_string(), Some(data));
+    }
+
+    /// Remove a path and everything under it; false if it didn't exist
+    fn remove(&mut self, path: &str) -> bool {
+        let prefix = format!("{}/", path);
+        let existed = self.files.remove(path).is_some();
+        self.files.retain(|p, _| !p.starts_with(&prefix));
+        existed
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        path == "/" || matches!(self.files.get(path), Some(None))
+    }
+
+    /// Children of a directory as (LS type, name)
+    fn children(&self, dir: &str) -> Vec<(u8, String)> {
+        self.files.iter()
+            .filter(|(path, _)| path.as_str() != "/" && parent_of(path) == dir)
+            .map(|(path, entry)| {
+                let name = path.rsplit('/').next().unwrap_or_default().to_string();
+                (if entry.is_none() { LS_TYPE_DIR } else { 0 }, name)
+            })
+            .collect()
+    }
+
+    /// Make the next `count` commands fail as `failure`
+    pub(crate) fn fail_next(&mut self, failure: SimulatedFailure, count: usize) {
+        self.failures.extend(std::iter::repeat_n(failure, count));
+    }
+
+    /// Accept bytes from the host and answer every complete packet
+    fn receive(&mut self, bytes: &[u8]) {
+        self.input.extend_from_slice(bytes);
+        loop {
+            if let Some((pending, _)) = &self.pending_put {
+                let len = pending.wire_len();
+                if self.input.len() < len {
+                    return;
+                }
+                let data: Vec<u8> = self.input.drain(..len).collect();
+                let (pending, apply) = self.pending_put.take().unwrap();
+                if apply {
+                    self.finish_put(pending, &data);
+                }
+            } else if self.input.len() >= PACKET_SIZE {
+                let packet: Vec<u8> = self.input.drain(..PACKET_SIZE).collect();
+                self.handle_packet(&packet);
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn finish_put(&mut self, pending: PendingPut, data: &[u8]) {
+        match pending {
+            PendingPut::File { path, size } => self.add_file(&path, data[..size].to_vec()),
+            PendingPut::Memory { space, address, size, .. } => self.write_memory(space, address, &data[..size]),
+            PendingPut::Vector { space, pairs } => {
+                let mut offset = 0;
+                for (size, address) in pairs {
+                    self.write_memory(space, address, &data[offset..offset + size as usize]);
+                    offset += size as usize;
+                }
+            }
+        }
+        self.run_nmi_hook();
+    }
+
+    /// There is no CPU to run a routine armed at $2C00 (see cmd.rs); act as if the NMI
+    /// hook ran it at once and it cleared its trigger byte
+    fn run_nmi_hook(&mut self) {
+        if let Some(trigger) = self.memory.get_mut(&SPACE_CMD).and_then(|cmd| cmd.get_mut(CMD_EXEC_ADDRESS as usize)) {
+            *trigger = 0;
+        }
+    }
+
+    /// Queue the next STREAM frame once it is due and the host has read everything else
+    fn pump_stream(&mut self) {
+        let Some((block_len, due)) = self.stream else {
+            return;
+        };
+        let now = Instant::now();
+        if !self.output.is_empty() || now < due {
+            return;
+        }
+        let frame = self.read_memory(SPACE_SNES, SIM_STREAM_ADDRESS, block_len);
+        self.output.extend(frame);
+        // Keep the frame rate, but don't burst to catch up after a slow reader
+        let next = due + Duration::from_millis(SIM_FRAME_MS);
+        self.stream = Some((block_len, next.max(now)));
+    }
+
+    /// Run one command packet and queue its RESPONSE and data phase
+    fn handle_packet(&mut self, packet: &[u8]) {
+        let (opcode, space, flags) = (packet[4], packet[5], packet[6]);
+        // Any command ends a stream; frames already queued still go out first
+        self.stream = None;
+        let failure = self.failures.pop_front();
+        let delay = self.delays.pop_front().unwrap_or_default();
+        let fails = matches!(failure, Some(SimulatedFailure::DeviceError));
+
+        let mut response = vec![0u8; PACKET_SIZE];
+        response[..4].copy_from_slice(b"USBA");
+        response[4] = 15;
+        // Byte 5 is the error code (set below on failure)
+        response[6] = flags;
+        let mut data = Vec::new();
+        let block_len = if flags & DATA64B_FLAG != 0 { 64 } else { 512 };
+        let mut error = fails;
+
+        match opcode {
+            // Memory GET/PUT carry the size at 252 and the address at 256; a zero size
+            // (hand-built packets) means one data block
+            0 | 1 if space != SPACE_FILE => {
+                let address = be_u32(&packet[256..260]);
+                let mut size = match be_u32(&packet[252..256]) as usize {
+                    0 => block_len,
+                    size => size,
+                };
+                if opcode == 1 && matches!(failure, Some(SimulatedFailure::ShortAck)) {
+                    size /= 2;
+                }
+                response[252..256].copy_from_slice(&(size as u32).to_be_bytes());
+                if opcode == 0 {
+                    if !fails {
+                        data = self.read_memory(space, address, size);
+                    }
+                } else {
+                    self.pending_put = Some((PendingPut::Memory { space, address, size, block_len }, !fails));
+                }
+            }
+            0 => {
+                let path = sim_path(&packet_path(packet, 8, MAX_PATH_LEN));
+                match self.files.get(&path) {
+                    Some(Some(contents)) if !fails => {
+                        response[252..256].copy_from_slice(&(contents.len() as u32).to_be_bytes());
+                        data = contents.clone();
+                    }
+                    _ => error = true,
+                }
+            }
+            1 => {
+                let path = sim_path(&packet_path(packet, 8, MAX_PATH_LEN));
+                let mut size = be_u32(&packet[252..256]) as usize;
+                if matches!(failure, Some(SimulatedFailure::ShortAck)) {
+                    size /= 2;
+                }
+                // A refused FILE PUT gets no data phase: the host checks the error byte first
+                error = fails || !self.is_dir(parent_of(&path)) || self.is_dir(&path);
+                if !error {
+                    response[252..256].copy_from_slice(&(size as u32).to_be_bytes());
+                    self.pending_put = Some((PendingPut::File { path, size }, true));
+                }
+            }
+            2 | 3 => {
+                let pairs = packet_pairs(packet);
+                let total: usize = pairs.iter().map(|&(size, _)| size as usize).sum();
+                response[252..256].copy_from_slice(&(total as u32).to_be_bytes());
+                if opcode == 2 {
+                    if !fails {
+                        for &(size, address) in &pairs {
+                            data.extend(self.read_memory(space, address, size as usize));
+                        }
+                    }
+                } else {
+                    self.pending_put = Some((PendingPut::Vector { space, pairs }, !fails));
+                }
+            }
+            4 => {
+                let path = sim_path(&packet_path(packet, 8, MAX_PATH_LEN));
+                if !fails && self.is_dir(&path) {
+                    let mut entries = vec![(LS_TYPE_DIR, ".".to_string()), (LS_TYPE_DIR, "..".to_string())];
+                    entries.extend(self.children(&path));
+                    for (file_type, name) in entries {
+                        data.push(file_type);
+                        data.extend_from_slice(name.as_bytes());
+                        data.push(0);
+                    }
+                    data.push(0xFF);
+                    response[252..256].copy_from_slice(&(data.len() as u32).to_be_bytes());
+                } else {
+                    error = true;
+                }
+            }
+            // RESET, and BOOT/MENU_RESET with ONLYRESET, keep the running ROM. There is no
+            // RTC to set, so TIME is only acknowledged
+            8 | 14 => {}
+            9 | 12 if !fails && flags & ONLYRESET_FLAG != 0 => {}
+            5 | 6 | 9 if !fails => {
+                let path = sim_path(&packet_path(packet, 8, MAX_PATH_LEN));
+                error = match opcode {
+                    5 if self.is_dir(parent_of(&path)) && !self.files.contains_key(&path) => {
+                        self.files.insert(path, None);
+                        false
+                    }
+                    6 => !self.remove(&path),
+                    9 if matches!(self.files.get(&path), Some(Some(_))) => {
+                        self.rom_running = path;
+                        false
+                    }
+                    _ => true,
+                };
+            }
+            7 if !fails => {
+                let from = sim_path(&packet_path(packet, 8, MAX_PATH_LEN));
+                let to = sim_path(&packet_path(packet, 256, MAX_MV_DEST_PATH_LEN));
+                error = !self.files.contains_key(&from) || self.files.contains_key(&to)
+                    || !self.is_dir(parent_of(&to));
+                if !error {
+                    let prefix = format!("{}/", from);
+                    let moved: Vec<String> = self.files.keys()
+                        .filter(|p| **p == from || p.starts_with(&prefix))
+                        .cloned()
+                        .collect();
+                    for old in moved {
+                        let entry = self.files.remove(&old).unwrap();
+                        self.files.insert(format!("{}{}", to, &old[from.len()..]), entry);
+                    }
+                }
+            }
+            // POWER_CYCLE and MENU_RESET return to the menu
+            10 | 12 if !fails => self.rom_running = MENU_PATH.to_string(),
+            // Frames follow the RESPONSE (see pump_stream())
+            13 if !fails => self.stream = Some((block_len, Instant::now() + Duration::from_millis(SIM_FRAME_MS))),
+            11 if !fails => {
+                response[6] = self.features;
+                let rom = self.rom_running.as_bytes();
+                let rom_len = rom.len().min(252 - 16 - 1);
+                response[16..16 + rom_len].copy_from_slice(&rom[..rom_len]);
+                response[256..260].copy_from_slice(&DEFAULT_REVISION.to_be_bytes());
+                let version = self.firmware_version.as_bytes();
+                let version_len = version.len().min(PACKET_SIZE - 260 - 1);
+                response[260..260 + version_len].copy_from_slice(&version[..version_len]);
+            }
+            _ => error = true,
+        }
+
+        if error {
+            response[5] = 1;
+            response[252..256].fill(0);
+            data.clear();
+        }
+        if let Some(SimulatedFailure::GarbledResponse) = failure {
+            response[..4].copy_from_slice(b"XXXX");
+        }
+        if flags & NORESP_FLAG != 0 || matches!(failure, Some(SimulatedFailure::NoResponse)) {
+            return;
+        }
+
+        self.ready_at = self.ready_at.max(Instant::now() + self.latency + delay);
+        if let Some(SimulatedFailure::StrayBytes) = failure {
+            self.output.extend([0xFF; SIM_STRAY_BYTES]);
+        }
+        self.output.extend(response);
+        if !data.is_empty() {
+            let padded = data.len().div_ceil(block_len) * block_len;
+            data.resize(padded, 0);
+            self.output.extend(data);
+        }
+    }
+}
+
+/// The simulated device seen as a serial port
+struct SimPort {
+    state: Arc<Mutex<SimState>>,
+    timeout: Duration,
+}
+
+impl Read for SimPort {
+    /// Waits up to the port timeout for output that is due, like a serial read
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                state.pump_stream();
+                if !state.output.is_empty() && Instant::now() >= state.ready_at {
+                    let n = buf.len().min(state.output.len());
+                    for (slot, byte) in buf.iter_mut().zip(state.output.drain(..n)) {
+                        *slot = byte;
+                    }
+                    return Ok(n);
+                }
+            }
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "Operation timed out"));
+            }
+            std::thread::sleep(Duration::from_millis(SIM_POLL_MS));
+        }
+    }
+}
+
+impl Write for SimPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.state.lock().unwrap().receive(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialPort for SimPort {
+    fn name(&self) -> Option<String> {
+        Some(SIMULATOR_PORT_NAME.to_string())
+    }
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(9600)
+    }
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(DataBits::Eight)
+    }
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(FlowControl::None)
+    }
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(Parity::None)
+    }
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(StopBits::One)
+    }
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+    fn set_baud_rate(&mut self, _: u32) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_parity(&mut self, _: Parity) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+    fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+    /// Dropping DTR resets the device, abandoning any data phase it was waiting for
+    fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+        if !level {
+            let mut state = self.state.lock().unwrap();
+            state.input.clear();
+            state.pending_put = None;
+            state.stream = None;
+        }
+        Ok(())
+    }
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        let state = self.state.lock().unwrap();
+        Ok(if Instant::now() >= state.ready_at { state.output.len() as u32 } else { 0 })
+    }
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+    fn clear(&self, buffer: ClearBuffer) -> serialport::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if matches!(buffer, ClearBuffer::Input | ClearBuffer::All) {
+            state.output.clear();
+        }
+        if matches!(buffer, ClearBuffer::Output | ClearBuffer::All) {
+            state.input.clear();
+            state.pending_put = None;
+        }
+        Ok(())
+    }
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(SimPort { state: self.state.clone(), timeout: self.timeout }))
+    }
+    fn set_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn clear_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+}
+
+/// Whether a port name selects the simulator rather than a serial port
+pub(crate) fn is_mock_uri(port_name: &str) -> bool {
+    port_name.starts_with(MOCK_URI_PREFIX)
+}
+
+fn missing(field: &str) -> CoreError {
+    CoreError::new(ErrorCode::ArgValidation, format!("simulator_control: missing `{}`", field))
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Connect to an in-process simulated device instead of a serial port
+    /// Everything else (commands, caching, diagnostics, reservations) behaves exactly
+    /// as with hardware. The device starts with "/sd2snes/menu.bin" on its SD card and
+    /// zeroed memory; use simulator_control() to set it up. `options` are the same as
+    /// for connect_with_options() (DTR/RTS levels are accepted and ignored).
+    /// connect("mock://") does the same with the default profile.
+    #[napi]
+    pub fn connect_simulated(&self, profile: Option<SimulatorProfile>, options: Option<ConnectOptions>) -> Result<()> {
+        let state = Arc::new(Mutex::new(SimState::new(profile.unwrap_or_default())?));
+        let port = SimPort { state: state.clone(), timeout: Duration::from_millis(READ_TIMEOUT_MS) };
+        let mut port_guard = self.lock_for_connect()?;
+        self.attach_port(
+            &mut port_guard,
+            Box::new(SerialTransport::emulated(Box::new(port))),
+            SIMULATOR_PORT_NAME.to_string(),
+            options.unwrap_or_default(),
+            |conn| conn.simulator = Some(state),
+        )
+    }
+
+    /// Script the simulated device (only while connected with connect_simulated())
+    /// Takes effect between commands: a FailNext or DelayNext applies to the next
+    /// command packet the device receives, whichever API sends it.
+    #[napi]
+    pub fn simulator_control(&self, control: SimulatorControl) -> Result<()> {
+        let state = self.with_connection(|conn: &mut Connection| {
+            conn.simulator.clone().ok_or_else(|| CoreError::new(ErrorCode::Unsupported,
+                "Unsupported: simulator_control() needs a connect_simulated() connection"
+            ))
+        })?;
+        let mut state = state.lock().unwrap();
+        let count = control.count.unwrap_or(1) as usize;
+
+        match control.action {
+            SimulatorAction::SetMemory => {
+                let space = control.space.unwrap_or(SPACE_SNES);
+                let address = control.address.ok_or_else(|| missing("address"))?;
+                let data = control.data.ok_or_else(|| missing("data"))?;
+                validate_address_range(space, address, data.len() as u32)?;
+                state.write_memory(space, address, &data);
+            }
+            SimulatorAction::AddFile => {
+                let path = normalize_path(control.path.as_deref().ok_or_else(|| missing("path"))?)?;
+                let data = control.data.map(|data| data.to_vec()).unwrap_or_default();
+                if state.is_dir(&path) {
+                    return Err(CoreError::new(ErrorCode::ArgValidation, format!("simulator_control: {} is a directory", path)));
+                }
+                state.add_file(&path, data);
+            }
+            SimulatorAction::AddDirectory => {
+                let path = normalize_path(control.path.as_deref().ok_or_else(|| missing("path"))?)?;
+                if matches!(state.files.get(&path), Some(Some(_))) {
+                    return Err(CoreError::new(ErrorCode::ArgValidation, format!("simulator_control: {} is a file", path)));
+                }
+                state.add_directory(&path);
+            }
+            SimulatorAction::RemoveFile => {
+                let path = normalize_path(control.path.as_deref().ok_or_else(|| missing("path"))?)?;
+                if !state.remove(&path) {
+                    return Err(CoreError::new(ErrorCode::ArgValidation, format!("simulator_control: {} does not exist", path)));
+                }
+            }
+            SimulatorAction::FailNext => {
+                state.fail_next(control.failure.unwrap_or(SimulatedFailure::DeviceError), count);
+            }
+            SimulatorAction::DelayNext => {
+                let ms = control.ms.ok_or_else(|| missing("ms"))?;
+                state.delays.extend(std::iter::repeat_n(Duration::from_millis(ms as u64), count));
+            }
+            SimulatorAction::SetLatency => {
+                state.latency = Duration::from_millis(control.ms.ok_or_else(|| missing("ms"))? as u64);
+            }
+            SimulatorAction::SetRomRunning => {
+                state.rom_running = control.path.ok_or_else(|| missing("path"))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+// Whole-region snapshots for crash reports and memory scanning
+// A snapshot reads a region in chunks, releasing the port between them so UI polling
+// keeps running, and retries chunks whose RESPONSE came back garbled (reads are
+// idempotent). diff_snapshots() compares two snapshots without another NAPI round trip.
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+use napi::{JsFunction, JsUnknown};
+use crate::errors::{CoreError, ErrorCode, Result};
+use std::time::Instant;
+
+use crate::regions::MemoryRegion;
+use crate::resync::is_garbled_response;
+use crate::{drain_input_locked, get_with_flags_locked, Usb2SnesCore};
+
+/// Bytes read per GET; small enough that other commands get the port between chunks
+const SNAPSHOT_CHUNK_LEN: u32 = 0x2000;
+
+/// Attempts per chunk before the snapshot fails
+const SNAPSHOT_CHUNK_ATTEMPTS: u32 = 3;
+
+/// Result of snapshot_domain()
+#[napi(object)]
+pub struct DomainSnapshot {
+    pub data: Buffer,
+    pub duration_ms: u32,
+    /// GETs that succeeded (one per chunk)
+    pub chunks: u32,
+    /// Chunk reads repeated after a garbled RESPONSE
+    pub retries: u32,
+}
+
+/// Progress event passed to snapshot_domain()'s `on_progress` after each chunk
+#[napi(object)]
+pub struct SnapshotProgress {
+    pub bytes_read: u32,
+    pub total: u32,
+}
+
+/// A run of differing bytes (see diff_snapshots())
+#[napi(object)]
+pub struct SnapshotDiff {
+    pub offset: u32,
+    pub len: u32,
+}
+
+/// Ranges where `a` and `b` differ; bytes past the end of the shorter one count as different
+#[napi]
+pub fn diff_snapshots(a: Buffer, b: Buffer) -> Vec<SnapshotDiff> {
+    let mut diffs: Vec<SnapshotDiff> = Vec::new();
+    let common = a.len().min(b.len());
+    let mut run_start = None;
+    for i in 0..=common {
+        let differs = i < common && a[i] != b[i];
+        match (differs, run_start) {
+            (true, None) => run_start = Some(i),
+            (false, Some(start)) => {
+                diffs.push(SnapshotDiff { offset: start as u32, len: (i - start) as u32 });
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+    let longest = a.len().max(b.len());
+    if longest > common {
+        match diffs.last_mut() {
+            Some(last) if (last.offset + last.len) as usize == common => last.len += (longest - common) as u32,
+            _ => diffs.push(SnapshotDiff { offset: common as u32, len: (longest - common) as u32 }),
+        }
+    }
+    diffs
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Read a whole memory region (e.g. all 128KB of WRAM) as fast as the link allows
+    /// Reads bypass the read cache. `on_progress` receives a SnapshotProgress after each
+    /// chunk, while the port is released. A chunk whose RESPONSE is garbled is retried
+    /// (up to 3 attempts) after draining the line.
+    #[napi]
+    pub fn snapshot_domain(&self, domain: MemoryRegion, on_progress: Option<JsFunction>) -> Result<DomainSnapshot> {
+        let (space, base, size) = domain.layout();
+        let started = Instant::now();
+        let mut data = Vec::with_capacity(size as usize);
+        let mut chunks = 0;
+        let mut retries = 0;
+
+        while (data.len() as u32) < size {
+            let offset = data.len() as u32;
+            let len = (size - offset).min(SNAPSHOT_CHUNK_LEN);
+            let mut attempt = 1;
+            let chunk = loop {
+                match self.with_connection(|conn| get_with_flags_locked(conn, space, 0, base + offset, len)) {
+                    Err(e) if attempt < SNAPSHOT_CHUNK_ATTEMPTS && is_garbled_response(&e.reason) => {
+                        self.with_connection(drain_input_locked)?;
+                        attempt += 1;
+                        retries += 1;
+                    }
+                    result => break result?,
+                }
+            };
+            if chunk.len() != len as usize {
+                return Err(CoreError::new(ErrorCode::InvalidResponse, format!(
+                    "Snapshot of {:?} got {} byte(s) at offset 0x{:X}, expected {}",
+                    domain, chunk.len(), offset, len
+                )));
+            }
+            data.extend_from_slice(&chunk);
+            chunks += 1;
+
+            if let Some(callback) = on_progress.as_ref() {
+                callback.call1::<SnapshotProgress, JsUnknown>(SnapshotProgress {
+                    bytes_read: data.len() as u32,
+                    total: size,
+                })?;
+            }
+        }
+
+        Ok(DomainSnapshot {
+            data: data.into(),
+            duration_ms: started.elapsed().as_millis() as u32,
+            chunks,
+            retries,
+        })
+    }
+}
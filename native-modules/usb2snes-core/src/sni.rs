@@ -0,0 +1,632 @@
+// SNI gRPC client backend
+// connect("sni://host:port") drives a device through a running SNI (port 8191 unless
+// given) instead of opening its serial port, so a device SNI has already claimed stays
+// usable. The backend is a Transport that decodes the core's command packets into calls
+// on SNI's services: DeviceMemory for GET/PUT/VGET/VPUT on the SNES space (in SNI's
+// FxPakPro address space, so addresses pass through unchanged), DeviceFilesystem for
+// file GET/PUT, LS, MKDIR, RM, MV and BOOT, DeviceControl for RESET/MENU_RESET and
+// DeviceInfo for INFO. The first device ListDevices reports is used unless the URI
+// names one after '#' ("sni://localhost:8191#fxpakpro://./dev/ttyACM0"). Emulator
+// devices need the game's mapping to place ROM/SRAM addresses; it is asked of SNI once
+// per command that touches them. The MSU/CMD spaces, POWER_CYCLE and STREAM have no SNI
+// call and fail with "Unsupported: ...".
+
+use std::time::Duration;
+use tonic::codec::ProstCodec;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Status};
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::regions::MemoryRegion;
+use crate::simulator::{be_u32, packet_path, packet_pairs, PendingPut};
+use crate::timeouts::ReadDeadlines;
+use crate::transport::{set_info_strings, ReplyQueue, Transport};
+use crate::{
+    DATA64B_FLAG, DEVICE_DISCONNECTED, LS_TYPE_DIR, MAX_MV_DEST_PATH_LEN, MAX_PATH_LEN, NORESP_FLAG, ONLYRESET_FLAG,
+    READ_TIMEOUT_MS, SPACE_FILE, SPACE_SNES,
+};
+
+const URI_PREFIX: &str = "sni://";
+
+/// SNI's default gRPC port
+const DEFAULT_PORT: u16 = 8191;
+
+/// Longest wait for a whole-file GetFile/PutFile; unlike a serial transfer there is
+/// no progress to watch, so the short per-command timeouts would cut off large files
+const FILE_TRANSFER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// sni.proto enum values used here
+const ADDRESS_SPACE_FXPAKPRO: i32 = 0;
+const MAPPING_UNKNOWN: i32 = 0;
+const DIR_ENTRY_DIRECTORY: i32 = 0;
+const FIELD_DEVICE_NAME: i32 = 0;
+const FIELD_DEVICE_VERSION: i32 = 1;
+const FIELD_ROM_FILE_NAME: i32 = 40;
+
+/// Messages of sni.proto, field for field (only those used here)
+/// Requests and replies that carry just a uri, or a uri and a path, share a struct: on
+/// the wire they are the same.
+mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct DevicesRequest {
+        #[prost(string, repeated, tag = "1")]
+        pub kinds: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct DevicesResponse {
+        #[prost(message, repeated, tag = "1")]
+        pub devices: Vec<Device>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Device {
+        #[prost(string, tag = "1")]
+        pub uri: String,
+        #[prost(string, tag = "2")]
+        pub display_name: String,
+        #[prost(string, tag = "3")]
+        pub kind: String,
+    }
+
+    /// ResetSystem/ResetToMenu requests and replies, DetectMemoryMappingRequest (no fallback)
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct UriRequest {
+        #[prost(string, tag = "1")]
+        pub uri: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct DetectMemoryMappingResponse {
+        #[prost(string, tag = "1")]
+        pub uri: String,
+        #[prost(int32, tag = "2")]
+        pub memory_mapping: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ReadMemoryRequest {
+        #[prost(uint32, tag = "1")]
+        pub request_address: u32,
+        #[prost(int32, tag = "2")]
+        pub request_address_space: i32,
+        #[prost(uint32, tag = "3")]
+        pub size: u32,
+        #[prost(int32, tag = "4")]
+        pub request_memory_mapping: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ReadMemoryResponse {
+        #[prost(uint32, tag = "1")]
+        pub request_address: u32,
+        #[prost(bytes = "vec", tag = "5")]
+        pub data: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct WriteMemoryRequest {
+        #[prost(uint32, tag = "1")]
+        pub request_address: u32,
+        #[prost(int32, tag = "2")]
+        pub request_address_space: i32,
+        #[prost(bytes = "vec", tag = "3")]
+        pub data: Vec<u8>,
+        #[prost(int32, tag = "4")]
+        pub request_memory_mapping: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct WriteMemoryResponse {
+        #[prost(uint32, tag = "1")]
+        pub request_address: u32,
+        #[prost(uint32, tag = "5")]
+        pub size: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MultiReadMemoryRequest {
+        #[prost(string, tag = "1")]
+        pub uri: String,
+        #[prost(message, repeated, tag = "2")]
+        pub requests: Vec<ReadMemoryRequest>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MultiReadMemoryResponse {
+        #[prost(string, tag = "1")]
+        pub uri: String,
+        #[prost(message, repeated, tag = "2")]
+        pub responses: Vec<ReadMemoryResponse>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MultiWriteMemoryRequest {
+        #[prost(string, tag = "1")]
+        pub uri: String,
+        #[prost(message, repeated, tag = "2")]
+        pub requests: Vec<WriteMemoryRequest>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MultiWriteMemoryResponse {
+        #[prost(string, tag = "1")]
+        pub uri: String,
+        #[prost(message, repeated, tag = "2")]
+        pub responses: Vec<WriteMemoryResponse>,
+    }
+
+    /// ReadDirectory/MakeDirectory/RemoveFile/GetFile/BootFile requests, and the
+    /// MakeDirectory/RemoveFile/BootFile replies
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PathMessage {
+        #[prost(string, tag = "1")]
+        pub uri: String,
+        #[prost(string, tag = "2")]
+        pub path: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct DirEntry {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(int32, tag = "2")]
+        pub r#type: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ReadDirectoryResponse {
+        #[prost(string, tag = "1")]
+        pub uri: String,
+        #[prost(string, tag = "2")]
+        pub path: String,
+        #[prost(message, repeated, tag = "3")]
+        pub entries: Vec<DirEntry>,
+    }
+
+    /// Also decodes RenameFileResponse, which has the same fields
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RenameFileRequest {
+        #[prost(string, tag = "1")]
+        pub uri: String,
+        #[prost(string, tag = "2")]
+        pub path: String,
+        #[prost(string, tag = "3")]
+        pub new_filename: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PutFileRequest {
+        #[prost(string, tag = "1")]
+        pub uri: String,
+        #[prost(string, tag = "2")]
+        pub path: String,
+        #[prost(bytes = "vec", tag = "3")]
+        pub data: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PutFileResponse {
+        #[prost(string, tag = "1")]
+        pub uri: String,
+        #[prost(string, tag = "2")]
+        pub path: String,
+        #[prost(uint32, tag = "3")]
+        pub size: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GetFileResponse {
+        #[prost(string, tag = "1")]
+        pub uri: String,
+        #[prost(string, tag = "2")]
+        pub path: String,
+        #[prost(uint32, tag = "3")]
+        pub size: u32,
+        #[prost(bytes = "vec", tag = "4")]
+        pub data: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct FieldsRequest {
+        #[prost(string, tag = "1")]
+        pub uri: String,
+        #[prost(int32, repeated, tag = "2")]
+        pub fields: Vec<i32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct FieldsResponse {
+        #[prost(string, tag = "1")]
+        pub uri: String,
+        #[prost(int32, repeated, tag = "2")]
+        pub fields: Vec<i32>,
+        #[prost(string, repeated, tag = "3")]
+        pub values: Vec<String>,
+    }
+}
+
+/// Result of an SNI call; Status is boxed as it is large
+type CallResult<T> = std::result::Result<T, Box<Status>>;
+
+fn failure(code: Code, message: impl Into<String>) -> Box<Status> {
+    Box::new(Status::new(code, message))
+}
+
+/// Whether a port name is an SNI URI rather than a serial port
+pub(crate) fn is_sni_uri(port_name: &str) -> bool {
+    port_name.starts_with(URI_PREFIX)
+}
+
+/// A device behind SNI, seen as a Transport
+struct SniTransport {
+    runtime: tokio::runtime::Runtime,
+    grpc: tonic::client::Grpc<Channel>,
+    uri: String,
+    /// SNI's uri of the device in use
+    device: String,
+    timeout: Duration,
+    /// SNI MemoryMapping of the running game, asked for on first need within a command
+    mapping: Option<i32>,
+    /// Data phase expected from the core before the next command packet
+    pending_put: Option<PendingPut>,
+    /// Data phase bytes written by the core so far
+    input: Vec<u8>,
+    replies: ReplyQueue,
+}
+
+impl SniTransport {
+    fn connect(uri: &str) -> Result<SniTransport> {
+        let (address, device) = match uri[URI_PREFIX.len()..].split_once('#') {
+            Some((address, device)) => (address, Some(device)),
+            None => (&uri[URI_PREFIX.len()..], None),
+        };
+        let address = match address {
+            "" => format!("127.0.0.1:{}", DEFAULT_PORT),
+            address if address.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) => address.to_string(),
+            address => format!("{}:{}", address, DEFAULT_PORT),
+        };
+        let io_error = |e: String| CoreError::new(ErrorCode::IoError, format!("Failed to connect to {}: {}", uri, e));
+        let timeout = Duration::from_millis(READ_TIMEOUT_MS);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| io_error(e.to_string()))?;
+        let endpoint = Endpoint::from_shared(format!("http://{}", address))
+            .map_err(|e| io_error(e.to_string()))?
+            .connect_timeout(timeout);
+        let channel = runtime.block_on(endpoint.connect())
+            .map_err(|e| CoreError::new(ErrorCode::NotConnected, format!("{}: SNI is not reachable ({})", uri, e)))?;
+
+        let mut transport = SniTransport {
+            runtime,
+            grpc: tonic::client::Grpc::new(channel),
+            uri: uri.to_string(),
+            device: String::new(),
+            timeout,
+            mapping: None,
+            pending_put: None,
+            input: Vec::new(),
+            replies: ReplyQueue::default(),
+        };
+        transport.device = transport.pick_device(device)
+            .map_err(|e| CoreError::new(ErrorCode::NotConnected, format!("{}: {}", uri, e.message())))?;
+        Ok(transport)
+    }
+
+    /// SNI uri of the device named by `device` (its uri or display name), else the first
+    fn pick_device(&mut self, device: Option<&str>) -> CallResult<String> {
+        let response: proto::DevicesResponse = self.call("/Devices/ListDevices", proto::DevicesRequest::default(), self.timeout)?;
+        let names: Vec<&str> = response.devices.iter().map(|d| d.uri.as_str()).collect();
+        let found = match device {
+            Some(name) => response.devices.iter().find(|d| d.uri == name || d.display_name == name),
+            None => response.devices.first(),
+        };
+        match (found, device) {
+            (Some(found), _) => Ok(found.uri.clone()),
+            (None, Some(name)) => Err(failure(Code::NotFound, format!("no device named {} (SNI has {:?})", name, names))),
+            (None, None) => Err(failure(Code::NotFound, "SNI lists no devices")),
+        }
+    }
+
+    /// One unary call, failing with DeadlineExceeded after `timeout`
+    fn call<Req, Res>(&mut self, path: &'static str, request: Req, timeout: Duration) -> CallResult<Res>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Res: prost::Message + Default + Send + Sync + 'static,
+    {
+        let grpc = &mut self.grpc;
+        self.runtime.block_on(async {
+            let call = async {
+                grpc.ready().await.map_err(|e| failure(Code::Unavailable, e.to_string()))?;
+                grpc.unary(Request::new(request), PathAndQuery::from_static(path), ProstCodec::default()).await.map_err(Box::new)
+            };
+            match tokio::time::timeout(timeout, call).await {
+                Ok(result) => result.map(tonic::Response::into_inner),
+                Err(_) => Err(failure(Code::DeadlineExceeded, format!("no reply to {} within {}ms", path, timeout.as_millis()))),
+            }
+        })
+    }
+
+    fn path_message(&self, path: String) -> proto::PathMessage {
+        proto::PathMessage { uri: self.device.clone(), path }
+    }
+
+    /// SNI mapping to send with a request at `address`; only ROM/SRAM need a known one
+    fn mapping_for(&mut self, address: u32) -> CallResult<i32> {
+        let (_, wram_base, wram_size) = MemoryRegion::Wram.layout();
+        if (wram_base..wram_base + wram_size).contains(&address) {
+            return Ok(MAPPING_UNKNOWN);
+        }
+        if let Some(mapping) = self.mapping {
+            return Ok(mapping);
+        }
+        let request = proto::UriRequest { uri: self.device.clone() };
+        let response: proto::DetectMemoryMappingResponse = self.call("/DeviceMemory/MappingDetect", request, self.timeout)?;
+        self.mapping = Some(response.memory_mapping);
+        Ok(response.memory_mapping)
+    }
+
+    /// Read (address, size) ranges of the SNES space in one MultiRead
+    fn read_memory(&mut self, ranges: &[(u32, usize)]) -> CallResult<Vec<u8>> {
+        let mut requests = Vec::with_capacity(ranges.len());
+        for &(address, size) in ranges {
+            requests.push(proto::ReadMemoryRequest {
+                request_address: address,
+                request_address_space: ADDRESS_SPACE_FXPAKPRO,
+                size: size as u32,
+                request_memory_mapping: self.mapping_for(address)?,
+            });
+        }
+        let request = proto::MultiReadMemoryRequest { uri: self.device.clone(), requests };
+        let response: proto::MultiReadMemoryResponse = self.call("/DeviceMemory/MultiRead", request, self.timeout)?;
+        let mut data = Vec::new();
+        for (&(address, size), read) in ranges.iter().zip(&response.responses) {
+            if read.data.len() != size {
+                return Err(failure(Code::DataLoss, format!(
+                    "read of 0x{:06X} returned {} of {} bytes", address, read.data.len(), size
+                )));
+            }
+            data.extend_from_slice(&read.data);
+        }
+        if response.responses.len() != ranges.len() {
+            return Err(failure(Code::DataLoss, format!(
+                "MultiRead answered {} of {} reads", response.responses.len(), ranges.len()
+            )));
+        }
+        Ok(data)
+    }
+
+    /// Write (address, data) ranges of the SNES space in one MultiWrite
+    fn write_memory(&mut self, ranges: Vec<(u32, Vec<u8>)>) -> CallResult<()> {
+        let mut requests = Vec::with_capacity(ranges.len());
+        for (address, data) in ranges {
+            requests.push(proto::WriteMemoryRequest {
+                request_address: address,
+                request_address_space: ADDRESS_SPACE_FXPAKPRO,
+                data,
+                request_memory_mapping: self.mapping_for(address)?,
+            });
+        }
+        let request = proto::MultiWriteMemoryRequest { uri: self.device.clone(), requests };
+        let _: proto::MultiWriteMemoryResponse = self.call("/DeviceMemory/MultiWrite", request, self.timeout)?;
+        Ok(())
+    }
+
+    /// Answer one command packet, queueing its RESPONSE and data phase
+    fn handle_packet(&mut self, packet: &[u8]) -> CallResult<()> {
+        let (opcode, space, flags) = (packet[4], packet[5], packet[6]);
+        let mut response = ReplyQueue::response(flags);
+        let mut data = Vec::new();
+        let block_len = if flags & DATA64B_FLAG != 0 { 64 } else { 512 };
+        let path = || packet_path(packet, 8, MAX_PATH_LEN);
+
+        match opcode {
+            0..=3 if space != SPACE_SNES && space != SPACE_FILE => {
+                return Err(failure(Code::Unimplemented, format!("SNI has no access to space {}", space)));
+            }
+            0 | 1 if space == SPACE_SNES => {
+                let address = be_u32(&packet[256..260]);
+                let size = match be_u32(&packet[252..256]) as usize {
+                    0 => block_len,
+                    size => size,
+                };
+                response[252..256].copy_from_slice(&(size as u32).to_be_bytes());
+                if opcode == 0 {
+                    data = self.read_memory(&[(address, size)])?;
+                } else {
+                    self.pending_put = Some(PendingPut::Memory { space, address, size, block_len });
+                }
+            }
+            0 => {
+                let request = self.path_message(path());
+                let file: proto::GetFileResponse = self.call(
+                    "/DeviceFilesystem/GetFile", request, self.timeout.max(FILE_TRANSFER_TIMEOUT)
+                )?;
+                response[252..256].copy_from_slice(&(file.data.len() as u32).to_be_bytes());
+                data = file.data;
+            }
+            1 => {
+                let size = be_u32(&packet[252..256]) as usize;
+                response[252..256].copy_from_slice(&(size as u32).to_be_bytes());
+                self.pending_put = Some(PendingPut::File { path: path(), size });
+            }
+            2 | 3 if space == SPACE_FILE => {
+                return Err(failure(Code::Unimplemented, "VGET/VPUT need a memory space"));
+            }
+            2 | 3 => {
+                let pairs = packet_pairs(packet);
+                let total: usize = pairs.iter().map(|&(size, _)| size as usize).sum();
+                response[252..256].copy_from_slice(&(total as u32).to_be_bytes());
+                if opcode == 2 {
+                    let ranges: Vec<(u32, usize)> = pairs.iter().map(|&(size, address)| (address, size as usize)).collect();
+                    data = self.read_memory(&ranges)?;
+                } else {
+                    self.pending_put = Some(PendingPut::Vector { space, pairs });
+                }
+            }
+            4 => {
+                let request = self.path_message(path());
+                let listing: proto::ReadDirectoryResponse = self.call("/DeviceFilesystem/ReadDirectory", request, self.timeout)?;
+                for entry in listing.entries {
+                    data.push(if entry.r#type == DIR_ENTRY_DIRECTORY { LS_TYPE_DIR } else { 0 });
+                    data.extend_from_slice(entry.name.as_bytes());
+                    data.push(0);
+                }
+                data.push(0xFF);
+                response[252..256].copy_from_slice(&(data.len() as u32).to_be_bytes());
+            }
+            // Only resetting the SNES is what ResetSystem does
+            9 | 12 if flags & ONLYRESET_FLAG != 0 => {
+                let request = proto::UriRequest { uri: self.device.clone() };
+                let _: proto::UriRequest = self.call("/DeviceControl/ResetSystem", request, self.timeout)?;
+            }
+            5 | 6 | 9 => {
+                let method = match opcode {
+                    5 => "/DeviceFilesystem/MakeDirectory",
+                    6 => "/DeviceFilesystem/RemoveFile",
+                    _ => "/DeviceFilesystem/BootFile",
+                };
+                let request = self.path_message(path());
+                let _: proto::PathMessage = self.call(method, request, self.timeout)?;
+            }
+            7 => {
+                let request = proto::RenameFileRequest {
+                    uri: self.device.clone(),
+                    path: path(),
+                    new_filename: packet_path(packet, 256, MAX_MV_DEST_PATH_LEN),
+                };
+                let _: proto::RenameFileRequest = self.call("/DeviceFilesystem/RenameFile", request, self.timeout)?;
+            }
+            8 | 12 => {
+                let method = if opcode == 8 { "/DeviceControl/ResetSystem" } else { "/DeviceControl/ResetToMenu" };
+                let request = proto::UriRequest { uri: self.device.clone() };
+                let _: proto::UriRequest = self.call(method, request, self.timeout)?;
+            }
+            11 => {
+                let request = proto::FieldsRequest {
+                    uri: self.device.clone(),
+                    fields: vec![FIELD_DEVICE_NAME, FIELD_DEVICE_VERSION, FIELD_ROM_FILE_NAME],
+                };
+                let fields: proto::FieldsResponse = self.call("/DeviceInfo/FetchFields", request, self.timeout)?;
+                let field = |wanted: i32| fields.fields.iter()
+                    .position(|&field| field == wanted)
+                    .and_then(|index| fields.values.get(index))
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                let firmware = match field(FIELD_DEVICE_VERSION) {
+                    "" => field(FIELD_DEVICE_NAME),
+                    version => version,
+                };
+                set_info_strings(&mut response, firmware, field(FIELD_ROM_FILE_NAME));
+            }
+            _ => return Err(failure(Code::Unimplemented, format!("SNI has no equivalent of opcode {}", opcode))),
+        }
+
+        if flags & NORESP_FLAG != 0 {
+            return Ok(());
+        }
+        self.replies.push(response, data, block_len);
+        Ok(())
+    }
+
+    /// Send a completed data phase to the device
+    fn write_pending(&mut self, pending: PendingPut, data: Vec<u8>) -> CallResult<()> {
+        match pending {
+            PendingPut::Memory { address, size, .. } => self.write_memory(vec![(address, data[..size].to_vec())]),
+            PendingPut::Vector { pairs, .. } => {
+                let mut offset = 0;
+                let ranges = pairs.iter().map(|&(size, address)| {
+                    let range = (address, data[offset..offset + size as usize].to_vec());
+                    offset += size as usize;
+                    range
+                }).collect();
+                self.write_memory(ranges)
+            }
+            PendingPut::File { path, size } => {
+                let request = proto::PutFileRequest { uri: self.device.clone(), path, data: data[..size].to_vec() };
+                let _: proto::PutFileResponse = self.call(
+                    "/DeviceFilesystem/PutFile", request, self.timeout.max(FILE_TRANSFER_TIMEOUT)
+                )?;
+                Ok(())
+            }
+        }
+    }
+
+    /// A failed SNI call as a core error
+    /// Unavailable means SNI went away or lost the device: a disconnect.
+    fn error(&self, status: &Status) -> CoreError {
+        match status.code() {
+            Code::Unimplemented => CoreError::new(ErrorCode::Unsupported, format!("Unsupported: {}", status.message())),
+            Code::DeadlineExceeded => CoreError::new(ErrorCode::Timeout, format!("SNI at {}: {}", self.uri, status.message())),
+            Code::Unavailable => CoreError::new(ErrorCode::NotConnected, format!(
+                "{}: SNI at {} is unavailable: {}", DEVICE_DISCONNECTED, self.uri, status.message()
+            )),
+            code => CoreError::new(ErrorCode::DeviceError, format!("SNI at {}: {:?}: {}", self.uri, code, status.message())),
+        }
+    }
+}
+
+impl Transport for SniTransport {
+    /// A call that timed out queues nothing, so read_packet() reports the timeout and
+    /// the core's retry policy applies as on hardware
+    fn write_packet(&mut self, packet: &[u8]) -> Result<()> {
+        self.mapping = None;
+        self.pending_put = None;
+        self.input.clear();
+        match self.handle_packet(packet) {
+            Ok(()) => Ok(()),
+            Err(status) if status.code() == Code::DeadlineExceeded => Ok(()),
+            Err(status) => Err(self.error(&status)),
+        }
+    }
+
+    fn read_packet(&mut self, buf: &mut [u8], deadlines: ReadDeadlines) -> Result<()> {
+        self.replies.take(buf, deadlines, true)
+    }
+
+    fn read_data(&mut self, block: &mut [u8], deadlines: ReadDeadlines) -> Result<()> {
+        self.replies.take(block, deadlines, false)
+    }
+
+    /// Data nobody asked for (the data phase of a refused PUT) is dropped
+    fn write_data(&mut self, data: &[u8]) -> Result<()> {
+        let Some(pending) = &self.pending_put else {
+            return Ok(());
+        };
+        self.input.extend_from_slice(data);
+        if self.input.len() < pending.wire_len() {
+            return Ok(());
+        }
+        let pending = self.pending_put.take().unwrap();
+        let data = std::mem::take(&mut self.input);
+        self.write_pending(pending, data).map_err(|status| self.error(&status))
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    /// Calls complete or fail as a whole, so only queued replies can be stale
+    fn drain_input(&mut self, quiet: Duration, _limit: Duration) -> Result<u32> {
+        self.timeout = quiet;
+        Ok(self.replies.clear())
+    }
+
+    /// No control lines; accepted and ignored like on the simulator
+    fn set_dtr(&mut self, _level: bool) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn set_rts(&mut self, _level: bool) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Connect to SNI as the core's transport (see is_sni_uri())
+pub(crate) fn open_sni(uri: &str) -> Result<Box<dyn Transport>> {
+    Ok(Box::new(SniTransport::connect(uri)?))
+}
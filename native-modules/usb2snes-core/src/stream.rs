@@ -0,0 +1,296 @@
+// STREAM (opcode 13): frames pushed by the device, delivered to a JS callback
+// start_stream() sends STREAM and hands the port to a background thread that reads
+// the frames the device sends after the RESPONSE - one data block each, 512 bytes or
+// 64 with block_size 64 - and passes every one to `on_frame(err, frame)`. The device
+// keeps streaming until it receives another command, so the stream owns the port while
+// it runs: other calls fail with DeviceBusy rather than queueing behind it.
+// stop_stream() ends it by sending INFO, throws away the frames still in flight up to
+// the INFO RESPONSE (which starts on a block boundary) and returns once the port is
+// usable again. Backpressure: at most max_queued_frames frames wait for the JS thread;
+// with that many pending the reader stops reading, which holds the device back through
+// USB flow control, or with drop_frames reads on and drops frames, reporting the count
+// in the next delivered frame's `dropped`.
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+use napi::threadsafe_function::{ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::{JsFunction, Status};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::queue::Lane;
+use crate::recording::unix_millis;
+use crate::resync::RESPONSE_HEADER;
+use crate::timeouts::{ReadDeadlines, TimeoutSource};
+use crate::{
+    apply_timeout_locked, build_packet, check_device_error, data_block_len, exchange, send_packet_locked, Connection,
+    Usb2SnesCore, DATA64B_FLAG, PACKET_SIZE, SPACE_FILE, SPACE_SNES, STREAM_BURST_FLAG,
+};
+
+/// Frames that may wait for the JS thread before backpressure applies (default)
+const DEFAULT_MAX_QUEUED_FRAMES: u32 = 16;
+
+/// Longest wait for a frame before the reader checks for stop_stream()
+const STREAM_IDLE_MS: u64 = 50;
+
+/// How long stop_stream() looks for the INFO RESPONSE among the last frames
+const STREAM_END_MS: u64 = 2000;
+
+/// Options for start_stream()
+#[napi(object)]
+#[derive(Default)]
+pub struct StreamOptions {
+    /// Frame size, 64 or 512 (default 512); 64 sets the DATA64B flag
+    pub block_size: Option<u32>,
+    /// Set the STREAM_BURST flag
+    pub burst: Option<bool>,
+    /// Frames that may wait for the callback before backpressure applies (default 16)
+    pub max_queued_frames: Option<u32>,
+    /// Drop frames instead of pausing the reader when the queue is full (default false)
+    pub drop_frames: Option<bool>,
+}
+
+/// Passed to start_stream()'s callback for each frame
+#[napi(object)]
+pub struct StreamFrame {
+    pub data: Buffer,
+    /// Number of the frame read from the device, from 1; dropped frames count too
+    pub sequence: u32,
+    /// Frames dropped since the previous delivered one (drop_frames only)
+    pub dropped: u32,
+    pub unix_ms: f64,
+}
+
+/// A frame read by the stream thread, turned into a StreamFrame on the JS thread
+struct Frame {
+    data: Vec<u8>,
+    sequence: u32,
+    dropped: u32,
+    unix_ms: u128,
+}
+
+/// The thread running a stream
+pub(crate) struct StreamEngine {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Result<()>>,
+}
+
+impl StreamEngine {
+    /// Whether the stream thread still owns the port
+    pub(crate) fn is_running(&self) -> bool {
+        !self.thread.is_finished()
+    }
+}
+
+/// Settings of a running stream, fixed by start_stream()
+struct StreamSettings {
+    flags: u8,
+    max_queued: u32,
+    drop_frames: bool,
+}
+
+/// Error of calls made while a stream owns the port
+pub(crate) fn stream_busy_error() -> CoreError {
+    CoreError::new(ErrorCode::DeviceBusy, "Busy: a stream owns the port, call stop_stream() first")
+}
+
+/// Read frames until `stop` (or a disconnect); `pending` counts frames not yet delivered
+fn read_frames_locked(
+    core: &Usb2SnesCore,
+    conn: &mut Connection,
+    settings: &StreamSettings,
+    callback: &ThreadsafeFunction<Frame, ErrorStrategy::CalleeHandled>,
+    pending: &AtomicU32,
+    stop: &AtomicBool,
+) -> Result<()> {
+    let block_len = data_block_len(settings.flags);
+    let idle = Duration::from_millis(STREAM_IDLE_MS);
+    conn.transport.set_timeout(idle)
+        .map_err(|e| CoreError::new(ErrorCode::IoError, format!("Failed to set timeout: {}", e)))?;
+    conn.port_timeout = idle;
+    // A frame may be long in coming; once it starts the usual progress deadline applies
+    let deadlines = ReadDeadlines { first_byte: (idle, TimeoutSource::PerCall), ..conn.read_deadlines };
+
+    let mut sequence = 0u32;
+    let mut dropped = 0u32;
+    while !stop.load(Ordering::SeqCst) && !core.closing.load(Ordering::SeqCst) {
+        if !settings.drop_frames && pending.load(Ordering::SeqCst) >= settings.max_queued {
+            std::thread::sleep(deadlines.poll);
+            continue;
+        }
+        let mut data = vec![0u8; block_len];
+        match conn.transport.read_packet(&mut data, deadlines) {
+            Ok(()) => {}
+            // No frame yet (the first-byte deadline); a frame cut short is an error
+            Err(e) if e.code == ErrorCode::Timeout && e.reason.starts_with("Read timeout - no response") => continue,
+            Err(e) => return Err(e),
+        }
+        sequence = sequence.wrapping_add(1);
+        if pending.load(Ordering::SeqCst) >= settings.max_queued {
+            dropped += 1;
+            continue;
+        }
+        pending.fetch_add(1, Ordering::SeqCst);
+        let frame = Frame { data, sequence, dropped, unix_ms: unix_millis() };
+        match callback.call(Ok(frame), ThreadsafeFunctionCallMode::NonBlocking) {
+            Status::Ok => dropped = 0,
+            // The callback was released (Node shutting down): nobody is listening
+            _ => return Ok(()),
+        }
+    }
+    Ok(())
+}
+
+/// Send INFO to end the stream and skip the frames before its RESPONSE
+fn end_stream_locked(conn: &mut Connection, flags: u8) -> Result<()> {
+    let block_len = data_block_len(flags);
+    let packet = build_packet(11, SPACE_FILE, 0, None)?;
+    // Blocks are read with the progress deadline, so INFO's short first-byte one doesn't apply
+    apply_timeout_locked(conn, &packet)?;
+    send_packet_locked(conn, &packet)?;
+
+    let deadline = Instant::now() + Duration::from_millis(STREAM_END_MS);
+    let mut block = vec![0u8; block_len];
+    let mut skipped = 0usize;
+    while Instant::now() < deadline {
+        conn.transport.read_data(&mut block, conn.read_deadlines)?;
+        if block.starts_with(RESPONSE_HEADER) {
+            let mut rest = vec![0u8; PACKET_SIZE - block_len];
+            conn.transport.read_data(&mut rest, conn.read_deadlines)?;
+            conn.cache.lock().unwrap().invalidate();
+            conn.diagnostics.lock().unwrap().record_warning(&format!(
+                "Stream ended; {} bytes of frames discarded", skipped
+            ));
+            return Ok(());
+        }
+        skipped += block_len;
+    }
+    Err(CoreError::new(ErrorCode::Timeout, format!(
+        "StreamNotStopped: no INFO RESPONSE within {}ms of stopping the stream ({} bytes of frames discarded); try resync() with a reset",
+        STREAM_END_MS, skipped
+    )))
+}
+
+impl Usb2SnesCore {
+    /// Whether a stream owns the port (see with_connection_in())
+    pub(crate) fn is_streaming(&self) -> bool {
+        self.stream.lock().unwrap().as_ref().is_some_and(StreamEngine::is_running)
+    }
+
+    /// Body of the stream thread; `started` gets the outcome of the STREAM command
+    fn stream_thread(
+        &self,
+        settings: StreamSettings,
+        callback: ThreadsafeFunction<Frame, ErrorStrategy::CalleeHandled>,
+        pending: &AtomicU32,
+        stop: &AtomicBool,
+        started: mpsc::Sender<Result<()>>,
+    ) -> Result<()> {
+        let mut reported = false;
+        let result = self.with_port_in(Lane::Bulk, |conn| {
+            let packet = build_packet(13, SPACE_SNES, settings.flags, None)?;
+            let begun = exchange(conn, &packet).and_then(|response| check_device_error(&response, "STREAM", "the SNES bus"));
+            if let Err(e) = begun {
+                let _ = started.send(Err(CoreError::new(e.code, e.reason.clone())));
+                return Err(e);
+            }
+            let _ = started.send(Ok(()));
+
+            if let Err(e) = read_frames_locked(self, conn, &settings, &callback, pending, stop) {
+                reported = true;
+                callback.call(Err(CoreError::new(e.code, e.reason.clone()).into()), ThreadsafeFunctionCallMode::NonBlocking);
+                return Err(e);
+            }
+            // disconnect() is closing the port anyway
+            if self.closing.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+            end_stream_locked(conn, settings.flags)
+        });
+        // A failure the callback was told about isn't stop_stream()'s
+        if reported { Ok(()) } else { result }
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Send STREAM and deliver the device's frames to `on_frame(err, frame)` on a
+    /// background thread until stop_stream() (see StreamOptions)
+    /// Throws if the device refuses STREAM. While the stream runs, other calls fail
+    /// with DeviceBusy; a read error ends the stream and is passed to the callback.
+    /// The thread keeps Node's event loop alive until stop_stream().
+    #[napi(ts_args_type = "onFrame: (err: Error | null, frame: StreamFrame) => void, options?: StreamOptions")]
+    pub fn start_stream(&self, on_frame: JsFunction, options: Option<StreamOptions>) -> Result<()> {
+        let options = options.unwrap_or_default();
+        let block_flags = match options.block_size {
+            None | Some(512) => 0,
+            Some(64) => DATA64B_FLAG,
+            Some(size) => {
+                return Err(CoreError::new(ErrorCode::ArgValidation,
+                    format!("Invalid block_size {}: must be 64 or 512", size)
+                ));
+            }
+        };
+        let burst_flag = if options.burst.unwrap_or(false) { STREAM_BURST_FLAG } else { 0 };
+        let settings = StreamSettings {
+            flags: block_flags | burst_flag,
+            max_queued: options.max_queued_frames.unwrap_or(DEFAULT_MAX_QUEUED_FRAMES).max(1),
+            drop_frames: options.drop_frames.unwrap_or(false),
+        };
+
+        let mut stream = self.stream.lock().unwrap();
+        if stream.as_ref().is_some_and(StreamEngine::is_running) {
+            return Err(CoreError::new(ErrorCode::DeviceBusy, "start_stream: a stream is already running"));
+        }
+
+        let pending = Arc::new(AtomicU32::new(0));
+        let delivered = pending.clone();
+        let callback: ThreadsafeFunction<Frame, ErrorStrategy::CalleeHandled> = on_frame
+            .create_threadsafe_function(0, move |ctx: ThreadSafeCallContext<Frame>| {
+                delivered.fetch_sub(1, Ordering::SeqCst);
+                let frame = ctx.value;
+                Ok(vec![StreamFrame {
+                    data: frame.data.into(),
+                    sequence: frame.sequence,
+                    dropped: frame.dropped,
+                    unix_ms: frame.unix_ms as f64,
+                }])
+            })
+            .map_err(CoreError::from)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (started, outcome) = mpsc::channel();
+        let core = self.clone();
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || core.stream_thread(settings, callback, &pending, &thread_stop, started));
+        let engine = StreamEngine { stop, thread };
+        // No outcome means the thread never got the port (not connected, closing)
+        match outcome.recv() {
+            Ok(Ok(())) => {
+                *stream = Some(engine);
+                Ok(())
+            }
+            Ok(Err(_)) | Err(_) => match engine.thread.join() {
+                Ok(result) => result,
+                Err(_) => Err(CoreError::new(ErrorCode::DeviceError, "start_stream: stream thread panicked")),
+            },
+        }
+    }
+
+    /// End the stream and wait until the port is usable again
+    /// Returns false if no stream was started; throws if the device didn't leave
+    /// streaming (see StreamNotStopped).
+    #[napi]
+    pub fn stop_stream(&self) -> Result<bool> {
+        let Some(engine) = self.stream.lock().unwrap().take() else {
+            return Ok(false);
+        };
+        engine.stop.store(true, Ordering::SeqCst);
+        engine.thread.join()
+            .map_err(|_| CoreError::new(ErrorCode::DeviceError, "stop_stream: stream thread panicked"))??;
+        Ok(true)
+    }
+}
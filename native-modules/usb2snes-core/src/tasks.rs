@@ -0,0 +1,188 @@
+// Promise-returning variants of the blocking transfer methods
+// Every other method runs its serial I/O on the calling thread, which for Electron is
+// the main process event loop; a large GET/PUT freezes it for the whole transfer. The
+// *_async methods run the same code on the libuv thread pool instead. They need no
+// queue of their own: each job waits its turn at the port like any other call (see
+// queue.rs), so async and sync commands never interleave on the wire, and a disconnect
+// rejects waiting jobs with "Cancelled: ...". Memory reads overtake queued file
+// transfers; within a lane jobs run in the order the thread pool starts them, which
+// for jobs started together isn't fixed, so await them in turn when order matters.
+
+use napi_derive::napi;
+use napi::bindgen_prelude::{AsyncTask, Buffer, Either, JsError, ToNapiValue, TypeName};
+use napi::{Env, Error as NapiError, Task};
+use std::marker::PhantomData;
+
+use crate::cancel::CancelToken;
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::protocol::{self, CommandRequest};
+use crate::queue::Lane;
+use crate::{download_file_locked, get_file_locked, normalize_path, MemoryRead, Usb2SnesCore, DATA64B_FLAG};
+
+type Job<T> = Box<dyn FnOnce(&Usb2SnesCore) -> Result<T> + Send>;
+
+/// One call run on the thread pool against a clone of the core (sharing its port)
+/// `T` is what the job produces off the JS thread, `J` what the Promise resolves to
+pub struct CoreTask<T, J> {
+    core: Usb2SnesCore,
+    job: Option<Job<T>>,
+    /// The job's error, kept so the rejection carries its ErrorCode
+    error: Option<CoreError>,
+    resolved: PhantomData<fn() -> J>,
+}
+
+impl<T, J> CoreTask<T, J> {
+    fn spawn(core: &Usb2SnesCore, job: impl FnOnce(&Usb2SnesCore) -> Result<T> + Send + 'static) -> AsyncTask<Self>
+    where
+        Self: Task,
+    {
+        AsyncTask::new(CoreTask { core: core.clone(), job: Some(Box::new(job)), error: None, resolved: PhantomData })
+    }
+}
+
+impl<T, J> Task for CoreTask<T, J>
+where
+    T: Into<J> + Send + 'static,
+    J: ToNapiValue + TypeName,
+{
+    type Output = T;
+    type JsValue = J;
+
+    fn compute(&mut self) -> napi::Result<T> {
+        let job = self.job.take().expect("CoreTask is computed once");
+        job(&self.core).map_err(|error| {
+            let plain = NapiError::from_reason(error.reason.clone());
+            self.error = Some(error);
+            plain
+        })
+    }
+
+    fn resolve(&mut self, _env: Env, output: T) -> napi::Result<J> {
+        Ok(output.into())
+    }
+
+    fn reject(&mut self, env: Env, error: NapiError) -> napi::Result<J> {
+        match self.error.take() {
+            Some(error) => Err(NapiError::from(JsError::<ErrorCode>::from(error).into_unknown(env))),
+            None => Err(error),
+        }
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// send_command() on the thread pool
+    #[napi(ts_return_type = "Promise<Array<number>>")]
+    pub fn send_command_async(&self, command: CommandRequest) -> AsyncTask<CoreTask<Vec<u8>, Vec<u8>>> {
+        CoreTask::spawn(self, move |core| core.send_command(command))
+    }
+
+    /// get_memory() on the thread pool; `cancel` stops it (see cancel.rs)
+    #[napi(ts_return_type = "Promise<Buffer>")]
+    pub fn get_memory_async(&self, read: MemoryRead, cancel: Option<&CancelToken>) -> AsyncTask<CoreTask<Vec<u8>, Buffer>> {
+        let cancel = cancel.cloned();
+        CoreTask::spawn(self, move |core| {
+            let flags = protocol::method_flags("get_memory_async", read.flags, DATA64B_FLAG)?;
+            core.get_memory_with(read.address, read.size, read.space, flags, cancel.as_ref())
+        })
+    }
+
+    /// put_memory() on the thread pool
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn put_memory_async(
+        &self,
+        address: u32,
+        data: Buffer,
+        space: Option<u8>,
+        flags: Option<u32>,
+    ) -> AsyncTask<CoreTask<(), ()>> {
+        CoreTask::spawn(self, move |core| core.put_memory(address, data, space, flags))
+    }
+
+    /// Download a whole file on the thread pool (get_file() without options)
+    /// `cancel` stops it (see cancel.rs)
+    #[napi(ts_return_type = "Promise<Buffer>")]
+    pub fn get_file_async(&self, path: String, cancel: Option<&CancelToken>) -> AsyncTask<CoreTask<Vec<u8>, Buffer>> {
+        let cancel = cancel.cloned();
+        CoreTask::spawn(self, move |core| {
+            let path = normalize_path(&path)?;
+            let Some(cancel) = cancel else {
+                return core.with_connection_in(Lane::Bulk, |conn| get_file_locked(conn, &path));
+            };
+            let what = format!("download of {}", path);
+            cancel.check(&what)?;
+            let mut data = Vec::new();
+            core.with_connection_in(Lane::Bulk, |conn| download_file_locked(conn, &path, |block| {
+                cancel.check(&what)?;
+                data.extend_from_slice(block);
+                Ok(())
+            }))?;
+            Ok(data)
+        })
+    }
+
+    /// put_file() on the thread pool; `cancel` stops it and removes the partial file
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn put_file_async(&self, path: String, data: Buffer, cancel: Option<&CancelToken>) -> AsyncTask<CoreTask<(), ()>> {
+        let cancel = cancel.cloned();
+        CoreTask::spawn(self, move |core| core.put_file_with(path, &data, cancel.as_ref()))
+    }
+
+    /// download_to() on the thread pool; resolves to the byte count
+    /// `cancel` stops it; as on any failure, an existing host file is left untouched
+    #[napi(ts_return_type = "Promise<number>")]
+    pub fn download_to_async(&self, device_path: String, host_path: String, cancel: Option<&CancelToken>) -> AsyncTask<CoreTask<u32, u32>> {
+        let cancel = cancel.cloned();
+        CoreTask::spawn(self, move |core| match cancel {
+            Some(cancel) => {
+                let device_path = normalize_path(&device_path)?;
+                let what = format!("download of {}", device_path);
+                cancel.check(&what)?;
+                core.download_host_file(&device_path, &host_path, |_, _| cancel.check(&what))
+            }
+            None => core.download_to(device_path, host_path, None).map(|size| match size {
+                Either::A(size) => size,
+                Either::B(transfer) => transfer.size,
+            }),
+        })
+    }
+
+    /// upload_from() on the thread pool; resolves to the byte count
+    /// `cancel` stops it and removes the partial device file
+    #[napi(ts_return_type = "Promise<number>")]
+    pub fn upload_from_async(&self, host_path: String, device_path: String, cancel: Option<&CancelToken>) -> AsyncTask<CoreTask<u32, u32>> {
+        let cancel = cancel.cloned();
+        CoreTask::spawn(self, move |core| match cancel {
+            Some(cancel) => {
+                let device_path = normalize_path(&device_path)?;
+                cancel.check(&format!("upload of {}", host_path))?;
+                core.upload_from_cancellable(&host_path, &device_path, &cancel, |_, _| Ok(()))
+            }
+            None => core.upload_from(host_path, device_path),
+        })
+    }
+
+    /// upload_file() on the thread pool, without progress; resolves to the byte count
+    #[napi(ts_args_type = "source: string | Buffer, remotePath: string, cancel?: CancelToken", ts_return_type = "Promise<number>")]
+    pub fn upload_file_async(
+        &self,
+        source: Either<String, Buffer>,
+        remote_path: String,
+        cancel: Option<&CancelToken>,
+    ) -> AsyncTask<CoreTask<u32, u32>> {
+        let cancel = cancel.cloned();
+        CoreTask::spawn(self, move |core| core.upload_file_with(source, &remote_path, cancel.as_ref(), |_, _| Ok(())))
+    }
+
+    /// download_file() on the thread pool, without progress
+    #[napi(ts_return_type = "Promise<Buffer | number>")]
+    pub fn download_file_async(
+        &self,
+        remote_path: String,
+        host_path: Option<String>,
+        cancel: Option<&CancelToken>,
+    ) -> AsyncTask<CoreTask<Either<Buffer, u32>, Either<Buffer, u32>>> {
+        let cancel = cancel.cloned();
+        CoreTask::spawn(self, move |core| core.download_file_with(&remote_path, host_path, cancel.as_ref(), |_, _| Ok(())))
+    }
+}
@@ -0,0 +1,324 @@
+// Per-opcode-class read timeouts
+// INFO and small reads should fail fast while BOOT and file transfers legitimately
+// take seconds. Precedence: per-call override > connect options > table default.
+// The class timeout is a first-byte deadline: how long the device may take to start
+// its RESPONSE. Once bytes flow, only the progress deadline applies (the longest gap
+// with no new bytes), so a long transfer that keeps moving is never cut off.
+// The retry policy lives alongside: read-only commands whose RESPONSE times out can
+// be resent after draining the line, and a minimum gap can be kept between command
+// packets for bridges that drop back-to-back writes. set_timeouts() swaps both on a
+// live connection, e.g. short deadlines while polling, long ones for a transfer.
+
+use napi_derive::napi;
+use std::fmt;
+use std::time::Duration;
+
+use crate::errors::Result;
+use crate::{Usb2SnesCore, NORESP_FLAG, SPACE_FILE};
+
+/// Opcode classes that share a timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OpcodeClass {
+    /// INFO, MKDIR, RM, MV, RESET, MENU_RESET, POWER_CYCLE, TIME, STREAM
+    Control,
+    /// Memory GET/VGET and LS
+    SmallRead,
+    /// FILE-space GET (the device opens the file before replying)
+    BulkRead,
+    /// PUT/VPUT
+    BulkWrite,
+    Boot,
+}
+
+impl OpcodeClass {
+    pub(crate) fn of_packet(packet: &[u8]) -> Self {
+        match (packet[4], packet[5]) {
+            (0, SPACE_FILE) => OpcodeClass::BulkRead,
+            (0, _) | (2, _) | (4, _) => OpcodeClass::SmallRead,
+            (1, _) | (3, _) => OpcodeClass::BulkWrite,
+            (9, _) => OpcodeClass::Boot,
+            _ => OpcodeClass::Control,
+        }
+    }
+}
+
+/// Timeout overrides for connect_with_options() and set_timeouts(), in milliseconds
+/// The per-class values are the longest wait for the first byte of the RESPONSE
+#[napi(object)]
+#[derive(Clone, Default)]
+pub struct TimeoutOptions {
+    /// Every class not given below (default: each class's own default)
+    pub default_ms: Option<u32>,
+    /// INFO, MKDIR, RM, MV, RESET, ... (default 1000)
+    pub control_ms: Option<u32>,
+    /// Memory GET/VGET and LS (default 1000)
+    pub small_read_ms: Option<u32>,
+    /// FILE-space GET (default 5000)
+    pub bulk_read_ms: Option<u32>,
+    /// PUT/VPUT (default 5000)
+    pub bulk_write_ms: Option<u32>,
+    /// BOOT (default 10000)
+    pub boot_ms: Option<u32>,
+    /// Longest gap with no new bytes once a RESPONSE has started, data phase
+    /// included, for every class (default 2000)
+    pub progress_ms: Option<u32>,
+    /// Sleep between port reads while no data is available (default 10)
+    pub poll_ms: Option<u32>,
+}
+
+/// Default of TimeoutOptions.progress_ms
+const DEFAULT_PROGRESS_MS: u64 = 2000;
+
+/// Default of TimeoutOptions.poll_ms
+const DEFAULT_POLL_MS: u64 = 10;
+
+/// Retry policy for connect_with_options() and set_timeouts()
+#[napi(object)]
+#[derive(Clone, Default)]
+pub struct RetryOptions {
+    /// Resend INFO, GET, VGET and LS this many times when their RESPONSE times out
+    /// (default 0); the line is drained before each attempt
+    pub retries: Option<u32>,
+    /// Wait before retry n is n times this long (default 50)
+    pub retry_backoff_ms: Option<u32>,
+    /// Minimum time between two command packets (default 0)
+    pub inter_command_delay_ms: Option<u32>,
+}
+
+/// Default of RetryOptions.retry_backoff_ms
+const DEFAULT_RETRY_BACKOFF_MS: u64 = 50;
+
+/// Where an effective timeout came from, for error messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TimeoutSource {
+    /// The timeout the port was opened with, before any command picked one
+    PortDefault,
+    TableDefault(OpcodeClass),
+    ConnectOption(OpcodeClass),
+    PerCall,
+    ProgressDefault,
+    ProgressOption,
+}
+
+impl fmt::Display for TimeoutSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeoutSource::PortDefault => write!(f, "port default"),
+            TimeoutSource::TableDefault(class) => write!(f, "{:?} table default", class),
+            TimeoutSource::ConnectOption(class) => write!(f, "{:?} option", class),
+            TimeoutSource::PerCall => write!(f, "per-call override"),
+            TimeoutSource::ProgressDefault => write!(f, "progress default"),
+            TimeoutSource::ProgressOption => write!(f, "progress option"),
+        }
+    }
+}
+
+/// Deadlines of the command in progress (see SerialTransport::read_block())
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ReadDeadlines {
+    /// Longest wait for the first byte of the RESPONSE
+    pub(crate) first_byte: (Duration, TimeoutSource),
+    /// Longest gap with no new bytes after that, data phase included
+    pub(crate) progress: (Duration, TimeoutSource),
+    /// Sleep between reads that returned no data
+    pub(crate) poll: Duration,
+}
+
+/// Whether `packet` changes nothing and gets a RESPONSE, so it is safe to resend
+pub(crate) fn is_read_only(packet: &[u8]) -> bool {
+    matches!(packet[4], 0 | 2 | 4 | 11) && packet[6] & NORESP_FLAG == 0
+}
+
+/// Resolved timeouts and retry policy for a connection
+pub(crate) struct TimeoutTable {
+    options: TimeoutOptions,
+    retry: RetryOptions,
+}
+
+impl TimeoutTable {
+    pub(crate) fn new(options: TimeoutOptions, retry: RetryOptions) -> Self {
+        Self { options, retry }
+    }
+
+    /// One-line summary of the effective timeouts, for diagnostics
+    pub(crate) fn describe(&self) -> String {
+        [OpcodeClass::Control, OpcodeClass::SmallRead, OpcodeClass::BulkRead, OpcodeClass::BulkWrite, OpcodeClass::Boot]
+            .iter()
+            .map(|&class| {
+                let (timeout, source) = self.lookup(class);
+                let origin = if matches!(source, TimeoutSource::ConnectOption(_)) { " (option)" } else { "" };
+                format!("{:?} {}ms{}", class, timeout.as_millis(), origin)
+            })
+            .chain(std::iter::once({
+                let (timeout, source) = self.progress();
+                let origin = if source == TimeoutSource::ProgressOption { " (option)" } else { "" };
+                format!("progress {}ms{}", timeout.as_millis(), origin)
+            }))
+            .chain(std::iter::once(format!(
+                "poll {}ms, {} retries (backoff {}ms), inter-command delay {}ms",
+                self.poll().as_millis(),
+                self.retries(),
+                self.retry_backoff().as_millis(),
+                self.inter_command_delay().as_millis()
+            )))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub(crate) fn poll(&self) -> Duration {
+        Duration::from_millis(self.options.poll_ms.map_or(DEFAULT_POLL_MS, u64::from))
+    }
+
+    /// Retries allowed for `packet`: only read-only commands are resent
+    pub(crate) fn retries_for(&self, packet: &[u8]) -> u32 {
+        if is_read_only(packet) { self.retries() } else { 0 }
+    }
+
+    fn retries(&self) -> u32 {
+        self.retry.retries.unwrap_or(0)
+    }
+
+    pub(crate) fn retry_backoff(&self) -> Duration {
+        Duration::from_millis(self.retry.retry_backoff_ms.map_or(DEFAULT_RETRY_BACKOFF_MS, u64::from))
+    }
+
+    pub(crate) fn inter_command_delay(&self) -> Duration {
+        Duration::from_millis(self.retry.inter_command_delay_ms.map_or(0, u64::from))
+    }
+
+    /// The progress deadline, shared by every class
+    pub(crate) fn progress(&self) -> (Duration, TimeoutSource) {
+        match self.options.progress_ms {
+            Some(ms) => (Duration::from_millis(ms as u64), TimeoutSource::ProgressOption),
+            None => (Duration::from_millis(DEFAULT_PROGRESS_MS), TimeoutSource::ProgressDefault),
+        }
+    }
+
+    pub(crate) fn lookup(&self, class: OpcodeClass) -> (Duration, TimeoutSource) {
+        let (configured, default_ms) = match class {
+            OpcodeClass::Control => (self.options.control_ms, 1000),
+            OpcodeClass::SmallRead => (self.options.small_read_ms, 1000),
+            OpcodeClass::BulkRead => (self.options.bulk_read_ms, 5000),
+            OpcodeClass::BulkWrite => (self.options.bulk_write_ms, 5000),
+            OpcodeClass::Boot => (self.options.boot_ms, 10000),
+        };
+        match configured.or(self.options.default_ms) {
+            Some(ms) => (Duration::from_millis(ms as u64), TimeoutSource::ConnectOption(class)),
+            None => (Duration::from_millis(default_ms), TimeoutSource::TableDefault(class)),
+        }
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Replace the connection's timeouts and retry policy, from the next command on
+    /// Unset fields fall back to the defaults, not to the previous values; a new
+    /// connection starts again from its connect options
+    #[napi]
+    pub fn set_timeouts(&self, timeouts: TimeoutOptions, retry: Option<RetryOptions>) -> Result<()> {
+        self.with_connection(|conn| {
+            conn.timeouts = TimeoutTable::new(timeouts, retry.unwrap_or_default());
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apply_timeout_locked, build_packet, with_timeout_locked, ConnectOptions, SPACE_SNES};
+
+    /// First-byte deadline a memory GET gets on a simulated connection made with
+    /// `options`, optionally inside a per-call override of `per_call_ms`
+    fn get_deadline(options: TimeoutOptions, per_call_ms: Option<u64>) -> (Duration, TimeoutSource) {
+        let core = Usb2SnesCore::new();
+        let connect = ConnectOptions { timeouts: Some(options), ..Default::default() };
+        core.connect_simulated(None, Some(connect)).unwrap();
+        let packet = build_packet(0, SPACE_SNES, 0, Some(vec!["F50000".into(), "10".into()])).unwrap();
+        core.with_connection(|conn| {
+            let apply = |conn: &mut crate::Connection| {
+                apply_timeout_locked(conn, &packet)?;
+                Ok(conn.read_deadlines.first_byte)
+            };
+            match per_call_ms {
+                Some(ms) => with_timeout_locked(conn, Duration::from_millis(ms), apply),
+                None => apply(conn),
+            }
+        }).unwrap()
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn table_default_without_options() {
+        let deadline = get_deadline(TimeoutOptions::default(), None);
+        assert_eq!(deadline, (ms(1000), TimeoutSource::TableDefault(OpcodeClass::SmallRead)));
+    }
+
+    #[test]
+    fn connect_default_beats_table_default() {
+        let options = TimeoutOptions { default_ms: Some(300), ..Default::default() };
+        let deadline = get_deadline(options, None);
+        assert_eq!(deadline, (ms(300), TimeoutSource::ConnectOption(OpcodeClass::SmallRead)));
+    }
+
+    #[test]
+    fn class_option_beats_connect_default() {
+        let options = TimeoutOptions { default_ms: Some(300), small_read_ms: Some(200), ..Default::default() };
+        let deadline = get_deadline(options, None);
+        assert_eq!(deadline, (ms(200), TimeoutSource::ConnectOption(OpcodeClass::SmallRead)));
+    }
+
+    #[test]
+    fn per_call_timeout_beats_every_option() {
+        let options = TimeoutOptions { default_ms: Some(300), small_read_ms: Some(200), ..Default::default() };
+        let deadline = get_deadline(options, Some(50));
+        assert_eq!(deadline, (ms(50), TimeoutSource::PerCall));
+    }
+
+    #[test]
+    fn class_options_only_apply_to_their_class() {
+        let table = TimeoutTable::new(
+            TimeoutOptions { boot_ms: Some(30000), default_ms: Some(700), ..Default::default() },
+            RetryOptions::default(),
+        );
+        assert_eq!(table.lookup(OpcodeClass::Boot), (ms(30000), TimeoutSource::ConnectOption(OpcodeClass::Boot)));
+        assert_eq!(table.lookup(OpcodeClass::BulkRead), (ms(700), TimeoutSource::ConnectOption(OpcodeClass::BulkRead)));
+
+        let defaults = TimeoutTable::new(TimeoutOptions::default(), RetryOptions::default());
+        let cases = [
+            (OpcodeClass::Control, 1000),
+            (OpcodeClass::SmallRead, 1000),
+            (OpcodeClass::BulkRead, 5000),
+            (OpcodeClass::BulkWrite, 5000),
+            (OpcodeClass::Boot, 10000),
+        ];
+        for (class, default_ms) in cases {
+            assert_eq!(defaults.lookup(class), (ms(default_ms), TimeoutSource::TableDefault(class)));
+        }
+    }
+
+    #[test]
+    fn opcode_classes() {
+        let cases = [
+            (0, SPACE_FILE, OpcodeClass::BulkRead),
+            (0, SPACE_SNES, OpcodeClass::SmallRead),
+            (1, SPACE_FILE, OpcodeClass::BulkWrite),
+            (1, SPACE_SNES, OpcodeClass::BulkWrite),
+            (2, SPACE_SNES, OpcodeClass::SmallRead),
+            (3, SPACE_SNES, OpcodeClass::BulkWrite),
+            (4, SPACE_FILE, OpcodeClass::SmallRead),
+            (9, SPACE_FILE, OpcodeClass::Boot),
+            (11, SPACE_FILE, OpcodeClass::Control),
+            (8, SPACE_SNES, OpcodeClass::Control),
+        ];
+        for (opcode, space, class) in cases {
+            let mut packet = [0u8; 8];
+            packet[4] = opcode;
+            packet[5] = space;
+            assert_eq!(OpcodeClass::of_packet(&packet), class, "opcode {} space {}", opcode, space);
+        }
+    }
+}
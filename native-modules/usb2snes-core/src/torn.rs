@@ -0,0 +1,132 @@
+// Torn-read mitigation: only accept samples read within one game frame
+// A multi-byte value the game updates while a read is in flight comes back half old
+// and half new (a 16-bit timer whose low byte has wrapped but whose high byte hasn't).
+// With `torn_read` set, read_multiple() and the watch thread resample until they get a
+// consistent sample, all under one port hold so nothing runs between the paired reads.
+// DoubleRead reads the ranges again right away and accepts when both reads match;
+// FrameCounter reads a game-provided frame counter before and after the ranges, in the
+// same VGET batch, and accepts when it didn't change. After max_retries failed
+// attempts the last sample is returned with `stable: false` (watches drop it).
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::pipeline::ReadRequest;
+use crate::Connection;
+
+/// Default resamples after the first attempt
+const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// How a torn read was detected
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum TornReadStrategy {
+    /// Read twice, accept when both reads match
+    DoubleRead,
+    /// Read the game's frame counter before and after, accept when it didn't change
+    FrameCounter,
+}
+
+/// Torn-read mitigation for read_multiple() and start_watches()
+#[napi(object)]
+pub struct TornReadOptions {
+    /// The game's frame counter; switches the strategy to FrameCounter (default DoubleRead)
+    pub frame_counter: Option<ReadRequest>,
+    /// Resamples after the first attempt before giving up (default 3)
+    pub max_retries: Option<u32>,
+}
+
+/// read_multiple()'s result with `torn_read`
+#[napi(object)]
+pub struct StableReads {
+    pub data: Vec<Buffer>,
+    pub strategy: TornReadStrategy,
+    /// Resamples it took; 0 when the first attempt was consistent
+    pub retries: u32,
+    /// False if the sample was still inconsistent after max_retries
+    pub stable: bool,
+}
+
+/// TornReadOptions with the frame counter resolved
+#[derive(Clone, Copy)]
+pub(crate) struct TornReadSettings {
+    /// (space, address, size) of the frame counter
+    frame_counter: Option<(u8, u32, u32)>,
+    max_retries: u32,
+}
+
+/// How one mitigated read went
+#[derive(Clone, Copy)]
+pub(crate) struct TornReadOutcome {
+    pub(crate) frame_counter: bool,
+    pub(crate) retries: u32,
+    pub(crate) stable: bool,
+}
+
+impl TornReadSettings {
+    pub(crate) fn new(options: TornReadOptions) -> Result<Self> {
+        let frame_counter = match options.frame_counter {
+            Some(ReadRequest { size: 0, .. }) => {
+                return Err(CoreError::new(ErrorCode::ArgValidation, "torn_read: frame_counter size must be non-zero"));
+            }
+            Some(counter) => {
+                let (space, address) = counter.region.resolve(counter.offset, counter.size)?;
+                Some((space, address, counter.size))
+            }
+            None => None,
+        };
+        Ok(Self { frame_counter, max_retries: options.max_retries.unwrap_or(DEFAULT_MAX_RETRIES) })
+    }
+}
+
+impl TornReadOutcome {
+    pub(crate) fn strategy(&self) -> TornReadStrategy {
+        if self.frame_counter { TornReadStrategy::FrameCounter } else { TornReadStrategy::DoubleRead }
+    }
+}
+
+/// Read `ranges` of `space` with `read` until a sample is consistent (see torn.rs)
+/// `read` returns one Vec per range it is given; every attempt runs on the one port hold.
+pub(crate) fn read_stable_locked(
+    conn: &mut Connection,
+    space: u8,
+    ranges: &[(u32, u32)],
+    settings: TornReadSettings,
+    mut read: impl FnMut(&mut Connection, &[(u32, u32)]) -> Result<Vec<Vec<u8>>>,
+) -> Result<(Vec<Vec<u8>>, TornReadOutcome)> {
+    let mut outcome = TornReadOutcome { frame_counter: settings.frame_counter.is_some(), retries: 0, stable: false };
+
+    if let Some((counter_space, address, size)) = settings.frame_counter {
+        if counter_space != space {
+            return Err(CoreError::new(ErrorCode::ArgValidation, format!(
+                "torn_read: the frame counter is in space {} but the reads are in space {}", counter_space, space
+            )));
+        }
+        let mut spans = Vec::with_capacity(ranges.len() + 2);
+        spans.push((address, size));
+        spans.extend_from_slice(ranges);
+        spans.push((address, size));
+        loop {
+            let mut sample = read(conn, &spans)?;
+            let after = sample.pop().unwrap_or_default();
+            let before = sample.remove(0);
+            outcome.stable = before == after;
+            if outcome.stable || outcome.retries == settings.max_retries {
+                return Ok((sample, outcome));
+            }
+            outcome.retries += 1;
+        }
+    }
+
+    let mut sample = read(conn, ranges)?;
+    loop {
+        let again = read(conn, ranges)?;
+        outcome.stable = again == sample;
+        if outcome.stable || outcome.retries == settings.max_retries {
+            return Ok((again, outcome));
+        }
+        outcome.retries += 1;
+        sample = again;
+    }
+}
@@ -0,0 +1,186 @@
+// Whole-file SD card transfers with progress reporting
+// upload_file() takes a host path or a Buffer, and download_file() returns a Buffer or
+// writes to a host path, so callers don't need to pick between put_file()/upload_from()
+// and get_file()/download_to(). Progress is reported every 64KB and once at the end,
+// with the average rate so far. Callbacks run while the port is held, so they must not
+// call back into this core. A CancelToken stops either at the next 512-byte block (see
+// cancel.rs).
+
+use napi_derive::napi;
+use napi::bindgen_prelude::{Buffer, Either};
+use napi::{JsFunction, JsUnknown};
+use crate::errors::{CoreError, Result};
+use std::time::Instant;
+
+use crate::cancel::{put_file_cancellable_locked, CancelToken};
+use crate::journal::JournalOp;
+use crate::queue::Lane;
+use crate::{download_file_sized_locked, normalize_path, upload_file_locked, Usb2SnesCore};
+
+/// Bytes between progress events
+const PROGRESS_INTERVAL: u32 = 64 * 1024;
+
+/// Progress event passed to upload_file()'s and download_file()'s `on_progress`
+#[napi(object)]
+pub struct TransferProgress {
+    pub bytes_done: u32,
+    pub total: u32,
+    /// Average rate since the transfer started
+    pub bytes_per_sec: f64,
+}
+
+/// Calls a progress callback every PROGRESS_INTERVAL bytes and at completion
+pub(crate) struct ProgressReporter<'a> {
+    callback: Option<&'a JsFunction>,
+    started: Instant,
+    reported: Option<u32>,
+}
+
+impl<'a> ProgressReporter<'a> {
+    pub(crate) fn new(callback: Option<&'a JsFunction>) -> Self {
+        ProgressReporter { callback, started: Instant::now(), reported: None }
+    }
+
+    pub(crate) fn report(&mut self, done: u32, total: u32) -> Result<()> {
+        let due = match self.reported {
+            Some(reported) => done - reported >= PROGRESS_INTERVAL || (done == total && reported != total),
+            None => done >= PROGRESS_INTERVAL || done == total,
+        };
+        let Some(callback) = self.callback.filter(|_| due) else {
+            return Ok(());
+        };
+        self.reported = Some(done);
+        let elapsed = self.started.elapsed().as_secs_f64();
+        callback.call1::<TransferProgress, JsUnknown>(TransferProgress {
+            bytes_done: done,
+            total,
+            bytes_per_sec: if elapsed > 0.0 { done as f64 / elapsed } else { 0.0 },
+        }).map(|_| ()).map_err(CoreError::from)
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Upload a host file (by path) or a Buffer to the SD card (PUT, FILE space)
+    /// The data goes out in 512-byte blocks, streamed from disk for a host path.
+    /// `on_progress` receives a TransferProgress every 64KB and at the end. `cancel`
+    /// stops the upload at the next block and removes the partial file. Returns the
+    /// byte count; host filesystem failures are "HostIoError: ...".
+    #[napi(ts_args_type = "source: string | Buffer, remotePath: string, onProgress?: (progress: TransferProgress) => void, cancel?: CancelToken")]
+    pub fn upload_file(
+        &self,
+        source: Either<String, Buffer>,
+        remote_path: String,
+        on_progress: Option<JsFunction>,
+        cancel: Option<&CancelToken>,
+    ) -> Result<u32> {
+        let mut progress = ProgressReporter::new(on_progress.as_ref());
+        let size = self.upload_file_with(source, &remote_path, cancel, |sent, total| progress.report(sent, total))?;
+        // An empty file has no data blocks to report progress on
+        if size == 0 {
+            progress.report(0, 0)?;
+        }
+        Ok(size)
+    }
+
+    /// Download a file from the SD card (GET, FILE space), into a Buffer or to `host_path`
+    /// Returns the Buffer, or the byte count when writing to `host_path` (streamed to
+    /// disk block by block; a failed download leaves any existing file untouched). `on_progress`
+    /// receives a TransferProgress every 64KB and at the end. `cancel` stops the
+    /// download at the next block.
+    #[napi(ts_return_type = "Buffer | number")]
+    pub fn download_file(
+        &self,
+        remote_path: String,
+        host_path: Option<String>,
+        on_progress: Option<JsFunction>,
+        cancel: Option<&CancelToken>,
+    ) -> Result<Either<Buffer, u32>> {
+        let mut progress = ProgressReporter::new(on_progress.as_ref());
+        let result = self.download_file_with(&remote_path, host_path, cancel, |done, total| progress.report(done, total))?;
+        let size = match &result {
+            Either::A(data) => data.len() as u32,
+            Either::B(size) => *size,
+        };
+        if size == 0 {
+            progress.report(0, 0)?;
+        }
+        Ok(result)
+    }
+}
+
+impl Usb2SnesCore {
+    /// upload_file() with `on_block(sent, total)` called after each block
+    pub(crate) fn upload_file_with(
+        &self,
+        source: Either<String, Buffer>,
+        remote_path: &str,
+        cancel: Option<&CancelToken>,
+        mut on_block: impl FnMut(u32, u32) -> Result<()>,
+    ) -> Result<u32> {
+        let path = normalize_path(remote_path)?;
+        if let Some(cancel) = cancel {
+            cancel.check(&format!("upload to {}", path))?;
+        }
+
+        match source {
+            Either::A(host_path) => match cancel {
+                Some(cancel) => self.upload_from_cancellable(&host_path, &path, cancel, on_block),
+                None => self.upload_host_file(&host_path, &path, false, on_block),
+            },
+            Either::B(data) => {
+                let size = data.len() as u32;
+                self.with_connection_in(Lane::Bulk, |conn| match cancel {
+                    Some(cancel) => put_file_cancellable_locked(conn, &path, &data, cancel, |sent| on_block(sent, size)),
+                    None => {
+                        let mut sent = 0usize;
+                        upload_file_locked(conn, &path, size, |block| {
+                            block.copy_from_slice(&data[sent..sent + block.len()]);
+                            sent += block.len();
+                            on_block(sent as u32, size)
+                        })
+                    }
+                })?;
+                self.journal.lock().unwrap().record(JournalOp::Put, &path, None, Some(size), None);
+                Ok(size)
+            }
+        }
+    }
+
+    /// download_file() with `on_block(received, total)` called after each block
+    pub(crate) fn download_file_with(
+        &self,
+        remote_path: &str,
+        host_path: Option<String>,
+        cancel: Option<&CancelToken>,
+        mut on_block: impl FnMut(u32, u32) -> Result<()>,
+    ) -> Result<Either<Buffer, u32>> {
+        let path = normalize_path(remote_path)?;
+        let what = format!("download of {}", path);
+        let check = || cancel.map_or(Ok(()), |cancel| cancel.check(&what));
+        check()?;
+
+        match host_path {
+            Some(host_path) => {
+                let mut received = 0u32;
+                let size = self.download_host_file(&path, &host_path, |block, total| {
+                    check()?;
+                    received += block.len() as u32;
+                    on_block(received, total)
+                })?;
+                Ok(Either::B(size))
+            }
+            None => {
+                let mut data = Vec::new();
+                self.with_connection_in(Lane::Bulk, |conn| {
+                    download_file_sized_locked(conn, &path, |block, total| {
+                        check()?;
+                        data.extend_from_slice(block);
+                        on_block(data.len() as u32, total)
+                    })
+                })?;
+                Ok(Either::A(data.into()))
+            }
+        }
+    }
+}
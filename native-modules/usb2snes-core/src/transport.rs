@@ -0,0 +1,301 @@
+// Transport: what carries packets between the core and a device
+// The packet logic (building commands, validating RESPONSEs, data phases, retries,
+// resync) only needs to send a 512-byte command, read a 512-byte RESPONSE and move
+// data phase blocks, so that is what a Connection holds. SerialTransport is the
+// serial backend; the simulator and the WebSocket client present themselves as
+// serial ports and go through it too. DTR/RTS and the modem lines are serial-only:
+// a transport without them keeps the defaults, which report Unsupported.
+
+use serialport::SerialPort;
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind};
+use std::time::{Duration, Instant};
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::timeouts::ReadDeadlines;
+use crate::{DEVICE_DISCONNECTED, PACKET_SIZE};
+
+/// Modem control line levels (None = the transport couldn't read it)
+#[derive(Default)]
+pub(crate) struct ModemLines {
+    pub(crate) cts: Option<bool>,
+    pub(crate) dsr: Option<bool>,
+    pub(crate) cd: Option<bool>,
+    pub(crate) ri: Option<bool>,
+}
+
+pub(crate) trait Transport: Send {
+    /// Send one command packet
+    fn write_packet(&mut self, packet: &[u8]) -> Result<()>;
+
+    /// Fill `buf` with a RESPONSE packet; the first-byte deadline applies until it starts
+    fn read_packet(&mut self, buf: &mut [u8], deadlines: ReadDeadlines) -> Result<()>;
+
+    /// Fill `block` with one data phase block
+    fn read_data(&mut self, block: &mut [u8], deadlines: ReadDeadlines) -> Result<()>;
+
+    /// Send data phase blocks (already padded) and flush them
+    fn write_data(&mut self, data: &[u8]) -> Result<()>;
+
+    /// Longest a single blocking read may wait
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+
+    /// Discard pending input until nothing arrives for `quiet` or `limit` passes
+    /// Returns the number of bytes discarded; the read timeout is left at `quiet`.
+    fn drain_input(&mut self, quiet: Duration, limit: Duration) -> Result<u32>;
+
+    fn set_dtr(&mut self, _level: bool) -> io::Result<()> {
+        Err(io::Error::new(ErrorKind::Unsupported, "transport has no DTR line"))
+    }
+
+    fn set_rts(&mut self, _level: bool) -> io::Result<()> {
+        Err(io::Error::new(ErrorKind::Unsupported, "transport has no RTS line"))
+    }
+
+    fn modem_lines(&mut self) -> ModemLines {
+        ModemLines::default()
+    }
+
+    /// set_dtr(), set_rts() and modem_lines() reach real serial lines
+    fn drives_modem_lines(&self) -> bool {
+        false
+    }
+}
+
+/// A serial port (or anything implementing SerialPort) as a Transport
+pub(crate) struct SerialTransport {
+    port: Box<dyn SerialPort>,
+    /// `port` is a real serial port rather than one emulated in-process
+    native: bool,
+}
+
+impl SerialTransport {
+    pub(crate) fn new(port: Box<dyn SerialPort>) -> Self {
+        Self { port, native: cfg!(any(unix, windows)) }
+    }
+
+    /// A SerialPort emulated in-process (simulator, WebSocket client): its modem line
+    /// calls are accepted but drive nothing
+    pub(crate) fn emulated(port: Box<dyn SerialPort>) -> Self {
+        Self { port, native: false }
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        self.port.write_all(data).map_err(|e| write_error("Write", e))?;
+        // Flush output to ensure data is sent (matching C# behavior)
+        self.port.flush().map_err(|e| write_error("Flush", e))
+    }
+
+    /// Read a full block from the port (matching C# _serial_port.Read loop)
+    /// `awaiting_response` is set for the RESPONSE block: until its first byte arrives the
+    /// first-byte deadline applies. Otherwise (and after that byte) the read fails once no
+    /// new byte has arrived for the progress deadline, so a transfer that keeps moving has
+    /// no overall cap. Either error names the deadline and the bytes received; EOF fails
+    /// with ConnectionClosed.
+    fn read_block(&mut self, buf: &mut [u8], deadlines: ReadDeadlines, awaiting_response: bool) -> Result<()> {
+        let mut total_read = 0;
+        let mut last_progress = Instant::now();
+
+        while total_read < buf.len() {
+            let limit = if awaiting_response && total_read == 0 {
+                deadlines.first_byte.0
+            } else {
+                deadlines.progress.0
+            };
+            if last_progress.elapsed() > limit {
+                return Err(read_timeout_error(deadlines, awaiting_response, total_read, buf.len()));
+            }
+
+            // Read remaining bytes (matching C#: Read(numArray, num5 % 512, 512 - (num5 % 512)))
+            match self.port.read(&mut buf[total_read..]) {
+                Ok(0) => {
+                    // EOF - connection closed, even mid-block: padding here would hand
+                    // zero-filled data from an unplugged device to the caller as real
+                    return Err(CoreError::new(ErrorCode::NotConnected, format!(
+                        "ConnectionClosed: connection closed during read (bytes_received {} of {})",
+                        total_read, buf.len()
+                    )));
+                }
+                Ok(n) => {
+                    total_read += n;
+                    last_progress = Instant::now();
+                }
+                Err(e) => {
+                    // Timeout or would-block: no data yet, the deadline check above decides
+                    if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock {
+                        std::thread::sleep(deadlines.poll);
+                        continue;
+                    }
+                    return Err(CoreError::new(ErrorCode::IoError,
+                        format!("Read error: {}", e)
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Transport for SerialTransport {
+    /// Matching C# _serial_port.Write(numArray, 0, count) where count = 512
+    fn write_packet(&mut self, packet: &[u8]) -> Result<()> {
+        self.write_all(packet)
+    }
+
+    fn read_packet(&mut self, buf: &mut [u8], deadlines: ReadDeadlines) -> Result<()> {
+        self.read_block(buf, deadlines, true)
+    }
+
+    fn read_data(&mut self, block: &mut [u8], deadlines: ReadDeadlines) -> Result<()> {
+        self.read_block(block, deadlines, false)
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> Result<()> {
+        self.write_all(data)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.port.set_timeout(timeout).map_err(io::Error::from)
+    }
+
+    fn drain_input(&mut self, quiet: Duration, limit: Duration) -> Result<u32> {
+        self.set_timeout(quiet)
+            .map_err(|e| CoreError::new(ErrorCode::IoError, format!("Failed to set timeout: {}", e)))?;
+
+        let deadline = Instant::now() + limit;
+        let mut drained = 0u32;
+        let mut buf = [0u8; 512];
+        loop {
+            if Instant::now() >= deadline {
+                return Ok(drained);
+            }
+            match self.port.read(&mut buf) {
+                Ok(0) => return Ok(drained),
+                Ok(n) => drained += n as u32,
+                Err(e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock => return Ok(drained),
+                Err(e) => return Err(CoreError::new(ErrorCode::IoError, format!("Read error: {}", e))),
+            }
+        }
+    }
+
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        self.port.write_data_terminal_ready(level).map_err(io::Error::from)
+    }
+
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        self.port.write_request_to_send(level).map_err(io::Error::from)
+    }
+
+    fn modem_lines(&mut self) -> ModemLines {
+        ModemLines {
+            cts: self.port.read_clear_to_send().ok(),
+            dsr: self.port.read_data_set_ready().ok(),
+            cd: self.port.read_carrier_detect().ok(),
+            ri: self.port.read_ring_indicator().ok(),
+        }
+    }
+
+    fn drives_modem_lines(&self) -> bool {
+        self.native
+    }
+}
+
+/// RESPONSE packets and data blocks of a transport that answers each command as it
+/// is written (RetroArch, SNI); reading more than was queued is a timeout
+#[derive(Default)]
+pub(crate) struct ReplyQueue {
+    output: VecDeque<u8>,
+}
+
+impl ReplyQueue {
+    /// A RESPONSE packet for a command with `flags`, to be filled in and push()ed
+    pub(crate) fn response(flags: u8) -> Vec<u8> {
+        let mut response = vec![0u8; PACKET_SIZE];
+        response[..4].copy_from_slice(b"USBA");
+        response[4] = 15;
+        response[6] = flags;
+        response
+    }
+
+    /// Queue a RESPONSE and its data phase, zero-padded to whole `block_len` blocks
+    pub(crate) fn push(&mut self, response: Vec<u8>, mut data: Vec<u8>, block_len: usize) {
+        self.output.extend(response);
+        if !data.is_empty() {
+            data.resize(data.len().div_ceil(block_len) * block_len, 0);
+            self.output.extend(data);
+        }
+    }
+
+    /// Fill `buf`, or fail with the timeout a serial read would have hit
+    pub(crate) fn take(&mut self, buf: &mut [u8], deadlines: ReadDeadlines, awaiting_response: bool) -> Result<()> {
+        let len = buf.len();
+        if self.output.len() < len {
+            let received = self.output.len();
+            self.output.clear();
+            return Err(read_timeout_error(deadlines, awaiting_response, received, len));
+        }
+        for (slot, byte) in buf.iter_mut().zip(self.output.drain(..len)) {
+            *slot = byte;
+        }
+        Ok(())
+    }
+
+    /// Drop everything queued; returns the number of bytes dropped
+    pub(crate) fn clear(&mut self) -> u32 {
+        let len = self.output.len() as u32;
+        self.output.clear();
+        len
+    }
+}
+
+/// Fill INFO's firmware version (byte 260) and running ROM (byte 16) fields
+pub(crate) fn set_info_strings(response: &mut [u8], firmware: &str, rom: &str) {
+    let rom_len = rom.len().min(252 - 16 - 1);
+    response[16..16 + rom_len].copy_from_slice(&rom.as_bytes()[..rom_len]);
+    let firmware_len = firmware.len().min(PACKET_SIZE - 260 - 1);
+    response[260..260 + firmware_len].copy_from_slice(&firmware.as_bytes()[..firmware_len]);
+}
+
+/// Timeout error of a block read that stopped after `received` of `len` bytes
+/// Names the deadline that ran out: first-byte while a RESPONSE hasn't started, else progress
+fn read_timeout_error(deadlines: ReadDeadlines, awaiting_response: bool, received: usize, len: usize) -> CoreError {
+    if awaiting_response && received == 0 {
+        let (limit, source) = deadlines.first_byte;
+        CoreError::new(ErrorCode::Timeout, format!(
+            "Read timeout - no response after {}ms (first-byte deadline, {})", limit.as_millis(), source
+        ))
+    } else {
+        let (limit, source) = deadlines.progress;
+        CoreError::new(ErrorCode::Timeout, format!(
+            "Read timeout - no new data for {}ms after {} of {} bytes (progress deadline, {})",
+            limit.as_millis(), received, len, source
+        ))
+    }
+}
+
+/// Error for a failed write or flush; errors meaning the device is gone (unplugged
+/// mid-write) are "DeviceDisconnected: ...", which makes with_connection() drop the port
+fn write_error(action: &str, e: io::Error) -> CoreError {
+    let gone = matches!(
+        e.kind(),
+        ErrorKind::BrokenPipe | ErrorKind::PermissionDenied | ErrorKind::NotConnected
+            | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted
+    ) || is_device_gone_errno(&e);
+    if gone {
+        CoreError::new(ErrorCode::NotConnected, format!("{}: {} failed, device gone: {}", DEVICE_DISCONNECTED, action, e))
+    } else {
+        CoreError::new(ErrorCode::IoError, format!("{} failed: {}", action, e))
+    }
+}
+
+/// EIO/ENXIO/ENODEV: what Linux and macOS report for a tty whose USB device was unplugged
+#[cfg(unix)]
+fn is_device_gone_errno(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(5) | Some(6) | Some(19))
+}
+
+#[cfg(not(unix))]
+fn is_device_gone_errno(_: &io::Error) -> bool {
+    false
+}
@@ -0,0 +1,186 @@
+// Sanity checks for hand-built commands
+// send_command() encodes whatever opcode/space/flags it is given, and some combinations
+// can never work: a GET with NORESP leaves its data phase on the line, a path opcode
+// outside the FILE space makes the firmware read the path as an address, a flag on a
+// command it means nothing to is silently ignored. The table below rejects those
+// before anything is sent. The command methods that take flags always check; for
+// send_command() set_command_validation(false) turns it off for protocol experiments,
+// and send_command_with_buffer() is never checked.
+
+use napi_derive::napi;
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::protocol::flag_names;
+use std::sync::atomic::Ordering;
+
+use crate::{
+    Usb2SnesCore, CLRX_FLAG, DATA64B_FLAG, NORESP_FLAG, ONLYRESET_FLAG, SETX_FLAG, SKIPRESET_FLAG, SPACE_CMD,
+    SPACE_FILE, STREAM_BURST_FLAG,
+};
+
+/// A rule violated by an opcode/space/flags combination
+struct Rule {
+    opcodes: &'static [u8],
+    violated: fn(space: u8, flags: u8) -> bool,
+    reason: &'static str,
+}
+
+const RULES: &[Rule] = &[
+    Rule {
+        opcodes: &[0, 2, 4, 11],
+        violated: |_, flags| flags & NORESP_FLAG != 0,
+        reason: "must not set NORESP: its RESPONSE or data phase carries the result",
+    },
+    Rule {
+        opcodes: &[4, 5, 6, 7, 9],
+        violated: |space, _| space != SPACE_FILE,
+        reason: "takes a path and requires the FILE space (0)",
+    },
+    Rule {
+        opcodes: &[2, 3],
+        violated: |_, flags| flags & DATA64B_FLAG == 0,
+        reason: "requires DATA64B: its data phase uses 64-byte blocks",
+    },
+    Rule {
+        opcodes: &[8, 12],
+        violated: |_, flags| flags & NORESP_FLAG == 0,
+        reason: "requires NORESP: the device resets instead of answering",
+    },
+    Rule {
+        opcodes: &[9, 12],
+        violated: |_, flags| flags & (SKIPRESET_FLAG | ONLYRESET_FLAG) == SKIPRESET_FLAG | ONLYRESET_FLAG,
+        reason: "can't set both SKIPRESET and ONLYRESET",
+    },
+    Rule {
+        opcodes: &[0, 1, 2, 3, 4, 5, 6, 7, 8, 10, 11, 13, 14],
+        violated: |_, flags| flags & (SKIPRESET_FLAG | ONLYRESET_FLAG) != 0,
+        reason: "takes no SKIPRESET/ONLYRESET: only BOOT and MENU_RESET do",
+    },
+    Rule {
+        opcodes: &[1, 3],
+        violated: |space, flags| flags & (CLRX_FLAG | SETX_FLAG) != 0 && space != SPACE_CMD,
+        reason: "sets CLRX/SETX, which only apply to the CMD space (3)",
+    },
+    Rule {
+        opcodes: &[1, 3],
+        violated: |_, flags| flags & (CLRX_FLAG | SETX_FLAG) == CLRX_FLAG | SETX_FLAG,
+        reason: "can't set both CLRX and SETX",
+    },
+    Rule {
+        opcodes: &[0, 2, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14],
+        violated: |_, flags| flags & (CLRX_FLAG | SETX_FLAG) != 0,
+        reason: "takes no CLRX/SETX: only PUT/VPUT to the CMD space do",
+    },
+    Rule {
+        opcodes: &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 14],
+        violated: |_, flags| flags & STREAM_BURST_FLAG != 0,
+        reason: "takes no STREAM_BURST: only STREAM does",
+    },
+    Rule {
+        opcodes: &[4, 5, 6, 7, 8, 9, 10, 11, 12, 14],
+        violated: |_, flags| flags & DATA64B_FLAG != 0,
+        reason: "takes no DATA64B: it has no data phase in 64-byte blocks",
+    },
+];
+
+
+/// Request opcodes 0..=14 by number; 15 is RESPONSE, which only the device sends
+const OPCODE_NAMES: [&str; 15] = [
+    "GET", "PUT", "VGET", "VPUT", "LS", "MKDIR", "RM", "MV", "RESET", "BOOT", "POWER_CYCLE", "INFO", "MENU_RESET",
+    "STREAM", "TIME",
+];
+
+/// RESPONSE opcode of device-to-host packets
+const RESPONSE_OPCODE: u8 = 15;
+
+/// Opcode names for error messages
+fn opcode_name(opcode: u8) -> &'static str {
+    OPCODE_NAMES.get(opcode as usize).copied().unwrap_or("unknown opcode")
+}
+
+/// Error for an opcode build_packet() doesn't know, listing the valid ones
+pub(crate) fn unhandled_opcode_error(opcode: u8, space: u8, flags: u8) -> CoreError {
+    let valid = OPCODE_NAMES.iter().enumerate()
+        .map(|(opcode, name)| format!("{}={}", opcode, name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let hint = if opcode == RESPONSE_OPCODE {
+        format!("{} is RESPONSE, which the device sends to the host and can't be sent as a request; ", opcode)
+    } else {
+        String::new()
+    };
+    CoreError::new(ErrorCode::ArgValidation, format!(
+        "Unhandled Command: {} space: {} flags: {} ({}valid request opcodes are 0..{}: {})",
+        opcode, space, flags, hint, OPCODE_NAMES.len() - 1, valid
+    ))
+}
+
+fn violated_rule(opcode: u8, space: u8, flags: u8) -> Option<&'static Rule> {
+    RULES.iter().find(|rule| rule.opcodes.contains(&opcode) && (rule.violated)(space, flags))
+}
+
+/// Reject opcode/space/flags combinations the firmware can't handle
+pub(crate) fn validate_command(opcode: u8, space: u8, flags: u8) -> Result<()> {
+    match violated_rule(opcode, space, flags) {
+        Some(rule) => Err(CoreError::new(ErrorCode::ArgValidation, format!(
+            "InvalidCommand: {} ({}) {} (space {}, flags 0x{:02X}); disable with set_command_validation(false)",
+            opcode_name(opcode), opcode, rule.reason, space, flags
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// validate_command() for a command method's `flags`, which can't be turned off
+pub(crate) fn validate_method_flags(method: &str, opcode: u8, space: u8, flags: u8) -> Result<()> {
+    match violated_rule(opcode, space, flags) {
+        Some(rule) => Err(CoreError::new(ErrorCode::ArgValidation, format!(
+            "InvalidCommand: {}(): {} {} (space {}, flags {})",
+            method, opcode_name(opcode), rule.reason, space, flag_names(flags)
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Enable or disable send_command()'s opcode/space/flags checks (enabled by default)
+    #[napi]
+    pub fn set_command_validation(&self, enabled: bool) {
+        self.command_validation.store(enabled, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = "valid request opcodes are 0..14: 0=GET, 1=PUT, 2=VGET, 3=VPUT, 4=LS, 5=MKDIR, 6=RM, 7=MV, \
+        8=RESET, 9=BOOT, 10=POWER_CYCLE, 11=INFO, 12=MENU_RESET, 13=STREAM, 14=TIME";
+
+    #[test]
+    fn unhandled_opcode_messages() {
+        let cases = [
+            (14, format!("Unhandled Command: 14 space: 1 flags: 0 ({})", VALID)),
+            (15, format!(
+                "Unhandled Command: 15 space: 1 flags: 0 (15 is RESPONSE, which the device sends to the host \
+                 and can't be sent as a request; {})",
+                VALID
+            )),
+            (16, format!("Unhandled Command: 16 space: 1 flags: 0 ({})", VALID)),
+        ];
+        for (opcode, message) in cases {
+            let error = unhandled_opcode_error(opcode, 1, 0);
+            assert_eq!(error.code, ErrorCode::ArgValidation, "opcode {}", opcode);
+            assert_eq!(error.reason, message, "opcode {}", opcode);
+        }
+    }
+
+    #[test]
+    fn build_packet_reports_unhandled_opcodes() {
+        // TIME is a request build_packet() encodes, so it fails on its arguments instead
+        let time = crate::build_packet(14, 1, 0, None).unwrap_err();
+        assert!(!time.reason.starts_with("Unhandled Command"), "{}", time.reason);
+
+        let response = crate::build_packet(15, 1, 0, None).unwrap_err();
+        assert_eq!(response.reason, unhandled_opcode_error(15, 1, 0).reason);
+    }
+}
@@ -0,0 +1,125 @@
+// VGET/VPUT with structured ranges instead of hex argument strings
+// The firmware takes at most 8 (size, address) pairs per command and a pair's size is
+// one byte. vget()/vput() split longer ranges into 255-byte pairs and pack them 8 to a
+// command, holding the port for the whole batch so no other command lands between
+// the pieces. The data phases use 64-byte blocks (DATA64B).
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::pipeline::MAX_PAIR_SIZE;
+use crate::queue::Lane;
+use crate::{validate_address_range, vget_locked, vput_locked, Connection, Usb2SnesCore, SPACE_FILE, SPACE_SNES, VGET_MAX_PAIRS};
+
+/// One range of vget()
+#[napi(object)]
+pub struct VectorRead {
+    pub address: u32,
+    pub size: u32,
+}
+
+/// One write of vput()
+#[napi(object)]
+pub struct VectorWrite {
+    pub address: u32,
+    pub data: Buffer,
+}
+
+/// Memory space for vget()/vput(): SNES unless given; FILE has no addresses
+fn memory_space(space: Option<u8>, method: &str) -> Result<u8> {
+    match space.unwrap_or(SPACE_SNES) {
+        SPACE_FILE => Err(CoreError::new(
+            ErrorCode::ArgValidation,
+            format!("{}: FILE space is addressed by path", method),
+        )),
+        space => Ok(space),
+    }
+}
+
+/// validate_address_range(), plus the 32-bit address wrap for the other spaces
+fn validate_range(space: u8, address: u32, size: u32) -> Result<()> {
+    validate_address_range(space, address, size)?;
+    if address.checked_add(size).is_none() {
+        return Err(CoreError::new(
+            ErrorCode::ArgValidation,
+            format!("AddressOutOfRange: 0x{:X} + 0x{:X} bytes wraps past the end of the address space", address, size),
+        ));
+    }
+    Ok(())
+}
+
+/// Split `size` bytes at `address` into (size, address) pairs of at most 255 bytes
+fn split_range(address: u32, size: u32) -> impl Iterator<Item = (u8, u32)> {
+    (0..size).step_by(MAX_PAIR_SIZE as usize)
+        .map(move |offset| ((size - offset).min(MAX_PAIR_SIZE) as u8, address + offset))
+}
+
+/// Read (address, size) ranges with as few VGETs as possible, one Vec per range
+/// Ranges must already be validated
+pub(crate) fn vget_ranges_locked(conn: &mut Connection, space: u8, ranges: &[(u32, u32)]) -> Result<Vec<Vec<u8>>> {
+    let pairs: Vec<(usize, (u8, u32))> = ranges.iter().enumerate()
+        .flat_map(|(index, &(address, size))| split_range(address, size).map(move |pair| (index, pair)))
+        .collect();
+
+    let mut out = vec![Vec::new(); ranges.len()];
+    for batch in pairs.chunks(VGET_MAX_PAIRS) {
+        let batch_pairs: Vec<(u8, u32)> = batch.iter().map(|&(_, pair)| pair).collect();
+        let data = vget_locked(conn, space, &batch_pairs)?;
+        for (&(index, _), chunk) in batch.iter().zip(data) {
+            out[index].extend_from_slice(&chunk);
+        }
+    }
+    Ok(out)
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Read several memory ranges with as few VGETs as needed (SNES space unless given)
+    /// Ranges of any length are split into 255-byte pairs, 8 pairs per VGET. Zero-size
+    /// ranges return empty buffers. Returns one buffer per range, in order.
+    #[napi]
+    pub fn vget(&self, ranges: Vec<VectorRead>, space: Option<u8>) -> Result<Vec<Buffer>> {
+        let space = memory_space(space, "vget")?;
+        for range in &ranges {
+            validate_range(space, range.address, range.size)?;
+        }
+        let ranges: Vec<(u32, u32)> = ranges.iter().map(|range| (range.address, range.size)).collect();
+        let out = if ranges.iter().all(|&(_, size)| size == 0) {
+            vec![Vec::new(); ranges.len()]
+        } else {
+            self.with_connection_replayable(Lane::Interactive, |conn| vget_ranges_locked(conn, space, &ranges))?
+        };
+        Ok(out.into_iter().map(Buffer::from).collect())
+    }
+
+    /// Write several memory ranges with as few VPUTs as needed (SNES space unless given)
+    /// Writes of any length are split into 255-byte pairs, 8 pairs per VPUT, and go out
+    /// in order, so a later write to the same address wins. Empty writes are skipped.
+    #[napi]
+    pub fn vput(&self, writes: Vec<VectorWrite>, space: Option<u8>) -> Result<()> {
+        let space = memory_space(space, "vput")?;
+        for write in &writes {
+            validate_range(space, write.address, write.data.len() as u32)?;
+        }
+        // (pair, its bytes) for every piece of every write
+        let pieces: Vec<((u8, u32), &[u8])> = writes.iter()
+            .flat_map(|write| split_range(write.address, write.data.len() as u32).map(move |(size, address)| {
+                let offset = (address - write.address) as usize;
+                ((size, address), &write.data[offset..offset + size as usize])
+            }))
+            .collect();
+        if pieces.is_empty() {
+            return Ok(());
+        }
+
+        self.with_connection_in(Lane::Interactive, |conn| {
+            for batch in pieces.chunks(VGET_MAX_PAIRS) {
+                let pairs: Vec<(u8, u32)> = batch.iter().map(|&(pair, _)| pair).collect();
+                let data: Vec<u8> = batch.iter().flat_map(|&(_, bytes)| bytes.iter().copied()).collect();
+                vput_locked(conn, space, &pairs, &data)?;
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,278 @@
+// Memory watches: poll memory on a background thread and report only changes
+// Watches are registered with add_watch() and polled by one thread per core once
+// start_watches() hands it a callback. Every tick the due watches are read together
+// with batched VGETs under one port hold, so a tracker with dozens of flags costs a
+// few commands rather than one GET each, and timing doesn't depend on the JS event
+// loop. The callback is node-style: (err, event). It gets an event for a watch's
+// first value and whenever the bytes differ from the previous read; a failing read
+// is reported once, not on every tick, until a read succeeds again. With torn_read each
+// tick's reads are resampled until consistent (see torn.rs) and an inconsistent tick
+// reports nothing.
+
+use napi_derive::napi;
+use napi::bindgen_prelude::Buffer;
+use napi::threadsafe_function::{ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::JsFunction;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::queue::Lane;
+use crate::recording::unix_millis;
+use crate::regions::MemoryRegion;
+use crate::torn::{read_stable_locked, TornReadOptions, TornReadOutcome, TornReadSettings, TornReadStrategy};
+use crate::vectors::vget_ranges_locked;
+use crate::Usb2SnesCore;
+
+/// Longest sleep of the watch thread, so stop_watches() and new watches are noticed
+const WATCH_IDLE_POLL_MS: u64 = 20;
+
+/// A memory range for add_watch()
+#[napi(object)]
+pub struct WatchOptions {
+    pub region: MemoryRegion,
+    /// Offset within the region
+    pub offset: u32,
+    pub size: u32,
+    /// Time between two reads of this watch
+    pub interval_ms: u32,
+    /// Passed back in every event for this watch
+    pub label: Option<String>,
+}
+
+/// Passed to start_watches()' callback when a watched value changes
+#[napi(object)]
+pub struct WatchEvent {
+    pub id: u32,
+    pub label: Option<String>,
+    pub value: Buffer,
+    /// The value before the change (None for the first read)
+    pub previous: Option<Buffer>,
+    pub unix_ms: f64,
+    /// With torn_read: the strategy used and the resamples the tick took
+    pub strategy: Option<TornReadStrategy>,
+    pub retries: Option<u32>,
+}
+
+/// Options for start_watches()
+#[napi(object)]
+#[derive(Default)]
+pub struct StartWatchesOptions {
+    /// Resample each tick's reads until the values are consistent (see torn.rs)
+    pub torn_read: Option<TornReadOptions>,
+}
+
+struct Watch {
+    id: u32,
+    label: Option<String>,
+    space: u8,
+    address: u32,
+    size: u32,
+    interval: Duration,
+    next_due: Instant,
+    last: Option<Vec<u8>>,
+}
+
+/// A change found by the watch thread, turned into a WatchEvent on the JS thread
+struct WatchChange {
+    id: u32,
+    label: Option<String>,
+    value: Vec<u8>,
+    previous: Option<Vec<u8>>,
+    unix_ms: u128,
+    torn_read: Option<TornReadOutcome>,
+}
+
+struct WatchEngine {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// Registered watches of one core and the thread polling them
+#[derive(Default)]
+pub(crate) struct Watches {
+    next_id: u32,
+    list: Vec<Watch>,
+    engine: Option<WatchEngine>,
+}
+
+impl Usb2SnesCore {
+    /// Body of the watch thread; returns when `stop` is set
+    fn watch_loop(
+        &self,
+        callback: ThreadsafeFunction<WatchChange, ErrorStrategy::CalleeHandled>,
+        torn_read: Option<TornReadSettings>,
+        stop: &AtomicBool,
+    ) {
+        let mut failing = false;
+        while !stop.load(Ordering::SeqCst) {
+            // Due watches by space, as (id, address, size)
+            let now = Instant::now();
+            let mut due: BTreeMap<u8, Vec<(u32, u32, u32)>> = BTreeMap::new();
+            let next_wake = {
+                let mut watches = self.watches.lock().unwrap();
+                for watch in watches.list.iter_mut().filter(|watch| watch.next_due <= now) {
+                    due.entry(watch.space).or_default().push((watch.id, watch.address, watch.size));
+                    // Keep the cadence, but don't try to catch up after a slow tick
+                    watch.next_due += watch.interval;
+                    if watch.next_due <= now {
+                        watch.next_due = now + watch.interval;
+                    }
+                }
+                watches.list.iter().map(|watch| watch.next_due).min()
+            };
+
+            if !due.is_empty() {
+                let read = self.with_connection_in(Lane::Interactive, |conn| {
+                    let mut values = Vec::new();
+                    for (&space, ranges) in &due {
+                        let spans: Vec<(u32, u32)> = ranges.iter().map(|&(_, address, size)| (address, size)).collect();
+                        let (data, outcome) = match torn_read {
+                            Some(settings) => {
+                                let (data, outcome) = read_stable_locked(conn, space, &spans, settings, |conn, spans| {
+                                    vget_ranges_locked(conn, space, spans)
+                                })?;
+                                if !outcome.stable {
+                                    continue;
+                                }
+                                (data, Some(outcome))
+                            }
+                            None => (vget_ranges_locked(conn, space, &spans)?, None),
+                        };
+                        values.extend(ranges.iter().zip(data).map(|(&(id, _, _), value)| (id, value, outcome)));
+                    }
+                    Ok(values)
+                });
+                match read {
+                    Ok(values) => {
+                        failing = false;
+                        for change in self.record_watch_values(values) {
+                            callback.call(Ok(change), ThreadsafeFunctionCallMode::NonBlocking);
+                        }
+                    }
+                    Err(e) if !failing => {
+                        failing = true;
+                        callback.call(Err(e.into()), ThreadsafeFunctionCallMode::NonBlocking);
+                    }
+                    Err(_) => {}
+                }
+            }
+
+            let idle = Duration::from_millis(WATCH_IDLE_POLL_MS);
+            let sleep = next_wake.map_or(idle, |wake| wake.saturating_duration_since(Instant::now()).min(idle));
+            std::thread::sleep(sleep);
+        }
+    }
+
+    /// Store freshly read values and return the ones that changed
+    /// Watches removed while the read was in flight are skipped
+    fn record_watch_values(&self, values: Vec<(u32, Vec<u8>, Option<TornReadOutcome>)>) -> Vec<WatchChange> {
+        let mut watches = self.watches.lock().unwrap();
+        let unix_ms = unix_millis();
+        values.into_iter()
+            .filter_map(|(id, value, torn_read)| {
+                let watch = watches.list.iter_mut().find(|watch| watch.id == id)?;
+                if watch.last.as_ref() == Some(&value) {
+                    return None;
+                }
+                let previous = watch.last.replace(value.clone());
+                Some(WatchChange { id, label: watch.label.clone(), value, previous, unix_ms, torn_read })
+            })
+            .collect()
+    }
+}
+
+#[napi]
+impl Usb2SnesCore {
+    /// Register a memory watch; returns its id
+    /// Takes effect on the next tick if start_watches() is running. Several watches may
+    /// cover the same memory.
+    #[napi]
+    pub fn add_watch(&self, options: WatchOptions) -> Result<u32> {
+        let WatchOptions { region, offset, size, interval_ms, label } = options;
+        let (space, address) = region.resolve(offset, size)?;
+        if size == 0 || interval_ms == 0 {
+            return Err(CoreError::new(ErrorCode::ArgValidation, "add_watch: size and interval_ms must be non-zero"));
+        }
+
+        let mut watches = self.watches.lock().unwrap();
+        watches.next_id += 1;
+        let id = watches.next_id;
+        watches.list.push(Watch {
+            id,
+            label,
+            space,
+            address,
+            size,
+            interval: Duration::from_millis(interval_ms as u64),
+            next_due: Instant::now(),
+            last: None,
+        });
+        Ok(id)
+    }
+
+    /// Unregister a watch; returns false if there was none with that id
+    #[napi]
+    pub fn remove_watch(&self, id: u32) -> bool {
+        let mut watches = self.watches.lock().unwrap();
+        let before = watches.list.len();
+        watches.list.retain(|watch| watch.id != id);
+        watches.list.len() != before
+    }
+
+    /// Start polling the registered watches on a background thread
+    /// `on_change(err, event)` gets a WatchEvent for each watch's first value and each
+    /// change after that, and an error (once per run of failures) when a read fails.
+    /// The thread keeps Node's event loop alive until stop_watches().
+    #[napi(ts_args_type = "onChange: (err: Error | null, event: WatchEvent) => void, options?: StartWatchesOptions")]
+    pub fn start_watches(&self, on_change: JsFunction, options: Option<StartWatchesOptions>) -> Result<()> {
+        let torn_read = options.and_then(|o| o.torn_read).map(TornReadSettings::new).transpose()?;
+        let mut watches = self.watches.lock().unwrap();
+        if watches.engine.is_some() {
+            return Err(CoreError::new(ErrorCode::DeviceBusy, "start_watches: watches are already running"));
+        }
+        // Start over so the first tick reports every watch's current value
+        for watch in watches.list.iter_mut() {
+            watch.last = None;
+            watch.next_due = Instant::now();
+        }
+
+        let callback: ThreadsafeFunction<WatchChange, ErrorStrategy::CalleeHandled> = on_change
+            .create_threadsafe_function(0, |ctx: ThreadSafeCallContext<WatchChange>| {
+                let change = ctx.value;
+                Ok(vec![WatchEvent {
+                    id: change.id,
+                    label: change.label,
+                    value: change.value.into(),
+                    previous: change.previous.map(Buffer::from),
+                    unix_ms: change.unix_ms as f64,
+                    strategy: change.torn_read.map(|outcome| outcome.strategy()),
+                    retries: change.torn_read.map(|outcome| outcome.retries),
+                }])
+            })
+            .map_err(CoreError::from)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let core = self.clone();
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || core.watch_loop(callback, torn_read, &thread_stop));
+        watches.engine = Some(WatchEngine { stop, thread });
+        Ok(())
+    }
+
+    /// Stop the watch thread; registered watches are kept for the next start_watches()
+    /// Returns false if it wasn't running
+    #[napi]
+    pub fn stop_watches(&self) -> Result<bool> {
+        let Some(engine) = self.watches.lock().unwrap().engine.take() else {
+            return Ok(false);
+        };
+        engine.stop.store(true, Ordering::SeqCst);
+        engine.thread.join()
+            .map_err(|_| CoreError::new(ErrorCode::DeviceError, "stop_watches: watch thread panicked"))?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,467 @@
+// usb2snes WebSocket client backend
+// connect("ws://host:port") talks to a running QUsb2Snes, Usb2Snes-Uploader or SNI
+// (usb2snes protocol) instead of a serial port. The backend is a SerialPort that
+// decodes the 512-byte command packets the core writes, like the simulator does, and
+// turns each into a usb2snes request: GET/PUT become GetAddress/PutAddress or
+// GetFile/PutFile, VGET/VPUT become multi-pair GetAddress/PutAddress, LS/MKDIR/RM/MV/
+// BOOT become List/MakeDir/Remove/Rename/Boot, RESET/MENU_RESET become Reset/Menu and
+// INFO becomes Info. The replies are turned back into RESPONSE packets and data blocks,
+// so everything above the port (timeouts, retries, caching, transfers) is unchanged.
+// The first device in DeviceList is attached unless the URI names one after '#'
+// ("ws://localhost:23074#SD2SNES COM3"). usb2snes servers close the socket when a
+// request fails (e.g. a missing file), so such failures surface as a disconnect;
+// POWER_CYCLE, STREAM and TIME have no usb2snes request and report a device error.
+
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use tungstenite::{Message, WebSocket};
+
+use crate::errors::{CoreError, ErrorCode, Result};
+use crate::json::{json_string, parse_json, Json};
+use crate::simulator::{be_u32, packet_path, packet_pairs, PendingPut, FEATURE_NAMES};
+use crate::{
+    DATA64B_FLAG, LS_TYPE_DIR, MAX_MV_DEST_PATH_LEN, MAX_PATH_LEN, NORESP_FLAG, ONLYRESET_FLAG, PACKET_SIZE,
+    READ_TIMEOUT_MS, SPACE_FILE,
+};
+
+/// Name this client reports to the server (shown in QUsb2Snes' client list)
+const CLIENT_NAME: &str = "usb2snes-core";
+
+/// Whether a port name is a usb2snes WebSocket URI rather than a serial port
+pub(crate) fn is_ws_uri(port_name: &str) -> bool {
+    port_name.starts_with("ws://")
+}
+
+/// usb2snes name of an address space
+fn space_name(space: u8) -> &'static str {
+    match space {
+        SPACE_FILE => "FILE",
+        1 => "SNES",
+        2 => "MSU",
+        3 => "CMD",
+        _ => "CONFIG",
+    }
+}
+
+fn hex(value: impl std::fmt::UpperHex) -> String {
+    format!("{:X}", value)
+}
+
+/// A usb2snes server seen as a serial port
+struct WsPort {
+    socket: WebSocket<TcpStream>,
+    uri: String,
+    timeout: Duration,
+    /// Bytes written by the core and not yet consumed
+    input: Vec<u8>,
+    /// Data phase expected from the core before the next command packet
+    pending_put: Option<PendingPut>,
+    /// RESPONSE packets and data blocks for the core
+    output: VecDeque<u8>,
+    /// Set once the socket is unusable; later writes fail as a disconnect
+    closed: Option<String>,
+}
+
+impl WsPort {
+    fn connect(uri: &str) -> Result<WsPort> {
+        let (url, device) = match uri.split_once('#') {
+            Some((url, device)) => (url, Some(device)),
+            None => (uri, None),
+        };
+        let io_error = |e: String| CoreError::new(ErrorCode::IoError, format!("Failed to connect to {}: {}", uri, e));
+        let parsed: tungstenite::http::Uri = url.parse().map_err(|e| io_error(format!("{}", e)))?;
+        let host = parsed.host().ok_or_else(|| io_error("no host".to_string()))?;
+        let port = parsed.port_u16().unwrap_or(80);
+
+        let timeout = Duration::from_millis(READ_TIMEOUT_MS);
+        let addresses = (host, port).to_socket_addrs().map_err(|e| io_error(e.to_string()))?;
+        let mut last_error = io_error("host did not resolve".to_string());
+        let mut stream = None;
+        for address in addresses {
+            match TcpStream::connect_timeout(&address, timeout) {
+                Ok(connected) => {
+                    stream = Some(connected);
+                    break;
+                }
+                Err(e) => last_error = io_error(e.to_string()),
+            }
+        }
+        let stream = stream.ok_or(last_error)?;
+        stream.set_read_timeout(Some(timeout)).map_err(|e| io_error(e.to_string()))?;
+        stream.set_nodelay(true).map_err(|e| io_error(e.to_string()))?;
+        let (socket, _) = tungstenite::client(url, stream).map_err(|e| io_error(e.to_string()))?;
+
+        let mut port = WsPort {
+            socket,
+            uri: uri.to_string(),
+            timeout,
+            input: Vec::new(),
+            pending_put: None,
+            output: VecDeque::new(),
+            closed: None,
+        };
+        port.attach(device).map_err(|e| CoreError::new(ErrorCode::NotConnected, format!("{}: {}", uri, e)))?;
+        Ok(port)
+    }
+
+    /// Pick the device and attach to it
+    fn attach(&mut self, device: Option<&str>) -> io::Result<()> {
+        self.request("DeviceList", SPACE_FILE, Vec::new())?;
+        let devices = self.results()?;
+        let device = match device {
+            Some(name) if devices.iter().any(|d| d == name) => name.to_string(),
+            Some(name) => return Err(io::Error::other(format!("no device named {} (server has {:?})", name, devices))),
+            None => devices.into_iter().next().ok_or_else(|| io::Error::other("the server lists no devices"))?,
+        };
+        self.request("Name", SPACE_FILE, vec![CLIENT_NAME.to_string()])?;
+        self.request("Attach", SPACE_FILE, vec![device])
+    }
+
+    fn request(&mut self, opcode: &str, space: u8, operands: Vec<String>) -> io::Result<()> {
+        let operands: Vec<String> = operands.iter().map(|operand| json_string(operand)).collect();
+        let text = format!(
+            "{{\"Opcode\":{},\"Space\":{},\"Operands\":[{}]}}",
+            json_string(opcode), json_string(space_name(space)), operands.join(",")
+        );
+        self.socket.send(Message::Text(text)).map_err(socket_error)
+    }
+
+    /// Next message from the server, waiting up to the port timeout
+    fn next_message(&mut self) -> io::Result<Message> {
+        self.socket.get_ref().set_read_timeout(Some(self.timeout))?;
+        loop {
+            match self.socket.read() {
+                Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_)) => continue,
+                Ok(Message::Close(frame)) => {
+                    let reason = frame.map(|frame| frame.reason.to_string()).unwrap_or_default();
+                    return Err(io::Error::new(io::ErrorKind::ConnectionAborted,
+                        format!("usb2snes server closed the connection: {}", reason)
+                    ));
+                }
+                Ok(message) => return Ok(message),
+                Err(e) => return Err(socket_error(e)),
+            }
+        }
+    }
+
+    /// The Results of a text reply
+    fn results(&mut self) -> io::Result<Vec<String>> {
+        let text = match self.next_message()? {
+            Message::Text(text) => text,
+            other => return Err(io::Error::other(format!("expected a Results reply, got {:?}", other))),
+        };
+        let Some(Json::Object(fields)) = parse_json(&text) else {
+            return Err(io::Error::other(format!("malformed reply: {}", text)));
+        };
+        fields.into_iter()
+            .find_map(|(key, value)| match (key.as_str(), value) {
+                ("Results", Json::Array(items)) => Some(items),
+                _ => None,
+            })
+            .ok_or_else(|| io::Error::other(format!("reply without Results: {}", text)))?
+            .into_iter()
+            .map(|item| match item {
+                Json::Str(result) => Ok(result),
+                _ => Err(io::Error::other("non-string entry in Results")),
+            })
+            .collect()
+    }
+
+    /// `len` bytes of binary replies
+    fn binary(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            match self.next_message()? {
+                Message::Binary(bytes) => data.extend_from_slice(&bytes),
+                other => return Err(io::Error::other(format!("expected binary data, got {:?}", other))),
+            }
+        }
+        data.truncate(len);
+        Ok(data)
+    }
+
+    /// Accept bytes from the core and answer every complete packet
+    fn receive(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.input.extend_from_slice(bytes);
+        loop {
+            if let Some(pending) = &self.pending_put {
+                let len = pending.wire_len();
+                if self.input.len() < len {
+                    return Ok(());
+                }
+                let data: Vec<u8> = self.input.drain(..len).collect();
+                let pending = self.pending_put.take().unwrap();
+                let size = match &pending {
+                    PendingPut::File { size, .. } | PendingPut::Memory { size, .. } => *size,
+                    PendingPut::Vector { pairs, .. } => pairs.iter().map(|&(size, _)| size as usize).sum(),
+                };
+                self.socket.send(Message::Binary(data[..size].to_vec())).map_err(socket_error)?;
+            } else if self.input.len() >= PACKET_SIZE {
+                let packet: Vec<u8> = self.input.drain(..PACKET_SIZE).collect();
+                self.handle_packet(&packet)?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Run one command packet through the server and queue its RESPONSE and data phase
+    fn handle_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        let (opcode, space, flags) = (packet[4], packet[5], packet[6]);
+        let mut response = vec![0u8; PACKET_SIZE];
+        response[..4].copy_from_slice(b"USBA");
+        response[4] = 15;
+        response[6] = flags;
+        let mut data = Vec::new();
+        let block_len = if flags & DATA64B_FLAG != 0 { 64 } else { 512 };
+        let path = || packet_path(packet, 8, MAX_PATH_LEN);
+        let mut supported = true;
+
+        match opcode {
+            0 | 1 if space != SPACE_FILE => {
+                let address = be_u32(&packet[256..260]);
+                let size = match be_u32(&packet[252..256]) as usize {
+                    0 => block_len,
+                    size => size,
+                };
+                response[252..256].copy_from_slice(&(size as u32).to_be_bytes());
+                if opcode == 0 {
+                    self.request("GetAddress", space, vec![hex(address), hex(size)])?;
+                    data = self.binary(size)?;
+                } else {
+                    self.request("PutAddress", space, vec![hex(address), hex(size)])?;
+                    self.pending_put = Some(PendingPut::Memory { space, address, size, block_len });
+                }
+            }
+            0 => {
+                self.request("GetFile", SPACE_FILE, vec![path()])?;
+                let size = self.results()?.first()
+                    .and_then(|size| usize::from_str_radix(size, 16).ok())
+                    .ok_or_else(|| io::Error::other("GetFile reply without a size"))?;
+                response[252..256].copy_from_slice(&(size as u32).to_be_bytes());
+                data = self.binary(size)?;
+            }
+            1 => {
+                let size = be_u32(&packet[252..256]) as usize;
+                self.request("PutFile", SPACE_FILE, vec![path(), hex(size)])?;
+                response[252..256].copy_from_slice(&(size as u32).to_be_bytes());
+                self.pending_put = Some(PendingPut::File { path: path(), size });
+            }
+            2 | 3 => {
+                let pairs = packet_pairs(packet);
+                let total: usize = pairs.iter().map(|&(size, _)| size as usize).sum();
+                response[252..256].copy_from_slice(&(total as u32).to_be_bytes());
+                let operands = pairs.iter().flat_map(|&(size, address)| [hex(address), hex(size)]).collect();
+                if opcode == 2 {
+                    self.request("GetAddress", space, operands)?;
+                    data = self.binary(total)?;
+                } else {
+                    self.request("PutAddress", space, operands)?;
+                    self.pending_put = Some(PendingPut::Vector { space, pairs });
+                }
+            }
+            4 => {
+                self.request("List", SPACE_FILE, vec![path()])?;
+                // The protocol's types are "0" for a directory and "1" for a file
+                for entry in self.results()?.chunks(2) {
+                    if let [file_type, name] = entry {
+                        data.push(if file_type == "0" { LS_TYPE_DIR } else { 0 });
+                        data.extend_from_slice(name.as_bytes());
+                        data.push(0);
+                    }
+                }
+                data.push(0xFF);
+                response[252..256].copy_from_slice(&(data.len() as u32).to_be_bytes());
+            }
+            5 => self.request("MakeDir", SPACE_FILE, vec![path()])?,
+            6 => self.request("Remove", SPACE_FILE, vec![path()])?,
+            7 => self.request("Rename", SPACE_FILE, vec![path(), packet_path(packet, 256, MAX_MV_DEST_PATH_LEN)])?,
+            // Only resetting the SNES is what Reset does
+            9 | 12 if flags & ONLYRESET_FLAG != 0 => self.request("Reset", SPACE_FILE, Vec::new())?,
+            9 => self.request("Boot", SPACE_FILE, vec![path()])?,
+            8 => self.request("Reset", SPACE_FILE, Vec::new())?,
+            12 => self.request("Menu", SPACE_FILE, Vec::new())?,
+            11 => {
+                self.request("Info", SPACE_FILE, Vec::new())?;
+                let results = self.results()?;
+                let field = |index: usize| results.get(index).map(String::as_str).unwrap_or_default();
+                let mut features = 0u8;
+                for flag in results.iter().skip(3) {
+                    if let Some(bit) = FEATURE_NAMES.iter().position(|name| name == flag) {
+                        features |= 1 << bit;
+                    }
+                }
+                response[6] = features;
+                let rom = field(2).as_bytes();
+                let rom_len = rom.len().min(252 - 16 - 1);
+                response[16..16 + rom_len].copy_from_slice(&rom[..rom_len]);
+                let revision = u32::from_str_radix(field(1), 16).unwrap_or(0);
+                response[256..260].copy_from_slice(&revision.to_be_bytes());
+                let version = field(0).as_bytes();
+                let version_len = version.len().min(PACKET_SIZE - 260 - 1);
+                response[260..260 + version_len].copy_from_slice(&version[..version_len]);
+            }
+            _ => supported = false,
+        }
+
+        if !supported {
+            response[5] = 1;
+        }
+        if flags & NORESP_FLAG != 0 {
+            return Ok(());
+        }
+        self.output.extend(response);
+        if !data.is_empty() {
+            let padded = data.len().div_ceil(block_len) * block_len;
+            data.resize(padded, 0);
+            self.output.extend(data);
+        }
+        Ok(())
+    }
+}
+
+/// A socket failure as an I/O error the core treats as a disconnect
+fn socket_error(error: tungstenite::Error) -> io::Error {
+    match error {
+        tungstenite::Error::Io(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+            io::Error::new(io::ErrorKind::TimedOut, "usb2snes server did not reply")
+        }
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            io::Error::new(io::ErrorKind::ConnectionAborted, "usb2snes server closed the connection")
+        }
+        tungstenite::Error::Protocol(e) => io::Error::new(io::ErrorKind::ConnectionAborted, e.to_string()),
+        tungstenite::Error::Io(e) => e,
+        other => io::Error::other(other.to_string()),
+    }
+}
+
+impl Read for WsPort {
+    /// Replies are queued when the command is written, so there is never anything to wait for
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.output.is_empty() {
+            std::thread::sleep(self.timeout);
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "Operation timed out"));
+        }
+        let n = buf.len().min(self.output.len());
+        for (slot, byte) in buf.iter_mut().zip(self.output.drain(..n)) {
+            *slot = byte;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for WsPort {
+    /// A request that fails at the socket level leaves the server's state unknown, so
+    /// the port is closed for good. A closed socket is reported right away (as a
+    /// disconnect); a server that stopped answering makes the command time out waiting
+    /// for its RESPONSE, and the next write reports the disconnect.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(reason) = &self.closed {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, reason.clone()));
+        }
+        let Err(e) = self.receive(buf) else {
+            return Ok(buf.len());
+        };
+        let _ = self.socket.close(None);
+        self.input.clear();
+        self.pending_put = None;
+        if e.kind() == io::ErrorKind::TimedOut {
+            self.closed = Some(format!("usb2snes request failed: {}", e));
+            return Ok(buf.len());
+        }
+        self.closed = Some(e.to_string());
+        Err(e)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialPort for WsPort {
+    fn name(&self) -> Option<String> {
+        Some(self.uri.clone())
+    }
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(9600)
+    }
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(DataBits::Eight)
+    }
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(FlowControl::None)
+    }
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(Parity::None)
+    }
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(StopBits::One)
+    }
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+    fn set_baud_rate(&mut self, _: u32) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_parity(&mut self, _: Parity) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+    fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn write_data_terminal_ready(&mut self, _: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(self.closed.is_none())
+    }
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(self.output.len() as u32)
+    }
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+    fn clear(&self, _: ClearBuffer) -> serialport::Result<()> {
+        // Queued replies can't be dropped through &self; drain_input_locked() reads them out
+        Ok(())
+    }
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Err(serialport::Error::new(serialport::ErrorKind::Unknown, "a usb2snes WebSocket can't be cloned"))
+    }
+    fn set_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn clear_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+}
+
+/// Open a usb2snes server as the core's port (see is_ws_uri())
+pub(crate) fn open_ws_port(uri: &str) -> Result<Box<dyn SerialPort>> {
+    Ok(Box::new(WsPort::connect(uri)?))
+}